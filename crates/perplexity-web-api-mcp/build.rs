@@ -0,0 +1,31 @@
+//! Records the git commit the server is built from, for `perplexity_about`.
+//!
+//! `PERPLEXITY_GIT_COMMIT` set in the build environment wins, so builds from
+//! a source archive can still name their commit. Outside a git checkout and
+//! without it, the commit is simply not reported.
+
+use std::path::Path;
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=PERPLEXITY_GIT_COMMIT");
+    let git = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../.git");
+    for watched in ["HEAD", "refs/heads", "packed-refs"] {
+        let path = git.join(watched);
+        if path.exists() {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+    }
+
+    if std::env::var_os("PERPLEXITY_GIT_COMMIT").is_some() {
+        return;
+    }
+    let output = Command::new("git").args(["rev-parse", "--short=12", "HEAD"]).output();
+    if let Some(output) = output.ok().filter(|output| output.status.success()) {
+        let commit = String::from_utf8_lossy(&output.stdout);
+        let commit = commit.trim();
+        if !commit.is_empty() {
+            println!("cargo:rustc-env=PERPLEXITY_GIT_COMMIT={}", commit);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Build and runtime details of the server, as reported by `perplexity_about`.
+//!
+//! Bug reports from deployed instances are triaged by what the server was
+//! built from and how it runs: the crate version and git commit, the
+//! Perplexity API version it speaks, the optional features compiled in, the
+//! MCP transport it serves, and the models it knows.
+
+use perplexity_web_api::{ApiVersion, Model};
+use rmcp::schemars;
+use serde::{Deserialize, Serialize};
+
+use crate::server::ModelInfo;
+
+/// Git commit the server was built from, recorded by the build script.
+const GIT_COMMIT: Option<&str> = option_env!("PERPLEXITY_GIT_COMMIT");
+
+/// How the server exchanges MCP messages with clients.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    /// Standard input and output.
+    #[default]
+    Stdio,
+    /// Streamable HTTP.
+    Http,
+    /// Streamable HTTP with health probes, as a container service.
+    Container,
+}
+
+/// What the server is and how it runs.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct AboutResponse {
+    /// Version of perplexity-web-api-mcp.
+    pub version: String,
+
+    /// Git commit the server was built from, if the build could tell.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_commit: Option<String>,
+
+    /// Version of the Perplexity web API the client speaks.
+    pub api_version: String,
+
+    /// Optional Cargo features compiled in, e.g. "store-sqlite".
+    pub features: Vec<String>,
+
+    /// Transport the server is serving.
+    pub transport: Transport,
+
+    /// Models the server knows, as listed by perplexity_list_models.
+    pub models: Vec<ModelInfo>,
+}
+
+impl AboutResponse {
+    /// Describes this build, serving over `transport` and speaking
+    /// `api_version`.
+    pub fn new(transport: Transport, api_version: ApiVersion) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git_commit: GIT_COMMIT.map(String::from),
+            api_version: api_version.to_string(),
+            features: features().into_iter().map(String::from).collect(),
+            transport,
+            models: Model::ALL.iter().copied().map(ModelInfo::from).collect(),
+        }
+    }
+}
+
+/// Optional features compiled in.
+fn features() -> Vec<&'static str> {
+    [
+        ("store-sqlite", cfg!(feature = "store-sqlite")),
+        ("otel", cfg!(feature = "otel")),
+        ("wasm-plugins", cfg!(feature = "wasm-plugins")),
+        ("keyring", cfg!(feature = "keyring")),
+        ("systemd", cfg!(feature = "systemd")),
+        ("windows-service", cfg!(feature = "windows-service")),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_about_describes_build() {
+        let about = AboutResponse::new(Transport::Http, ApiVersion::current());
+        assert_eq!(about.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(about.api_version, perplexity_web_api::API_VERSION);
+        assert_eq!(about.features.contains(&"otel".to_string()), cfg!(feature = "otel"));
+        assert_eq!(about.models.len(), Model::ALL.len());
+
+        let json = serde_json::to_value(&about).unwrap();
+        assert_eq!(json["transport"], "http");
+    }
+}
@@ -0,0 +1,195 @@
+//! Circuit breaking for research providers.
+//!
+//! After a number of consecutive failures that mean the provider is down,
+//! such as timeouts, rate limiting and server errors, the breaker opens:
+//! calls fail straight away with [`Error::ProviderUnavailable`], saying when
+//! to retry, instead of adding load to an endpoint that is turning requests
+//! away. Once the cooldown has passed the breaker half-opens and lets one
+//! call through as a probe. If the probe succeeds the breaker closes; if it
+//! fails the breaker opens for another cooldown.
+
+use perplexity_web_api::{
+    Error, ResearchFuture, ResearchProvider, SearchEvent, SearchRequest,
+};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Default number of consecutive failures that open the breaker.
+pub const DEFAULT_FAILURES: u32 = 5;
+
+/// Default time the breaker stays open before probing the provider.
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    /// Calls go through; `failures` consecutive ones failed.
+    Closed { failures: u32 },
+    /// Calls fail fast until `until`.
+    Open { until: Instant },
+    /// A probe started at `since` is running; other calls fail fast.
+    HalfOpen { since: Instant },
+}
+
+/// Runs queries on `inner`, failing them fast while it is down.
+pub struct CircuitBreaker {
+    inner: Arc<dyn ResearchProvider>,
+    failures: u32,
+    cooldown: Duration,
+    state: Mutex<State>,
+}
+
+impl CircuitBreaker {
+    /// Creates a breaker that opens after `failures` consecutive failures
+    /// and probes `inner` again `cooldown` later.
+    pub fn new(inner: Arc<dyn ResearchProvider>, failures: u32, cooldown: Duration) -> Self {
+        Self {
+            inner,
+            failures: failures.max(1),
+            cooldown,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    /// How long calls keep failing fast, or `None` if they go through.
+    pub fn retry_after(&self) -> Option<Duration> {
+        let state = *self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        self.blocked(state, Instant::now())
+    }
+
+    fn blocked(&self, state: State, now: Instant) -> Option<Duration> {
+        match state {
+            State::Closed { .. } => None,
+            State::Open { until } => (until > now).then(|| until - now),
+            // A probe whose call was dropped never reports back, so another
+            // one is let through after a cooldown.
+            State::HalfOpen { since } => {
+                let until = since + self.cooldown;
+                (until > now).then(|| until - now)
+            }
+        }
+    }
+
+    /// Lets a call through, or returns how long until one will be.
+    fn admit(&self) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = Instant::now();
+        if let Some(retry_after) = self.blocked(*state, now) {
+            return Err(retry_after);
+        }
+        if !matches!(*state, State::Closed { .. }) {
+            tracing::info!("Probing whether {} has recovered", self.inner.name());
+            *state = State::HalfOpen { since: now };
+        }
+        Ok(())
+    }
+
+    /// Records the outcome of a call let through by [`admit`](Self::admit).
+    fn record(&self, outage: bool) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        *state = match (*state, outage) {
+            (State::HalfOpen { .. }, false) => {
+                tracing::info!("{} has recovered", self.inner.name());
+                State::Closed { failures: 0 }
+            }
+            (_, false) => State::Closed { failures: 0 },
+            (State::Closed { failures }, true) if failures + 1 < self.failures => {
+                State::Closed { failures: failures + 1 }
+            }
+            (_, true) => {
+                tracing::warn!(
+                    "{} is unavailable, failing calls for {}s",
+                    self.inner.name(),
+                    self.cooldown.as_secs()
+                );
+                State::Open { until: Instant::now() + self.cooldown }
+            }
+        };
+    }
+}
+
+impl ResearchProvider for CircuitBreaker {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn research<'a>(
+        &'a self,
+        request: SearchRequest,
+        on_event: &'a mut (dyn FnMut(&SearchEvent) + Send),
+    ) -> ResearchFuture<'a> {
+        Box::pin(async move {
+            if let Err(retry_after) = self.admit() {
+                return Err(Error::ProviderUnavailable {
+                    provider: self.inner.name().to_string(),
+                    retry_after,
+                });
+            }
+            let result = self.inner.research(request, on_event).await;
+            // Errors with the request itself show the provider is answering.
+            self.record(result.as_ref().is_err_and(Error::is_unavailable));
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use perplexity_web_api::SearchResponse;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    /// Answers while `up`, otherwise fails with a server error; counts calls.
+    #[derive(Default)]
+    struct Flaky {
+        up: AtomicBool,
+        calls: AtomicUsize,
+    }
+
+    impl ResearchProvider for Flaky {
+        fn name(&self) -> &str {
+            "flaky"
+        }
+
+        fn research<'a>(
+            &'a self,
+            _request: SearchRequest,
+            _on_event: &'a mut (dyn FnMut(&SearchEvent) + Send),
+        ) -> ResearchFuture<'a> {
+            Box::pin(async move {
+                self.calls.fetch_add(1, Ordering::SeqCst);
+                if self.up.load(Ordering::SeqCst) {
+                    Ok(SearchResponse::from(SearchEvent::default()))
+                } else {
+                    Err(Error::Server { status: 503, message: "down".to_string() })
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_opens_fails_fast_and_recovers() {
+        let flaky = Arc::new(Flaky::default());
+        let breaker = CircuitBreaker::new(flaky.clone(), 2, Duration::from_millis(50));
+        let call = || async { breaker.research(SearchRequest::new("q"), &mut |_| {}).await };
+
+        for _ in 0..2 {
+            assert!(matches!(call().await, Err(Error::Server { status: 503, .. })));
+        }
+        let fast = call().await;
+        assert!(matches!(fast, Err(Error::ProviderUnavailable { .. })));
+        assert!(fast.is_err_and(|e| e.is_unavailable()));
+        assert_eq!(flaky.calls.load(Ordering::SeqCst), 2);
+        assert!(breaker.retry_after().is_some());
+
+        // A failed probe opens the breaker again.
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(matches!(call().await, Err(Error::Server { .. })));
+        assert!(matches!(call().await, Err(Error::ProviderUnavailable { .. })));
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        flaky.up.store(true, Ordering::SeqCst);
+        assert!(call().await.is_ok());
+        assert!(breaker.retry_after().is_none());
+        assert_eq!(flaky.calls.load(Ordering::SeqCst), 4);
+    }
+}
@@ -0,0 +1,282 @@
+//! In-memory cache of complete answers.
+//!
+//! Every complete answer is stored, keyed by mode, query, sources,
+//! language, answer style, recency filter, model and attached files.
+//! Callers opt into reading from the cache per query by giving a maximum
+//! age; with stale-while-revalidate, an older answer is returned
+//! immediately while a single background refresh replaces it.
+//!
+//! Answers carry the tags of the queries that stored or reused them, so a
+//! topic's answers can be purged without clearing the rest of the cache.
+
+use perplexity_web_api::{AnswerStyle, Model, Recency, SearchMode};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::server::{PerplexityRequest, PerplexityResponse};
+
+/// Identifies queries that produce interchangeable answers.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    mode: &'static str,
+    query: String,
+    sources: Vec<String>,
+    language: Option<String>,
+    answer_style: Option<&'static str>,
+    recency: Option<&'static str>,
+    model: Option<&'static str>,
+    files: Vec<String>,
+}
+
+impl CacheKey {
+    /// Builds the key for `params` run in `mode`.
+    ///
+    /// Sources are compared case-insensitively and in any order, with no
+    /// sources meaning the default web search. Surrounding whitespace in the
+    /// query is ignored.
+    pub fn new(params: &PerplexityRequest, mode: SearchMode) -> Self {
+        let mut sources: Vec<String> =
+            params.sources.iter().flatten().map(|s| s.trim().to_lowercase()).collect();
+        sources.sort();
+        sources.dedup();
+        if sources.is_empty() {
+            sources.push("web".to_string());
+        }
+        let mut files = params.files.clone().unwrap_or_default();
+        files.sort();
+
+        Self {
+            mode: mode.as_str(),
+            query: params.query.trim().to_string(),
+            sources,
+            language: params.language.clone(),
+            answer_style: params
+                .answer_style
+                .as_deref()
+                .and_then(|style| style.parse::<AnswerStyle>().ok())
+                .map(|style| style.as_str()),
+            recency: params
+                .recency
+                .as_deref()
+                .and_then(|recency| recency.parse::<Recency>().ok())
+                .map(|recency| recency.as_str()),
+            model: params
+                .model
+                .as_deref()
+                .and_then(|model| model.parse::<Model>().ok())
+                .map(|model| model.as_str()),
+            files,
+        }
+    }
+}
+
+struct Entry {
+    response: PerplexityResponse,
+    stored_at: Instant,
+    refreshing: bool,
+    tags: Vec<String>,
+}
+
+/// The tags of `params`, trimmed, lowercased and without repeats.
+pub fn tags(params: &PerplexityRequest) -> Vec<String> {
+    let mut tags: Vec<String> = params
+        .tags
+        .iter()
+        .flatten()
+        .map(|tag| tag.trim().to_lowercase())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+/// Bounded store of recent answers, shared by all clones.
+#[derive(Clone)]
+pub struct AnswerCache {
+    entries: Arc<Mutex<HashMap<CacheKey, Entry>>>,
+    capacity: usize,
+}
+
+impl AnswerCache {
+    /// Creates a cache holding at most `capacity` answers. A capacity of 0
+    /// disables caching.
+    pub fn new(capacity: usize) -> Self {
+        Self { entries: Arc::default(), capacity }
+    }
+
+    /// Returns the cached answer for `key` and its age.
+    pub fn get(&self, key: &CacheKey) -> Option<(PerplexityResponse, Duration)> {
+        self.lock().get(key).map(|entry| (entry.response.clone(), entry.stored_at.elapsed()))
+    }
+
+    /// Stores a complete answer with `tags`, evicting the oldest one if the
+    /// cache is full. An answer it replaces passes on its tags.
+    pub fn insert(&self, key: CacheKey, response: PerplexityResponse, mut tags: Vec<String>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.lock();
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            let oldest =
+                entries.iter().min_by_key(|(_, e)| e.stored_at).map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        if let Some(old) = entries.get(&key) {
+            add_tags(&mut tags, old.tags.clone());
+        }
+        entries.insert(
+            key,
+            Entry { response, stored_at: Instant::now(), refreshing: false, tags },
+        );
+    }
+
+    /// Adds `tags` to the answer cached for `key`, when a tagged query
+    /// reuses it.
+    pub fn tag(&self, key: &CacheKey, tags: Vec<String>) {
+        if let Some(entry) = self.lock().get_mut(key) {
+            add_tags(&mut entry.tags, tags);
+        }
+    }
+
+    /// Removes the answers having any of `tags`, or with no tags given any
+    /// answer, that are older than `older_than` if given. Returns how many
+    /// were removed.
+    pub fn invalidate(&self, tags: &[String], older_than: Option<Duration>) -> usize {
+        let mut entries = self.lock();
+        let before = entries.len();
+        entries.retain(|_, entry| {
+            let tagged = tags.is_empty() || entry.tags.iter().any(|tag| tags.contains(tag));
+            let old = older_than.is_none_or(|age| entry.stored_at.elapsed() > age);
+            !(tagged && old)
+        });
+        before - entries.len()
+    }
+
+    /// Marks `key` as being refreshed. Returns false if a refresh is already
+    /// under way or nothing is cached for it.
+    pub fn begin_refresh(&self, key: &CacheKey) -> bool {
+        match self.lock().get_mut(key) {
+            Some(entry) if !entry.refreshing => {
+                entry.refreshing = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Clears the refresh mark after a failed refresh so a later request can
+    /// retry it. A successful refresh clears it by replacing the entry.
+    pub fn end_refresh(&self, key: &CacheKey) {
+        if let Some(entry) = self.lock().get_mut(key) {
+            entry.refreshing = false;
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<CacheKey, Entry>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn add_tags(tags: &mut Vec<String>, more: Vec<String>) {
+    tags.extend(more);
+    tags.sort();
+    tags.dedup();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(query: &str, sources: &[&str]) -> PerplexityRequest {
+        let sources = sources.iter().map(|s| (*s).to_string()).collect();
+        PerplexityRequest::plain(query.to_string(), Some(sources))
+    }
+
+    #[test]
+    fn test_key_ignores_source_order_and_case() {
+        let a = CacheKey::new(&request(" rust ", &["web", "Scholar"]), SearchMode::Pro);
+        let b = CacheKey::new(&request("rust", &["scholar", "web"]), SearchMode::Pro);
+        let c = CacheKey::new(&request("rust", &["web"]), SearchMode::Pro);
+        let d = CacheKey::new(&request("rust", &["web", "scholar"]), SearchMode::Auto);
+
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_ne!(a, d);
+        assert_eq!(CacheKey::new(&request("rust", &[]), SearchMode::Pro), c);
+    }
+
+    #[test]
+    fn test_evicts_oldest_when_full() {
+        let cache = AnswerCache::new(2);
+        let keys: Vec<_> = ["a", "b", "c"]
+            .iter()
+            .map(|q| CacheKey::new(&request(q, &[]), SearchMode::Auto))
+            .collect();
+
+        for key in &keys {
+            cache.insert(key.clone(), PerplexityResponse::answered("answer"), Vec::new());
+        }
+
+        assert!(cache.get(&keys[0]).is_none());
+        assert!(cache.get(&keys[1]).is_some());
+        assert!(cache.get(&keys[2]).is_some());
+    }
+
+    #[test]
+    fn test_single_refresh_at_a_time() {
+        let cache = AnswerCache::new(4);
+        let key = CacheKey::new(&request("q", &[]), SearchMode::Auto);
+        assert!(!cache.begin_refresh(&key));
+
+        cache.insert(key.clone(), PerplexityResponse::answered("old"), Vec::new());
+        assert!(cache.begin_refresh(&key));
+        assert!(!cache.begin_refresh(&key));
+
+        cache.end_refresh(&key);
+        assert!(cache.begin_refresh(&key));
+        cache.insert(key.clone(), PerplexityResponse::answered("new"), Vec::new());
+        assert!(cache.begin_refresh(&key));
+        assert_eq!(cache.get(&key).unwrap().0.answer.as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn test_invalidate_by_tag_and_age() {
+        let cache = AnswerCache::new(4);
+        let mut tagged = request("acme pricing", &[]);
+        tagged.tags = Some(vec![" Competitors ".to_string(), "competitors".to_string()]);
+        assert_eq!(tags(&tagged), ["competitors"]);
+        let keys: Vec<_> = ["acme pricing", "our pricing", "rust"]
+            .iter()
+            .map(|q| CacheKey::new(&request(q, &[]), SearchMode::Auto))
+            .collect();
+        cache.insert(keys[0].clone(), PerplexityResponse::answered("a"), tags(&tagged));
+        cache.insert(
+            keys[1].clone(),
+            PerplexityResponse::answered("b"),
+            vec!["pricing".to_string()],
+        );
+        cache.insert(keys[2].clone(), PerplexityResponse::answered("c"), Vec::new());
+        // A refresh keeps the tags, and a tagged reuse adds its own.
+        cache.insert(keys[0].clone(), PerplexityResponse::answered("a2"), Vec::new());
+        cache.tag(&keys[2], vec!["pricing".to_string()]);
+
+        let competitors = ["competitors".to_string()];
+        assert_eq!(cache.invalidate(&competitors, Some(Duration::from_secs(3600))), 0);
+        assert_eq!(cache.invalidate(&competitors, None), 1);
+        assert!(cache.get(&keys[0]).is_none());
+        assert_eq!(cache.invalidate(&["pricing".to_string()], Some(Duration::ZERO)), 2);
+    }
+
+    #[test]
+    fn test_zero_capacity_disables_cache() {
+        let cache = AnswerCache::new(0);
+        let key = CacheKey::new(&request("q", &[]), SearchMode::Auto);
+        cache.insert(key.clone(), PerplexityResponse::answered("answer"), Vec::new());
+        assert!(cache.get(&key).is_none());
+    }
+}
@@ -0,0 +1,223 @@
+//! Splitting answers into claims and the citations that support them.
+//!
+//! A claim is a sentence of the answer, with headings skipped and Markdown
+//! list markers removed. Numeric references such as `[1]` are taken out of
+//! the text and attributed to the sentence they follow, including when they
+//! come after its closing punctuation, as in `Rust is fast.[1][2]`.
+//!
+//! [`verify_citations`] then checks each citation against the web result it
+//! points to. A citation is weak when the result's name and snippet contain
+//! too few of the claim's content words, or when no such result exists.
+//! Word overlap cannot tell a paraphrase from a fabrication, so a weak
+//! citation is a reason to open the source, not proof that it was made up.
+
+use rmcp::schemars;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Share of a claim's content words that the cited result must contain for
+/// the citation to support it.
+const MIN_OVERLAP: f64 = 0.2;
+
+/// Words too common to show that a source is about the claim.
+const STOPWORDS: &[&str] = &[
+    "the", "and", "for", "are", "was", "were", "has", "have", "had", "its", "it's", "that",
+    "this", "these", "those", "with", "from", "into", "than", "then", "but", "not", "can",
+    "also", "such", "which", "who", "whom", "what", "when", "where", "how", "all", "any",
+    "more", "most", "other", "some", "their", "there", "they", "them", "you", "your", "our",
+    "been", "being", "will", "would", "could", "should", "may", "might", "about", "over",
+    "under", "between", "each", "both", "only", "very", "while", "use", "used", "uses",
+];
+
+/// A sentence of an answer and the citations attached to it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct Claim {
+    /// The sentence, without citation references.
+    pub text: String,
+
+    /// Citation numbers referenced by the sentence, in order of appearance.
+    /// Citation `n` is the `n`-th entry of `web_results`.
+    pub citation_indices: Vec<usize>,
+
+    /// Citations whose web result shares too few words with the sentence,
+    /// or that point past the web results, when requested with
+    /// `verify_citations`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weak_citations: Option<Vec<usize>>,
+}
+
+/// Splits `answer` into claims.
+pub fn extract_claims(answer: &str) -> Vec<Claim> {
+    let mut claims = Vec::new();
+
+    for line in answer.lines().map(str::trim).filter(|line| !line.starts_with('#')) {
+        let mut text = String::new();
+        let mut citations = Vec::new();
+        let mut ended = false;
+        let mut rest = strip_list_marker(line);
+
+        while let Some(c) = rest.chars().next() {
+            if let Some((index, after)) = citation(rest) {
+                // Drop the space before the reference so "fact [1]." reads "fact.".
+                text.truncate(text.trim_end().len());
+                if !citations.contains(&index) {
+                    citations.push(index);
+                }
+                rest = after;
+                continue;
+            }
+
+            rest = &rest[c.len_utf8()..];
+            if ended && c.is_whitespace() {
+                push_claim(&mut claims, &text, std::mem::take(&mut citations));
+                text.clear();
+                ended = false;
+                continue;
+            }
+            text.push(c);
+            ended = matches!(c, '.' | '!' | '?');
+        }
+        push_claim(&mut claims, &text, citations);
+    }
+
+    claims
+}
+
+fn push_claim(claims: &mut Vec<Claim>, sentence: &str, citation_indices: Vec<usize>) {
+    let text = sentence.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().any(char::is_alphanumeric) {
+        claims.push(Claim { text, citation_indices, weak_citations: None });
+    } else if let Some(last) = claims.last_mut() {
+        // References left on their own belong to the sentence before them.
+        for index in citation_indices {
+            if !last.citation_indices.contains(&index) {
+                last.citation_indices.push(index);
+            }
+        }
+    }
+}
+
+/// Flags the weak citations of `claims`. `sources` holds the text of each
+/// web result in order, its name and snippet, so citation `n` is checked
+/// against `sources[n - 1]`.
+pub fn verify_citations(claims: &mut [Claim], sources: &[String]) {
+    let sources: Vec<HashSet<String>> =
+        sources.iter().map(|source| content_words(source).collect()).collect();
+
+    for claim in claims {
+        let words: HashSet<String> = content_words(&claim.text).collect();
+        let weak = claim
+            .citation_indices
+            .iter()
+            .copied()
+            .filter(|&index| {
+                let Some(source) = index.checked_sub(1).and_then(|i| sources.get(i)) else {
+                    return true;
+                };
+                // A sentence of stopwords alone gives nothing to compare.
+                if words.is_empty() {
+                    return false;
+                }
+                let shared = words.intersection(source).count();
+                let overlap = shared as f64 / words.len() as f64;
+                overlap < MIN_OVERLAP
+            })
+            .collect();
+        claim.weak_citations = Some(weak);
+    }
+}
+
+/// The lowercase words of `text` that say what it is about: numbers and
+/// words of three letters or more other than stopwords, with a plural `s`
+/// dropped so "crates" matches "crate".
+fn content_words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .map(|word| word.trim_matches('\'').to_lowercase())
+        .filter(|word| {
+            let number = !word.is_empty() && word.chars().all(|c| c.is_ascii_digit());
+            number || (word.chars().count() >= 3 && !STOPWORDS.contains(&word.as_str()))
+        })
+        .map(|word| match word.strip_suffix('s') {
+            Some(stem) if stem.chars().count() >= 3 && !stem.ends_with('s') => {
+                stem.to_string()
+            }
+            _ => word,
+        })
+}
+
+/// Parses a numeric citation reference such as `[12]` at the start of `text`.
+fn citation(text: &str) -> Option<(usize, &str)> {
+    let after = text.strip_prefix('[')?;
+    let digits = after.len() - after.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let rest = after[digits..].strip_prefix(']')?;
+    let index = after[..digits].parse().ok()?;
+    Some((index, rest))
+}
+
+/// Removes a leading quote, bullet or numbered-list marker.
+fn strip_list_marker(line: &str) -> &str {
+    let line = line.trim_start_matches('>').trim_start();
+    if let Some(rest) = line.strip_prefix(['-', '*', '+']) {
+        return rest.trim_start();
+    }
+
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    match line[digits..].strip_prefix(['.', ')']) {
+        Some(rest) if digits > 0 && rest.starts_with(' ') => rest.trim_start(),
+        _ => line,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claim(text: &str, citation_indices: &[usize]) -> Claim {
+        Claim {
+            text: text.to_string(),
+            citation_indices: citation_indices.to_vec(),
+            weak_citations: None,
+        }
+    }
+
+    #[test]
+    fn test_maps_citations_to_sentences() {
+        let answer = "Rust is fast [1]. It is memory safe.[2][3] It has no GC.\n\n\
+                      - Cargo builds code [3][1].";
+
+        assert_eq!(
+            extract_claims(answer),
+            [
+                claim("Rust is fast.", &[1]),
+                claim("It is memory safe.", &[2, 3]),
+                claim("It has no GC.", &[]),
+                claim("Cargo builds code.", &[3, 1]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trailing_references_join_previous_claim() {
+        assert_eq!(
+            extract_claims("## Sources\nRelease notes say so. [4]\nSee [docs] [5]"),
+            [claim("Release notes say so.", &[4]), claim("See [docs]", &[5])]
+        );
+    }
+
+    #[test]
+    fn test_flags_weak_citations() {
+        let mut claims = extract_claims(
+            "Tokio schedules tasks across worker threads [1][2]. It was released in 2016.[1][9] \
+             It is great [2].",
+        );
+        let sources = [
+            "Tokio runtime: The scheduler spreads tasks over a pool of worker threads.",
+            "Pancake recipes: Mix flour, eggs and milk.",
+        ]
+        .map(String::from);
+        verify_citations(&mut claims, &sources);
+
+        let weak: Vec<_> = claims.iter().map(|c| c.weak_citations.clone().unwrap()).collect();
+        assert_eq!(weak, [vec![2], vec![1, 9], vec![2]]);
+    }
+}
@@ -0,0 +1,140 @@
+//! Extraction of fenced code blocks from answers.
+//!
+//! Follows the CommonMark rules that matter for generated answers: fences of
+//! three or more backticks or tildes, an optional info string whose first
+//! word is the language, and indentation of the opening fence removed from
+//! the block's lines. An unterminated block at the end of the answer, as in
+//! a partial answer, runs to the end of the text.
+
+use serde::Serialize;
+
+/// A code block found in an answer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CodeSnippet {
+    /// Language tag from the opening fence, lowercased, e.g. "rust".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+
+    /// The code, without the fences.
+    pub code: String,
+}
+
+struct Fence {
+    marker: char,
+    len: usize,
+    indent: usize,
+}
+
+/// Returns the fenced code blocks in `answer`, in order.
+pub fn extract_code_blocks(answer: &str) -> Vec<CodeSnippet> {
+    let mut snippets = Vec::new();
+    let mut open: Option<(Fence, Option<String>, Vec<&str>)> = None;
+
+    for line in answer.lines() {
+        match open.take() {
+            None => {
+                if let Some((fence, info)) = opening_fence(line) {
+                    let language = info
+                        .split_whitespace()
+                        .next()
+                        .map(|lang| lang.trim_start_matches('{').trim_start_matches('.'))
+                        .map(str::to_lowercase)
+                        .filter(|lang| !lang.is_empty());
+                    open = Some((fence, language, Vec::new()));
+                }
+            }
+            Some((fence, language, lines)) if closes(line, &fence) => {
+                snippets.push(CodeSnippet { language, code: lines.join("\n") });
+            }
+            Some((fence, language, mut lines)) => {
+                lines.push(strip_indent(line, fence.indent));
+                open = Some((fence, language, lines));
+            }
+        }
+    }
+
+    if let Some((_, language, lines)) = open {
+        let code = lines.join("\n");
+        if !code.trim().is_empty() {
+            snippets.push(CodeSnippet { language, code });
+        }
+    }
+
+    snippets
+}
+
+fn opening_fence(line: &str) -> Option<(Fence, &str)> {
+    let trimmed = line.trim_start_matches(' ');
+    let indent = line.len() - trimmed.len();
+    let marker = trimmed.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let len = trimmed.chars().take_while(|&c| c == marker).count();
+    let info = trimmed[len..].trim();
+
+    // Backtick fences may not contain backticks in their info string.
+    if indent > 3 || len < 3 || (marker == '`' && info.contains('`')) {
+        return None;
+    }
+    Some((Fence { marker, len, indent }, info))
+}
+
+fn closes(line: &str, fence: &Fence) -> bool {
+    let trimmed = line.trim();
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    indent <= 3 && trimmed.len() >= fence.len && trimmed.chars().all(|c| c == fence.marker)
+}
+
+fn strip_indent(line: &str, indent: usize) -> &str {
+    let spaces = line.len() - line.trim_start_matches(' ').len();
+    &line[spaces.min(indent)..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extracts_blocks_with_languages() {
+        let answer = "Use a vector:\n\n```Rust\nlet v = vec![1, 2];\n\nprintln!(\"{:?}\", v);\n```\n\nOr in Python:\n~~~python title=\"x.py\"\nv = [1, 2]\n~~~\n\n```\nplain\n```";
+
+        assert_eq!(
+            extract_code_blocks(answer),
+            vec![
+                CodeSnippet {
+                    language: Some("rust".to_string()),
+                    code: "let v = vec![1, 2];\n\nprintln!(\"{:?}\", v);".to_string(),
+                },
+                CodeSnippet {
+                    language: Some("python".to_string()),
+                    code: "v = [1, 2]".to_string()
+                },
+                CodeSnippet { language: None, code: "plain".to_string() },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nested_and_indented_fences() {
+        let answer = "1. Write:\n   ````markdown\n   ```sh\n   ls\n   ```\n   ````\n";
+
+        assert_eq!(
+            extract_code_blocks(answer),
+            vec![CodeSnippet {
+                language: Some("markdown".to_string()),
+                code: "```sh\nls\n```".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_block_runs_to_end() {
+        assert_eq!(
+            extract_code_blocks("Partial:\n```js\nconsole.log(1);"),
+            vec![CodeSnippet {
+                language: Some("js".to_string()),
+                code: "console.log(1);".to_string(),
+            }]
+        );
+        assert!(extract_code_blocks("Inline `code` only, and ``` a b ` c").is_empty());
+        assert!(extract_code_blocks("```\n").is_empty());
+    }
+}
@@ -0,0 +1,288 @@
+//! Comparison matrices.
+//!
+//! `perplexity_compare` asks one query per entity covering every criterion,
+//! reads the answer back one line per criterion, and lays the cells out as
+//! a matrix. Citations are renumbered to one merged source list, so each
+//! cell's `citations` and the `[n]` markers in the Markdown table refer to
+//! the same `web_results`.
+
+use perplexity_web_api::{CitationMerger, SearchWebResult};
+use rmcp::schemars;
+use serde::{Deserialize, Serialize};
+
+use crate::claims::extract_claims;
+use crate::server::{PerplexityResponse, WebResultInfo};
+
+/// Most entities a comparison may have.
+pub const MAX_ENTITIES: usize = 8;
+
+/// Most criteria a comparison may have.
+pub const MAX_CRITERIA: usize = 10;
+
+/// Request parameters for a comparison.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct CompareRequest {
+    /// What to compare, 2 to 8 entries, e.g. ["PostgreSQL", "MySQL"].
+    pub entities: Vec<String>,
+
+    /// What to compare them on, 1 to 10 entries, e.g. ["License",
+    /// "Replication", "JSON support"].
+    pub criteria: Vec<String>,
+
+    /// Search mode of the per-entity queries: "auto", "pro", "reasoning",
+    /// or "deep research". Defaults to "pro".
+    #[serde(default)]
+    pub mode: Option<String>,
+
+    /// Sources to search, as for perplexity_search.
+    #[serde(default)]
+    pub sources: Option<Vec<String>>,
+}
+
+/// A comparison matrix.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct CompareResponse {
+    /// The criteria, in column order.
+    pub criteria: Vec<String>,
+
+    /// One row per entity, in request order.
+    pub rows: Vec<ComparisonRow>,
+
+    /// The matrix as a Markdown table, followed by the merged sources.
+    pub table: String,
+
+    /// Sources of all cells, deduplicated by URL. Citation `n` is the
+    /// `n`-th entry.
+    pub web_results: Vec<WebResultInfo>,
+
+    /// Cells Perplexity did not answer and entities that failed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// An entity's row of the matrix.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct ComparisonRow {
+    /// The entity.
+    pub entity: String,
+
+    /// One cell per criterion, in column order.
+    pub cells: Vec<ComparisonCell>,
+
+    /// Why the entity's query failed; its cells are then empty.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A cell of the matrix.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct ComparisonCell {
+    /// The criterion.
+    pub criterion: String,
+
+    /// The answer for the criterion, without citation markers. Missing if
+    /// the answer did not cover it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+
+    /// Citation numbers supporting the value.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub citations: Vec<usize>,
+}
+
+/// Checks the sizes of a comparison request.
+pub fn validate(request: &CompareRequest) -> Result<(), String> {
+    if !(2..=MAX_ENTITIES).contains(&request.entities.len()) {
+        return Err(format!("entities must have 2 to {} entries", MAX_ENTITIES));
+    }
+    if !(1..=MAX_CRITERIA).contains(&request.criteria.len()) {
+        return Err(format!("criteria must have 1 to {} entries", MAX_CRITERIA));
+    }
+    if request.entities.iter().chain(&request.criteria).any(|s| s.trim().is_empty()) {
+        return Err("entities and criteria must not be empty".into());
+    }
+    Ok(())
+}
+
+/// The query covering every criterion for `entity`.
+pub fn entity_query(entity: &str, criteria: &[String]) -> String {
+    let mut query = format!(
+        "Describe {} on each of the following criteria in one or two sentences, citing \
+         sources. Reply with exactly one line per criterion, starting with the criterion's \
+         name and a colon, and nothing else.\n",
+        entity
+    );
+    for criterion in criteria {
+        query.push_str(&format!("\n- {}", criterion));
+    }
+    query
+}
+
+/// Reads the cell of each criterion from an answer, one line per criterion
+/// labelled with its name. Lines without a known label continue the cell
+/// before them.
+fn parse_cells(answer: &str, criteria: &[String]) -> Vec<Option<String>> {
+    let mut cells: Vec<Option<String>> = vec![None; criteria.len()];
+    let mut current = None;
+    for line in answer.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let line = line.trim_start_matches(['-', '*', '+', '#']).trim_start();
+        let labelled = line.split_once(':').and_then(|(label, rest)| {
+            let label = label.trim().trim_matches('*').trim();
+            let i = criteria.iter().position(|c| c.trim().eq_ignore_ascii_case(label))?;
+            Some((i, rest.trim_start_matches('*').trim()))
+        });
+        match (labelled, current) {
+            (Some((i, rest)), _) => {
+                cells[i] = Some(rest.to_string());
+                current = Some(i);
+            }
+            (None, Some(i)) => {
+                if let Some(cell) = &mut cells[i] {
+                    cell.push(' ');
+                    cell.push_str(line);
+                }
+            }
+            (None, None) => {}
+        }
+    }
+    cells
+}
+
+/// Builds the matrix from each entity's answer.
+pub fn assemble(
+    criteria: Vec<String>,
+    answers: Vec<(String, Result<PerplexityResponse, String>)>,
+) -> CompareResponse {
+    let mut merger = CitationMerger::new();
+    let mut warnings = Vec::new();
+    let mut rows = Vec::with_capacity(answers.len());
+
+    for (entity, result) in answers {
+        let response = match result {
+            Ok(response) => response,
+            Err(e) => {
+                warnings.push(format!("{}: {}", entity, e));
+                let cells = criteria
+                    .iter()
+                    .map(|criterion| ComparisonCell {
+                        criterion: criterion.clone(),
+                        value: None,
+                        citations: Vec::new(),
+                    })
+                    .collect();
+                rows.push(ComparisonRow { entity, cells, error: Some(e) });
+                continue;
+            }
+        };
+        let results: Vec<SearchWebResult> =
+            response.web_results.iter().map(SearchWebResult::from).collect();
+        let answer = merger.add(response.answer.as_deref().unwrap_or_default(), &results);
+        let cells = parse_cells(&answer, &criteria)
+            .into_iter()
+            .zip(&criteria)
+            .map(|(cell, criterion)| {
+                let claims = cell.as_deref().map(extract_claims).unwrap_or_default();
+                let value = claims.iter().map(|claim| claim.text.as_str()).collect::<Vec<_>>();
+                let mut citations: Vec<usize> = Vec::new();
+                for index in claims.iter().flat_map(|claim| &claim.citation_indices) {
+                    if !citations.contains(index) {
+                        citations.push(*index);
+                    }
+                }
+                if value.is_empty() {
+                    warnings.push(format!("{}: no answer for {}", entity, criterion));
+                }
+                ComparisonCell {
+                    criterion: criterion.clone(),
+                    value: (!value.is_empty()).then(|| value.join(" ")),
+                    citations,
+                }
+            })
+            .collect();
+        rows.push(ComparisonRow { entity, cells, error: None });
+    }
+
+    let web_results: Vec<WebResultInfo> =
+        merger.into_results().into_iter().map(WebResultInfo::from).collect();
+    let mut table = markdown_table(&criteria, &rows);
+    table.push_str(&crate::report::sources(&web_results));
+    CompareResponse { criteria, rows, table, web_results, warnings }
+}
+
+/// Lays out `rows` as a Markdown table with an entity column.
+fn markdown_table(criteria: &[String], rows: &[ComparisonRow]) -> String {
+    let escape = |text: &str| text.replace('|', "\\|").replace('\n', " ");
+    let mut table = "| Entity |".to_string();
+    for criterion in criteria {
+        table.push_str(&format!(" {} |", escape(criterion)));
+    }
+    table.push_str(&format!("\n|---|{}\n", "---|".repeat(criteria.len())));
+    for row in rows {
+        table.push_str(&format!("| {} |", escape(&row.entity)));
+        for cell in &row.cells {
+            let text = match (&cell.value, &row.error) {
+                (Some(value), _) => {
+                    let markers: String =
+                        cell.citations.iter().map(|n| format!("[{}]", n)).collect();
+                    escape(&format!("{} {}", value, markers)).trim_end().to_string()
+                }
+                (None, Some(_)) => "_failed_".to_string(),
+                (None, None) => "—".to_string(),
+            };
+            table.push_str(&format!(" {} |", text));
+        }
+        table.push('\n');
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assemble_matrix_with_merged_citations() {
+        let criteria = vec!["License".to_string(), "JSON support".to_string()];
+        let source = |url: &str| WebResultInfo {
+            name: url.to_string(),
+            url: url.to_string(),
+            snippet: String::new(),
+            score: None,
+        };
+        let mut postgres = PerplexityResponse::answered(
+            "**License:** PostgreSQL License [1].\n- json support: jsonb | json types [2]\n\
+             with indexing [1].",
+        );
+        postgres.web_results = vec![source("https://postgresql.org"), source("https://a.dev")];
+        let mut mysql = PerplexityResponse::answered("License: GPLv2 [1][2]");
+        mysql.web_results = vec![source("https://mysql.com"), source("https://a.dev/")];
+
+        let matrix = assemble(
+            criteria,
+            vec![
+                ("PostgreSQL".to_string(), Ok(postgres)),
+                ("MySQL".to_string(), Ok(mysql)),
+                ("SQLite".to_string(), Err("rate limited".to_string())),
+            ],
+        );
+        assert_eq!(matrix.web_results.len(), 3);
+        assert_eq!(
+            matrix.rows[0].cells[1],
+            ComparisonCell {
+                criterion: "JSON support".to_string(),
+                value: Some("jsonb | json types with indexing.".to_string()),
+                citations: vec![2, 1],
+            }
+        );
+        assert_eq!(matrix.rows[1].cells[0].citations, [3, 2]);
+        assert_eq!(
+            matrix.warnings,
+            ["MySQL: no answer for JSON support", "SQLite: rate limited"]
+        );
+        assert!(matrix.table.starts_with(
+            "| Entity | License | JSON support |\n|---|---|---|\n\
+             | PostgreSQL | PostgreSQL License. [1] | jsonb \\| json types with indexing. [2][1] |\n\
+             | MySQL | GPLv2 [3][2] | — |\n| SQLite | _failed_ | _failed_ |\n\n## Sources\n"
+        ));
+    }
+}
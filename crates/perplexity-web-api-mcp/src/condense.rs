@@ -0,0 +1,127 @@
+//! Condensing of long answers by the client's model.
+//!
+//! Deep Research reports can run to tens of thousands of characters. When
+//! a length limit is configured and the client supports MCP sampling, a
+//! longer answer is sent back to the host LLM with a request to condense
+//! it, keeping the citation markers that refer to the sources. Without
+//! sampling, or if the request fails, the full answer is returned.
+
+use rmcp::model::{CreateMessageRequestParams, SamplingMessage};
+use rmcp::{Peer, RoleServer};
+use std::time::Duration;
+
+use crate::server::PerplexityResponse;
+
+/// How long the client may take to produce the condensed answer.
+const SAMPLING_TIMEOUT: Duration = Duration::from_secs(2 * 60);
+
+/// Characters per token assumed when sizing the sampling request.
+const CHARS_PER_TOKEN: usize = 3;
+
+const SYSTEM_PROMPT: &str = "You condense research reports. Keep the key findings, figures \
+and conclusions, and keep citation markers such as [1] next to the statements they support. \
+Reply with the condensed report only.";
+
+/// Condenses answers longer than a character limit.
+#[derive(Debug, Clone, Copy)]
+pub struct Condenser {
+    max_chars: usize,
+}
+
+impl Condenser {
+    pub fn new(max_chars: usize) -> Self {
+        Self { max_chars }
+    }
+
+    /// Replaces an over-long answer in `response` with a condensed version
+    /// written by `peer`'s model, if the client supports sampling.
+    pub async fn condense(&self, peer: &Peer<RoleServer>, response: &mut PerplexityResponse) {
+        let Some(answer) = &response.answer else {
+            return;
+        };
+        let length = answer.chars().count();
+        if length <= self.max_chars {
+            return;
+        }
+        if peer.peer_info().is_none_or(|info| info.capabilities.sampling.is_none()) {
+            tracing::debug!(length, "Client does not support sampling; not condensing answer");
+            return;
+        }
+
+        let request = sampling_request(answer, self.max_chars);
+        let result =
+            match tokio::time::timeout(SAMPLING_TIMEOUT, peer.create_message(request)).await {
+                Ok(Ok(result)) => result,
+                Ok(Err(e)) => {
+                    tracing::warn!("Condensing answer failed: {}", e);
+                    response.warnings.push(format!("Could not condense the answer: {}", e));
+                    return;
+                }
+                Err(_) => {
+                    tracing::warn!("Condensing answer timed out");
+                    response.warnings.push("Condensing the answer timed out".to_string());
+                    return;
+                }
+            };
+
+        let condensed: String = result
+            .message
+            .content
+            .iter()
+            .filter_map(|content| content.as_text())
+            .map(|text| text.text.as_str())
+            .collect();
+        if condensed.trim().is_empty() {
+            response
+                .warnings
+                .push("The client returned an empty condensed answer".to_string());
+            return;
+        }
+
+        response.warnings.push(format!(
+            "Answer condensed from {} to {} characters by {}",
+            length,
+            condensed.chars().count(),
+            result.model
+        ));
+        response.answer = Some(condensed);
+    }
+}
+
+/// Sampling request asking for `answer` to be cut down to `max_chars`.
+fn sampling_request(answer: &str, max_chars: usize) -> CreateMessageRequestParams {
+    CreateMessageRequestParams {
+        meta: None,
+        task: None,
+        messages: vec![SamplingMessage::user_text(format!(
+            "Condense this report to at most {} characters:\n\n{}",
+            max_chars, answer
+        ))],
+        model_preferences: None,
+        system_prompt: Some(SYSTEM_PROMPT.to_string()),
+        include_context: None,
+        temperature: Some(0.2),
+        max_tokens: u32::try_from(max_chars / CHARS_PER_TOKEN).unwrap_or(u32::MAX).max(256),
+        stop_sequences: None,
+        metadata: None,
+        tools: None,
+        tool_choice: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sampling_request() {
+        let request = sampling_request("A long report [1].", 3000);
+        assert!(request.validate().is_ok());
+        assert_eq!(request.max_tokens, 1000);
+        let prompt = request.messages[0].content.first().unwrap().as_text().unwrap();
+        assert!(prompt.text.starts_with("Condense this report to at most 3000 characters"));
+        assert!(prompt.text.ends_with("A long report [1]."));
+
+        assert_eq!(sampling_request("Short.", 100).max_tokens, 256);
+    }
+}
@@ -0,0 +1,293 @@
+//! Heuristic confidence annotation for complete answers.
+//!
+//! Two kinds of signal are combined. Reasoning steps streamed before the
+//! final answer are scanned for explicit markers, either a stated
+//! `confidence: low|medium|high` or admissions such as "not sure"; a stated
+//! level is used as the starting point. The answer itself is checked
+//! for hedging language sentence by sentence, and the share of hedged
+//! sentences sets the level when reasoning states none. Uncertainty in the
+//! reasoning or an answer without sources caps the level at medium.
+//!
+//! The annotation is a hint for deciding whether to cross-check, not a
+//! calibrated probability.
+
+use rmcp::schemars;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::claims::extract_claims;
+
+/// Phrases that mark a sentence as hedged, matched on whole words.
+const HEDGES: &[&str] = &[
+    "may",
+    "might",
+    "possibly",
+    "perhaps",
+    "probably",
+    "likely",
+    "unlikely",
+    "unclear",
+    "uncertain",
+    "reportedly",
+    "allegedly",
+    "apparently",
+    "appears to",
+    "seems to",
+    "it is possible",
+    "not clear",
+    "no definitive",
+    "limited information",
+    "could not find",
+    "couldn't find",
+    "unable to verify",
+    "not able to verify",
+    "unverified",
+    "conflicting",
+];
+
+/// Phrases in reasoning steps that admit uncertainty.
+const UNSURE: &[&str] = &[
+    "unsure",
+    "not sure",
+    "uncertain",
+    "unclear",
+    "cannot verify",
+    "can't verify",
+    "could not verify",
+    "could not find",
+    "couldn't find",
+    "no reliable",
+    "conflicting",
+];
+
+/// At most this many reasoning markers are reported.
+const MAX_MARKERS: usize = 5;
+
+/// How far an answer can be relied on without cross-checking.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    schemars::JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfidenceLevel {
+    Low,
+    Medium,
+    High,
+}
+
+/// Confidence annotation attached to a complete answer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct Confidence {
+    /// Overall assessment.
+    pub level: ConfidenceLevel,
+
+    /// Number of answer sentences containing hedging language.
+    pub hedged_sentences: usize,
+
+    /// Total number of answer sentences.
+    pub sentences: usize,
+
+    /// Uncertainty markers found in the reasoning steps, e.g. "not sure".
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reasoning_markers: Vec<String>,
+
+    /// Human-readable reasons for the level.
+    pub signals: Vec<String>,
+}
+
+impl Confidence {
+    /// Assesses an answer, given the raw final event holding its reasoning
+    /// steps and the number of web results it cites.
+    pub fn assess(answer: &str, raw: &Value, web_results: usize) -> Self {
+        let claims = extract_claims(answer);
+        let sentences = claims.len();
+        let hedged_sentences =
+            claims.iter().filter(|claim| has_phrase(&words(&claim.text), HEDGES)).count();
+        let (stated, reasoning_markers) = reasoning_signals(raw);
+
+        let mut signals = Vec::new();
+        let mut level = match stated {
+            Some(level) => {
+                signals.push(format!("reasoning states {} confidence", level.as_str()));
+                level
+            }
+            None if sentences == 0 => {
+                signals.push("empty answer".to_string());
+                ConfidenceLevel::Low
+            }
+            None => {
+                signals.push(format!(
+                    "hedging language in {} of {} sentences",
+                    hedged_sentences, sentences
+                ));
+                // Up to one in ten hedged sentences is normal caution.
+                if hedged_sentences * 10 <= sentences {
+                    ConfidenceLevel::High
+                } else if hedged_sentences * 10 <= sentences * 3 {
+                    ConfidenceLevel::Medium
+                } else {
+                    ConfidenceLevel::Low
+                }
+            }
+        };
+
+        if !reasoning_markers.is_empty() {
+            signals.push("reasoning expresses uncertainty".to_string());
+            level = level.min(ConfidenceLevel::Medium);
+        }
+        if web_results == 0 {
+            signals.push("no cited sources".to_string());
+            level = level.min(ConfidenceLevel::Medium);
+        }
+
+        Self { level, hedged_sentences, sentences, reasoning_markers, signals }
+    }
+}
+
+impl ConfidenceLevel {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::Medium => "medium",
+            Self::High => "high",
+        }
+    }
+}
+
+/// Scans the non-final steps of the event's `text` field for a stated
+/// confidence level (the last one wins) and uncertainty phrases.
+fn reasoning_signals(raw: &Value) -> (Option<ConfidenceLevel>, Vec<String>) {
+    let mut stated = None;
+    let mut markers = Vec::new();
+    let steps =
+        raw.get("text").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+
+    for step in steps {
+        if step.get("step_type").and_then(Value::as_str) == Some("FINAL") {
+            continue;
+        }
+        let mut texts = Vec::new();
+        collect_strings(step.get("content").unwrap_or(step), &mut texts);
+
+        for text in texts {
+            let words = words(text);
+            if let Some(level) = stated_level(&words) {
+                stated = Some(level);
+            }
+            for phrase in UNSURE {
+                if markers.len() < MAX_MARKERS
+                    && contains(&words, phrase)
+                    && !markers.iter().any(|m| m == phrase)
+                {
+                    markers.push((*phrase).to_string());
+                }
+            }
+        }
+    }
+
+    (stated, markers)
+}
+
+/// Finds "confidence: low", "confidence level is high", "low confidence" and
+/// similar statements.
+fn stated_level(words: &[String]) -> Option<ConfidenceLevel> {
+    let level = |word: &str| match word {
+        "low" => Some(ConfidenceLevel::Low),
+        "medium" | "moderate" => Some(ConfidenceLevel::Medium),
+        "high" => Some(ConfidenceLevel::High),
+        _ => None,
+    };
+
+    let position = words.iter().position(|w| w == "confidence")?;
+    let before = position.checked_sub(1).and_then(|i| level(&words[i]));
+    let after = words[position + 1..].iter().take(3).find_map(|w| level(w));
+    before.or(after)
+}
+
+fn collect_strings<'a>(value: &'a Value, out: &mut Vec<&'a str>) {
+    match value {
+        Value::String(s) => out.push(s),
+        Value::Array(items) => items.iter().for_each(|item| collect_strings(item, out)),
+        Value::Object(map) => map.values().for_each(|item| collect_strings(item, out)),
+        _ => {}
+    }
+}
+
+/// Lowercase words, keeping apostrophes so "couldn't" stays one word.
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '\'' && c != '\u{2019}')
+        .filter(|w| !w.is_empty())
+        .map(|w| w.replace('\u{2019}', "'").to_lowercase())
+        .collect()
+}
+
+fn has_phrase(words: &[String], phrases: &[&str]) -> bool {
+    phrases.iter().any(|phrase| contains(words, phrase))
+}
+
+fn contains(words: &[String], phrase: &str) -> bool {
+    let phrase: Vec<&str> = phrase.split(' ').collect();
+    words.windows(phrase.len()).any(|window| window.iter().zip(&phrase).all(|(w, p)| w == p))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_hedging_lowers_confidence() {
+        let plain =
+            Confidence::assess("Rust 1.0 shipped in 2015 [1]. It is stable.", &json!({}), 2);
+        assert_eq!(plain.level, ConfidenceLevel::High);
+        assert_eq!((plain.hedged_sentences, plain.sentences), (0, 2));
+
+        let hedged = Confidence::assess(
+            "It may ship next year. Reports are conflicting. It's probably delayed. Rust is fast.",
+            &json!({}),
+            2,
+        );
+        assert_eq!(hedged.level, ConfidenceLevel::Low);
+        assert_eq!(hedged.hedged_sentences, 3);
+
+        let unsourced = Confidence::assess("Rust is fast.", &json!({}), 0);
+        assert_eq!(unsourced.level, ConfidenceLevel::Medium);
+        assert_eq!(unsourced.signals.last().map(String::as_str), Some("no cited sources"));
+    }
+
+    #[test]
+    fn test_reasoning_markers() {
+        let raw = json!({
+            "text": [
+                { "step_type": "REASONING", "content": { "thought": "I'm not sure which release." } },
+                { "step_type": "REASONING", "content": { "thought": "Confidence: high overall." } },
+                { "step_type": "FINAL", "content": { "answer": "Unsure? Low confidence." } }
+            ]
+        });
+        let confidence = Confidence::assess("Rust 1.81 is current.", &raw, 3);
+
+        assert_eq!(confidence.reasoning_markers, ["not sure"]);
+        // A stated high confidence is capped by the uncertainty before it.
+        assert_eq!(confidence.level, ConfidenceLevel::Medium);
+        assert_eq!(confidence.signals[0], "reasoning states high confidence");
+    }
+
+    #[test]
+    fn test_stated_level_forms() {
+        assert_eq!(stated_level(&words("low confidence in this")), Some(ConfidenceLevel::Low));
+        assert_eq!(
+            stated_level(&words("my confidence level is moderate")),
+            Some(ConfidenceLevel::Medium)
+        );
+        assert_eq!(stated_level(&words("confidence intervals are wide")), None);
+        assert!(contains(&words("I couldn\u{2019}t find it"), "couldn't find"));
+    }
+}
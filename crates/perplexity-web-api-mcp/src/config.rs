@@ -0,0 +1,161 @@
+//! The configuration file.
+//!
+//! Every setting is an environment variable, and the configuration file is
+//! a `.env` style list of them, read at startup for the variables that are
+//! not already set. It lives at `PERPLEXITY_CONFIG`, or by default at
+//! `perplexity-web-api-mcp/config.env` in the user's configuration
+//! directory, and is written by `setup`.
+
+use std::collections::HashSet;
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Returns the path of the configuration file.
+pub fn path() -> PathBuf {
+    if let Some(path) = env::var_os("PERPLEXITY_CONFIG") {
+        return PathBuf::from(path);
+    }
+    let dir = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+    };
+    dir.unwrap_or_default().join("perplexity-web-api-mcp").join("config.env")
+}
+
+/// A configuration file that was read.
+#[derive(Debug, Clone)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    /// Variables the file set, leaving out those already set.
+    pub names: Vec<String>,
+}
+
+/// Reads the configuration file into the environment, leaving variables
+/// that are already set alone. Returns `None` if there is no configuration
+/// file.
+///
+/// Must run before the program starts any other thread.
+pub fn load() -> io::Result<Option<ConfigFile>> {
+    let path = path();
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut names = Vec::new();
+    for (name, value) in parse_env_file(&text) {
+        if env::var_os(&name).is_none() {
+            // SAFETY: called from `main` before the runtime or any other
+            // thread exists, so nothing reads the environment concurrently.
+            unsafe { env::set_var(&name, value) };
+            names.push(name);
+        }
+    }
+    Ok(Some(ConfigFile { path, names }))
+}
+
+/// Writes `settings` to the configuration file at `path`, replacing the
+/// lines that set them and keeping the rest of the file. A setting without
+/// a value is removed.
+pub fn save(path: &Path, settings: &[(&str, Option<String>)]) -> io::Result<()> {
+    let existing = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    crate::util::write_atomically(path, update(&existing, settings).as_bytes())?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+/// Returns `text` with the lines setting `settings` replaced by new ones
+/// appended at the end.
+fn update(text: &str, settings: &[(&str, Option<String>)]) -> String {
+    let names: HashSet<&str> = settings.iter().map(|(name, _)| *name).collect();
+    let mut updated: String = text
+        .lines()
+        .filter(|line| parse_line(line).is_none_or(|(name, _)| !names.contains(name.as_str())))
+        .flat_map(|line| [line, "\n"])
+        .collect();
+    for (name, value) in settings {
+        let Some(value) = value else { continue };
+        updated.push_str(&format!("{}={}\n", name, quote(value)));
+    }
+    updated
+}
+
+/// Quotes `value` if it would not otherwise read back unchanged.
+fn quote(value: &str) -> String {
+    if value.is_empty()
+        || value.contains(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '#'))
+    {
+        format!("'{}'", value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// Reads `NAME=value` lines, skipping comments and blank lines. Values may
+/// be quoted, and lines may start with `export`.
+pub fn parse_env_file(text: &str) -> Vec<(String, String)> {
+    text.lines().filter_map(parse_line).collect()
+}
+
+fn parse_line(line: &str) -> Option<(String, String)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let line = line.strip_prefix("export ").unwrap_or(line);
+    let (name, value) = line.split_once('=')?;
+    let value = value.trim();
+    let value = ['"', '\'']
+        .iter()
+        .find_map(|quote| value.strip_prefix(*quote)?.strip_suffix(*quote))
+        .unwrap_or(value);
+    Some((name.trim().to_string(), value.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_env_file() {
+        let text = "# tokens\nPERPLEXITY_SESSION_TOKEN=abc=def\n\n\
+                    export PERPLEXITY_CSRF_TOKEN = \"x y\"\nRUST_LOG='debug'\nnot a pair\n";
+        assert_eq!(
+            parse_env_file(text),
+            [
+                ("PERPLEXITY_SESSION_TOKEN".to_string(), "abc=def".to_string()),
+                ("PERPLEXITY_CSRF_TOKEN".to_string(), "x y".to_string()),
+                ("RUST_LOG".to_string(), "debug".to_string()),
+            ]
+        );
+
+        let updated = update(
+            "# mine\nRUST_LOG=debug\nPERPLEXITY_CSRF_TOKEN=old\nPERPLEXITY_SESSION_TOKEN=x\n",
+            &[
+                ("PERPLEXITY_CSRF_TOKEN", Some("new".into())),
+                ("PERPLEXITY_SESSION_TOKEN", None),
+                ("PERPLEXITY_DEFAULT_MODEL", Some("a b".into())),
+            ],
+        );
+        assert_eq!(
+            updated,
+            "# mine\nRUST_LOG=debug\nPERPLEXITY_CSRF_TOKEN=new\nPERPLEXITY_DEFAULT_MODEL='a b'\n"
+        );
+        assert_eq!(parse_env_file(&updated)[2].1, "a b");
+    }
+}
@@ -0,0 +1,102 @@
+//! Confirmation of long-running queries.
+//!
+//! A Deep Research query takes several minutes, which an agent may start
+//! without the user expecting it. When the server requires confirmation, a
+//! call needs `confirm: true`; without it, the user is asked through MCP
+//! elicitation if the client supports it. Clients that support neither get
+//! an error telling the agent to ask the user and repeat the call.
+
+use rmcp::model::ErrorCode;
+use rmcp::schemars;
+use rmcp::service::ElicitationError;
+use rmcp::{ErrorData as McpError, Peer, RoleServer};
+use serde::Deserialize;
+use std::time::Duration;
+
+use crate::failure::{self, NOT_CONFIRMED};
+use crate::server::PerplexityRequest;
+
+/// How long the user may take to answer.
+const ELICITATION_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Longest part of the query quoted in the question.
+const QUOTED_QUERY_CHARS: usize = 200;
+
+/// The user's answer to a confirmation request.
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+struct Confirmation {
+    /// Run the query.
+    confirm: bool,
+}
+
+rmcp::elicit_safe!(Confirmation);
+
+/// Returns an error unless the Deep Research query in `params` is
+/// confirmed, asking the user if needed.
+pub async fn research(
+    peer: &Peer<RoleServer>,
+    params: &PerplexityRequest,
+) -> Result<(), McpError> {
+    match params.confirm {
+        Some(true) => return Ok(()),
+        Some(false) => return Err(declined()),
+        None => {}
+    }
+
+    let message = format!(
+        "Run a Deep Research query? It usually takes several minutes.\n\n{}",
+        quote(&params.query)
+    );
+    match peer.elicit_with_timeout::<Confirmation>(message, Some(ELICITATION_TIMEOUT)).await {
+        Ok(Some(Confirmation { confirm: true })) => Ok(()),
+        Ok(_) | Err(ElicitationError::UserDeclined | ElicitationError::UserCancelled) => {
+            Err(declined())
+        }
+        Err(ElicitationError::CapabilityNotSupported) => Err(failure::coded(
+            ErrorCode::INVALID_PARAMS,
+            "Deep Research needs confirmation on this server: ask the user, then repeat the \
+             call with confirm: true",
+            NOT_CONFIRMED,
+        )),
+        Err(e) => {
+            tracing::warn!("Asking for confirmation failed: {}", e);
+            Err(failure::coded(
+                ErrorCode::INTERNAL_ERROR,
+                format!("Could not ask the user for confirmation: {}", e),
+                NOT_CONFIRMED,
+            ))
+        }
+    }
+}
+
+fn declined() -> McpError {
+    failure::coded(
+        ErrorCode::INVALID_REQUEST,
+        "The user did not confirm the Deep Research query",
+        NOT_CONFIRMED,
+    )
+}
+
+/// The query as quoted in the question, shortened if long.
+fn quote(query: &str) -> String {
+    let query = query.trim();
+    match query.char_indices().nth(QUOTED_QUERY_CHARS) {
+        Some((end, _)) => format!("\u{201c}{}\u{2026}\u{201d}", &query[..end]),
+        None => format!("\u{201c}{}\u{201d}", query),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote(" What is Rust? "), "\u{201c}What is Rust?\u{201d}");
+
+        let long = "é".repeat(QUOTED_QUERY_CHARS + 10);
+        let quoted = quote(&long);
+        assert_eq!(quoted.chars().count(), QUOTED_QUERY_CHARS + 3);
+        assert!(quoted.ends_with("\u{2026}\u{201d}"));
+    }
+}
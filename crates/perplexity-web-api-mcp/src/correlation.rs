@@ -0,0 +1,80 @@
+//! Correlation ids for tool calls.
+//!
+//! Every tool call gets a fresh id. It is recorded on the call's tracing
+//! span, returned in the result's `_meta` (or the error's `data`), and sent
+//! to Perplexity as the `x-request-id` header of the queries the call makes.
+
+use rmcp::ErrorData as McpError;
+use rmcp::model::{CallToolResult, Meta};
+use serde_json::Value;
+use std::future::Future;
+use uuid::Uuid;
+
+/// Key of the id in result metadata and error data.
+pub const META_KEY: &str = "requestId";
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Creates an id for a new tool call.
+pub fn new_id() -> String {
+    Uuid::new_v4().to_string()
+}
+
+/// Runs `future` with `id` as the current tool call's id.
+pub async fn scope<F: Future>(id: String, future: F) -> F::Output {
+    REQUEST_ID.scope(id, future).await
+}
+
+/// Returns the id of the tool call being handled, if any.
+///
+/// Background work such as jobs outlives the call and has none.
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// Adds `id` to a tool result's metadata or an error's data.
+pub fn tag(
+    result: Result<CallToolResult, McpError>,
+    id: &str,
+) -> Result<CallToolResult, McpError> {
+    match result {
+        Ok(mut result) => {
+            result
+                .meta
+                .get_or_insert_with(Meta::new)
+                .0
+                .insert(META_KEY.to_string(), Value::from(id));
+            Ok(result)
+        }
+        Err(mut error) => {
+            match &mut error.data {
+                Some(Value::Object(data)) => {
+                    data.insert(META_KEY.to_string(), Value::from(id));
+                }
+                Some(_) => {}
+                None => error.data = Some(serde_json::json!({ META_KEY: id })),
+            }
+            Err(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scope_and_tag() {
+        assert_eq!(current(), None);
+        let seen = scope("abc".to_string(), async { current() }).await;
+        assert_eq!(seen.as_deref(), Some("abc"));
+
+        let result = tag(Ok(CallToolResult::success(Vec::new())), "abc").unwrap();
+        assert_eq!(result.meta.unwrap().0[META_KEY], "abc");
+
+        let error = tag(Err(McpError::invalid_params("bad", None)), "abc").unwrap_err();
+        assert_eq!(error.data.unwrap()[META_KEY], "abc");
+    }
+}
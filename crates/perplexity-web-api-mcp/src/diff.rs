@@ -0,0 +1,161 @@
+//! Claim-level comparison of two answers.
+//!
+//! Answers are split into [claims](crate::claims), without citation
+//! references such as `[1]`, so that reflowed or renumbered text still
+//! compares equal.
+
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::claims::extract_claims;
+
+/// Differences between an earlier and a later answer.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AnswerDiff {
+    /// Claims only present in the later answer, in answer order.
+    pub added_claims: Vec<String>,
+
+    /// Claims only present in the earlier answer, in answer order.
+    pub removed_claims: Vec<String>,
+
+    /// Number of claims present in both answers.
+    pub unchanged_claims: usize,
+
+    /// Citation URLs only present in the later answer.
+    pub added_citations: Vec<String>,
+
+    /// Citation URLs only present in the earlier answer.
+    pub removed_citations: Vec<String>,
+
+    /// The changes as `+`/`-` prefixed lines, claims first, then citations.
+    pub summary: String,
+}
+
+impl AnswerDiff {
+    /// Compares two answers and their citation URLs.
+    pub fn between(
+        before: &str,
+        before_citations: &[String],
+        after: &str,
+        after_citations: &[String],
+    ) -> Self {
+        let before_claims = claims(before);
+        let after_claims = claims(after);
+        let before_keys: HashSet<String> =
+            before_claims.iter().map(|c| normalize(c)).collect();
+        let after_keys: HashSet<String> = after_claims.iter().map(|c| normalize(c)).collect();
+
+        let added_claims = only_in(&after_claims, &before_keys, normalize);
+        let removed_claims = only_in(&before_claims, &after_keys, normalize);
+        let unchanged_claims = after_keys.intersection(&before_keys).count();
+
+        let before_urls: HashSet<String> =
+            before_citations.iter().map(|u| url_key(u)).collect();
+        let after_urls: HashSet<String> = after_citations.iter().map(|u| url_key(u)).collect();
+        let added_citations = only_in(after_citations, &before_urls, url_key);
+        let removed_citations = only_in(before_citations, &after_urls, url_key);
+
+        let mut summary = String::new();
+        for (prefix, lines) in [
+            ('+', &added_claims),
+            ('-', &removed_claims),
+            ('+', &added_citations),
+            ('-', &removed_citations),
+        ] {
+            for line in lines {
+                summary.push(prefix);
+                summary.push(' ');
+                summary.push_str(line);
+                summary.push('\n');
+            }
+        }
+
+        Self {
+            added_claims,
+            removed_claims,
+            unchanged_claims,
+            added_citations,
+            removed_citations,
+            summary,
+        }
+    }
+}
+
+/// Returns the items whose key is not in `other`, without duplicates.
+fn only_in(
+    items: &[String],
+    other: &HashSet<String>,
+    key: impl Fn(&str) -> String,
+) -> Vec<String> {
+    let mut seen = HashSet::new();
+    items
+        .iter()
+        .filter(|item| {
+            let key = key(item);
+            !other.contains(&key) && seen.insert(key)
+        })
+        .cloned()
+        .collect()
+}
+
+/// Splits an answer into sentences, without their citation references.
+fn claims(answer: &str) -> Vec<String> {
+    extract_claims(answer).into_iter().map(|claim| claim.text).collect()
+}
+
+/// Comparison key for a claim: lowercase, without trailing punctuation or
+/// Markdown emphasis.
+fn normalize(claim: &str) -> String {
+    claim
+        .chars()
+        .filter(|c| !matches!(c, '*' | '_' | '`'))
+        .collect::<String>()
+        .trim_end_matches(['.', '!', '?', ':', ';', ','])
+        .to_lowercase()
+}
+
+/// Comparison key for a citation URL, ignoring a trailing slash.
+fn url_key(url: &str) -> String {
+    url.trim().trim_end_matches('/').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| (*s).to_string()).collect()
+    }
+
+    #[test]
+    fn test_reports_added_and_removed_claims() {
+        let before = "Rust 1.80 is the latest release [1]. It ships every six weeks.";
+        let after =
+            "## Summary\n- It ships every six weeks [2].\n- Rust 1.81 is the latest release.";
+        let diff = AnswerDiff::between(before, &[], after, &[]);
+
+        assert_eq!(diff.added_claims, ["Rust 1.81 is the latest release."]);
+        assert_eq!(diff.removed_claims, ["Rust 1.80 is the latest release."]);
+        assert_eq!(diff.unchanged_claims, 1);
+    }
+
+    #[test]
+    fn test_reports_citation_changes() {
+        let before = urls(&["https://a.example/", "https://b.example"]);
+        let after = urls(&["https://a.example", "https://c.example", "https://c.example"]);
+        let diff = AnswerDiff::between("Same.", &before, "Same.", &after);
+
+        assert!(diff.added_claims.is_empty() && diff.removed_claims.is_empty());
+        assert_eq!(diff.added_citations, ["https://c.example"]);
+        assert_eq!(diff.removed_citations, ["https://b.example"]);
+        assert_eq!(diff.summary, "+ https://c.example\n- https://b.example\n");
+    }
+
+    #[test]
+    fn test_claims_keep_decimals_and_numbered_lists() {
+        assert_eq!(
+            claims("1. Version 3.5 added traits.\n2) Done\nSee [docs] here!"),
+            ["Version 3.5 added traits.", "Done", "See [docs] here!"]
+        );
+    }
+}
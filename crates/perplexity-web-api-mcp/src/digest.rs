@@ -0,0 +1,317 @@
+//! News digests.
+//!
+//! `perplexity_news_digest` asks one recency-filtered query per topic for
+//! the period's main stories, then merges the answers into one document.
+//! A story already listed under an earlier topic, because it cites the
+//! same page or tells mostly the same thing, is listed once, with the other
+//! topics noted. Citations are renumbered to one merged source list.
+//! Schedules created by `perplexity_schedule_digest` run the same digest
+//! as a background job.
+
+use perplexity_web_api::{
+    CitationMerger, Recency, SearchEvent, SearchResponse, SearchWebResult,
+};
+use rmcp::schemars;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::claims::extract_claims;
+use crate::queue::Priority;
+use crate::server::{PerplexityResponse, WebResultInfo};
+
+/// Most topics a digest may have.
+pub const MAX_TOPICS: usize = 10;
+
+/// Share of distinctive words two stories must have in common to count as
+/// the same story.
+const SAME_STORY_OVERLAP: f64 = 0.5;
+
+/// Request parameters for a news digest.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct NewsDigestRequest {
+    /// Topics to cover, 1 to 10 entries, e.g. ["Rust language", "WebAssembly"].
+    pub topics: Vec<String>,
+
+    /// Period to cover: "day", "week", "month", or "year". Only sources
+    /// published within it are searched. Defaults to "day".
+    #[serde(default)]
+    pub window: Option<String>,
+
+    /// Search mode of the per-topic queries: "auto", "pro", "reasoning", or
+    /// "deep research". Defaults to "pro".
+    #[serde(default)]
+    pub mode: Option<String>,
+
+    /// Sources to search, as for perplexity_search.
+    #[serde(default)]
+    pub sources: Option<Vec<String>>,
+
+    /// Priority of the per-topic queries, as for perplexity_search.
+    /// Defaults to "normal", or "low" for scheduled digests.
+    #[serde(default)]
+    pub priority: Option<Priority>,
+}
+
+/// A news digest.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct NewsDigestResponse {
+    /// The period covered.
+    pub window: String,
+
+    /// Markdown digest: a section per topic, then the merged sources.
+    pub digest: String,
+
+    /// The stories, each listed once, in digest order.
+    pub stories: Vec<Story>,
+
+    /// Sources of all stories, deduplicated by URL. Citation `n` is the
+    /// `n`-th entry.
+    pub web_results: Vec<WebResultInfo>,
+
+    /// Topics whose query failed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// A story of a digest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct Story {
+    /// What happened, without citation markers.
+    pub summary: String,
+
+    /// Topics whose answers reported the story, the one it is listed under
+    /// first.
+    pub topics: Vec<String>,
+
+    /// Citation numbers supporting the story.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub citations: Vec<usize>,
+}
+
+impl NewsDigestResponse {
+    /// The digest as the response of a scheduled run: the document as the
+    /// answer, with the merged sources.
+    pub fn into_response(self) -> PerplexityResponse {
+        let event = SearchEvent {
+            answer: Some(self.digest),
+            web_results: self.web_results.iter().map(SearchWebResult::from).collect(),
+            ..SearchEvent::default()
+        };
+        let mut response = PerplexityResponse::from(SearchResponse::from(event));
+        // Confidence is assessed for one answer, not a merged document.
+        response.confidence = None;
+        response.warnings = self.warnings;
+        response
+    }
+}
+
+/// Checks a digest request, returning its period.
+pub fn validate(request: &NewsDigestRequest) -> Result<Recency, String> {
+    if !(1..=MAX_TOPICS).contains(&request.topics.len()) {
+        return Err(format!("topics must have 1 to {} entries", MAX_TOPICS));
+    }
+    if request.topics.iter().any(|topic| topic.trim().is_empty()) {
+        return Err("topics must not be empty".into());
+    }
+    match &request.window {
+        Some(window) => window.parse::<Recency>().map_err(|e| e.to_string()),
+        None => Ok(Recency::Day),
+    }
+}
+
+/// Describes the digest for the schedule list.
+pub fn title(topics: &[String]) -> String {
+    format!("News digest: {}", topics.join(", "))
+}
+
+/// The query for `topic`'s stories within `window`.
+pub fn topic_query(topic: &str, window: Recency) -> String {
+    let period = match window {
+        Recency::Day => "past 24 hours",
+        Recency::Week => "past week",
+        Recency::Month => "past month",
+        Recency::Year => "past year",
+    };
+    format!(
+        "What are the most important news stories about {} from the {}? List each story as \
+         a separate bullet point with one or two sentences saying what happened and when, \
+         citing sources. Reply with the list only.",
+        topic, period
+    )
+}
+
+/// Builds the digest from each topic's answer.
+pub fn assemble(
+    window: Recency,
+    answers: Vec<(String, Result<PerplexityResponse, String>)>,
+) -> NewsDigestResponse {
+    let mut merger = CitationMerger::new();
+    let mut stories: Vec<Story> = Vec::new();
+    let mut words: Vec<HashSet<String>> = Vec::new();
+    let mut sections = Vec::with_capacity(answers.len());
+    let mut warnings = Vec::new();
+
+    for (topic, result) in answers {
+        let response = match result {
+            Ok(response) => response,
+            Err(e) => {
+                warnings.push(format!("{}: {}", topic, e));
+                sections.push((topic, Err(e)));
+                continue;
+            }
+        };
+        let results: Vec<SearchWebResult> =
+            response.web_results.iter().map(SearchWebResult::from).collect();
+        let answer = merger.add(response.answer.as_deref().unwrap_or_default(), &results);
+
+        // Stories found earlier for this topic are not compared with each
+        // other: only stories under other topics can be the same one.
+        let earlier = stories.len();
+        let mut listed = Vec::new();
+        for (summary, citations) in answer.lines().filter_map(story) {
+            let distinct = distinctive_words(&summary);
+            let same = (0..earlier).find(|&i| {
+                citations.iter().any(|n| stories[i].citations.contains(n))
+                    || overlap(&distinct, &words[i]) >= SAME_STORY_OVERLAP
+            });
+            match same {
+                Some(i) => {
+                    if !stories[i].topics.contains(&topic) {
+                        stories[i].topics.push(topic.clone());
+                    }
+                }
+                None => {
+                    listed.push(stories.len());
+                    stories.push(Story { summary, topics: vec![topic.clone()], citations });
+                    words.push(distinct);
+                }
+            }
+        }
+        sections.push((topic, Ok(listed)));
+    }
+
+    let mut digest = format!("# News digest (past {})\n", window);
+    for (topic, listed) in sections {
+        digest.push_str(&format!("\n## {}\n\n", topic));
+        match listed {
+            Err(e) => digest.push_str(&format!("_Not answered: {}_\n", e)),
+            Ok(listed) if listed.is_empty() => digest.push_str("_No new stories._\n"),
+            Ok(listed) => {
+                for story in listed.into_iter().map(|i| &stories[i]) {
+                    let markers: String =
+                        story.citations.iter().map(|n| format!("[{}]", n)).collect();
+                    digest.push_str(format!("- {} {}", story.summary, markers).trim_end());
+                    if story.topics.len() > 1 {
+                        digest
+                            .push_str(&format!(" _(also {})_", story.topics[1..].join(", ")));
+                    }
+                    digest.push('\n');
+                }
+            }
+        }
+    }
+
+    let web_results: Vec<WebResultInfo> =
+        merger.into_results().into_iter().map(WebResultInfo::from).collect();
+    digest.push_str(&crate::report::sources(&web_results));
+    NewsDigestResponse { window: window.to_string(), digest, stories, web_results, warnings }
+}
+
+/// Reads a story from a line of an answer: its text and citations. Lines
+/// that are headings or introduce the list are not stories.
+fn story(line: &str) -> Option<(String, Vec<usize>)> {
+    let line = line.trim();
+    if line.starts_with('#') || line.ends_with(':') {
+        return None;
+    }
+    let claims = extract_claims(line);
+    let summary = claims.iter().map(|claim| claim.text.as_str()).collect::<Vec<_>>().join(" ");
+    let mut citations: Vec<usize> = Vec::new();
+    for index in claims.iter().flat_map(|claim| &claim.citation_indices) {
+        if !citations.contains(index) {
+            citations.push(*index);
+        }
+    }
+    (!summary.is_empty()).then_some((summary, citations))
+}
+
+/// Lowercased words of at least four letters or digits, which carry most
+/// of what a story is about.
+fn distinctive_words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= 4)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Share of the words of either set that both have.
+fn overlap(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_digest_dedupes_stories_across_topics() {
+        let source = |url: &str| WebResultInfo {
+            name: url.to_string(),
+            url: url.to_string(),
+            snippet: String::new(),
+            score: None,
+        };
+        let mut rust = PerplexityResponse::answered(
+            "Here are the top stories:\n\n- Rust 1.90 was released with faster linking [1].\n\
+             - The Rust Foundation announced new security grants [2].",
+        );
+        rust.web_results = vec![source("https://blog.rust-lang.org"), source("https://f.org")];
+        let mut wasm = PerplexityResponse::answered(
+            "1. Rust 1.90 released, with faster linking on Linux.\n\
+             2. WASI 0.3 adds async support [2].\n3. Grants for security work [1].",
+        );
+        wasm.web_results = vec![source("https://f.org/"), source("https://wasi.dev")];
+
+        let digest = assemble(
+            Recency::Week,
+            vec![
+                ("Rust".to_string(), Ok(rust)),
+                ("WebAssembly".to_string(), Ok(wasm)),
+                ("Go".to_string(), Err("rate limited".to_string())),
+            ],
+        );
+        assert_eq!(digest.stories.len(), 3);
+        assert_eq!(digest.stories[0].topics, ["Rust", "WebAssembly"]);
+        assert_eq!(digest.stories[1].topics, ["Rust", "WebAssembly"]);
+        assert_eq!(
+            digest.stories[2],
+            Story {
+                summary: "WASI 0.3 adds async support.".to_string(),
+                topics: vec!["WebAssembly".to_string()],
+                citations: vec![3],
+            }
+        );
+        assert_eq!(digest.web_results.len(), 3);
+        assert_eq!(digest.warnings, ["Go: rate limited"]);
+        assert!(digest.digest.starts_with(
+            "# News digest (past week)\n\n## Rust\n\n\
+             - Rust 1.90 was released with faster linking. [1] _(also WebAssembly)_\n\
+             - The Rust Foundation announced new security grants. [2] _(also WebAssembly)_\n\n\
+             ## WebAssembly\n\n- WASI 0.3 adds async support. [3]\n\n\
+             ## Go\n\n_Not answered: rate limited_\n\n## Sources\n"
+        ));
+
+        let request = NewsDigestRequest {
+            topics: vec!["Rust".to_string()],
+            window: Some("24h".to_string()),
+            mode: None,
+            sources: None,
+            priority: None,
+        };
+        assert_eq!(validate(&request), Ok(Recency::Day));
+    }
+}
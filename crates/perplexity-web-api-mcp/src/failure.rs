@@ -0,0 +1,121 @@
+//! Containment and classification of tool call failures.
+//!
+//! A panic inside a tool is caught and turned into an MCP error instead of
+//! unwinding through the transport, so one bad call cannot end the session.
+//! The panic message is only logged: it may contain query text or internal
+//! details, so the client gets a generic message and the call's request id.
+//!
+//! Every error returned to the client carries a stable `code` in its `data`
+//! that agents can branch on without parsing messages.
+
+use futures_util::FutureExt;
+use rmcp::ErrorData as McpError;
+use rmcp::model::{CallToolResult, ErrorCode};
+use serde_json::Value;
+use std::any::Any;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+
+/// Key of the error classification in error data.
+pub const CODE_KEY: &str = "code";
+
+/// Code of errors raised by a panic inside a tool.
+pub const PANIC: &str = "panic";
+
+/// Code of errors reported by the Perplexity API or client.
+pub const UPSTREAM: &str = "upstream_error";
+
+/// Code of errors for calls failed fast while Perplexity is unavailable.
+pub const UNAVAILABLE: &str = "provider_unavailable";
+
+/// Code of errors for invalid tool arguments.
+pub const INVALID_PARAMS: &str = "invalid_params";
+
+/// Code of errors for queries Perplexity rejected.
+pub const QUERY_REJECTED: &str = "query_rejected";
+
+/// Code of errors for queries Perplexity declined to answer.
+pub const REFUSED: &str = "refused";
+
+/// Code of errors for external sources that could not be fetched.
+pub const FETCH_FAILED: &str = "fetch_failed";
+
+/// Code of errors for queries the user did not confirm.
+pub const NOT_CONFIRMED: &str = "not_confirmed";
+
+/// Runs a tool call, converting a panic into an internal error.
+pub async fn contain(
+    tool: &str,
+    call: impl Future<Output = Result<CallToolResult, McpError>>,
+) -> Result<CallToolResult, McpError> {
+    match AssertUnwindSafe(call).catch_unwind().await {
+        Ok(result) => result,
+        Err(panic) => {
+            tracing::error!("Tool {} panicked: {}", tool, panic_message(panic.as_ref()));
+            Err(McpError::internal_error(
+                format!("Internal error while running {}; see the server logs", tool),
+                Some(serde_json::json!({ CODE_KEY: PANIC })),
+            ))
+        }
+    }
+}
+
+/// Builds an error with the given code in its data.
+pub fn coded(code: ErrorCode, message: impl Into<String>, kind: &'static str) -> McpError {
+    McpError::new(code, message.into(), Some(serde_json::json!({ CODE_KEY: kind })))
+}
+
+/// Adds a `code` derived from the JSON-RPC error code to errors that have
+/// none.
+pub fn classify(result: Result<CallToolResult, McpError>) -> Result<CallToolResult, McpError> {
+    result.map_err(|mut error| {
+        let kind = match error.code {
+            ErrorCode::INVALID_PARAMS => INVALID_PARAMS,
+            ErrorCode::INVALID_REQUEST => "invalid_request",
+            ErrorCode::METHOD_NOT_FOUND => "not_found",
+            ErrorCode::RESOURCE_NOT_FOUND => "not_found",
+            _ => "internal_error",
+        };
+        match &mut error.data {
+            Some(Value::Object(data)) => {
+                data.entry(CODE_KEY).or_insert_with(|| Value::from(kind));
+            }
+            Some(_) => {}
+            None => error.data = Some(serde_json::json!({ CODE_KEY: kind })),
+        }
+        error
+    })
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("non-string panic payload")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_panics_become_errors() {
+        let error = contain("perplexity_search", async { panic!("secret query text") })
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.code, ErrorCode::INTERNAL_ERROR);
+        assert!(!error.message.contains("secret"));
+        assert_eq!(error.data.unwrap()[CODE_KEY], PANIC);
+    }
+
+    #[test]
+    fn test_classify_keeps_existing_codes() {
+        let invalid = classify(Err(McpError::invalid_params("bad", None))).unwrap_err();
+        assert_eq!(invalid.data.unwrap()[CODE_KEY], "invalid_params");
+
+        let upstream = classify(Err(coded(ErrorCode::INTERNAL_ERROR, "down", UPSTREAM)));
+        assert_eq!(upstream.unwrap_err().data.unwrap()[CODE_KEY], UPSTREAM);
+    }
+}
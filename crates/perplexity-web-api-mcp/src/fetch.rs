@@ -0,0 +1,680 @@
+//! Fetching of external sources cited in answers.
+//!
+//! Sources are fetched with clients of their own, one per domain, so cookies
+//! a site sets are only ever sent back to that site, and neither the
+//! Perplexity session cookies nor its browser headers reach third parties.
+//! Redirects are followed hop by hop, each with the client of the domain it
+//! leads to. Optionally, each domain's robots.txt is honored, only some
+//! hosts may be fetched, and pages are kept in a [`PageCache`] and
+//! revalidated instead of downloaded again.
+
+use crate::pagecache::{CachedPage, PageCache};
+use perplexity_web_api::HostAllowlist;
+use rquest::Client as HttpClient;
+use rquest::Url;
+use rquest::header::{
+    CONTENT_TYPE, ETAG, HeaderMap, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, LOCATION,
+};
+use rquest::redirect::Policy;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Token identifying the fetcher in robots.txt groups.
+const AGENT_TOKEN: &str = "perplexity-web-api-mcp";
+
+/// How long fetching one URL, including redirects, may take.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Bytes of a response body read at most.
+const MAX_BODY_BYTES: usize = 5 * 1024 * 1024;
+
+/// Bytes of a robots.txt read at most; RFC 9309 asks for at least 500 KiB.
+const MAX_ROBOTS_BYTES: usize = 500 * 1024;
+
+/// Redirects followed at most for a robots.txt (RFC 9309, section 2.3.1.2).
+const MAX_ROBOTS_REDIRECTS: usize = 5;
+
+/// Domains whose clients are kept; the least recently used is dropped
+/// beyond this.
+const MAX_DOMAINS: usize = 64;
+
+/// A fetched source, reduced to text.
+#[derive(Debug, Clone, Serialize)]
+pub struct FetchedSource {
+    /// URL the content was served from, after redirects.
+    pub url: String,
+    /// HTTP status of the response.
+    pub status: u16,
+    /// Media type of the response, e.g. "text/html".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// The content, with HTML markup, scripts and styles removed.
+    pub text: String,
+    /// Whether the text was cut short at the requested length.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    /// Whether the content came from the page cache, after the site
+    /// confirmed it had not changed.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub revalidated: bool,
+}
+
+struct Domain {
+    http: HttpClient,
+    robots: Option<Arc<Robots>>,
+    used: Instant,
+}
+
+/// Fetches external URLs with a separate client per domain.
+#[derive(Clone)]
+pub struct SourceFetcher {
+    domains: Arc<Mutex<HashMap<String, Domain>>>,
+    respect_robots: bool,
+    allowed_hosts: HostAllowlist,
+    max_redirects: usize,
+    cache: Option<Arc<PageCache>>,
+}
+
+impl Default for SourceFetcher {
+    fn default() -> Self {
+        Self {
+            domains: Arc::default(),
+            respect_robots: false,
+            allowed_hosts: HostAllowlist::public(),
+            max_redirects: perplexity_web_api::DEFAULT_MAX_REDIRECTS,
+            cache: None,
+        }
+    }
+}
+
+impl SourceFetcher {
+    /// Refuses URLs, including redirect targets, on hosts not in `hosts`,
+    /// and follows at most `max_redirects` redirects.
+    pub fn limited_to(mut self, hosts: HostAllowlist, max_redirects: usize) -> Self {
+        self.allowed_hosts = hosts;
+        self.max_redirects = max_redirects;
+        self
+    }
+
+    /// Refuses URLs that the domain's robots.txt disallows for this agent.
+    pub fn respecting_robots(mut self) -> Self {
+        self.respect_robots = true;
+        self
+    }
+
+    /// Keeps fetched pages in `cache`, asking sites whether a cached page
+    /// changed instead of downloading it again.
+    pub fn caching_in(mut self, cache: PageCache) -> Self {
+        self.cache = Some(Arc::new(cache));
+        self
+    }
+
+    /// Fetches `url` and returns at most `max_chars` characters of its text.
+    pub async fn fetch(&self, url: &str, max_chars: usize) -> Result<FetchedSource, String> {
+        tokio::time::timeout(FETCH_TIMEOUT, self.fetch_inner(url, max_chars))
+            .await
+            .map_err(|_| format!("timed out after {:?}", FETCH_TIMEOUT))?
+    }
+
+    async fn fetch_inner(&self, url: &str, max_chars: usize) -> Result<FetchedSource, String> {
+        let mut url = Url::parse(url).map_err(|e| format!("invalid URL: {}", e))?;
+
+        for _ in 0..=self.max_redirects {
+            if !matches!(url.scheme(), "http" | "https") {
+                return Err(format!("unsupported URL scheme '{}'", url.scheme()));
+            }
+            let host = url.host_str().ok_or("URL has no host")?.to_ascii_lowercase();
+            if !self.allowed_hosts.allows(&host) {
+                return Err(format!("{} is not an allowed host", host));
+            }
+            let http = self.client(&host)?;
+            if self.respect_robots && !self.robots(&host, &url).await.allows(url.path()) {
+                return Err(format!("robots.txt of {} disallows {}", host, url.path()));
+            }
+
+            let cached = self.cached(&url).await;
+            let mut request = http.get(url.clone());
+            if let Some(page) = &cached {
+                if let Some(etag) = &page.etag {
+                    request = request.header(IF_NONE_MATCH, etag);
+                }
+                if let Some(date) = &page.last_modified {
+                    request = request.header(IF_MODIFIED_SINCE, date);
+                }
+            }
+            let mut response = request.send().await.map_err(|e| e.to_string())?;
+            let status = response.status();
+            if status.as_u16() == 304
+                && let Some(page) = cached
+            {
+                self.touch(&page.url);
+                let mut source =
+                    extract(page.url, page.status, page.content_type, &page.body, max_chars);
+                source.revalidated = true;
+                return Ok(source);
+            }
+            if status.is_redirection() {
+                let location = response
+                    .headers()
+                    .get(LOCATION)
+                    .and_then(|value| value.to_str().ok())
+                    .ok_or_else(|| format!("{} redirect without a location", status))?;
+                url = url.join(location).map_err(|e| format!("invalid redirect: {}", e))?;
+                continue;
+            }
+
+            let content_type = response
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.split(';').next().unwrap_or(value).trim().to_lowercase());
+            if let Some(media) = &content_type
+                && !is_textual(media)
+            {
+                return Err(format!("cannot extract text from {}", media));
+            }
+
+            let body = read_body(&mut response, MAX_BODY_BYTES).await?;
+            let page = CachedPage {
+                url: url.to_string(),
+                status: status.as_u16(),
+                content_type,
+                etag: header(response.headers(), ETAG),
+                last_modified: header(response.headers(), LAST_MODIFIED),
+                body,
+            };
+            // A body cut at the size limit is not the page.
+            if status.is_success() && page.revalidatable() && page.body.len() < MAX_BODY_BYTES
+            {
+                self.store(&page).await;
+            }
+            return Ok(extract(
+                page.url,
+                page.status,
+                page.content_type,
+                &page.body,
+                max_chars,
+            ));
+        }
+        Err(format!("more than {} redirects", self.max_redirects))
+    }
+
+    /// The cached copy of `url`, if pages are cached and there is one.
+    async fn cached(&self, url: &Url) -> Option<CachedPage> {
+        let cache = Arc::clone(self.cache.as_ref()?);
+        let url = url.to_string();
+        tokio::task::spawn_blocking(move || cache.get(&url)).await.ok().flatten()
+    }
+
+    /// Keeps `page` in the cache, if pages are cached.
+    async fn store(&self, page: &CachedPage) {
+        let Some(cache) = self.cache.clone() else {
+            return;
+        };
+        let page = page.clone();
+        match tokio::task::spawn_blocking(move || cache.put(&page)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::warn!("Failed to cache fetched page: {}", e),
+            Err(e) => tracing::warn!("Failed to cache fetched page: {}", e),
+        }
+    }
+
+    /// Marks the cached copy of `url` as used, in the background.
+    fn touch(&self, url: &str) {
+        if let Some(cache) = self.cache.clone() {
+            let url = url.to_string();
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = cache.touch(&url) {
+                    tracing::debug!("Failed to mark cached page as used: {}", e);
+                }
+            });
+        }
+    }
+
+    /// Returns the client of `host`, creating it on first use.
+    fn client(&self, host: &str) -> Result<HttpClient, String> {
+        let mut domains = self.domains.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(domain) = domains.get_mut(host) {
+            domain.used = Instant::now();
+            return Ok(domain.http.clone());
+        }
+
+        let http = HttpClient::builder()
+            .cookie_store(true)
+            .redirect(Policy::none())
+            .dns_resolver(self.allowed_hosts.resolver())
+            .user_agent(concat!("perplexity-web-api-mcp/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .map_err(|e| e.to_string())?;
+        if domains.len() >= MAX_DOMAINS
+            && let Some(oldest) = domains
+                .iter()
+                .min_by_key(|(_, domain)| domain.used)
+                .map(|(host, _)| host.clone())
+        {
+            domains.remove(&oldest);
+        }
+        domains.insert(
+            host.to_string(),
+            Domain { http: http.clone(), robots: None, used: Instant::now() },
+        );
+        Ok(http)
+    }
+
+    /// Returns the robots.txt rules of `host`, fetching them on first use.
+    async fn robots(&self, host: &str, url: &Url) -> Arc<Robots> {
+        let cached = {
+            let domains = self.domains.lock().unwrap_or_else(PoisonError::into_inner);
+            domains.get(host).and_then(|domain| domain.robots.clone())
+        };
+        if let Some(robots) = cached {
+            return robots;
+        }
+
+        let mut robots_url = url.clone();
+        robots_url.set_path("/robots.txt");
+        robots_url.set_query(None);
+        let robots = Arc::new(self.fetch_robots(robots_url).await);
+
+        let mut domains = self.domains.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(domain) = domains.get_mut(host) {
+            domain.robots = Some(Arc::clone(&robots));
+        }
+        robots
+    }
+
+    /// Downloads and parses the robots.txt at `url`, following redirects hop
+    /// by hop like pages.
+    async fn fetch_robots(&self, mut url: Url) -> Robots {
+        for _ in 0..=MAX_ROBOTS_REDIRECTS {
+            // Without a robots.txt everything is allowed; an unreachable one
+            // means nothing is (RFC 9309, section 2.3.1).
+            let Some(host) = url.host_str().map(str::to_ascii_lowercase) else {
+                return Robots::default();
+            };
+            if !matches!(url.scheme(), "http" | "https") || !self.allowed_hosts.allows(&host) {
+                return Robots::default();
+            }
+            let Ok(http) = self.client(&host) else {
+                return Robots::disallow_all();
+            };
+            let Ok(mut response) = http.get(url.clone()).send().await else {
+                return Robots::disallow_all();
+            };
+            let status = response.status();
+            if status.is_redirection() {
+                let Some(next) = response
+                    .headers()
+                    .get(LOCATION)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|location| url.join(location).ok())
+                else {
+                    return Robots::default();
+                };
+                url = next;
+                continue;
+            }
+            return if status.is_success() {
+                match read_body(&mut response, MAX_ROBOTS_BYTES).await {
+                    Ok(body) => Robots::parse(&String::from_utf8_lossy(&body), AGENT_TOKEN),
+                    Err(_) => Robots::disallow_all(),
+                }
+            } else if status.is_client_error() {
+                Robots::default()
+            } else {
+                Robots::disallow_all()
+            };
+        }
+        // Too many redirects count as no robots.txt.
+        Robots::default()
+    }
+}
+
+/// Reads at most `limit` bytes of `response`'s body.
+async fn read_body(response: &mut rquest::Response, limit: usize) -> Result<Vec<u8>, String> {
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        body.extend_from_slice(&chunk);
+        if body.len() >= limit {
+            body.truncate(limit);
+            break;
+        }
+    }
+    Ok(body)
+}
+
+/// Reduces a downloaded body to the text of a [`FetchedSource`].
+fn extract(
+    url: String,
+    status: u16,
+    content_type: Option<String>,
+    body: &[u8],
+    max_chars: usize,
+) -> FetchedSource {
+    let body = String::from_utf8_lossy(body);
+    let text = if content_type.as_deref().is_none_or(|media| media.contains("html")) {
+        html_to_text(&body)
+    } else {
+        body.trim().to_string()
+    };
+    let truncated = text.chars().count() > max_chars;
+
+    FetchedSource {
+        url,
+        status,
+        content_type,
+        text: if truncated { text.chars().take(max_chars).collect() } else { text },
+        truncated,
+        revalidated: false,
+    }
+}
+
+/// The value of header `name`, if it is present and readable.
+fn header(headers: &HeaderMap, name: rquest::header::HeaderName) -> Option<String> {
+    headers.get(name).and_then(|value| value.to_str().ok()).map(str::to_string)
+}
+
+/// Whether text can be extracted from responses of `media` type.
+fn is_textual(media: &str) -> bool {
+    media.starts_with("text/")
+        || media.ends_with("+xml")
+        || media.ends_with("+json")
+        || matches!(media, "application/json" | "application/xml" | "application/javascript")
+}
+
+/// The robots.txt rules that apply to this agent.
+#[derive(Debug, Default)]
+struct Robots {
+    /// Path patterns and whether they are allowed.
+    rules: Vec<(String, bool)>,
+}
+
+impl Robots {
+    fn disallow_all() -> Self {
+        Self { rules: vec![("/".to_string(), false)] }
+    }
+
+    /// Reads the rules of the groups naming `agent`, or of the `*` groups
+    /// if none does.
+    fn parse(body: &str, agent: &str) -> Self {
+        let mut specific = Vec::new();
+        let mut general = Vec::new();
+        let (mut names_agent, mut names_any, mut in_agents) = (false, false, false);
+
+        for line in body.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let Some((field, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match field.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => {
+                    if !in_agents {
+                        (names_agent, names_any) = (false, false);
+                    }
+                    in_agents = true;
+                    names_agent |= value.eq_ignore_ascii_case(agent);
+                    names_any |= value == "*";
+                }
+                field @ ("allow" | "disallow") => {
+                    in_agents = false;
+                    if value.is_empty() {
+                        continue;
+                    }
+                    let rule = (value.to_string(), field == "allow");
+                    if names_agent {
+                        specific.push(rule);
+                    } else if names_any {
+                        general.push(rule);
+                    }
+                }
+                _ => in_agents = false,
+            }
+        }
+        Self { rules: if specific.is_empty() { general } else { specific } }
+    }
+
+    /// Whether `path` may be fetched: the longest matching rule decides,
+    /// with allow winning ties.
+    fn allows(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|(pattern, _)| pattern_matches(pattern, path))
+            .max_by_key(|(pattern, allow)| (pattern.len(), *allow))
+            .is_none_or(|(_, allow)| *allow)
+    }
+}
+
+/// Matches a robots.txt path pattern, where `*` stands for any characters
+/// and a trailing `$` anchors the end of the path.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        if anchored && i + 1 == parts.len() {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+/// Reduces an HTML document to its visible text, one block per line.
+fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let (inner, after) = rest[1..].split_once('>').unwrap_or((&rest[1..], ""));
+        let closing = inner.starts_with('/');
+        let tag = inner.trim_start_matches('/').to_ascii_lowercase();
+        let name =
+            tag.split(|c: char| c.is_whitespace() || c == '/').next().unwrap_or_default();
+        rest = after;
+
+        if matches!(name, "script" | "style" | "noscript" | "template")
+            && !closing
+            && !tag.ends_with('/')
+        {
+            let close = format!("</{}", name);
+            let skipped = rest.to_ascii_lowercase().find(&close).unwrap_or(rest.len());
+            rest = &rest[skipped..];
+            rest = rest.find('>').map_or("", |end| &rest[end + 1..]);
+        } else if matches!(
+            name,
+            "p" | "div"
+                | "br"
+                | "li"
+                | "tr"
+                | "h1"
+                | "h2"
+                | "h3"
+                | "h4"
+                | "h5"
+                | "h6"
+                | "section"
+                | "article"
+                | "header"
+                | "footer"
+                | "pre"
+                | "blockquote"
+                | "title"
+        ) {
+            text.push('\n');
+        }
+    }
+    text.push_str(rest);
+
+    let text = decode_entities(&text);
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !line.is_empty() {
+            lines.push(line);
+        }
+    }
+    lines.join("\n")
+}
+
+/// Decodes the character references common in page text.
+fn decode_entities(text: &str) -> String {
+    const ENTITIES: &[(&str, &str)] = &[
+        ("&nbsp;", " "),
+        ("&lt;", "<"),
+        ("&gt;", ">"),
+        ("&quot;", "\""),
+        ("&#39;", "'"),
+        ("&apos;", "'"),
+        ("&amp;", "&"),
+    ];
+    ENTITIES.iter().fold(text.to_string(), |text, (entity, c)| text.replace(entity, c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_robots_rules() {
+        let robots = Robots::parse(
+            "User-agent: *\nDisallow: /private\n\n\
+             User-agent: Perplexity-Web-API-MCP\nUser-agent: other\n\
+             Disallow: /drafts/ # comment\nAllow: /drafts/public\nDisallow: /*.pdf$\n",
+            AGENT_TOKEN,
+        );
+        assert!(robots.allows("/private"));
+        assert!(!robots.allows("/drafts/x"));
+        assert!(robots.allows("/drafts/public/x"));
+        assert!(!robots.allows("/files/report.pdf"));
+        assert!(robots.allows("/files/report.pdf.html"));
+
+        let general = Robots::parse("User-agent: *\nDisallow: /private\nDisallow:\n", "bot");
+        assert!(!general.allows("/private/x"));
+        assert!(general.allows("/"));
+        assert!(!Robots::disallow_all().allows("/"));
+    }
+
+    #[test]
+    fn test_html_to_text() {
+        let html = "<html><head><title>Rust</title><style>p { color: red }</style></head>\
+                    <body><h1>Release&nbsp;notes</h1><script>var a = '<p>';</script>\
+                    <p>Fast &amp; <b>safe</b>.</p><ul><li>One</li><li>Two</li></ul></body></html>";
+        assert_eq!(html_to_text(html), "Rust\nRelease notes\nFast & safe.\nOne\nTwo");
+    }
+
+    #[tokio::test]
+    async fn test_local_addresses_are_refused_by_default() {
+        let fetcher = SourceFetcher::default();
+        for url in ["http://127.0.0.1/", "http://169.254.169.254/latest/meta-data/"] {
+            let error = fetcher.fetch(url, 100).await.unwrap_err();
+            assert!(error.contains("not an allowed host"), "{}: {}", url, error);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cached_pages_are_revalidated() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Serves the page with an ETag, then answers 304 to requests naming it.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = vec![0; 4096];
+                let read = stream.read(&mut request).await.unwrap();
+                let request = String::from_utf8_lossy(&request[..read]).to_lowercase();
+                let response = if request.contains("if-none-match: \"v1\"") {
+                    "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n".to_string()
+                } else {
+                    let body = "<p>Release notes</p>";
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nETag: \"v1\"\r\n\
+                         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                };
+                stream.write_all(response.as_bytes()).await.unwrap();
+                requests.push(request);
+            }
+            requests
+        });
+
+        let dir = std::env::temp_dir().join(format!("fetch-test-{}", uuid::Uuid::new_v4()));
+        let cache = PageCache::open(&dir, 1024 * 1024, None).unwrap();
+        let fetcher = SourceFetcher::default()
+            .limited_to(HostAllowlist::any(), perplexity_web_api::DEFAULT_MAX_REDIRECTS)
+            .caching_in(cache);
+        let url = format!("http://{}/notes", address);
+
+        let first = fetcher.fetch(&url, 100).await.unwrap();
+        assert_eq!((first.text.as_str(), first.revalidated), ("Release notes", false));
+        let second = fetcher.fetch(&url, 100).await.unwrap();
+        assert_eq!((second.text.as_str(), second.revalidated), ("Release notes", true));
+        assert_eq!(second.status, 200);
+
+        let requests = server.await.unwrap();
+        assert!(!requests[0].contains("if-none-match"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_robots_redirects_are_followed() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Moves robots.txt elsewhere on the same host, as an http to https
+        // or apex to www redirect would.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = vec![0; 4096];
+                let read = stream.read(&mut request).await.unwrap();
+                let request = String::from_utf8_lossy(&request[..read]).to_string();
+                let (status, location, body) = if request.starts_with("GET /robots.txt ") {
+                    ("301 Moved Permanently", "Location: /site/robots.txt\r\n", "")
+                } else if request.starts_with("GET /site/robots.txt ") {
+                    ("200 OK", "", "User-agent: *\nDisallow: /private\n")
+                } else {
+                    ("200 OK", "", "Public page")
+                };
+                let response = format!(
+                    "HTTP/1.1 {}\r\n{}Content-Type: text/plain\r\nContent-Length: {}\r\n\
+                     Connection: close\r\n\r\n{}",
+                    status,
+                    location,
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        let fetcher = SourceFetcher::default()
+            .limited_to(HostAllowlist::any(), perplexity_web_api::DEFAULT_MAX_REDIRECTS)
+            .respecting_robots();
+        let error = fetcher.fetch(&format!("http://{}/private", address), 100).await;
+        assert!(error.unwrap_err().contains("disallows /private"));
+        let page = fetcher.fetch(&format!("http://{}/public", address), 100).await.unwrap();
+        assert_eq!(page.text, "Public page");
+        server.abort();
+    }
+}
@@ -0,0 +1,89 @@
+//! Liveness and readiness probes for container deployments.
+//!
+//! `GET /healthz` answers 200 while the process is serving requests at all.
+//! `GET /readyz` answers 200 once the server has finished starting up (the
+//! Perplexity session is warmed up) and 503 before that and while it drains
+//! connections after a shutdown signal, so an orchestrator only routes
+//! traffic to instances that can answer it.
+
+use axum::Router;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use serde_json::json;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+const STARTING: u8 = 0;
+const READY: u8 = 1;
+const DRAINING: u8 = 2;
+
+/// Readiness of the server, shared by all clones.
+#[derive(Debug, Clone, Default)]
+pub struct Health {
+    state: Arc<AtomicU8>,
+}
+
+impl Health {
+    /// Marks the server ready, unless it is already draining.
+    pub fn set_ready(&self) {
+        let _ =
+            self.state.compare_exchange(STARTING, READY, Ordering::AcqRel, Ordering::Acquire);
+    }
+
+    /// Marks the server as shutting down.
+    pub fn set_draining(&self) {
+        self.state.store(DRAINING, Ordering::Release);
+    }
+
+    fn status(&self) -> &'static str {
+        match self.state.load(Ordering::Acquire) {
+            STARTING => "starting",
+            READY => "ready",
+            _ => "draining",
+        }
+    }
+}
+
+/// Routes serving `/healthz` and `/readyz`.
+pub fn router(health: Health) -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .with_state(health)
+}
+
+async fn healthz() -> Response {
+    axum::Json(json!({ "status": "ok" })).into_response()
+}
+
+async fn readyz(State(health): State<Health>) -> Response {
+    let status = health.status();
+    let code =
+        if status == "ready" { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (code, axum::Json(json!({ "status": status }))).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_readiness_follows_lifecycle() {
+        let health = Health::default();
+        assert_eq!(
+            readyz(State(health.clone())).await.status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+
+        health.set_ready();
+        assert_eq!(readyz(State(health.clone())).await.status(), StatusCode::OK);
+
+        health.set_draining();
+        health.set_ready();
+        assert_eq!(health.status(), "draining");
+        assert_eq!(readyz(State(health)).await.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(healthz().await.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,490 @@
+//! SQLite-backed record of every query answered by the server.
+
+use rmcp::schemars;
+use rusqlite::{Connection, MAIN_DB, params};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::server::WebResultInfo;
+use crate::signing::{ResultSigner, Signature};
+use crate::storage::{self, StorageKey};
+use crate::util::unix_now;
+use crate::util::write_atomically;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS queries (
+        id           INTEGER PRIMARY KEY AUTOINCREMENT,
+        created_at   INTEGER NOT NULL,
+        mode         TEXT NOT NULL,
+        query        TEXT NOT NULL,
+        answer       TEXT,
+        web_results  TEXT NOT NULL,
+        backend_uuid TEXT,
+        signature    TEXT,
+        tags         TEXT NOT NULL DEFAULT '[]'
+    );
+    CREATE INDEX IF NOT EXISTS queries_created_at ON queries (created_at);
+";
+
+/// Full-text index over queries and answers, kept in sync by triggers.
+const FTS_SCHEMA: &str = "
+    CREATE VIRTUAL TABLE IF NOT EXISTS queries_fts USING fts5 (
+        query, answer, content = 'queries', content_rowid = 'id', tokenize = 'porter unicode61'
+    );
+    CREATE TRIGGER IF NOT EXISTS queries_fts_insert AFTER INSERT ON queries BEGIN
+        INSERT INTO queries_fts (rowid, query, answer) VALUES (new.id, new.query, new.answer);
+    END;
+    CREATE TRIGGER IF NOT EXISTS queries_fts_delete AFTER DELETE ON queries BEGIN
+        INSERT INTO queries_fts (queries_fts, rowid, query, answer)
+        VALUES ('delete', old.id, old.query, old.answer);
+    END;
+";
+
+/// A single recorded query and its answer.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct HistoryEntry {
+    /// Row id of the entry.
+    pub id: i64,
+
+    /// Unix timestamp (seconds) when the answer was recorded.
+    pub created_at: i64,
+
+    /// Search mode used for the query.
+    pub mode: String,
+
+    /// The original query text.
+    pub query: String,
+
+    /// The answer returned by Perplexity.
+    pub answer: Option<String>,
+
+    /// Citations returned with the answer.
+    pub web_results: Vec<WebResultInfo>,
+
+    /// Thread id (backend UUID) for follow-up queries.
+    pub backend_uuid: Option<String>,
+
+    /// Tags the query was asked with.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+
+    /// Signature over the entry, when the server has a signing key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Signature>,
+}
+
+/// A history entry ranked by relevance to a lookup question.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct HistoryMatch {
+    /// Relevance score; higher is more relevant.
+    pub relevance: f64,
+
+    #[serde(flatten)]
+    pub entry: HistoryEntry,
+}
+
+/// Data for a new history entry.
+pub struct NewEntry<'a> {
+    pub mode: &'a str,
+    pub query: &'a str,
+    pub answer: Option<&'a str>,
+    pub web_results: &'a [WebResultInfo],
+    pub backend_uuid: Option<&'a str>,
+    pub tags: &'a [String],
+}
+
+/// Handle to the history database.
+///
+/// All methods are blocking; call them from `tokio::task::spawn_blocking`.
+#[derive(Clone)]
+pub struct HistoryStore {
+    conn: Arc<Mutex<Connection>>,
+    signer: Option<ResultSigner>,
+    /// File an encrypted database is written back to after each change.
+    sealed: Option<(PathBuf, StorageKey)>,
+}
+
+impl HistoryStore {
+    /// Opens (or creates) the history database at `path`.
+    pub fn open(path: impl AsRef<Path>) -> rusqlite::Result<Self> {
+        Self::init(Connection::open(path)?, None)
+    }
+
+    /// Opens (or creates) the history database encrypted with `key` at
+    /// `path`.
+    ///
+    /// The database is kept in memory and the whole file is encrypted and
+    /// rewritten after each recorded entry. An unencrypted database at
+    /// `path` is read and encrypted right away.
+    pub fn open_encrypted(path: impl AsRef<Path>, key: StorageKey) -> rusqlite::Result<Self> {
+        let path = path.as_ref();
+        let mut conn = Connection::open_in_memory()?;
+        match std::fs::read(path) {
+            Ok(bytes) => {
+                let bytes = storage::unseal(Some(&key), bytes)
+                    .map_err(|e| io_error(rusqlite::ffi::SQLITE_CANTOPEN, &e))?;
+                let len = bytes.len();
+                conn.deserialize_read_exact(MAIN_DB, bytes.as_slice(), len, false)?;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(io_error(rusqlite::ffi::SQLITE_CANTOPEN, &e)),
+        }
+
+        let store = Self::init(conn, Some((path.to_path_buf(), key)))?;
+        store.write_back(&store.conn.lock().unwrap_or_else(PoisonError::into_inner))?;
+        Ok(store)
+    }
+
+    fn init(
+        conn: Connection,
+        sealed: Option<(PathBuf, StorageKey)>,
+    ) -> rusqlite::Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        let has_signature: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM pragma_table_info('queries') WHERE name = 'signature')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_signature {
+            // Databases created before entries were signed.
+            conn.execute("ALTER TABLE queries ADD COLUMN signature TEXT", [])?;
+        }
+        let has_tags: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM pragma_table_info('queries') WHERE name = 'tags')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_tags {
+            // Databases created before queries could be tagged.
+            conn.execute(
+                "ALTER TABLE queries ADD COLUMN tags TEXT NOT NULL DEFAULT '[]'",
+                [],
+            )?;
+        }
+
+        let has_fts: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE name = 'queries_fts')",
+            [],
+            |row| row.get(0),
+        )?;
+        conn.execute_batch(FTS_SCHEMA)?;
+        if !has_fts {
+            // Index entries recorded before full-text search was available.
+            conn.execute("INSERT INTO queries_fts (queries_fts) VALUES ('rebuild')", [])?;
+        }
+
+        Ok(Self { conn: Arc::new(Mutex::new(conn)), signer: None, sealed })
+    }
+
+    /// Encrypts the database and writes it to its file, if it is encrypted.
+    fn write_back(&self, conn: &Connection) -> rusqlite::Result<()> {
+        let Some((path, key)) = &self.sealed else {
+            return Ok(());
+        };
+        let data = conn.serialize(MAIN_DB)?;
+        key.seal(&data)
+            .and_then(|sealed| write_atomically(path, &sealed))
+            .map_err(|e| io_error(rusqlite::ffi::SQLITE_IOERR, &e))
+    }
+
+    /// Signs entries recorded from now on with `signer`.
+    pub fn with_signer(mut self, signer: ResultSigner) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Records a completed query.
+    pub fn record(&self, entry: &NewEntry<'_>) -> rusqlite::Result<i64> {
+        let conversion = |e: String| rusqlite::Error::ToSqlConversionFailure(e.into());
+        let web_results = serde_json::to_string(entry.web_results)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let tags = serde_json::to_string(entry.tags)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let created_at = unix_now();
+        let signature = match &self.signer {
+            Some(signer) => {
+                let signature = signer
+                    .sign(&HistoryEntry {
+                        id: 0,
+                        created_at,
+                        mode: entry.mode.to_string(),
+                        query: entry.query.to_string(),
+                        answer: entry.answer.map(str::to_string),
+                        web_results: entry.web_results.to_vec(),
+                        backend_uuid: entry.backend_uuid.map(str::to_string),
+                        tags: entry.tags.to_vec(),
+                        signature: None,
+                    })
+                    .map_err(conversion)?;
+                Some(
+                    serde_json::to_string(&signature)
+                        .map_err(|e| conversion(e.to_string()))?,
+                )
+            }
+            None => None,
+        };
+
+        let conn = self.conn.lock().unwrap_or_else(PoisonError::into_inner);
+        conn.execute(
+            "INSERT INTO queries
+                 (created_at, mode, query, answer, web_results, backend_uuid, signature, tags)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                created_at,
+                entry.mode,
+                entry.query,
+                entry.answer,
+                web_results,
+                entry.backend_uuid,
+                signature,
+                tags
+            ],
+        )?;
+        let id = conn.last_insert_rowid();
+        self.write_back(&conn)?;
+        Ok(id)
+    }
+
+    /// Returns the most recent entries whose query or answer contains `text`.
+    ///
+    /// With no `text`, returns the most recent entries.
+    pub fn search(
+        &self,
+        text: Option<&str>,
+        limit: u32,
+    ) -> rusqlite::Result<Vec<HistoryEntry>> {
+        let pattern = format!("%{}%", escape_like(text.unwrap_or_default()));
+
+        let conn = self.conn.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut stmt = conn.prepare(
+            "SELECT id, created_at, mode, query, answer, web_results, backend_uuid, signature,
+                    tags
+             FROM queries
+             WHERE query LIKE ?1 ESCAPE '\\' OR answer LIKE ?1 ESCAPE '\\'
+             ORDER BY id DESC
+             LIMIT ?2",
+        )?;
+
+        stmt.query_map(params![pattern, limit], row_to_entry)?.collect()
+    }
+
+    /// Finds past entries relevant to a natural-language `question`, best match first.
+    ///
+    /// Any shared word counts towards a match; results are ranked with BM25 so
+    /// entries sharing rarer terms score higher.
+    pub fn lookup(&self, question: &str, limit: u32) -> rusqlite::Result<Vec<HistoryMatch>> {
+        let Some(fts_query) = fts_query(question) else {
+            return Ok(Vec::new());
+        };
+
+        let conn = self.conn.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut stmt = conn.prepare(
+            "SELECT q.id, q.created_at, q.mode, q.query, q.answer, q.web_results, q.backend_uuid,
+                    q.signature, q.tags, -bm25(queries_fts, 2.0, 1.0)
+             FROM queries_fts
+             JOIN queries q ON q.id = queries_fts.rowid
+             WHERE queries_fts MATCH ?1
+             ORDER BY bm25(queries_fts, 2.0, 1.0)
+             LIMIT ?2",
+        )?;
+
+        stmt.query_map(params![fts_query, limit], |row| {
+            Ok(HistoryMatch { entry: row_to_entry(row)?, relevance: row.get(9)? })
+        })?
+        .collect()
+    }
+
+    /// Deletes the entries having any of `tags`, or with no tags given any
+    /// entry, that were recorded before the Unix timestamp `before` if
+    /// given. Returns how many were deleted.
+    pub fn purge(&self, tags: &[String], before: Option<i64>) -> rusqlite::Result<usize> {
+        let tags = serde_json::to_string(tags)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let conn = self.conn.lock().unwrap_or_else(PoisonError::into_inner);
+        let deleted = conn.execute(
+            "DELETE FROM queries
+             WHERE (?1 = '[]' OR EXISTS (
+                        SELECT 1 FROM json_each(queries.tags)
+                        WHERE value IN (SELECT value FROM json_each(?1))))
+               AND (?2 IS NULL OR created_at < ?2)",
+            params![tags, before],
+        )?;
+        if deleted > 0 {
+            self.write_back(&conn)?;
+        }
+        Ok(deleted)
+    }
+}
+
+/// Turns free text into an FTS5 query matching any of its words.
+///
+/// Words are quoted so that FTS5 operators in the input (`AND`, `*`, `:`)
+/// are treated as plain text.
+fn fts_query(text: &str) -> Option<String> {
+    let terms: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("\"{}\"", word.to_lowercase()))
+        .collect();
+
+    if terms.is_empty() { None } else { Some(terms.join(" OR ")) }
+}
+
+fn row_to_entry(row: &rusqlite::Row<'_>) -> rusqlite::Result<HistoryEntry> {
+    let web_results: String = row.get(5)?;
+    let signature: Option<String> = row.get(7)?;
+    let tags: String = row.get(8)?;
+    Ok(HistoryEntry {
+        id: row.get(0)?,
+        created_at: row.get(1)?,
+        mode: row.get(2)?,
+        query: row.get(3)?,
+        answer: row.get(4)?,
+        web_results: serde_json::from_str(&web_results).unwrap_or_default(),
+        backend_uuid: row.get(6)?,
+        tags: serde_json::from_str(&tags).unwrap_or_default(),
+        signature: signature.and_then(|signature| serde_json::from_str(&signature).ok()),
+    })
+}
+
+/// Escapes `%`, `_` and the escape character itself for use in a LIKE pattern.
+fn escape_like(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Reports a failure reading or writing an encrypted database file.
+fn io_error(code: std::ffi::c_int, e: &std::io::Error) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(rusqlite::ffi::Error::new(code), Some(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry<'a>(
+        query: &'a str,
+        answer: &'a str,
+        web_results: &'a [WebResultInfo],
+    ) -> NewEntry<'a> {
+        NewEntry {
+            mode: "auto",
+            query,
+            answer: Some(answer),
+            web_results,
+            backend_uuid: Some("thread-1"),
+            tags: &[],
+        }
+    }
+
+    #[test]
+    fn test_record_and_search() {
+        let store = HistoryStore::open(":memory:").unwrap();
+        let citations = vec![WebResultInfo {
+            name: "Rust".to_string(),
+            url: "https://rust-lang.org".to_string(),
+            snippet: "A language".to_string(),
+            score: None,
+        }];
+
+        store.record(&entry("What is Rust?", "A systems language", &citations)).unwrap();
+        store.record(&entry("What is Go?", "Another language", &[])).unwrap();
+
+        let found = store.search(Some("rust"), 10).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].query, "What is Rust?");
+        assert_eq!(found[0].web_results.len(), 1);
+        assert_eq!(found[0].backend_uuid.as_deref(), Some("thread-1"));
+
+        let all = store.search(None, 10).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].query, "What is Go?");
+    }
+
+    #[test]
+    fn test_lookup_ranks_by_relevance() {
+        let store = HistoryStore::open(":memory:").unwrap();
+        store.record(&entry("Rust async runtimes", "Tokio and smol", &[])).unwrap();
+        store.record(&entry("Best pasta recipes", "Carbonara", &[])).unwrap();
+        store.record(&entry("Rust borrow checker", "Ownership rules", &[])).unwrap();
+
+        let found = store.lookup("have I researched async Rust runtimes?", 10).unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].entry.query, "Rust async runtimes");
+        assert!(found[0].relevance > found[1].relevance);
+    }
+
+    #[test]
+    fn test_lookup_ignores_fts_syntax() {
+        let store = HistoryStore::open(":memory:").unwrap();
+        store.record(&entry("What is NEAR protocol", "A blockchain", &[])).unwrap();
+
+        assert_eq!(store.lookup("NEAR AND * \"protocol", 10).unwrap().len(), 1);
+        assert!(store.lookup("?!", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_signed_entries_verify() {
+        let signer = ResultSigner::from_hex(&"11".repeat(32)).unwrap();
+        let store = HistoryStore::open(":memory:").unwrap().with_signer(signer.clone());
+        store.record(&entry("What is Rust?", "A systems language", &[])).unwrap();
+
+        let found = store.lookup("rust", 10).unwrap();
+        let record = serde_json::to_value(&found[0]).unwrap();
+        assert_eq!(crate::signing::verify(&record).unwrap(), signer.public_key());
+    }
+
+    #[test]
+    fn test_encrypted_database_round_trips() {
+        let path =
+            std::env::temp_dir().join(format!("pplx-history-{}.db", uuid::Uuid::new_v4()));
+        let key = StorageKey::new(&"5a".repeat(32)).unwrap();
+        let store = HistoryStore::open_encrypted(&path, key.clone()).unwrap();
+        store.record(&entry("What is Rust?", "A systems language", &[])).unwrap();
+        drop(store);
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert!(storage::is_sealed(&bytes));
+        assert!(HistoryStore::open(&path).is_err());
+        let reopened = HistoryStore::open_encrypted(&path, key);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(reopened.unwrap().lookup("rust", 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_purge_by_tag_and_age() {
+        let store = HistoryStore::open(":memory:").unwrap();
+        let tags = ["competitors".to_string(), "pricing".to_string()];
+        store.record(&NewEntry { tags: &tags, ..entry("Acme pricing", "$10", &[]) }).unwrap();
+        store
+            .record(&NewEntry { tags: &tags[1..], ..entry("Our pricing", "$8", &[]) })
+            .unwrap();
+        store.record(&entry("What is Rust?", "A language", &[])).unwrap();
+        assert_eq!(store.search(Some("Acme"), 10).unwrap()[0].tags, tags);
+
+        assert_eq!(store.purge(&tags[..1], None).unwrap(), 1);
+        assert_eq!(store.purge(&["pricing".to_string()], Some(0)).unwrap(), 0);
+        assert_eq!(store.purge(&["pricing".to_string()], Some(unix_now() + 1)).unwrap(), 1);
+        let left = store.search(None, 10).unwrap();
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].query, "What is Rust?");
+    }
+
+    #[test]
+    fn test_search_escapes_wildcards() {
+        let store = HistoryStore::open(":memory:").unwrap();
+        store.record(&entry("100% coverage", "yes", &[])).unwrap();
+        store.record(&entry("1000 coverage", "no", &[])).unwrap();
+
+        let found = store.search(Some("100%"), 10).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].query, "100% coverage");
+    }
+}
@@ -0,0 +1,103 @@
+//! Streamable HTTP transport.
+//!
+//! In HTTP mode the server listens on a TCP address instead of stdio and
+//! serves MCP at `/mcp`, one session per client. Optional routes, such as
+//! the OpenAI-compatible endpoint, are merged into the same listener.
+//! When an API key is set, `/mcp` only answers requests carrying it as a
+//! bearer token.
+
+use axum::Router;
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use rmcp::transport::streamable_http_server::{
+    StreamableHttpServerConfig, StreamableHttpService, session::local::LocalSessionManager,
+};
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+
+use crate::server::PerplexityServer;
+
+/// Serves MCP and `routes` on `listener` until `shutdown` completes. If
+/// `api_key` is set, MCP requests must carry it as a bearer token.
+pub async fn serve(
+    server: PerplexityServer,
+    listener: TcpListener,
+    api_key: Option<String>,
+    routes: Router,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> io::Result<()> {
+    let cancel = CancellationToken::new();
+    let mcp = StreamableHttpService::new(
+        move || Ok(server.clone()),
+        LocalSessionManager::default().into(),
+        StreamableHttpServerConfig {
+            cancellation_token: cancel.child_token(),
+            ..StreamableHttpServerConfig::default()
+        },
+    );
+    let mcp = Router::new().nest_service("/mcp", mcp);
+    let mcp = match api_key {
+        Some(api_key) => {
+            mcp.layer(middleware::from_fn_with_state(Arc::<str>::from(api_key), require_key))
+        }
+        None => mcp,
+    };
+    let app = routes.merge(mcp);
+
+    tracing::info!("MCP server listening on http://{}/mcp", listener.local_addr()?);
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move {
+            shutdown.await;
+            cancel.cancel();
+        })
+        .await
+}
+
+async fn require_key(
+    State(api_key): State<Arc<str>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !authorized(request.headers(), &api_key) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            "Invalid API key",
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+/// Whether `headers` carry `api_key` as a bearer token.
+fn authorized(headers: &HeaderMap, api_key: &str) -> bool {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    bearer == Some(api_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requires_bearer_token() {
+        let mut headers = HeaderMap::new();
+        assert!(!authorized(&headers, "key"));
+
+        headers.insert(header::AUTHORIZATION, "Bearer other".parse().unwrap());
+        assert!(!authorized(&headers, "key"));
+        headers.insert(header::AUTHORIZATION, "key".parse().unwrap());
+        assert!(!authorized(&headers, "key"));
+
+        headers.insert(header::AUTHORIZATION, "Bearer key".parse().unwrap());
+        assert!(authorized(&headers, "key"));
+    }
+}
@@ -0,0 +1,541 @@
+//! Background execution of long-running queries.
+//!
+//! Jobs move through `queued → running → completed | failed`. At most
+//! `concurrency` jobs run at once and at most `capacity` may be unfinished;
+//! further submissions are rejected rather than buffered. When a state file is
+//! configured, the job table is persisted on every change so that unfinished
+//! jobs can be resumed after a restart and results survive it.
+
+use perplexity_web_api::SearchMode;
+use rmcp::schemars;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+use uuid::Uuid;
+
+use crate::queue::{Priority, Slots};
+use crate::server::{PerplexityRequest, PerplexityResponse};
+use crate::signing::ResultSigner;
+use crate::storage::{self, StorageKey};
+use crate::util::{read_json, unix_now, write_atomically};
+use crate::webhook::Webhook;
+
+/// Lifecycle state of a background job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    /// Waiting for a free execution slot.
+    Queued,
+    /// Currently executing.
+    Running,
+    /// Finished with a result.
+    Completed,
+    /// Finished with an error.
+    Failed,
+}
+
+impl JobStatus {
+    fn is_finished(self) -> bool {
+        matches!(self, Self::Completed | Self::Failed)
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Running => "running",
+            Self::Completed => "completed",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+/// A job as stored in the job table and the state file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobRecord {
+    pub id: String,
+    pub status: JobStatus,
+    pub mode: String,
+    pub request: PerplexityRequest,
+    pub created_at: i64,
+    pub started_at: Option<i64>,
+    pub finished_at: Option<i64>,
+    pub result: Option<PerplexityResponse>,
+    pub error: Option<String>,
+}
+
+/// Body posted to the webhook when a job finishes.
+#[derive(Serialize)]
+struct JobNotification<'a> {
+    job_id: &'a str,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<&'a PerplexityResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+}
+
+/// Settings for a [`JobManager`].
+pub struct JobOptions {
+    /// Where finished results are delivered, if anywhere.
+    pub webhook: Option<Webhook>,
+    /// Maximum number of unfinished (queued or running) jobs.
+    pub capacity: usize,
+    /// Maximum number of jobs running at the same time.
+    pub concurrency: usize,
+    /// Number of finished jobs kept for result retrieval.
+    pub retention: usize,
+    /// File the job table is persisted to.
+    pub state_file: Option<PathBuf>,
+    /// Whether to drop snippet text from results before they are stored or
+    /// sent to the webhook.
+    pub redact_snippets: bool,
+    /// Key signing results before they are stored or sent to the webhook.
+    pub signer: Option<ResultSigner>,
+    /// Key the state file is encrypted with.
+    pub storage_key: Option<StorageKey>,
+}
+
+impl Default for JobOptions {
+    fn default() -> Self {
+        Self {
+            webhook: None,
+            capacity: 16,
+            concurrency: 2,
+            retention: 100,
+            state_file: None,
+            redact_snippets: false,
+            signer: None,
+            storage_key: None,
+        }
+    }
+}
+
+/// Runs queries in the background and keeps track of their outcome.
+#[derive(Clone)]
+pub struct JobManager {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    jobs: Mutex<Table>,
+    slots: Slots,
+    webhook: Option<Webhook>,
+    capacity: usize,
+    retention: usize,
+    state_file: Option<PathBuf>,
+    redact_snippets: bool,
+    signer: Option<ResultSigner>,
+    storage_key: Option<StorageKey>,
+    /// Version of the last snapshot written to the state file.
+    written: Mutex<u64>,
+}
+
+#[derive(Default)]
+struct Table {
+    records: Vec<JobRecord>,
+    version: u64,
+}
+
+impl JobManager {
+    /// Creates a manager with an empty job table.
+    pub fn new(options: JobOptions) -> Self {
+        Self::with_records(options, Vec::new())
+    }
+
+    /// Creates a manager, loading any jobs persisted in the state file.
+    ///
+    /// Jobs that were unfinished when the previous process exited are reset
+    /// to queued; call [`pending`](Self::pending) and [`resume`](Self::resume)
+    /// to run them again.
+    pub fn load(options: JobOptions) -> std::io::Result<Self> {
+        let mut records: Vec<JobRecord> = match &options.state_file {
+            Some(path) => read_json(path, options.storage_key.as_ref())?.unwrap_or_default(),
+            None => Vec::new(),
+        };
+        for record in &mut records {
+            if record.status == JobStatus::Running {
+                record.status = JobStatus::Queued;
+                record.started_at = None;
+            }
+        }
+
+        Ok(Self::with_records(options, records))
+    }
+
+    fn with_records(options: JobOptions, records: Vec<JobRecord>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                jobs: Mutex::new(Table { records, version: 0 }),
+                slots: Slots::new(options.concurrency),
+                webhook: options.webhook,
+                capacity: options.capacity.max(1),
+                retention: options.retention,
+                state_file: options.state_file,
+                redact_snippets: options.redact_snippets,
+                signer: options.signer,
+                storage_key: options.storage_key,
+                written: Mutex::new(0),
+            }),
+        }
+    }
+
+    /// Queues a new job running `work` and returns its id.
+    ///
+    /// Fails if `capacity` unfinished jobs already exist.
+    pub fn submit<F>(
+        &self,
+        request: PerplexityRequest,
+        mode: SearchMode,
+        work: F,
+    ) -> Result<String, String>
+    where
+        F: Future<Output = Result<PerplexityResponse, String>> + Send + 'static,
+    {
+        let id = {
+            let table = self.lock();
+            let unfinished = table.records.iter().filter(|r| !r.status.is_finished()).count();
+            if unfinished >= self.inner.capacity {
+                return Err(format!(
+                    "Job queue is full ({} unfinished jobs); retry after some complete",
+                    unfinished
+                ));
+            }
+            self.insert(table, request, mode, JobStatus::Queued)
+        };
+
+        self.resume(id.clone(), work);
+        Ok(id)
+    }
+
+    /// Registers `work` that is already under way as a running job and returns its id.
+    ///
+    /// Used when a foreground query overruns its soft deadline. The work does
+    /// not wait for an execution slot and is not rejected when the queue is
+    /// full, because it is already consuming resources either way.
+    pub fn adopt<F>(&self, request: PerplexityRequest, mode: SearchMode, work: F) -> String
+    where
+        F: Future<Output = Result<PerplexityResponse, String>> + Send + 'static,
+    {
+        let id = self.insert(self.lock(), request, mode, JobStatus::Running);
+
+        let manager = self.clone();
+        let job_id = id.clone();
+        tokio::spawn(async move {
+            let outcome = work.await;
+            manager.finish(&job_id, outcome).await;
+        });
+
+        id
+    }
+
+    fn insert(
+        &self,
+        mut table: std::sync::MutexGuard<'_, Table>,
+        request: PerplexityRequest,
+        mode: SearchMode,
+        status: JobStatus,
+    ) -> String {
+        let id = Uuid::new_v4().to_string();
+        let now = unix_now();
+        table.records.push(JobRecord {
+            id: id.clone(),
+            status,
+            mode: mode.as_str().to_string(),
+            request,
+            created_at: now,
+            started_at: (status == JobStatus::Running).then_some(now),
+            finished_at: None,
+            result: None,
+            error: None,
+        });
+        self.changed(table);
+        id
+    }
+
+    /// Returns queued jobs loaded from the state file that have no running task.
+    pub fn pending(&self) -> Vec<(String, PerplexityRequest, SearchMode)> {
+        self.lock()
+            .records
+            .iter()
+            .filter(|r| r.status == JobStatus::Queued)
+            .map(|r| (r.id.clone(), r.request.clone(), r.mode.parse().unwrap_or_default()))
+            .collect()
+    }
+
+    /// Runs `work` for the already-recorded job `id` once a slot is free.
+    /// Jobs whose request has a higher priority get a slot first; jobs that
+    /// name none are low priority.
+    ///
+    /// The task is intentionally detached: the tool call that submitted it has
+    /// already returned, and the outcome is picked up through the job table
+    /// or the webhook.
+    pub fn resume<F>(&self, id: String, work: F)
+    where
+        F: Future<Output = Result<PerplexityResponse, String>> + Send + 'static,
+    {
+        let manager = self.clone();
+        let priority = self.get(&id).and_then(|r| r.request.priority).unwrap_or(Priority::Low);
+        tokio::spawn(async move {
+            let _permit = manager.inner.slots.acquire(priority).await;
+
+            manager.update(&id, |record| {
+                record.status = JobStatus::Running;
+                record.started_at = Some(unix_now());
+            });
+
+            let outcome = work.await;
+            manager.finish(&id, outcome).await;
+        });
+    }
+
+    /// Returns a snapshot of the job with the given id.
+    pub fn get(&self, id: &str) -> Option<JobRecord> {
+        self.lock().records.iter().find(|r| r.id == id).cloned()
+    }
+
+    async fn finish(&self, id: &str, outcome: Result<PerplexityResponse, String>) {
+        let status = if outcome.is_ok() { JobStatus::Completed } else { JobStatus::Failed };
+        let (result, error) = match outcome {
+            Ok(mut response) => {
+                if self.inner.redact_snippets {
+                    response.redact_snippets();
+                }
+                if let Some(signer) = &self.inner.signer {
+                    response.sign(signer);
+                }
+                (Some(response), None)
+            }
+            Err(e) => (None, Some(e)),
+        };
+
+        tracing::info!(job_id = %id, status = status.as_str(), "Job finished");
+
+        // Saved before the webhook goes out, so a receiver that asks for the
+        // job's status on delivery already sees it finished.
+        self.update(id, |record| {
+            record.status = status;
+            record.finished_at = Some(unix_now());
+            record.result.clone_from(&result);
+            record.error.clone_from(&error);
+        });
+
+        if let Some(webhook) = &self.inner.webhook {
+            let notification = JobNotification {
+                job_id: id,
+                status: status.as_str(),
+                result: result.as_ref(),
+                error: error.as_deref(),
+            };
+            if let Err(e) = webhook.deliver(&notification).await {
+                tracing::warn!(job_id = %id, "Webhook delivery failed: {}", e);
+            }
+        }
+    }
+
+    fn update(&self, id: &str, f: impl FnOnce(&mut JobRecord)) {
+        let mut table = self.lock();
+        if let Some(record) = table.records.iter_mut().find(|r| r.id == id) {
+            f(record);
+        }
+        self.evict_finished(&mut table);
+        self.changed(table);
+    }
+
+    /// Drops the oldest finished jobs beyond the retention limit.
+    fn evict_finished(&self, table: &mut Table) {
+        let finished = table.records.iter().filter(|r| r.status.is_finished()).count();
+        let mut excess = finished.saturating_sub(self.inner.retention);
+        table.records.retain(|r| {
+            if excess > 0 && r.status.is_finished() {
+                excess -= 1;
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    /// Bumps the table version and persists a snapshot if a state file is set.
+    fn changed(&self, mut table: std::sync::MutexGuard<'_, Table>) {
+        table.version += 1;
+        let Some(path) = self.inner.state_file.clone() else {
+            return;
+        };
+
+        let version = table.version;
+        let snapshot = match serde_json::to_vec(&table.records) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                tracing::warn!("Failed to serialize job table: {}", e);
+                return;
+            }
+        };
+        drop(table);
+
+        let inner = Arc::clone(&self.inner);
+        tokio::task::spawn_blocking(move || {
+            let mut written = inner.written.lock().unwrap_or_else(PoisonError::into_inner);
+            // A newer snapshot may have been written while this one waited.
+            if version <= *written {
+                return;
+            }
+            let result = storage::seal(inner.storage_key.as_ref(), snapshot)
+                .and_then(|sealed| write_atomically(&path, &sealed));
+            if let Err(e) = result {
+                tracing::warn!("Failed to persist job table to {}: {}", path.display(), e);
+                return;
+            }
+            *written = version;
+        });
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Table> {
+        self.inner.jobs.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn request(query: &str) -> PerplexityRequest {
+        PerplexityRequest::plain(query.to_string(), None)
+    }
+
+    async fn wait_finished(jobs: &JobManager, id: &str) -> JobRecord {
+        for _ in 0..100 {
+            if let Some(record) = jobs.get(id).filter(|r| r.status.is_finished()) {
+                return record;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("job {} did not finish", id);
+    }
+
+    #[tokio::test]
+    async fn test_job_completes_with_result() {
+        let jobs = JobManager::new(JobOptions::default());
+        let id = jobs
+            .submit(request("q"), SearchMode::DeepResearch, async {
+                Ok(PerplexityResponse::answered("done"))
+            })
+            .unwrap();
+
+        let record = wait_finished(&jobs, &id).await;
+        assert_eq!(record.status, JobStatus::Completed);
+        assert_eq!(record.mode, "deep research");
+        assert_eq!(record.result.unwrap().answer.as_deref(), Some("done"));
+    }
+
+    #[tokio::test]
+    async fn test_redacts_snippets_from_results() {
+        let jobs =
+            JobManager::new(JobOptions { redact_snippets: true, ..JobOptions::default() });
+        let mut answer = PerplexityResponse::answered("done");
+        answer.web_results.push(crate::server::WebResultInfo {
+            name: "Example".to_string(),
+            url: "https://example.com".to_string(),
+            snippet: "Scraped text".to_string(),
+            score: None,
+        });
+        answer.context_pack = Some(crate::server::ContextPackInfo {
+            text: "[1] Example\nhttps://example.com\nScraped text".to_string(),
+            sources: vec![1],
+            estimated_tokens: 12,
+            truncated: false,
+        });
+        let id = jobs.submit(request("q"), SearchMode::Auto, async { Ok(answer) }).unwrap();
+
+        let result = wait_finished(&jobs, &id).await.result.unwrap();
+        assert_eq!(result.web_results[0].url, "https://example.com");
+        assert!(result.web_results[0].snippet.is_empty());
+        assert!(result.context_pack.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_job_failure_is_recorded() {
+        let jobs = JobManager::new(JobOptions::default());
+        let id = jobs
+            .submit(request("q"), SearchMode::Auto, async { Err("boom".to_string()) })
+            .unwrap();
+
+        let record = wait_finished(&jobs, &id).await;
+        assert_eq!(record.status, JobStatus::Failed);
+        assert_eq!(record.error.as_deref(), Some("boom"));
+    }
+
+    #[tokio::test]
+    async fn test_adopted_job_runs_without_slot() {
+        let jobs = JobManager::new(JobOptions { concurrency: 1, ..JobOptions::default() });
+        jobs.submit(request("slow"), SearchMode::Auto, std::future::pending()).unwrap();
+
+        let id = jobs.adopt(request("q"), SearchMode::DeepResearch, async {
+            Ok(PerplexityResponse::answered("x"))
+        });
+        let record = wait_finished(&jobs, &id).await;
+        assert_eq!(record.status, JobStatus::Completed);
+        assert!(record.started_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_submit_rejects_when_full() {
+        let jobs = JobManager::new(JobOptions { capacity: 1, ..JobOptions::default() });
+        jobs.submit(request("slow"), SearchMode::Auto, std::future::pending()).unwrap();
+
+        let err = jobs.submit(request("q"), SearchMode::Auto, async {
+            Ok(PerplexityResponse::answered("x"))
+        });
+        assert!(err.unwrap_err().contains("full"));
+    }
+
+    #[tokio::test]
+    async fn test_finished_jobs_are_evicted_beyond_retention() {
+        let jobs = JobManager::new(JobOptions { retention: 1, ..JobOptions::default() });
+        let first = jobs
+            .submit(request("a"), SearchMode::Auto, async {
+                Ok(PerplexityResponse::answered("a"))
+            })
+            .unwrap();
+        wait_finished(&jobs, &first).await;
+        let second = jobs
+            .submit(request("b"), SearchMode::Auto, async {
+                Ok(PerplexityResponse::answered("b"))
+            })
+            .unwrap();
+        wait_finished(&jobs, &second).await;
+
+        assert!(jobs.get(&first).is_none());
+        assert!(jobs.get(&second).is_some());
+    }
+
+    #[test]
+    fn test_unfinished_jobs_are_requeued_on_load() {
+        let path = std::env::temp_dir().join(format!("pplx-jobs-{}.json", Uuid::new_v4()));
+        let record = JobRecord {
+            id: "job-1".to_string(),
+            status: JobStatus::Running,
+            mode: "pro".to_string(),
+            request: request("resume me"),
+            created_at: 1,
+            started_at: Some(2),
+            finished_at: None,
+            result: None,
+            error: None,
+        };
+        std::fs::write(&path, serde_json::to_vec(&[record]).unwrap()).unwrap();
+
+        let jobs = JobManager::load(JobOptions {
+            state_file: Some(path.clone()),
+            ..JobOptions::default()
+        })
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let pending = jobs.pending();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].0, "job-1");
+        assert_eq!(pending[0].2, SearchMode::Pro);
+    }
+}
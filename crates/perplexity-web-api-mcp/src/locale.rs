@@ -0,0 +1,332 @@
+//! Languages of the text the server shows to end users.
+//!
+//! Some hosts pass tool descriptions and error messages straight to their
+//! users. A [`Locale`] other than English replaces the descriptions of the
+//! built-in tools, the server instructions and the first sentence of error
+//! messages with a translation. Error details from Perplexity or the client
+//! are kept as they are after the translation, and the stable `code` in the
+//! error data does not change, so agents branching on it are unaffected.
+//! Parameter docs, logs and the JSON of tool results stay in English.
+
+use rmcp::ErrorData as McpError;
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::failure;
+
+/// A language of user-facing text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Locale {
+    #[default]
+    English,
+    Indonesian,
+}
+
+impl Locale {
+    /// Every supported locale.
+    pub const ALL: &[Locale] = &[Locale::English, Locale::Indonesian];
+
+    /// Returns the language tag, e.g. `"id"`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::English => "en",
+            Self::Indonesian => "id",
+        }
+    }
+
+    /// The translated description of the built-in tool `tool`, by its
+    /// default name, or `None` to keep the English one.
+    pub fn description(&self, tool: &str) -> Option<&'static str> {
+        let catalog = match self {
+            Self::English => return None,
+            Self::Indonesian => INDONESIAN_TOOLS,
+        };
+        catalog.iter().find(|(name, _)| *name == tool).map(|(_, description)| *description)
+    }
+
+    /// The server instructions shown to MCP clients.
+    pub fn instructions(&self) -> &'static str {
+        match self {
+            Self::English => {
+                "Perplexity AI MCP server providing web search, deep research, and reasoning tools. \
+                 Use perplexity_search for quick queries, perplexity_research for comprehensive analysis, \
+                 and perplexity_reason for logical problem-solving."
+            }
+            Self::Indonesian => {
+                "Server MCP Perplexity AI yang menyediakan alat pencarian web, riset mendalam, dan \
+                 penalaran. Gunakan perplexity_search untuk pertanyaan singkat, perplexity_research \
+                 untuk analisis menyeluruh, dan perplexity_reason untuk pemecahan masalah logis."
+            }
+        }
+    }
+
+    /// Translates the message of `error` by its `code`, keeping the
+    /// original message as the detail.
+    pub fn localize_error(&self, mut error: McpError) -> McpError {
+        let code = error.data.as_ref().and_then(|data| data.get(failure::CODE_KEY));
+        let summary = match (self, code.and_then(Value::as_str)) {
+            (Self::English, _) | (_, None) => return error,
+            (Self::Indonesian, Some(code)) => indonesian_error(code),
+        };
+        error.message = format!("{}: {}", summary, error.message).into();
+        error
+    }
+}
+
+impl fmt::Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Locale {
+    type Err = String;
+
+    /// Parses a language tag such as `id` or `id-ID`, or the language's
+    /// English name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tag = s.trim().to_ascii_lowercase();
+        let language = tag.split(['-', '_']).next().unwrap_or_default();
+        match language {
+            "en" | "english" => Ok(Self::English),
+            "id" | "in" | "indonesian" => Ok(Self::Indonesian),
+            _ => Err(format!(
+                "unknown locale '{}', expected one of: {}",
+                s,
+                Self::ALL.iter().map(Locale::as_str).collect::<Vec<_>>().join(", ")
+            )),
+        }
+    }
+}
+
+/// The first sentence of Indonesian error messages, by error code.
+fn indonesian_error(code: &str) -> &'static str {
+    match code {
+        failure::INVALID_PARAMS => "Argumen tidak valid",
+        failure::QUERY_REJECTED => "Perplexity menolak kueri ini",
+        failure::REFUSED => "Perplexity menolak menjawab",
+        failure::UNAVAILABLE => "Perplexity sedang tidak tersedia",
+        failure::UPSTREAM => "Perplexity mengembalikan kesalahan",
+        failure::FETCH_FAILED => "Sumber tidak dapat diambil",
+        failure::NOT_CONFIRMED => "Kueri tidak dikonfirmasi",
+        "invalid_request" => "Permintaan tidak valid",
+        "not_found" => "Tidak ditemukan",
+        _ => "Terjadi kesalahan internal",
+    }
+}
+
+/// Indonesian descriptions of the built-in tools.
+const INDONESIAN_TOOLS: &[(&str, &str)] = &[
+    (
+        "perplexity_search",
+        "Pencarian web cepat dengan Perplexity AI. Cocok untuk: pertanyaan singkat, pencarian \
+         sehari-hari, dan pertanyaan percakapan yang terbantu oleh konteks web.",
+    ),
+    (
+        "perplexity_research",
+        "Riset mendalam dan menyeluruh dengan model sonar-deep-research dari Perplexity AI. \
+         Memberikan analisis lengkap beserta kutipan. Cocok untuk: topik kompleks yang perlu \
+         diselidiki secara rinci, laporan komprehensif, dan analisis mendalam.",
+    ),
+    (
+        "perplexity_reason",
+        "Penalaran dan pemecahan masalah tingkat lanjut dengan model sonar-reasoning-pro dari \
+         Perplexity AI. Cocok untuk: soal logika, analisis kompleks, pengambilan keputusan, dan \
+         tugas yang memerlukan penalaran langkah demi langkah.",
+    ),
+    (
+        "perplexity_code_search",
+        "Pencarian seputar pemrograman dengan mode pro Perplexity AI, diutamakan ke dokumentasi, \
+         repositori kode sumber, dan tanya jawab pengembang. Mengembalikan jawaban beserta \
+         `code_snippets`: blok kode berpagar dengan tag bahasanya. Cocok untuk: penggunaan API, \
+         pesan galat, dan contoh kode.",
+    ),
+    (
+        "perplexity_ask_to_file",
+        "Menjalankan kueri Perplexity (mode pro secara bawaan) dan menulis jawabannya beserta \
+         sumber bernomor sebagai laporan Markdown ke `path` di dalam workspace, lalu \
+         mengembalikan path tersebut, bukan jawabannya. Gagal jika berkas sudah ada, kecuali \
+         overwrite=true. Cocok untuk: menyusun dokumentasi atau catatan dari riset tanpa \
+         menyalin jawaban melalui percakapan.",
+    ),
+    (
+        "perplexity_research_plan",
+        "Meriset tujuan yang luas: memecahnya menjadi sub-pertanyaan spesifik (ditulis oleh \
+         model Anda melalui sampling bila didukung, jika tidak oleh kueri Perplexity singkat), \
+         menanyakannya secara paralel (mode pro secara bawaan), lalu menggabungkan jawabannya \
+         menjadi satu laporan Markdown yang kutipan [n]-nya merujuk ke satu daftar sumber tanpa \
+         duplikat. Cocok untuk: survei dan perbandingan yang terlalu luas untuk satu kueri, \
+         ketika riset mendalam terlalu lambat.",
+    ),
+    (
+        "perplexity_compare",
+        "Membandingkan 2-8 entitas (produk, pustaka, perusahaan...) berdasarkan 1-10 kriteria. \
+         Menjalankan satu kueri Perplexity per entitas (mode pro secara bawaan) dan \
+         mengembalikan matriks perbandingan: baris JSON dengan sel untuk tiap kriteria beserta \
+         kutipan pendukungnya, dan tabel Markdown yang penanda [n]-nya merujuk ke satu daftar \
+         sumber tanpa duplikat. Cocok untuk: perbandingan ala analis dan tabel evaluasi.",
+    ),
+    (
+        "perplexity_news_digest",
+        "Menyusun ringkasan berita: untuk tiap 1-10 topik, meminta Perplexity (mode pro secara \
+         bawaan) mencari berita utama dalam rentang waktu (hari, minggu, bulan, atau tahun; \
+         hanya sumber yang terbit dalam rentang itu yang dicari), mencantumkan berita yang \
+         muncul di beberapa topik sekali saja, dan mengembalikan satu ringkasan Markdown yang \
+         kutipan [n]-nya merujuk ke satu daftar sumber tanpa duplikat. Cocok untuk: briefing \
+         harian atau mingguan; jadwalkan dengan perplexity_schedule_digest bila penjadwalan \
+         diaktifkan.",
+    ),
+    (
+        "perplexity_cache_invalidate",
+        "Menghapus jawaban tersimpan di cache untuk kueri yang ditanyakan dengan salah satu tag \
+         yang diberikan, dan/atau yang lebih lama dari older_than_secs, agar kueri berikutnya \
+         tentang topik itu mengambil data baru; dengan include_history=true, entri yang cocok \
+         juga dihapus dari riwayat kueri. Jawaban tanpa tag yang cocok tetap disimpan.",
+    ),
+    (
+        "perplexity_share",
+        "Membuat tautan publik ke thread Perplexity agar manusia dapat membaca jawaban lengkap \
+         dan jejak risetnya, atau mencabutnya dengan revoke=true. Menerima \
+         follow_up.backend_uuid dari jawaban sebelumnya.",
+    ),
+    (
+        "perplexity_thread",
+        "Menampilkan judul, mode, model, waktu pembuatan dan pembaruan, serta jumlah giliran \
+         sebuah thread Perplexity. Berikan title untuk mengganti nama thread terlebih dahulu. \
+         Menerima follow_up.backend_uuid dari jawaban sebelumnya.",
+    ),
+    (
+        "perplexity_bookmark",
+        "Menyematkan thread Perplexity yang penting ke bookmark akun, atau menghapusnya dengan \
+         bookmarked=false. Menerima follow_up.backend_uuid dari jawaban sebelumnya.",
+    ),
+    (
+        "perplexity_bookmarks",
+        "Menampilkan daftar thread Perplexity yang di-bookmark di akun beserta id, judul, dan \
+         waktu pembaruan terakhirnya, dari yang terbaru.",
+    ),
+    (
+        "perplexity_diff_answers",
+        "Membandingkan dua jawaban, misalnya jawaban hari ini dan minggu lalu untuk kueri yang \
+         sama. Mengembalikan klaim (kalimat) yang ditambahkan atau dihapus, jumlah yang tidak \
+         berubah, dan URL kutipan yang ditambahkan atau dihapus. Cocok untuk: memantau \
+         perubahan jawaban atas pertanyaan yang berulang.",
+    ),
+    (
+        "perplexity_generate_image",
+        "Membuat gambar dari deskripsi teks dengan Perplexity AI. Mengembalikan gambar yang \
+         dihasilkan, beserta teks balasan Perplexity jika ada.",
+    ),
+    (
+        "perplexity_fetch_source",
+        "Mengambil halaman web yang dikutip sebuah jawaban, seperti URL web_results, dan \
+         mengembalikan teksnya tanpa markup. Halaman diambil langsung, tanpa Perplexity dan \
+         tanpa cookie sesinya. Cocok untuk: membaca sumber secara utuh untuk memverifikasi \
+         klaim.",
+    ),
+    (
+        "perplexity_list_models",
+        "Menampilkan daftar model yang dapat diberikan sebagai `model` ke \
+         perplexity_code_search (mode pro) dan perplexity_reason (mode penalaran), beserta \
+         penyedianya, apakah model bernalar langkah demi langkah, dan tingkat kecepatannya \
+         (fast, standard, atau slow). Cocok untuk: memilih model yang sesuai dengan tugas.",
+    ),
+    (
+        "perplexity_about",
+        "Menampilkan versi dan commit git server ini, versi API Perplexity yang digunakannya, \
+         fitur opsionalnya, transport MCP yang dilayaninya, dan model yang dikenalnya. Cocok \
+         untuk: disertakan dalam laporan bug.",
+    ),
+    (
+        "perplexity_usage_stats",
+        "Menampilkan berapa banyak kueri yang dijawab, gagal, dan dilayani dari cache oleh \
+         server ini di setiap mode pencarian, serta perkiraan token jawabannya, sejak statistik \
+         terakhir diatur ulang. Hitungan disimpan secara lokal dan tidak pernah dikirim ke mana \
+         pun.",
+    ),
+    (
+        "perplexity_ask_async",
+        "Memulai kueri Perplexity di latar belakang dan langsung mengembalikan id job. Pantau \
+         dengan perplexity_job_status dan ambil jawabannya dengan perplexity_job_result. Mode \
+         bawaannya riset mendalam. Cocok untuk: job Deep Research panjang yang melampaui batas \
+         waktu pemanggilan alat.",
+    ),
+    (
+        "perplexity_job_status",
+        "Memeriksa status job latar belakang yang dimulai dengan perplexity_ask_async: queued, \
+         running, completed, atau failed.",
+    ),
+    (
+        "perplexity_job_result",
+        "Mengambil hasil job latar belakang yang dimulai dengan perplexity_ask_async. \
+         Mengembalikan jawaban lengkap setelah job selesai, atau status terkininya jika belum.",
+    ),
+    (
+        "perplexity_schedule_query",
+        "Mendaftarkan kueri Perplexity agar berjalan berulang sesuai jadwal cron (UTC), \
+         misalnya \"0 9 * * 1\" untuk setiap Senin pukul 09.00. Setiap eksekusi adalah job \
+         latar belakang yang id-nya dilaporkan sebagai last_job_id oleh \
+         perplexity_list_schedules; hasilnya juga disimpan di riwayat dan dikirim ke webhook \
+         bila dikonfigurasi. Mode bawaannya pro. Cocok untuk: memantau sebuah topik dari waktu \
+         ke waktu.",
+    ),
+    (
+        "perplexity_schedule_digest",
+        "Mendaftarkan ringkasan berita, seperti yang disusun perplexity_news_digest, agar \
+         berjalan berulang sesuai jadwal cron (UTC), misalnya \"@daily\" dengan window \"day\" \
+         untuk briefing harian. Setiap eksekusi adalah job latar belakang yang hasilnya berisi \
+         ringkasan sebagai jawaban; job ini dilaporkan sebagai last_job_id oleh \
+         perplexity_list_schedules dan dikirim ke webhook bila dikonfigurasi.",
+    ),
+    (
+        "perplexity_list_schedules",
+        "Menampilkan daftar kueri Perplexity berulang yang didaftarkan dengan \
+         perplexity_schedule_query, beserta waktu eksekusi berikutnya dan terakhir, serta id \
+         job eksekusi terakhir.",
+    ),
+    (
+        "perplexity_cancel_schedule",
+        "Membatalkan kueri Perplexity berulang yang didaftarkan dengan \
+         perplexity_schedule_query. Eksekusi yang sudah dimulai tetap selesai seperti biasa.",
+    ),
+    (
+        "perplexity_history_search",
+        "Mencari kueri Perplexity yang pernah dijawab dan disimpan secara lokal. Mengembalikan \
+         kueri, jawaban, kutipan, dan id thread sebelumnya, dari yang terbaru. Gunakan sebelum \
+         mengajukan pertanyaan baru untuk memakai ulang riset sebelumnya.",
+    ),
+    (
+        "perplexity_history_lookup",
+        "Memeriksa apakah sebuah topik sudah pernah diriset. Mengurutkan jawaban Perplexity \
+         yang tersimpan secara lokal berdasarkan relevansinya dengan pertanyaan dalam bahasa \
+         alami. Gunakan sebelum mengajukan pertanyaan baru agar riset tidak terulang.",
+    ),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::model::ErrorCode;
+
+    #[test]
+    fn test_indonesian_descriptions_and_errors() {
+        assert_eq!("id-ID".parse(), Ok(Locale::Indonesian));
+        assert_eq!(" EN_us ".parse(), Ok(Locale::English));
+        assert!("fr".parse::<Locale>().unwrap_err().contains("en, id"));
+
+        let id = Locale::Indonesian;
+        assert!(id.description("perplexity_search").unwrap().starts_with("Pencarian web"));
+        assert_eq!(id.description("my_template"), None);
+        assert_eq!(Locale::English.description("perplexity_search"), None);
+
+        let error =
+            failure::coded(ErrorCode::INVALID_PARAMS, "query is empty", "invalid_params");
+        let localized = id.localize_error(error.clone());
+        assert_eq!(localized.message, "Argumen tidak valid: query is empty");
+        assert_eq!(localized.data, error.data);
+        assert_eq!(Locale::English.localize_error(error.clone()).message, error.message);
+        let uncoded = McpError::internal_error("oops", None);
+        assert_eq!(id.localize_error(uncoded).message, "oops");
+    }
+}
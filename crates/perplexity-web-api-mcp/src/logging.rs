@@ -0,0 +1,218 @@
+//! Forwarding of server logs to the MCP client.
+//!
+//! Implements the MCP `logging` capability: once the client picks a level
+//! with `logging/setLevel`, log records at or above it are sent as
+//! `notifications/message`, so retries, session refreshes and rate-limit
+//! waits show up in the host's UI as well as on stderr. Nothing is sent
+//! until the client asks for it.
+//!
+//! Records are queued on a bounded channel and dropped when it is full, so a
+//! slow client never holds up the code doing the logging.
+//!
+//! [`ProblemCount`] counts the warnings and errors logged, so that
+//! `validate-config` can fail on settings that were ignored.
+
+use rmcp::model::{LoggingLevel, LoggingMessageNotificationParam};
+use rmcp::{Peer, RoleServer};
+use serde_json::{Map, Value};
+use std::fmt;
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::Layer;
+use tracing_subscriber::layer::Context;
+
+/// Records waiting to be sent to the client.
+const QUEUE_CAPACITY: usize = 256;
+
+/// Level rank meaning the client has not asked for logs.
+const OFF: u8 = u8::MAX;
+
+/// Handle shared by the tracing layer and the server.
+#[derive(Clone)]
+pub struct ClientLog {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    level: AtomicU8,
+    sender: mpsc::Sender<LoggingMessageNotificationParam>,
+    receiver: Mutex<Option<mpsc::Receiver<LoggingMessageNotificationParam>>>,
+}
+
+impl Default for ClientLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClientLog {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        Self {
+            shared: Arc::new(Shared {
+                level: AtomicU8::new(OFF),
+                sender,
+                receiver: Mutex::new(Some(receiver)),
+            }),
+        }
+    }
+
+    /// Tracing layer that queues records for the client.
+    pub fn layer(&self) -> ClientLogLayer {
+        ClientLogLayer { shared: Arc::clone(&self.shared) }
+    }
+
+    /// Sets the lowest level forwarded to the client.
+    pub fn set_level(&self, level: LoggingLevel) {
+        self.shared.level.store(rank(level), Ordering::Relaxed);
+    }
+
+    /// Starts sending queued records to `peer`. Only the first call has an
+    /// effect.
+    pub fn attach(&self, peer: Peer<RoleServer>) {
+        let receiver = match self.shared.receiver.lock() {
+            Ok(mut receiver) => receiver.take(),
+            Err(_) => None,
+        };
+        let Some(mut receiver) = receiver else {
+            return;
+        };
+        tokio::spawn(async move {
+            while let Some(message) = receiver.recv().await {
+                // Logging here would feed back into the queue; a failed send
+                // means the session is closing.
+                if peer.notify_logging_message(message).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+/// Tracing layer created by [`ClientLog::layer`].
+pub struct ClientLogLayer {
+    shared: Arc<Shared>,
+}
+
+impl<S: Subscriber> Layer<S> for ClientLogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let level = match *metadata.level() {
+            Level::ERROR => LoggingLevel::Error,
+            Level::WARN => LoggingLevel::Warning,
+            Level::INFO => LoggingLevel::Info,
+            Level::DEBUG | Level::TRACE => LoggingLevel::Debug,
+        };
+        let threshold = self.shared.level.load(Ordering::Relaxed);
+        // rmcp logs while sending notifications; forwarding those would loop.
+        if threshold == OFF || rank(level) < threshold || metadata.target().starts_with("rmcp")
+        {
+            return;
+        }
+
+        let mut fields = Fields(Map::new());
+        event.record(&mut fields);
+        if let Some(request_id) = crate::correlation::current() {
+            fields.0.insert(crate::correlation::META_KEY.to_string(), Value::from(request_id));
+        }
+
+        // A full queue means the client is not keeping up; drop the record.
+        let _ = self.shared.sender.try_send(LoggingMessageNotificationParam {
+            level,
+            logger: Some(metadata.target().to_string()),
+            data: Value::Object(fields.0),
+        });
+    }
+}
+
+/// Collects an event's message and fields into a JSON object.
+struct Fields(Map<String, Value>);
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name().to_string(), Value::from(format!("{:?}", value)));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+}
+
+/// Tracing layer counting warnings and errors, shared by all clones.
+#[derive(Clone, Default)]
+pub struct ProblemCount(Arc<AtomicUsize>);
+
+impl ProblemCount {
+    /// Number of warnings and errors logged so far.
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl<S: Subscriber> Layer<S> for ProblemCount {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if *event.metadata().level() <= Level::WARN {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Orders levels from least to most severe.
+fn rank(level: LoggingLevel) -> u8 {
+    match level {
+        LoggingLevel::Debug => 0,
+        LoggingLevel::Info => 1,
+        LoggingLevel::Notice => 2,
+        LoggingLevel::Warning => 3,
+        LoggingLevel::Error => 4,
+        LoggingLevel::Critical => 5,
+        LoggingLevel::Alert => 6,
+        LoggingLevel::Emergency => 7,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_forwards_records_at_client_level() {
+        let log = ClientLog::new();
+        let mut receiver = log.shared.receiver.lock().unwrap().take().unwrap();
+        let subscriber = tracing_subscriber::registry().with(log.layer());
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!("dropped before setLevel");
+            log.set_level(LoggingLevel::Warning);
+            tracing::info!("below the level");
+            tracing::warn!(attempt = 2, "Retrying in {}s", 5);
+            tracing::warn!(target: "rmcp::service", "not forwarded");
+        });
+
+        let message = receiver.try_recv().unwrap();
+        assert!(matches!(message.level, LoggingLevel::Warning));
+        assert_eq!(message.data["message"], "Retrying in 5s");
+        assert_eq!(message.data["attempt"], 2);
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -1,17 +1,351 @@
 //! MCP server exposing Perplexity AI tools for search, research, and reasoning.
 
+mod about;
+mod breaker;
+mod cache;
+mod claims;
+mod code;
+mod compare;
+mod condense;
+mod confidence;
+mod config;
+mod confirm;
+mod correlation;
+mod diff;
+mod digest;
+mod doctor;
+mod failure;
+mod fetch;
+mod health;
+#[cfg(feature = "store-sqlite")]
+mod history;
+mod http;
+mod jobs;
+mod locale;
+mod logging;
+mod naming;
+mod offline;
+mod openai;
+#[cfg(feature = "otel")]
+mod otel;
+mod pagecache;
+mod plan;
+#[cfg(feature = "wasm-plugins")]
+mod plugin;
+mod progress;
+mod provider;
+mod queue;
+mod report;
+mod roots;
+mod schedule;
+mod secrets;
+mod selector;
 mod server;
+mod settings;
+mod setup;
+mod signing;
+mod stdio;
+mod storage;
+#[cfg(all(feature = "systemd", target_os = "linux"))]
+mod systemd;
+mod template;
+mod toolset;
+mod usage;
+mod util;
+mod webhook;
+#[cfg(all(feature = "windows-service", windows))]
+mod winservice;
 
-use perplexity_web_api::Client;
-use rmcp::{ServiceExt, transport::stdio};
-use std::{collections::HashMap, env};
-use tracing_subscriber::{EnvFilter, fmt};
+use clap::Parser;
+use perplexity_web_api::{
+    AnswerStyle, ApiVersion, Client, DEFAULT_MAX_REDIRECTS, HeaderStrategy, HostAllowlist,
+    Pipeline, SearchMode,
+};
+use rmcp::ServiceExt;
+use std::{collections::HashMap, env, sync::Arc, time::Duration};
+use tracing_subscriber::{EnvFilter, fmt, layer::SubscriberExt, util::SubscriberInitExt};
 
+use crate::about::Transport;
+use crate::jobs::{JobManager, JobOptions};
+use crate::logging::{ClientLog, ProblemCount};
+use crate::naming::ToolNaming;
+use crate::offline::Fixtures;
+use crate::provider::HttpProvider;
+use crate::schedule::{ScheduleOptions, Scheduler};
 use crate::server::PerplexityServer;
+use crate::toolset::ToolFilter;
+use crate::webhook::Webhook;
 
-/// Reads a required environment variable or exits with an error.
+/// Search tools that need a mode other than Auto, hidden in anonymous mode
+/// along with the account tools.
+const ANONYMOUS_UNAVAILABLE_TOOLS: &[&str] =
+    &["perplexity_research", "perplexity_reason", "perplexity_code_search"];
+
+/// Command-line options. Everything else is configured through environment
+/// variables.
+#[derive(Parser)]
+#[command(version, about, group(clap::ArgGroup::new("transport").args(["http", "serve"])))]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Skip warming up the Perplexity session at startup; the first query
+    /// then pays for connection setup.
+    #[arg(long, env = "PERPLEXITY_NO_WARMUP")]
+    no_warmup: bool,
+
+    /// Answer searches with canned responses from the fixtures directory
+    /// instead of Perplexity. No tokens or network access are needed.
+    #[arg(long, env = "PERPLEXITY_OFFLINE")]
+    offline: bool,
+
+    /// Query Perplexity as an anonymous guest, without tokens. Only the Auto
+    /// mode search tool is available; guest sessions are rotated as their
+    /// free queries run out.
+    #[arg(long, env = "PERPLEXITY_ANONYMOUS", conflicts_with = "offline")]
+    anonymous: bool,
+
+    /// Directory of offline fixtures.
+    #[arg(long, env = "PERPLEXITY_FIXTURES_DIR", default_value = "fixtures")]
+    fixtures: std::path::PathBuf,
+
+    /// Serve MCP over streamable HTTP on this address, e.g. `127.0.0.1:8080`,
+    /// instead of stdio.
+    #[arg(long, env = "PERPLEXITY_HTTP_ADDR", value_name = "ADDR")]
+    http: Option<std::net::SocketAddr>,
+
+    /// Run as a container service on this address, e.g. `0.0.0.0:8080`:
+    /// HTTP mode with `/healthz` and `/readyz` probes and JSON logs on
+    /// standard output.
+    #[arg(long, env = "PERPLEXITY_SERVE_ADDR", value_name = "ADDR", conflicts_with = "http")]
+    serve: Option<std::net::SocketAddr>,
+
+    /// In HTTP mode, also serve an OpenAI-compatible `/v1/chat/completions`
+    /// endpoint.
+    #[arg(long, env = "PERPLEXITY_OPENAI_COMPAT", requires = "transport")]
+    openai_compat: bool,
+
+    /// Check the signature of a result saved as JSON in this file, print
+    /// the public key that made it, and exit.
+    #[arg(long, value_name = "FILE")]
+    verify: Option<std::path::PathBuf>,
+
+    /// Save the secret read from standard input in the OS keyring under
+    /// this variable name, e.g. PERPLEXITY_SESSION_TOKEN, and exit.
+    #[cfg(feature = "keyring")]
+    #[arg(long, value_name = "NAME")]
+    store_secret: Option<String>,
+
+    /// Move the secrets set in this `.env` style file to the OS keyring and
+    /// exit.
+    #[cfg(feature = "keyring")]
+    #[arg(long, value_name = "FILE")]
+    import_secrets: Option<std::path::PathBuf>,
+
+    /// Install a Windows service that starts automatically and runs the
+    /// server with the other options given, which must select HTTP mode,
+    /// and exit.
+    #[cfg(all(feature = "windows-service", windows))]
+    #[arg(long, requires = "transport")]
+    install_service: bool,
+
+    /// Stop and remove the Windows service, and exit.
+    #[cfg(all(feature = "windows-service", windows))]
+    #[arg(long, conflicts_with = "install_service")]
+    uninstall_service: bool,
+
+    /// Run as the Windows service. Used by the service control manager.
+    #[cfg(all(feature = "windows-service", windows))]
+    #[arg(long, hide = true, requires = "transport")]
+    run_service: bool,
+}
+
+/// Server management commands. Without one, the server runs.
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Run the server (the default).
+    Serve,
+    /// Walk through signing in, choosing the search mode and default
+    /// model, and testing a query, then write the configuration file.
+    Setup,
+    /// Read the configuration as the server would, report problems, and
+    /// exit. Fails if a setting is invalid or was ignored.
+    ValidateConfig,
+    /// Check that Perplexity accepts the session tokens, and print the
+    /// account they sign in to.
+    TestAuth,
+    /// Diagnose connection, proxy, TLS, clock, token, API version and file
+    /// permission problems, and print how to fix them.
+    Doctor,
+    /// Print the local usage statistics: queries answered, failed and
+    /// served from the cache in each search mode.
+    Stats {
+        /// Clear the statistics instead.
+        #[arg(long)]
+        reset: bool,
+    },
+    /// Print the server's tool, prompt and resource definitions, with their
+    /// input schemas, as JSON.
+    PrintToolsSchema,
+    /// Answer a query with Perplexity and save the answer as an offline
+    /// fixture in the fixtures directory.
+    RecordFixture {
+        /// The query, exactly as the offline server will be asked it.
+        query: String,
+        /// Search mode to answer it in.
+        #[arg(long, default_value = "auto")]
+        mode: SearchMode,
+    },
+}
+
+/// Runs `--install-service` or `--uninstall-service`, if given, and returns
+/// the exit code.
+#[cfg(all(feature = "windows-service", windows))]
+fn service_command(args: &Args) -> Option<i32> {
+    let result = if args.install_service {
+        winservice::install()
+    } else if args.uninstall_service {
+        winservice::uninstall()
+    } else {
+        return None;
+    };
+    match result {
+        Ok(message) => {
+            println!("{}", message);
+            Some(0)
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            Some(1)
+        }
+    }
+}
+
+/// Runs `--store-secret` or `--import-secrets`, if given, and returns the
+/// exit code.
+#[cfg(feature = "keyring")]
+fn keyring_command(args: &Args) -> Option<i32> {
+    let result = if let Some(name) = &args.store_secret {
+        let mut value = String::new();
+        std::io::stdin()
+            .read_line(&mut value)
+            .map_err(|e| e.to_string())
+            .and_then(|_| secrets::store(name, value.trim_end_matches(['\r', '\n'])))
+            .map(|()| format!("Stored {} in the keyring", name))
+    } else if let Some(path) = &args.import_secrets {
+        secrets::import(path).map(|names| {
+            if names.is_empty() {
+                format!("{} sets no secrets", path.display())
+            } else {
+                format!("Moved {} from {} to the keyring", names.join(", "), path.display())
+            }
+        })
+    } else {
+        return None;
+    };
+    match result {
+        Ok(message) => {
+            println!("{}", message);
+            Some(0)
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            Some(1)
+        }
+    }
+}
+
+/// Checks the session tokens, for `test-auth`.
+async fn test_auth(client: &Client) -> Result<(), Box<dyn std::error::Error>> {
+    match client.session().await? {
+        Some(session) => {
+            let account = session.email.or(session.name).unwrap_or_else(|| "unknown".into());
+            match session.expires {
+                Some(expires) => println!("Signed in as {} until {}", account, expires),
+                None => println!("Signed in as {}", account),
+            }
+            Ok(())
+        }
+        None => Err("Perplexity did not accept the session tokens; copy fresh cookies".into()),
+    }
+}
+
+/// Answers `query` and saves the final stream event as its fixture, for
+/// `record-fixture`.
+async fn record_fixture(
+    client: &Client,
+    fixtures: &Fixtures,
+    query: &str,
+    mode: SearchMode,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use futures_util::StreamExt;
+
+    let mut events = std::pin::pin!(
+        client.search_stream(perplexity_web_api::SearchRequest::new(query).mode(mode)).await?
+    );
+    let mut last = None;
+    while let Some(event) = events.next().await {
+        last = Some(event?);
+    }
+    let event = last.ok_or("the search returned no answer")?;
+
+    let path = fixtures.path(query);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, serde_json::to_vec_pretty(&event)?)?;
+    println!("Saved {}", path.display());
+    Ok(())
+}
+
+/// Prints or clears the usage statistics, for `stats`.
+fn usage_command(
+    usage: &usage::UsageStats,
+    reset: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let location = usage.path().map_or("memory".into(), |path| path.display().to_string());
+    if reset {
+        usage.reset()?;
+        println!("Cleared the usage statistics in {}", location);
+        return Ok(());
+    }
+
+    let summary = usage.summary()?;
+    let Some(since) = &summary.since else {
+        println!("No queries counted yet ({})", location);
+        return Ok(());
+    };
+    println!("Usage since {} ({})", since, location);
+    println!();
+    println!(
+        "{:<15} {:>9} {:>9} {:>9} {:>14}",
+        "mode", "answered", "failed", "cached", "answer tokens"
+    );
+    let total = ("total".to_string(), summary.total);
+    for (mode, counts) in summary.modes.into_iter().chain([total]) {
+        println!(
+            "{:<15} {:>9} {:>9} {:>9} {:>14}",
+            mode, counts.answered, counts.failed, counts.cached, counts.answer_tokens
+        );
+    }
+    if let Some(last_used) = summary.last_used {
+        println!();
+        println!("Last query at {}", last_used);
+    }
+    Ok(())
+}
+
+/// Checks the signature of the signed result in `path`, for `--verify`.
+fn verify_file(path: &std::path::Path) -> Result<String, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let record = serde_json::from_str(&text).map_err(|e| format!("not JSON: {}", e))?;
+    signing::verify(&record)
+}
+
+/// Reads a required secret from the environment or the keyring, or exits
+/// with an error.
 fn require_env(name: &str) -> String {
-    env::var(name).unwrap_or_else(|_| {
+    secrets::secret(name).unwrap_or_else(|| {
         eprintln!("Error: Required environment variable {} is not set.", name);
         eprintln!();
         eprintln!("Usage:");
@@ -26,46 +360,821 @@ fn require_env(name: &str) -> String {
     })
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize tracing (logs to stderr to not interfere with stdio transport)
-    fmt()
-        .with_env_filter(
-            EnvFilter::from_default_env().add_directive(tracing::Level::INFO.into()),
-        )
-        .with_writer(std::io::stderr)
-        .with_ansi(false)
+/// Reads an optional numeric environment variable, ignoring unparsable values.
+fn env_usize(name: &str) -> Option<usize> {
+    let value = env::var(name).ok()?;
+    value.parse().map_err(|_| tracing::warn!("Ignoring invalid {}={}", name, value)).ok()
+}
+
+/// Reads an optional boolean environment variable ("1"/"true" or "0"/"false").
+fn env_flag(name: &str) -> bool {
+    match env::var(name).as_deref() {
+        Ok("1" | "true" | "TRUE" | "True") => true,
+        Ok("0" | "false" | "FALSE" | "False" | "") | Err(_) => false,
+        Ok(value) => {
+            tracing::warn!("Ignoring invalid {}={}", name, value);
+            false
+        }
+    }
+}
+
+/// Creates the HTTP client of the endpoint `url`, set in `name`, which must
+/// be on one of `hosts`. Redirects are followed only to `hosts` too.
+fn endpoint_client(
+    name: &str,
+    url: &str,
+    hosts: &HostAllowlist,
+    max_redirects: usize,
+) -> Result<rquest::Client, Box<dyn std::error::Error>> {
+    hosts.check(url).map_err(|e| {
+        eprintln!("Invalid {}: {}", name, e);
+        e
+    })?;
+    Ok(rquest::Client::builder()
+        .redirect(hosts.redirect_policy(max_redirects))
+        .dns_resolver(hosts.resolver())
+        .build()?)
+}
+
+/// Parses `PERPLEXITY_USER_AGENT`: `rotate` for the built-in user agents, or
+/// one or more `|`-separated user agent strings.
+fn header_strategy(value: &str) -> HeaderStrategy {
+    if value.trim().eq_ignore_ascii_case("rotate") {
+        return HeaderStrategy::rotating();
+    }
+    HeaderStrategy::user_agents(value.split('|').map(str::trim).filter(|ua| !ua.is_empty()))
+}
+
+/// Parses `PERPLEXITY_ANSWER_STYLES`: either a single style applied to every
+/// mode, or comma-separated `mode=style` pairs. Invalid entries are skipped.
+fn answer_styles(value: &str) -> Vec<(SearchMode, AnswerStyle)> {
+    let mut styles = Vec::new();
+
+    for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let parsed = match entry.split_once('=') {
+            Some((mode, style)) => mode
+                .trim()
+                .parse::<SearchMode>()
+                .and_then(|mode| Ok(vec![(mode, style.trim().parse()?)])),
+            None => entry
+                .parse::<AnswerStyle>()
+                .map(|style| SearchMode::ALL.iter().map(|&mode| (mode, style)).collect()),
+        };
+        match parsed {
+            Ok(parsed) => styles.extend(parsed),
+            Err(e) => tracing::warn!("Ignoring answer style '{}': {}", entry, e),
+        }
+    }
+
+    styles
+}
+
+/// Parses `PERPLEXITY_PIPELINE`, adding `wasm:<path>` steps for plugins
+/// when built with the `wasm-plugins` feature.
+/// Whether `name` looks like an IANA time zone name such as "Europe/Berlin"
+/// or "UTC". Whether the zone exists is left to Perplexity.
+fn is_timezone_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('/')
+        && !name.ends_with('/')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || "/_-+".contains(c))
+}
+
+fn parse_pipeline(spec: &str) -> perplexity_web_api::Result<Pipeline> {
+    #[cfg(feature = "wasm-plugins")]
+    return Pipeline::parse_with(spec, |name, arg| {
+        (name == "wasm").then(|| {
+            let invalid = |reason: String| perplexity_web_api::Error::InvalidPipelineStep {
+                step: format!("wasm:{}", arg.unwrap_or_default()),
+                reason,
+            };
+            let path = arg
+                .filter(|path| !path.is_empty())
+                .ok_or_else(|| invalid("needs the path of a plugin after ':'".to_string()))?;
+            let plugin =
+                plugin::WasmProcessor::load(std::path::Path::new(path)).map_err(invalid)?;
+            Ok(Box::new(plugin) as Box<dyn perplexity_web_api::Processor>)
+        })
+    });
+    #[cfg(not(feature = "wasm-plugins"))]
+    spec.parse()
+}
+
+/// Reads `PERPLEXITY_TOOLS_ENABLED` and `PERPLEXITY_TOOLS_DISABLED`, each a
+/// comma-separated list of tool names.
+fn tool_filter() -> ToolFilter {
+    let list = |value: String| -> Vec<String> {
+        value.split(',').map(str::trim).filter(|t| !t.is_empty()).map(String::from).collect()
+    };
+    let mut filter = ToolFilter::default();
+    if let Ok(enabled) = env::var("PERPLEXITY_TOOLS_ENABLED") {
+        filter = filter.allow(list(enabled));
+    }
+    if let Ok(disabled) = env::var("PERPLEXITY_TOOLS_DISABLED") {
+        filter = filter.deny(list(disabled));
+    }
+    filter
+}
+
+/// Reads `PERPLEXITY_TOOL_PREFIX` and `PERPLEXITY_TOOL_NAMES`, the latter
+/// holding comma-separated `tool=name` pairs. Malformed pairs are skipped.
+fn tool_naming() -> ToolNaming {
+    let mut naming = ToolNaming::default();
+    if let Ok(prefix) = env::var("PERPLEXITY_TOOL_PREFIX") {
+        naming = naming.prefix(prefix.trim());
+    }
+    let names = env::var("PERPLEXITY_TOOL_NAMES").unwrap_or_default();
+    for entry in names.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        match entry.split_once('=') {
+            Some((tool, name)) => naming = naming.rename(tool.trim(), name.trim()),
+            None => tracing::warn!("Ignoring tool name '{}': expected tool=name", entry),
+        }
+    }
+    naming
+}
+
+/// Completes on Ctrl-C, on SIGTERM where available, or when the Windows
+/// service is stopped.
+async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::warn!("Cannot listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                tracing::warn!("Cannot listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    #[cfg(all(feature = "windows-service", windows))]
+    let service_stop = winservice::stop_requested();
+    #[cfg(not(all(feature = "windows-service", windows)))]
+    let service_stop = std::future::pending::<()>();
+
+    tokio::select! {
+        () = interrupt => {}
+        () = terminate => {}
+        () = service_stop => {}
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // The configuration file fills in the environment, so it must be read
+    // before the runtime starts its threads.
+    let config = config::load();
+    tokio::runtime::Builder::new_multi_thread().enable_all().build()?.block_on(run(config))
+}
+
+async fn run(
+    config: std::io::Result<Option<config::ConfigFile>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    #[cfg(feature = "keyring")]
+    if let Some(code) = keyring_command(&args) {
+        std::process::exit(code);
+    }
+    #[cfg(all(feature = "windows-service", windows))]
+    if let Some(code) = service_command(&args) {
+        std::process::exit(code);
+    }
+    if let Some(path) = &args.verify {
+        match verify_file(path) {
+            Ok(public_key) => {
+                println!("Valid signature by {}", public_key);
+                return Ok(());
+            }
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+    }
+    let header_strategy = env::var("PERPLEXITY_USER_AGENT")
+        .map(|value| header_strategy(&value))
+        .unwrap_or_default();
+    if let Some(Command::Setup) = args.command {
+        return setup::run(&config::path(), header_strategy).await;
+    }
+    #[cfg(feature = "otel")]
+    let (telemetry, otel_layer) = otel::Telemetry::init().map_err(|e| {
+        eprintln!("Failed to set up OpenTelemetry export: {}", e);
+        e
+    })?;
+    #[cfg(not(feature = "otel"))]
+    let otel_layer: Option<tracing_subscriber::layer::Identity> = None;
+    #[cfg(all(feature = "windows-service", windows))]
+    let event_log = if args.run_service { winservice::EventLogLayer::new() } else { None };
+    #[cfg(not(all(feature = "windows-service", windows)))]
+    let event_log: Option<tracing_subscriber::layer::Identity> = None;
+
+    // Initialize tracing (logs to stderr to not interfere with stdio transport,
+    // or as JSON lines to stdout for container log collectors, and to the MCP
+    // client once it sets a level)
+    let client_log = ClientLog::new();
+    let problems = ProblemCount::default();
+    let json_logs = args.serve.is_some();
+    tracing_subscriber::registry()
+        .with(EnvFilter::from_default_env().add_directive(tracing::Level::INFO.into()))
+        .with((!json_logs).then(|| fmt::layer().with_writer(std::io::stderr).with_ansi(false)))
+        .with(json_logs.then(|| fmt::layer().json().with_writer(std::io::stdout)))
+        .with(client_log.layer())
+        .with(problems.clone())
+        .with(otel_layer)
+        .with(event_log)
         .init();
 
-    // Read required environment variables
-    let session_token = require_env("PERPLEXITY_SESSION_TOKEN");
-    let csrf_token = require_env("PERPLEXITY_CSRF_TOKEN");
+    match &config {
+        Ok(Some(file)) => tracing::info!("Read configuration from {}", file.path.display()),
+        Ok(None) => {}
+        Err(e) => tracing::warn!("Cannot read {}: {}", config::path().display(), e),
+    }
 
-    tracing::info!("Starting Perplexity MCP server");
+    let storage_key = storage::StorageKey::from_env().map_err(|e| {
+        eprintln!("Invalid storage key: {}", e);
+        e
+    })?;
+    let usage = match env::var_os("PERPLEXITY_STATS_FILE") {
+        Some(path) if path.is_empty() => None,
+        Some(path) => Some(path.into()),
+        None => Some(usage::UsageStats::default_path()),
+    };
+    let usage = match usage::UsageStats::open(usage, storage_key.clone()) {
+        Ok(usage) => usage,
+        Err(e) if matches!(args.command, Some(Command::Stats { .. })) => {
+            return Err(format!("Cannot read usage statistics: {}", e).into());
+        }
+        Err(e) => {
+            tracing::warn!("Cannot read usage statistics, counting in memory only: {}", e);
+            usage::UsageStats::default()
+        }
+    };
+    if let Some(Command::Stats { reset }) = args.command {
+        return usage_command(&usage, reset);
+    }
 
-    // Map env vars to Perplexity cookie names
+    #[cfg(all(feature = "windows-service", windows))]
+    if args.run_service {
+        winservice::start().map_err(|e| {
+            tracing::error!("Failed to start the service: {}", e);
+            e
+        })?;
+    }
+
+    // Read required environment variables, map them to Perplexity cookie names
     let mut cookies = HashMap::new();
-    cookies.insert("next-auth.session-token".to_string(), session_token);
-    cookies.insert("next-auth.csrf-token".to_string(), csrf_token);
+    if !args.offline && !args.anonymous {
+        for (name, cookie) in [
+            ("PERPLEXITY_SESSION_TOKEN", "next-auth.session-token"),
+            ("PERPLEXITY_CSRF_TOKEN", "next-auth.csrf-token"),
+        ] {
+            let token = match args.command {
+                Some(Command::PrintToolsSchema) => None,
+                // The doctor reports missing tokens itself.
+                Some(Command::Doctor) => secrets::secret(name),
+                _ => Some(require_env(name)),
+            };
+            if let Some(token) = token {
+                cookies.insert(cookie.to_string(), token);
+            }
+        }
+    }
+    let signed_in = cookies.len() == 2;
+
+    tracing::info!("Starting Perplexity MCP server");
+
+    // Hosts the server may contact besides Perplexity's own. Once any are
+    // listed, source fetches, the webhook and the fallback provider are held
+    // to them too; otherwise those may reach any public host, but nothing on
+    // the local network. `*` lifts both limits.
+    let extra_hosts = env::var("PERPLEXITY_ALLOWED_HOSTS").ok().map(|value| {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|host| !host.is_empty())
+            .map(String::from)
+            .collect::<Vec<_>>()
+    });
+    let allowed_hosts = HostAllowlist::perplexity().with_hosts(extra_hosts.iter().flatten());
+    let endpoint_hosts =
+        if extra_hosts.is_some() { allowed_hosts.clone() } else { HostAllowlist::public() };
+    let max_redirects = env_usize("PERPLEXITY_MAX_REDIRECTS").unwrap_or(DEFAULT_MAX_REDIRECTS);
 
     // Build the Perplexity client with authentication
-    let client = Client::builder().cookies(cookies).build().await.map_err(|e| {
+    let client = Client::builder()
+        .cookies(cookies)
+        .allowed_hosts(allowed_hosts)
+        .max_redirects(max_redirects)
+        .header_strategy(header_strategy)
+        .warm_up(false)
+        .detect_refusals(env_flag("PERPLEXITY_DETECT_REFUSALS"))
+        .anonymous(args.anonymous);
+    let client = match env_usize("PERPLEXITY_ANONYMOUS_SESSION_QUERIES") {
+        Some(count) => client.anonymous_session_queries(count),
+        None => client,
+    };
+    let client = match env::var("PERPLEXITY_API_VERSION") {
+        Ok(version) if version.trim().eq_ignore_ascii_case("auto") => {
+            client.detect_api_version(!args.offline)
+        }
+        Ok(version) => client.api_version(version.parse::<ApiVersion>().map_err(|e| {
+            eprintln!("Invalid PERPLEXITY_API_VERSION: {}", e);
+            e
+        })?),
+        Err(_) => client,
+    };
+    let client = match env_usize("PERPLEXITY_MAX_EVENT_BYTES") {
+        Some(bytes) => client.max_event_size(bytes),
+        None => client,
+    };
+    let client = match env_usize("PERPLEXITY_MAX_STREAM_BYTES") {
+        Some(bytes) => client.max_stream_size(bytes),
+        None => client,
+    };
+    let client = client.build().await.map_err(|e| {
         eprintln!("Failed to create Perplexity client: {}", e);
         e
     })?;
 
-    tracing::info!("Perplexity client initialized");
+    tracing::info!("Perplexity client initialized, API version {}", client.api_version());
+
+    match &args.command {
+        Some(Command::TestAuth) if args.offline || args.anonymous => {
+            return Err(
+                "test-auth checks the session tokens; drop --offline and --anonymous".into()
+            );
+        }
+        Some(Command::TestAuth) => return test_auth(&client).await,
+        Some(Command::Doctor) if args.offline => {
+            return Err("doctor checks the connection to Perplexity; drop --offline".into());
+        }
+        Some(Command::Doctor) => {
+            let problems = doctor::run(&client, args.anonymous, signed_in).await;
+            if problems > 0 {
+                eprintln!("Found {} problem(s)", problems);
+                std::process::exit(1);
+            }
+            println!("No problems found");
+            return Ok(());
+        }
+        Some(Command::RecordFixture { .. }) if args.offline => {
+            return Err("record-fixture asks Perplexity; drop --offline".into());
+        }
+        Some(Command::RecordFixture { query, mode }) => {
+            return record_fixture(&client, &Fixtures::new(&args.fixtures), query, *mode)
+                .await;
+        }
+        _ => {}
+    }
 
     // Create and start the MCP server
-    let server = PerplexityServer::new(client);
+    let server = PerplexityServer::new(client).with_client_log(client_log);
+    let server = server.with_config(settings::EffectiveConfig::capture(
+        config.as_ref().ok().and_then(Option::as_ref),
+    ));
+    let server = env::var("PERPLEXITY_ANSWER_STYLES")
+        .map(|value| answer_styles(&value))
+        .unwrap_or_default()
+        .into_iter()
+        .fold(server, |server, (mode, style)| server.with_answer_style(mode, style));
+    let redact_snippets = env_flag("PERPLEXITY_REDACT_SNIPPETS");
+    let server = if redact_snippets {
+        tracing::info!("Omitting snippets from stored results");
+        server.with_redacted_snippets()
+    } else {
+        server
+    };
+
+    let webhook = match env::var("PERPLEXITY_WEBHOOK_URL") {
+        Ok(url) => {
+            let http = endpoint_client(
+                "PERPLEXITY_WEBHOOK_URL",
+                &url,
+                &endpoint_hosts,
+                max_redirects,
+            )?;
+            let secret = secrets::secret("PERPLEXITY_WEBHOOK_SECRET");
+            if secret.is_none() {
+                tracing::warn!(
+                    "PERPLEXITY_WEBHOOK_SECRET is not set; webhooks will be unsigned"
+                );
+            }
+            Some(Webhook::new(url, secret, http))
+        }
+        Err(_) => None,
+    };
+    let signer = match secrets::secret("PERPLEXITY_SIGNING_KEY") {
+        Some(key) => {
+            let signer = signing::ResultSigner::from_hex(&key).map_err(|e| {
+                eprintln!("Invalid PERPLEXITY_SIGNING_KEY: {}", e);
+                e
+            })?;
+            tracing::info!("Signing results with public key {}", signer.public_key());
+            Some(signer)
+        }
+        None => None,
+    };
+    if storage_key.is_some() {
+        tracing::info!("Encrypting persisted state at rest");
+    }
+    let server = match &signer {
+        Some(signer) => server.with_signer(signer.clone()),
+        None => server,
+    };
+
+    let defaults = JobOptions::default();
+    let jobs = JobManager::load(JobOptions {
+        webhook,
+        capacity: env_usize("PERPLEXITY_JOBS_MAX").unwrap_or(defaults.capacity),
+        concurrency: env_usize("PERPLEXITY_JOBS_CONCURRENCY").unwrap_or(defaults.concurrency),
+        retention: defaults.retention,
+        state_file: env::var_os("PERPLEXITY_JOBS_FILE").map(Into::into),
+        redact_snippets,
+        signer: signer.clone(),
+        storage_key: storage_key.clone(),
+    })
+    .map_err(|e| {
+        eprintln!("Failed to load job state: {}", e);
+        e
+    })?;
+    let server = server.with_jobs(jobs);
+    let server = match env_usize("PERPLEXITY_CACHE_MAX") {
+        Some(capacity) => server.with_cache_capacity(capacity),
+        None => server,
+    };
+    let server = match env_usize("PERPLEXITY_MAX_CONCURRENT_QUERIES") {
+        Some(limit) if limit > 0 => server.with_query_limit(limit),
+        _ => server,
+    };
+    let server = match env_usize("PERPLEXITY_HARD_DEADLINE_SECS") {
+        Some(secs) => server.with_hard_deadline(Duration::from_secs(secs as u64)),
+        None => server,
+    };
+    let server = match env::var("PERPLEXITY_PIPELINE") {
+        Ok(spec) => {
+            let pipeline = parse_pipeline(&spec).map_err(|e| {
+                eprintln!("Invalid PERPLEXITY_PIPELINE: {}", e);
+                e
+            })?;
+            tracing::info!("Post-processing answers with {:?}", pipeline);
+            server.with_pipeline(pipeline)
+        }
+        Err(_) => server,
+    };
+    let server = if env_flag("PERPLEXITY_CONFIRM_RESEARCH") {
+        server.with_research_confirmation()
+    } else {
+        server
+    };
+    let server = server.with_fetch_limits(endpoint_hosts.clone(), max_redirects);
+    let server = if env_flag("PERPLEXITY_FETCH_RESPECT_ROBOTS") {
+        server.with_robots_txt()
+    } else {
+        server
+    };
+    let server = match env::var_os("PERPLEXITY_FETCH_CACHE_DIR") {
+        Some(dir) => {
+            let max_bytes = env_usize("PERPLEXITY_FETCH_CACHE_MAX_BYTES")
+                .map_or(pagecache::DEFAULT_MAX_BYTES, |max| max as u64);
+            let cache = pagecache::PageCache::open(&dir, max_bytes, storage_key.clone())
+                .map_err(|e| {
+                    eprintln!(
+                        "Cannot open PERPLEXITY_FETCH_CACHE_DIR {}: {}",
+                        dir.display(),
+                        e
+                    );
+                    e
+                })?;
+            server.with_fetch_cache(cache)
+        }
+        None => server,
+    };
+    let server = if env_flag("PERPLEXITY_INCLUDE_REASONING") {
+        server.with_reasoning_output()
+    } else {
+        server
+    };
+    let server = match env_usize("PERPLEXITY_MAX_SOURCES") {
+        Some(max) => server.with_max_sources(max),
+        None => server,
+    };
+    let server = server.with_usage_stats(usage);
+    let server = match env::var_os("PERPLEXITY_OUTPUT_DIR") {
+        Some(dir) if !std::path::Path::new(&dir).is_dir() => {
+            eprintln!("PERPLEXITY_OUTPUT_DIR {} is not a directory", dir.display());
+            return Err("invalid PERPLEXITY_OUTPUT_DIR".into());
+        }
+        Some(dir) => server.with_output_dir(dir.into()),
+        None => server,
+    };
+    let server = match env::var("PERPLEXITY_TIMEZONE") {
+        Ok(tz) if is_timezone_name(tz.trim()) => server.with_timezone(tz.trim().to_string()),
+        Ok(tz) => {
+            tracing::warn!("Ignoring PERPLEXITY_TIMEZONE {:?}: not a time zone name", tz);
+            server
+        }
+        Err(_) => server,
+    };
+    let server = match env::var("PERPLEXITY_SEARCH_MODE").map(|mode| mode.parse()) {
+        Ok(Ok(mode @ SearchMode::Auto)) => server.with_search_mode(mode),
+        Ok(Ok(mode @ SearchMode::Pro)) if !args.anonymous => server.with_search_mode(mode),
+        Ok(Ok(SearchMode::Pro)) => {
+            tracing::warn!(
+                "Ignoring PERPLEXITY_SEARCH_MODE=pro: guests only search in Auto mode"
+            );
+            server
+        }
+        Ok(_) => {
+            tracing::warn!("Ignoring PERPLEXITY_SEARCH_MODE: expected auto or pro");
+            server
+        }
+        Err(_) => server,
+    };
+    let server = match env::var("PERPLEXITY_DEFAULT_MODEL") {
+        Ok(model) if model.trim().is_empty() => server,
+        Ok(model)
+            if selector::Selector::parse(&model).is_some()
+                || model.trim().parse::<perplexity_web_api::Model>().is_ok() =>
+        {
+            server.with_default_model(model.trim().to_string())
+        }
+        Ok(model) => {
+            tracing::warn!("Ignoring PERPLEXITY_DEFAULT_MODEL {:?}: not a model name", model);
+            server
+        }
+        Err(_) => server,
+    };
+    let server = match env_usize("PERPLEXITY_HEARTBEAT_SECS").filter(|&secs| secs > 0) {
+        Some(secs) => server.with_heartbeat(Duration::from_secs(secs as u64)),
+        None => server,
+    };
+    let server = match env_usize("PERPLEXITY_RESEARCH_MAX_CHARS") {
+        Some(max_chars) => server.with_research_max_chars(max_chars),
+        None => server,
+    };
 
-    let service = server.serve(stdio()).await.inspect_err(|e| {
+    #[cfg(feature = "store-sqlite")]
+    let server = match env::var_os("PERPLEXITY_HISTORY_DB") {
+        Some(path) => {
+            let store = match &storage_key {
+                Some(key) => history::HistoryStore::open_encrypted(&path, key.clone()),
+                None => history::HistoryStore::open(&path),
+            };
+            let store = store.map_err(|e| {
+                eprintln!("Failed to open history database: {}", e);
+                e
+            })?;
+            tracing::info!("Recording query history to {}", path.to_string_lossy());
+            let store = match signer {
+                Some(signer) => store.with_signer(signer),
+                None => store,
+            };
+            server.with_history(store)
+        }
+        None => server,
+    };
+
+    let server = match env::var_os("PERPLEXITY_SCHEDULES_FILE") {
+        Some(path) => {
+            let scheduler = Scheduler::load(ScheduleOptions {
+                capacity: env_usize("PERPLEXITY_SCHEDULES_MAX")
+                    .unwrap_or(ScheduleOptions::default().capacity),
+                state_file: Some(path.into()),
+                storage_key: storage_key.clone(),
+            })
+            .map_err(|e| {
+                eprintln!("Failed to load schedules: {}", e);
+                e
+            })?;
+            server.with_scheduler(scheduler)
+        }
+        None => server,
+    };
+
+    let templates_file =
+        env::var_os("PERPLEXITY_TEMPLATES_FILE").map(std::path::PathBuf::from).or_else(|| {
+            Some(config::path().with_file_name("templates.json")).filter(|p| p.exists())
+        });
+    let server = match templates_file {
+        Some(path) => {
+            let mut templates = template::load(&path).map_err(|e| {
+                eprintln!("Failed to load templates from {}: {}", path.display(), e);
+                e
+            })?;
+            if args.anonymous {
+                templates.retain(|(template, mode)| {
+                    let auto = *mode == SearchMode::Auto;
+                    if !auto {
+                        tracing::warn!(
+                            "Skipping template {}: anonymous mode only supports Auto",
+                            template.name
+                        );
+                    }
+                    auto
+                });
+            }
+            tracing::info!(
+                "Loaded {} query template(s) from {}",
+                templates.len(),
+                path.display()
+            );
+            server.with_templates(templates).map_err(|e| {
+                eprintln!("Invalid templates: {}", e);
+                e
+            })?
+        }
+        None => server,
+    };
+
+    let (server, filter) = if args.offline {
+        tracing::info!("Offline mode: answering from fixtures in {}", args.fixtures.display());
+        (
+            server.with_provider(Arc::new(Fixtures::new(&args.fixtures))),
+            tool_filter().deny(offline::ONLINE_TOOLS.iter().copied()),
+        )
+    } else if args.anonymous {
+        tracing::info!("Anonymous mode: querying as a guest, Auto mode only");
+        let account_tools = ANONYMOUS_UNAVAILABLE_TOOLS.iter().chain(offline::ONLINE_TOOLS);
+        (server, tool_filter().deny(account_tools.copied()))
+    } else {
+        (server, tool_filter())
+    };
+    let server = match env_usize("PERPLEXITY_BREAKER_FAILURES") {
+        Some(0) => server,
+        failures => server.with_circuit_breaker(
+            failures
+                .map_or(breaker::DEFAULT_FAILURES, |n| u32::try_from(n).unwrap_or(u32::MAX)),
+            env_usize("PERPLEXITY_BREAKER_COOLDOWN_SECS")
+                .map_or(breaker::DEFAULT_COOLDOWN, |secs| Duration::from_secs(secs as u64)),
+            env_flag("PERPLEXITY_BREAKER_SERVE_CACHED"),
+        ),
+    };
+    let server = match env::var("PERPLEXITY_FALLBACK_URL") {
+        Ok(url) => {
+            tracing::info!("Falling back to {} while Perplexity is unavailable", url);
+            let http = endpoint_client(
+                "PERPLEXITY_FALLBACK_URL",
+                &url,
+                &endpoint_hosts,
+                max_redirects,
+            )?;
+            let api_key = secrets::secret("PERPLEXITY_FALLBACK_API_KEY");
+            server.with_fallback(Arc::new(HttpProvider::new(url, api_key, http)))
+        }
+        Err(_) => server,
+    };
+    let server = server.with_tool_filter(&filter).map_err(|e| {
+        eprintln!("Invalid tool selection: {}", e);
+        e
+    })?;
+    let server = match env::var("PERPLEXITY_LOCALE") {
+        Ok(locale) => server.with_locale(locale.parse().map_err(|e| {
+            eprintln!("Invalid PERPLEXITY_LOCALE: {}", e);
+            e
+        })?),
+        Err(_) => server,
+    };
+    let server = server.with_tool_naming(&tool_naming()).map_err(|e| {
+        eprintln!("Invalid tool names: {}", e);
+        e
+    })?;
+
+    match args.command {
+        Some(Command::ValidateConfig) => {
+            let count = problems.get();
+            if count > 0 {
+                eprintln!("Found {} configuration problem(s); see the warnings above", count);
+                std::process::exit(1);
+            }
+            println!("Configuration is valid");
+            return Ok(());
+        }
+        Some(Command::PrintToolsSchema) => {
+            println!("{}", serde_json::to_string_pretty(&server.definitions())?);
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let warm_up = (!args.no_warmup && !args.offline).then(|| server.warm_up());
+    server.resume_jobs();
+    server.start_scheduler();
+
+    // A socket passed by systemd takes the place of `--http` or `--serve`.
+    #[cfg(all(feature = "systemd", target_os = "linux"))]
+    let inherited = systemd::take_listener().map_err(|e| {
+        eprintln!("Failed to take the socket passed by systemd: {}", e);
+        e
+    })?;
+    #[cfg(not(all(feature = "systemd", target_os = "linux")))]
+    let inherited: Option<std::net::TcpListener> = None;
+    let listener = match (inherited, args.http.or(args.serve)) {
+        (Some(listener), _) => {
+            tracing::info!("Using the listening socket passed by systemd");
+            Some(tokio::net::TcpListener::from_std(listener)?)
+        }
+        (None, Some(addr)) => {
+            Some(tokio::net::TcpListener::bind(addr).await.map_err(|e| {
+                eprintln!("Failed to listen on {}: {}", addr, e);
+                e
+            })?)
+        }
+        (None, None) => None,
+    };
+    let server = server.with_transport(match (&listener, args.serve) {
+        (None, _) => Transport::Stdio,
+        (Some(_), Some(_)) => Transport::Container,
+        (Some(_), None) => Transport::Http,
+    });
+
+    if let Some(listener) = listener {
+        let api_key = secrets::secret("PERPLEXITY_HTTP_API_KEY");
+        let addr = listener.local_addr()?;
+        if api_key.is_none() && !addr.ip().is_loopback() {
+            return Err(format!(
+                "Listening on {} requires PERPLEXITY_HTTP_API_KEY, since anyone who can \
+                 reach it could use the Perplexity account",
+                addr
+            )
+            .into());
+        }
+        let health = health::Health::default();
+        let routes = if args.openai_compat {
+            let api_key = secrets::secret("PERPLEXITY_OPENAI_API_KEY");
+            if api_key.is_none() {
+                tracing::warn!(
+                    "PERPLEXITY_OPENAI_API_KEY is not set; /v1/chat/completions is open"
+                );
+            }
+            openai::router(server.clone(), api_key)
+        } else {
+            axum::Router::new()
+        };
+        let routes = if args.serve.is_some() {
+            let started = health.clone();
+            tokio::spawn(async move {
+                if let Some(warm_up) = warm_up {
+                    let _ = warm_up.await;
+                }
+                started.set_ready();
+            });
+            routes.merge(health::router(health.clone()))
+        } else {
+            routes
+        };
+        #[cfg(all(feature = "systemd", target_os = "linux"))]
+        systemd::ready("Serving MCP over HTTP");
+        let result = http::serve(server, listener, api_key, routes, async move {
+            shutdown_signal().await;
+            tracing::info!("Shutdown signal received");
+            health.set_draining();
+            #[cfg(all(feature = "systemd", target_os = "linux"))]
+            systemd::stopping();
+            #[cfg(all(feature = "windows-service", windows))]
+            winservice::stopping();
+        })
+        .await
+        .inspect_err(|e| tracing::error!("Server error: {}", e));
+
+        #[cfg(feature = "otel")]
+        telemetry.shutdown();
+        #[cfg(all(feature = "windows-service", windows))]
+        winservice::stopped();
+
+        result?;
+        return Ok(());
+    }
+
+    let service = server.serve(stdio::stdio()).await.inspect_err(|e| {
         tracing::error!("Server error: {:?}", e);
     })?;
 
     tracing::info!("MCP server running on stdio");
 
-    service.waiting().await?;
+    // Runs until stdin reaches EOF, or until interrupted or terminated.
+    let cancel = service.cancellation_token();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        tracing::info!("Shutdown signal received");
+        cancel.cancel();
+    });
+    let result = service.waiting().await;
+
+    #[cfg(feature = "otel")]
+    telemetry.shutdown();
 
+    result?;
     Ok(())
 }
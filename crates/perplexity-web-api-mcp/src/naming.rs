@@ -0,0 +1,202 @@
+//! Renaming of the exposed tools.
+//!
+//! Hosts that load several search servers can end up with clashing tool
+//! names. A [`ToolNaming`] swaps the `perplexity_` prefix for another one
+//! and renames individual tools; the new names are also substituted in tool
+//! descriptions and parameter docs, so cross-references such as "poll with
+//! `perplexity_job_status`" stay correct.
+
+use rmcp::model::{JsonObject, Tool};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Prefix of the built-in tool names.
+pub const DEFAULT_PREFIX: &str = "perplexity_";
+
+/// Longest tool name accepted by MCP hosts.
+const MAX_NAME_LENGTH: usize = 128;
+
+/// Requested tool names.
+#[derive(Debug, Clone, Default)]
+pub struct ToolNaming {
+    prefix: Option<String>,
+    renames: Vec<(String, String)>,
+}
+
+impl ToolNaming {
+    /// Replaces the `perplexity_` prefix of every tool with `prefix`, which
+    /// may be empty.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Exposes the tool `tool` (by its default name) as `name`. Takes
+    /// precedence over the prefix.
+    pub fn rename(mut self, tool: impl Into<String>, name: impl Into<String>) -> Self {
+        self.renames.push((tool.into(), name.into()));
+        self
+    }
+
+    /// Resolves the exposed names of `tools`, rejecting renames of unknown
+    /// tools and names that are invalid or clash.
+    pub fn resolve<'a>(
+        &self,
+        tools: impl IntoIterator<Item = &'a str>,
+    ) -> Result<ToolNames, String> {
+        let tools: Vec<&str> = tools.into_iter().collect();
+        if let Some((tool, _)) =
+            self.renames.iter().find(|(tool, _)| !tools.contains(&tool.as_str()))
+        {
+            return Err(format!("Cannot rename unknown tool '{}'", tool));
+        }
+
+        let mut names = HashMap::new();
+        let mut exposed: HashMap<String, &str> = HashMap::new();
+        for &tool in &tools {
+            let name = match self.renames.iter().rev().find(|(from, _)| from == tool) {
+                Some((_, name)) => name.clone(),
+                None => match (&self.prefix, tool.strip_prefix(DEFAULT_PREFIX)) {
+                    (Some(prefix), Some(rest)) => format!("{}{}", prefix, rest),
+                    _ => tool.to_string(),
+                },
+            };
+            if !is_valid_name(&name) {
+                return Err(format!("Invalid tool name '{}' for {}", name, tool));
+            }
+            if let Some(other) = exposed.insert(name.clone(), tool) {
+                return Err(format!(
+                    "Tools {} and {} would both be named '{}'",
+                    other, tool, name
+                ));
+            }
+            if name != tool {
+                names.insert(tool.to_string(), name);
+            }
+        }
+        Ok(ToolNames(names))
+    }
+}
+
+/// Exposed names of renamed tools, by default name.
+#[derive(Debug, Clone, Default)]
+pub struct ToolNames(HashMap<String, String>);
+
+impl ToolNames {
+    /// Renames `tool` and the tool names mentioned in its docs.
+    pub fn rename_tool(&self, tool: &mut Tool) {
+        if self.0.is_empty() {
+            return;
+        }
+        if let Some(name) = self.0.get(tool.name.as_ref()) {
+            tool.name = Cow::Owned(name.clone());
+        }
+        if let Some(description) = &tool.description {
+            tool.description = Some(Cow::Owned(self.rename_text(description)));
+        }
+        self.rename_schema(&mut tool.input_schema);
+        if let Some(schema) = &mut tool.output_schema {
+            self.rename_schema(schema);
+        }
+    }
+
+    /// Replaces the default tool names in `text` with their exposed names.
+    pub fn rename_text(&self, text: &str) -> String {
+        let mut renamed = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find(is_identifier) {
+            renamed.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let end = rest.find(|c: char| !is_identifier(c)).unwrap_or(rest.len());
+            let word = &rest[..end];
+            renamed.push_str(self.0.get(word).map_or(word, String::as_str));
+            rest = &rest[end..];
+        }
+        renamed.push_str(rest);
+        renamed
+    }
+
+    fn rename_schema(&self, schema: &mut Arc<JsonObject>) {
+        for value in Arc::make_mut(schema).values_mut() {
+            self.rename_value(value);
+        }
+    }
+
+    fn rename_value(&self, value: &mut Value) {
+        match value {
+            Value::String(text) => *text = self.rename_text(text),
+            Value::Array(values) => values.iter_mut().for_each(|v| self.rename_value(v)),
+            Value::Object(object) => object.values_mut().for_each(|v| self.rename_value(v)),
+            _ => {}
+        }
+    }
+}
+
+fn is_identifier(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LENGTH
+        && name.chars().all(|c| is_identifier(c) || c == '-' || c == '.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOOLS: &[&str] =
+        &["perplexity_search", "perplexity_bookmark", "perplexity_bookmarks"];
+
+    #[test]
+    fn test_resolve_prefix_and_renames() {
+        let names = ToolNaming::default()
+            .prefix("pplx_")
+            .rename("perplexity_search", "web_search")
+            .resolve(TOOLS.iter().copied())
+            .unwrap();
+
+        assert_eq!(
+            names.rename_text(
+                "Use perplexity_search, then perplexity_bookmarks (not perplexity_web)."
+            ),
+            "Use web_search, then pplx_bookmarks (not perplexity_web)."
+        );
+
+        let mut tool = Tool::new(
+            "perplexity_bookmark",
+            "Save a thread; list with perplexity_bookmarks",
+            Arc::new(
+                serde_json::json!({ "description": "see perplexity_search" })
+                    .as_object()
+                    .unwrap()
+                    .clone(),
+            ),
+        );
+        names.rename_tool(&mut tool);
+        assert_eq!(tool.name, "pplx_bookmark");
+        assert_eq!(
+            tool.description.as_deref(),
+            Some("Save a thread; list with pplx_bookmarks")
+        );
+        assert_eq!(tool.input_schema["description"], "see web_search");
+    }
+
+    #[test]
+    fn test_resolve_rejects_bad_names() {
+        let resolve = |naming: ToolNaming| naming.resolve(TOOLS.iter().copied());
+
+        assert!(resolve(ToolNaming::default().rename("perplexity_ask", "ask")).is_err());
+        assert!(
+            resolve(ToolNaming::default().rename("perplexity_search", "web search")).is_err()
+        );
+        assert!(
+            resolve(ToolNaming::default().rename("perplexity_search", "perplexity_bookmark"))
+                .is_err()
+        );
+        assert!(resolve(ToolNaming::default().prefix("")).is_ok());
+    }
+}
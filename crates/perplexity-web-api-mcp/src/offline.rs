@@ -0,0 +1,122 @@
+//! Canned answers for running the server without network or credentials.
+//!
+//! In offline mode, searches are answered from a fixtures directory instead
+//! of Perplexity. Each fixture is a JSON stream event, as the search stream
+//! would deliver it (`answer`, `web_results`, ...), stored as
+//! `<sha256 of the query>.json`. Queries without a fixture get a fixed
+//! placeholder answer naming the file to create, so agent flows keep working
+//! while fixtures are being written.
+
+use perplexity_web_api::{
+    Error, ResearchFuture, ResearchProvider, SearchEvent, SearchRequest, SearchResponse,
+};
+use sha2::{Digest, Sha256};
+use std::io;
+use std::path::PathBuf;
+
+/// Tools that always need Perplexity, hidden in offline mode.
+pub const ONLINE_TOOLS: &[&str] = &[
+    "perplexity_generate_image",
+    "perplexity_share",
+    "perplexity_thread",
+    "perplexity_bookmark",
+    "perplexity_bookmarks",
+];
+
+/// Directory of canned answers.
+pub struct Fixtures {
+    dir: PathBuf,
+}
+
+impl Fixtures {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Path of the fixture answering `query`.
+    pub fn path(&self, query: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", hex::encode(Sha256::digest(query.as_bytes()))))
+    }
+
+    /// Answers `query` from its fixture, or with a placeholder if there is
+    /// none.
+    pub async fn search(&self, query: &str) -> perplexity_web_api::Result<SearchResponse> {
+        let path = self.path(query);
+        let read_path = path.clone();
+        let read = tokio::task::spawn_blocking(move || std::fs::read(read_path))
+            .await
+            .map_err(|e| Error::DownloadFailed(format!("Fixture read failed: {}", e)))?;
+        let event: Option<SearchEvent> = match read {
+            Ok(bytes) => Some(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => {
+                return Err(Error::DownloadFailed(format!(
+                    "Cannot read fixture {}: {}",
+                    path.display(),
+                    e
+                )));
+            }
+        };
+
+        let response = match event {
+            Some(event) => SearchResponse::from(event),
+            None => {
+                tracing::info!(fixture = %path.display(), "No fixture for offline query");
+                let mut response = SearchResponse::from(SearchEvent {
+                    answer: Some(format!("Offline answer for: {}", query)),
+                    ..SearchEvent::default()
+                });
+                response.warnings.push(format!(
+                    "Offline mode: no fixture for this query; save one as {}",
+                    path.display()
+                ));
+                response
+            }
+        };
+        Ok(response)
+    }
+}
+
+impl ResearchProvider for Fixtures {
+    fn name(&self) -> &str {
+        "offline"
+    }
+
+    fn research<'a>(
+        &'a self,
+        request: SearchRequest,
+        _on_event: &'a mut (dyn FnMut(&SearchEvent) + Send),
+    ) -> ResearchFuture<'a> {
+        Box::pin(async move { self.search(&request.query).await })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_serves_fixtures_by_query_hash() {
+        let dir = std::env::temp_dir().join(format!("pplx-fixtures-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let fixtures = Fixtures::new(&dir);
+        std::fs::write(
+            fixtures.path("What is Rust?"),
+            r#"{"answer": "A systems language.", "web_results": [{"name": "Rust", "url": "https://www.rust-lang.org", "snippet": "Rust"}]}"#,
+        )
+        .unwrap();
+
+        let hit = fixtures.search("What is Rust?").await.unwrap();
+        assert_eq!(hit.answer.as_deref(), Some("A systems language."));
+        assert_eq!(hit.web_results.len(), 1);
+        assert!(hit.warnings.is_empty());
+
+        let miss = fixtures.search("What is Go?").await.unwrap();
+        assert_eq!(miss.answer.as_deref(), Some("Offline answer for: What is Go?"));
+        assert!(
+            miss.warnings[0].contains(&fixtures.path("What is Go?").display().to_string())
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
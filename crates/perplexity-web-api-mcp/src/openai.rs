@@ -0,0 +1,367 @@
+//! OpenAI-compatible chat completions endpoint.
+//!
+//! `POST /v1/chat/completions` answers OpenAI chat requests with a
+//! Perplexity search, so applications built on an OpenAI client library get
+//! grounded answers by pointing their base URL at this server. The model
+//! name selects the search mode (`auto`, `pro`, `reasoning`,
+//! `deep-research`, optionally prefixed with `perplexity-`); other models
+//! use the default mode. The cited URLs are returned in a non-standard
+//! `citations` field, as Perplexity's own API does.
+//!
+//! With `"stream": true` the answer is sent as server-sent events in
+//! OpenAI's `chat.completion.chunk` format, ending with `data: [DONE]`.
+
+use axum::extract::{Json, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Router, extract::rejection::JsonRejection};
+use futures_util::stream;
+use perplexity_web_api::SearchMode;
+use rmcp::model::ErrorCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+use crate::progress::ProgressSink;
+use crate::server::{PerplexityRequest, PerplexityResponse, PerplexityServer};
+use crate::util::unix_now;
+
+/// Chunks buffered for a streaming client before the search waits for it.
+const STREAM_BUFFER: usize = 64;
+
+struct ChatState {
+    server: PerplexityServer,
+    api_key: Option<String>,
+}
+
+/// Routes for the chat completions endpoint. If `api_key` is set, requests
+/// must carry it as a bearer token.
+pub fn router(server: PerplexityServer, api_key: Option<String>) -> Router {
+    Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .with_state(Arc::new(ChatState { server, api_key }))
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatRequest {
+    #[serde(default)]
+    model: Option<String>,
+    messages: Vec<ChatMessage>,
+    #[serde(default)]
+    stream: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatMessage {
+    role: String,
+    #[serde(default)]
+    content: Option<MessageContent>,
+}
+
+/// Message text, either a string or a list of content parts of which only
+/// the text parts are used.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Debug, Deserialize)]
+struct ContentPart {
+    #[serde(default)]
+    text: Option<String>,
+}
+
+impl ChatMessage {
+    fn text(&self) -> String {
+        match &self.content {
+            Some(MessageContent::Text(text)) => text.clone(),
+            Some(MessageContent::Parts(parts)) => parts
+                .iter()
+                .filter_map(|part| part.text.as_deref())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            None => String::new(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ChatCompletion {
+    id: String,
+    object: &'static str,
+    created: i64,
+    model: String,
+    choices: Vec<Choice>,
+    citations: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Choice {
+    index: u32,
+    message: AssistantMessage,
+    finish_reason: &'static str,
+}
+
+#[derive(Serialize)]
+struct AssistantMessage {
+    role: &'static str,
+    content: String,
+}
+
+/// An OpenAI-style error response.
+#[derive(Debug)]
+struct ChatError {
+    status: StatusCode,
+    message: String,
+    kind: &'static str,
+}
+
+impl ChatError {
+    fn invalid_request(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            message: message.into(),
+            kind: "invalid_request_error",
+        }
+    }
+
+    fn body(&self) -> Value {
+        json!({ "error": { "message": self.message, "type": self.kind, "code": Value::Null } })
+    }
+}
+
+impl From<rmcp::ErrorData> for ChatError {
+    fn from(e: rmcp::ErrorData) -> Self {
+        if e.code == ErrorCode::INVALID_PARAMS {
+            return Self::invalid_request(e.message);
+        }
+        Self { status: StatusCode::BAD_GATEWAY, message: e.message.into(), kind: "api_error" }
+    }
+}
+
+impl IntoResponse for ChatError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self.body())).into_response()
+    }
+}
+
+async fn chat_completions(
+    State(state): State<Arc<ChatState>>,
+    headers: HeaderMap,
+    request: Result<Json<ChatRequest>, JsonRejection>,
+) -> Result<Response, ChatError> {
+    if let Some(api_key) = &state.api_key {
+        let bearer = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if bearer != Some(api_key.as_str()) {
+            return Err(ChatError {
+                status: StatusCode::UNAUTHORIZED,
+                message: "Invalid API key".to_string(),
+                kind: "invalid_request_error",
+            });
+        }
+    }
+
+    let Json(request) = request.map_err(|e| ChatError::invalid_request(e.body_text()))?;
+    let query = conversation_query(&request.messages)?;
+    let model = request.model.unwrap_or_else(|| SearchMode::default().as_str().to_string());
+    let mode = model_mode(&model);
+    let params = PerplexityRequest::plain(query, None);
+    let id = format!("chatcmpl-{}", uuid::Uuid::new_v4().simple());
+
+    if request.stream {
+        return Ok(stream_completion(state.server.clone(), params, mode, id, model));
+    }
+
+    let response = state.server.complete_search(params, mode, None).await?;
+    let completion = ChatCompletion {
+        id,
+        object: "chat.completion",
+        created: unix_now(),
+        model,
+        citations: citations(&response),
+        choices: vec![Choice {
+            index: 0,
+            message: AssistantMessage {
+                role: "assistant",
+                content: response.answer.unwrap_or_default(),
+            },
+            finish_reason: "stop",
+        }],
+    };
+    Ok(Json(completion).into_response())
+}
+
+/// Runs the search in the background, sending the answer as it is
+/// generated.
+fn stream_completion(
+    server: PerplexityServer,
+    params: PerplexityRequest,
+    mode: SearchMode,
+    id: String,
+    model: String,
+) -> Response {
+    let heartbeat = server.heartbeat();
+    let (events, events_rx) = mpsc::channel(STREAM_BUFFER);
+    let created = unix_now();
+    let chunk = move |delta: Value, finish_reason: Option<&str>, extra: Option<Value>| {
+        let mut chunk = json!({
+            "id": id,
+            "object": "chat.completion.chunk",
+            "created": created,
+            "model": model,
+            "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }],
+        });
+        if let (Value::Object(chunk), Some(Value::Object(extra))) = (&mut chunk, extra) {
+            chunk.extend(extra);
+        }
+        Event::default().data(chunk.to_string())
+    };
+
+    tokio::spawn(async move {
+        let (sink, mut answer) = ProgressSink::channel();
+        let search = server.complete_search(params, mode, Some(&sink));
+        tokio::pin!(search);
+        let mut sent = String::new();
+        let mut streaming = true;
+        let send = |text: &str, sent: &mut String| {
+            let delta = match text.strip_prefix(sent.as_str()) {
+                Some("") => return None,
+                Some(delta) => delta.to_string(),
+                // Text already sent was revised; it cannot be taken back.
+                None => return None,
+            };
+            text.clone_into(sent);
+            Some(chunk(json!({ "content": delta }), None, None))
+        };
+
+        if events.send(chunk(json!({ "role": "assistant" }), None, None)).await.is_err() {
+            return;
+        }
+        let result = loop {
+            tokio::select! {
+                result = &mut search => break result,
+                changed = answer.changed(), if streaming => {
+                    if changed.is_err() {
+                        streaming = false;
+                        continue;
+                    }
+                    let text = answer.borrow_and_update().clone();
+                    if let Some(event) = send(&text, &mut sent)
+                        && events.send(event).await.is_err()
+                    {
+                        return;
+                    }
+                }
+            }
+        };
+
+        let last = match result {
+            Ok(response) => {
+                if let Some(event) = send(response.answer.as_deref().unwrap_or(""), &mut sent)
+                    && events.send(event).await.is_err()
+                {
+                    return;
+                }
+                chunk(
+                    json!({}),
+                    Some("stop"),
+                    Some(json!({ "citations": citations(&response) })),
+                )
+            }
+            Err(e) => Event::default().data(ChatError::from(e).body().to_string()),
+        };
+        for event in [last, Event::default().data("[DONE]")] {
+            if events.send(event).await.is_err() {
+                return;
+            }
+        }
+    });
+
+    let events = stream::unfold(events_rx, |mut events_rx| async move {
+        let event = events_rx.recv().await?;
+        Some((Ok::<_, Infallible>(event), events_rx))
+    });
+    let sse = Sse::new(events);
+    match heartbeat {
+        Some(interval) => sse.keep_alive(KeepAlive::new().interval(interval)).into_response(),
+        None => sse.into_response(),
+    }
+}
+
+/// Turns a chat into one search query: the last user message, preceded by
+/// the earlier messages as context.
+fn conversation_query(messages: &[ChatMessage]) -> Result<String, ChatError> {
+    let Some((last, earlier)) = messages.split_last() else {
+        return Err(ChatError::invalid_request("messages must not be empty"));
+    };
+    let question = last.text();
+    if last.role != "user" || question.trim().is_empty() {
+        return Err(ChatError::invalid_request("The last message must be a user message"));
+    }
+
+    let context: Vec<String> = earlier
+        .iter()
+        .map(|message| (message.role.as_str(), message.text()))
+        .filter(|(_, text)| !text.trim().is_empty())
+        .map(|(role, text)| format!("{}: {}", role, text))
+        .collect();
+    if context.is_empty() {
+        return Ok(question);
+    }
+    Ok(format!("{}\n\n{}", context.join("\n"), question))
+}
+
+/// Search mode requested by a model name.
+fn model_mode(model: &str) -> SearchMode {
+    let model = model.strip_prefix("perplexity-").unwrap_or(model);
+    model.parse().unwrap_or_default()
+}
+
+fn citations(response: &PerplexityResponse) -> Vec<String> {
+    response.web_results.iter().map(|result| result.url.clone()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str, content: Value) -> ChatMessage {
+        serde_json::from_value(json!({ "role": role, "content": content })).unwrap()
+    }
+
+    #[test]
+    fn test_conversation_query() {
+        let single = [message("user", json!("What is Rust?"))];
+        assert_eq!(conversation_query(&single).unwrap(), "What is Rust?");
+
+        let chat = [
+            message("system", json!("Answer briefly.")),
+            message("user", json!("What is Rust?")),
+            message("assistant", json!("A programming language.")),
+            message("user", json!([{ "type": "text", "text": "Who created it?" }])),
+        ];
+        assert_eq!(
+            conversation_query(&chat).unwrap(),
+            "system: Answer briefly.\nuser: What is Rust?\nassistant: A programming language.\n\nWho created it?"
+        );
+
+        assert!(conversation_query(&[]).is_err());
+        assert!(conversation_query(&[message("assistant", json!("Hi"))]).is_err());
+    }
+
+    #[test]
+    fn test_model_mode() {
+        assert_eq!(model_mode("perplexity-pro"), SearchMode::Pro);
+        assert_eq!(model_mode("deep-research"), SearchMode::DeepResearch);
+        assert_eq!(model_mode("gpt-4o"), SearchMode::Auto);
+    }
+}
@@ -0,0 +1,134 @@
+//! OpenTelemetry export of traces and metrics over OTLP.
+//!
+//! The exporters are configured with the standard `OTEL_EXPORTER_OTLP_*`
+//! environment variables and default to `http://localhost:4318`.
+
+use opentelemetry::metrics::{Counter, Histogram};
+use opentelemetry::propagation::Extractor;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::{Context, KeyValue, global};
+use opentelemetry_otlp::{ExporterBuildError, MetricExporter, SpanExporter};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::metrics::SdkMeterProvider;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{SdkTracer, SdkTracerProvider};
+use rmcp::model::Meta;
+use std::sync::LazyLock;
+use std::time::Duration;
+use tracing_opentelemetry::OpenTelemetryLayer;
+
+const SERVICE_NAME: &str = env!("CARGO_PKG_NAME");
+
+/// Installed OTLP providers; flushes pending data when shut down.
+pub struct Telemetry {
+    tracer_provider: SdkTracerProvider,
+    meter_provider: SdkMeterProvider,
+}
+
+impl Telemetry {
+    /// Creates the OTLP exporters, installs the global meter provider and
+    /// W3C trace-context propagator, and returns a layer exporting spans.
+    pub fn init<S>() -> Result<(Self, OpenTelemetryLayer<S, SdkTracer>), ExporterBuildError>
+    where
+        S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+    {
+        let resource = Resource::builder().with_service_name(SERVICE_NAME).build();
+
+        let tracer_provider = SdkTracerProvider::builder()
+            .with_batch_exporter(SpanExporter::builder().with_http().build()?)
+            .with_resource(resource.clone())
+            .build();
+        let meter_provider = SdkMeterProvider::builder()
+            .with_periodic_exporter(MetricExporter::builder().with_http().build()?)
+            .with_resource(resource)
+            .build();
+
+        global::set_meter_provider(meter_provider.clone());
+        global::set_text_map_propagator(TraceContextPropagator::new());
+
+        let layer =
+            tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer(SERVICE_NAME));
+        Ok((Self { tracer_provider, meter_provider }, layer))
+    }
+
+    /// Flushes and stops the exporters.
+    pub fn shutdown(self) {
+        if let Err(e) = self.tracer_provider.shutdown() {
+            tracing::warn!("Failed to flush trace export: {}", e);
+        }
+        if let Err(e) = self.meter_provider.shutdown() {
+            tracing::warn!("Failed to flush metric export: {}", e);
+        }
+    }
+}
+
+/// Reads a `traceparent`/`tracestate` pair from MCP request metadata.
+struct MetaExtractor<'a>(&'a Meta);
+
+impl Extractor for MetaExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(serde_json::Value::as_str)
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(String::as_str).collect()
+    }
+}
+
+/// Returns the caller's trace context propagated in the request's `_meta`.
+pub fn parent_context(meta: &Meta) -> Context {
+    global::get_text_map_propagator(|propagator| propagator.extract(&MetaExtractor(meta)))
+}
+
+struct Instruments {
+    queries: Counter<u64>,
+    duration: Histogram<f64>,
+}
+
+static INSTRUMENTS: LazyLock<Instruments> = LazyLock::new(|| {
+    let meter = global::meter(SERVICE_NAME);
+    Instruments {
+        queries: meter
+            .u64_counter("perplexity.queries")
+            .with_description("Queries answered, by mode and outcome")
+            .build(),
+        duration: meter
+            .f64_histogram("perplexity.query.duration")
+            .with_description("Time taken to answer a query")
+            .with_unit("s")
+            .build(),
+    }
+});
+
+/// Records a query answered by a tool call.
+///
+/// `outcome` is one of `"complete"`, `"partial"` or `"error"`.
+pub fn record_query(mode: &'static str, outcome: &'static str, elapsed: Duration) {
+    let attributes = [KeyValue::new("mode", mode), KeyValue::new("outcome", outcome)];
+    INSTRUMENTS.queries.add(1, &attributes);
+    INSTRUMENTS.duration.record(elapsed.as_secs_f64(), &attributes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::TraceContextExt;
+
+    #[test]
+    fn test_parent_context_from_meta() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let mut meta = Meta::new();
+        meta.insert(
+            "traceparent".to_string(),
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".into(),
+        );
+
+        let cx = parent_context(&meta);
+        let span = cx.span();
+        let span_context = span.span_context();
+        assert!(span_context.is_remote());
+        assert_eq!(span_context.trace_id().to_string(), "4bf92f3577b34da6a3ce929d0e0e4736");
+
+        assert!(!parent_context(&Meta::new()).span().span_context().is_valid());
+    }
+}
@@ -0,0 +1,211 @@
+//! On-disk cache of pages fetched by `perplexity_fetch_source`.
+//!
+//! Agents often fetch the same source several times in a session, checking
+//! one claim after another. Pages served with an `ETag` or `Last-Modified`
+//! validator are kept in a directory, one file per URL named by its SHA-256,
+//! and later fetches ask the site whether the page changed; a `304 Not
+//! Modified` is answered from the file without downloading the body again.
+//! Pages without a validator are not kept, since they would have to be
+//! downloaded again anyway. The files' total size is bounded by removing the
+//! least recently used, and they are encrypted with the storage key when one
+//! is configured.
+//!
+//! All methods are blocking; call them from `tokio::task::spawn_blocking`.
+
+use crate::storage::{self, StorageKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Default bound on the cache directory's size.
+pub const DEFAULT_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+/// A page as it was last downloaded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedPage {
+    /// URL the page was fetched from.
+    pub url: String,
+    /// HTTP status of the response that delivered the body.
+    pub status: u16,
+    /// Media type of the body.
+    pub content_type: Option<String>,
+    /// `ETag` the site sent, for `If-None-Match`.
+    pub etag: Option<String>,
+    /// `Last-Modified` date the site sent, for `If-Modified-Since`.
+    pub last_modified: Option<String>,
+    /// The body, as downloaded.
+    #[serde(skip)]
+    pub body: Vec<u8>,
+}
+
+impl CachedPage {
+    /// Whether the site can be asked if the page changed.
+    pub fn revalidatable(&self) -> bool {
+        self.etag.is_some() || self.last_modified.is_some()
+    }
+}
+
+/// Directory of cached pages.
+pub struct PageCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    key: Option<StorageKey>,
+}
+
+impl PageCache {
+    /// Opens the cache in `dir`, creating the directory if needed, keeping
+    /// at most `max_bytes` of files there.
+    pub fn open(
+        dir: impl Into<PathBuf>,
+        max_bytes: u64,
+        key: Option<StorageKey>,
+    ) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir, max_bytes, key })
+    }
+
+    /// The cached copy of `url`, if there is a readable one.
+    pub fn get(&self, url: &str) -> Option<CachedPage> {
+        let path = self.path(url);
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                tracing::warn!(path = %path.display(), "Cannot read cached page: {}", e);
+                return None;
+            }
+        };
+        match self.decode(bytes) {
+            // Two URLs sharing a hash would share a file.
+            Ok(page) if page.url == url => Some(page),
+            Ok(_) => None,
+            Err(e) => {
+                tracing::warn!(path = %path.display(), "Ignoring cached page: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Stores `page`, replacing an older copy and removing the least
+    /// recently used pages as needed to stay within the size bound. Pages
+    /// larger than the whole bound are not stored.
+    pub fn put(&self, page: &CachedPage) -> io::Result<()> {
+        let mut bytes = serde_json::to_vec(page)?;
+        bytes.push(b'\n');
+        bytes.extend_from_slice(&page.body);
+        let bytes = storage::seal(self.key.as_ref(), bytes)?;
+        let size = bytes.len() as u64;
+        if size > self.max_bytes {
+            return Ok(());
+        }
+
+        let path = self.path(&page.url);
+        self.evict(&path, size)?;
+        // Written aside and renamed, so readers never see half a page.
+        let partial = path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()));
+        fs::write(&partial, &bytes)?;
+        fs::rename(&partial, &path).inspect_err(|_| {
+            let _ = fs::remove_file(&partial);
+        })
+    }
+
+    /// Marks the cached copy of `url` as just used, after the site
+    /// confirmed it is current.
+    pub fn touch(&self, url: &str) -> io::Result<()> {
+        File::options().append(true).open(self.path(url))?.set_modified(SystemTime::now())
+    }
+
+    fn path(&self, url: &str) -> PathBuf {
+        self.dir.join(hex::encode(Sha256::digest(url.as_bytes())))
+    }
+
+    fn decode(&self, bytes: Vec<u8>) -> io::Result<CachedPage> {
+        let bytes = storage::unseal(self.key.as_ref(), bytes)?;
+        let split = bytes.iter().position(|&b| b == b'\n').ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "missing page header")
+        })?;
+        let (header, body) = bytes.split_at(split);
+        let mut page: CachedPage = serde_json::from_slice(header)?;
+        page.body = body.get(1..).unwrap_or_default().to_vec();
+        Ok(page)
+    }
+
+    /// Removes the least recently used files, other than `replacing`, until
+    /// `incoming` more bytes fit.
+    fn evict(&self, replacing: &Path, incoming: u64) -> io::Result<()> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_file() && entry.path() != replacing {
+                let used = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                files.push((used, metadata.len(), entry.path()));
+            }
+        }
+        files.sort();
+
+        let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+        for (_, len, path) in files {
+            if total + incoming <= self.max_bytes {
+                break;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => total -= len,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => total -= len,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(url: &str, body: &[u8]) -> CachedPage {
+        CachedPage {
+            url: url.to_string(),
+            status: 200,
+            content_type: Some("text/html".to_string()),
+            etag: Some("\"v1\"".to_string()),
+            last_modified: None,
+            body: body.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_pages_are_kept_within_bound() {
+        let dir =
+            std::env::temp_dir().join(format!("pagecache-test-{}", uuid::Uuid::new_v4()));
+        let key = StorageKey::new(&"ab".repeat(32)).unwrap();
+        let cache = PageCache::open(&dir, 4096, Some(key)).unwrap();
+
+        let first = page("https://example.com/a", &[b'a'; 1500]);
+        cache.put(&first).unwrap();
+        assert_eq!(cache.get("https://example.com/a"), Some(first.clone()));
+        assert_eq!(cache.get("https://example.com/b"), None);
+        let stored = fs::read(cache.path(&first.url)).unwrap();
+        assert!(storage::is_sealed(&stored));
+
+        // The oldest page makes room; the one just revalidated stays.
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        cache.put(&page("https://example.com/b", &[b'b'; 1500])).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        cache.touch("https://example.com/a").unwrap();
+        cache.put(&page("https://example.com/c", &[b'c'; 1500])).unwrap();
+        assert!(cache.get("https://example.com/a").is_some());
+        assert!(cache.get("https://example.com/b").is_none());
+        assert!(cache.get("https://example.com/c").is_some());
+
+        cache.put(&page("https://example.com/huge", &[b'h'; 8192])).unwrap();
+        assert!(cache.get("https://example.com/huge").is_none());
+        assert!(cache.get("https://example.com/c").is_some());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,290 @@
+//! Research plans.
+//!
+//! `perplexity_research_plan` takes a broad goal, breaks it into specific
+//! sub-questions, asks them in parallel and merges the answers into one
+//! report whose citations point into a single deduplicated source list.
+//! The host's model writes the sub-questions when the client supports MCP
+//! sampling; otherwise a quick Auto query to Perplexity does.
+
+use perplexity_web_api::{CitationMerger, SearchWebResult};
+use rmcp::model::{CreateMessageRequestParams, SamplingMessage};
+use rmcp::{Peer, RoleServer, schemars};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::server::{PerplexityResponse, WebResultInfo};
+
+/// Sub-questions asked when the request does not say.
+pub const DEFAULT_SUB_QUERIES: usize = 4;
+
+/// Most sub-questions a plan may have.
+pub const MAX_SUB_QUERIES: usize = 8;
+
+/// Sub-questions asked at once.
+pub const CONCURRENCY: usize = 3;
+
+/// How long the client may take to write the sub-questions.
+const SAMPLING_TIMEOUT: Duration = Duration::from_secs(60);
+
+const SYSTEM_PROMPT: &str = "You plan web research. Break the goal into specific, \
+self-contained search questions that together cover it without overlapping. Reply with one \
+question per line and nothing else.";
+
+/// Request parameters for a research plan.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct ResearchPlanRequest {
+    /// The broad research goal, e.g. "Compare the memory safety approaches
+    /// of Rust, Swift and C++ profiles".
+    pub goal: String,
+
+    /// How many sub-questions to ask, at most 8. Defaults to 4.
+    #[serde(default)]
+    pub max_sub_queries: Option<usize>,
+
+    /// Search mode of the sub-questions: "auto", "pro", "reasoning", or
+    /// "deep research". Defaults to "pro".
+    #[serde(default)]
+    pub mode: Option<String>,
+
+    /// Sources to search for every sub-question, as for perplexity_search.
+    #[serde(default)]
+    pub sources: Option<Vec<String>>,
+}
+
+/// A research plan's combined report.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct ResearchPlanResponse {
+    /// The research goal.
+    pub goal: String,
+
+    /// Markdown report: a section per sub-question, then the merged
+    /// sources. Citation `n` is the `n`-th entry of `web_results`.
+    pub report: String,
+
+    /// The sub-questions and their answers, in plan order.
+    pub sub_queries: Vec<SubQueryResult>,
+
+    /// Sources of all answers, deduplicated by URL.
+    pub web_results: Vec<WebResultInfo>,
+
+    /// Who wrote the sub-questions: "sampling:<model>" or "perplexity".
+    pub planned_by: String,
+
+    /// Problems with individual sub-questions, or with planning.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// One sub-question of a research plan.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct SubQueryResult {
+    /// The sub-question.
+    pub query: String,
+
+    /// The answer, with its citations renumbered to the merged
+    /// `web_results`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub answer: Option<String>,
+
+    /// Why the sub-question could not be answered.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The query asking Perplexity for up to `count` sub-questions of `goal`.
+pub fn planning_query(goal: &str, count: usize) -> String {
+    format!(
+        "List at most {} specific web search questions that together cover this research \
+         goal without overlapping. Reply with one question per line and nothing else.\n\n\
+         Goal: {}",
+        count, goal
+    )
+}
+
+/// Asks `peer`'s model for up to `count` sub-questions of `goal`. Returns
+/// the questions and the model's name, or `None` if the client does not
+/// support sampling or gave no usable answer.
+pub async fn sample_sub_queries(
+    peer: &Peer<RoleServer>,
+    goal: &str,
+    count: usize,
+) -> Option<(Vec<String>, String)> {
+    if peer.peer_info().is_none_or(|info| info.capabilities.sampling.is_none()) {
+        return None;
+    }
+    let request = CreateMessageRequestParams {
+        meta: None,
+        task: None,
+        messages: vec![SamplingMessage::user_text(format!(
+            "Write at most {} search questions for this research goal:\n\n{}",
+            count, goal
+        ))],
+        model_preferences: None,
+        system_prompt: Some(SYSTEM_PROMPT.to_string()),
+        include_context: None,
+        temperature: Some(0.2),
+        max_tokens: 512,
+        stop_sequences: None,
+        metadata: None,
+        tools: None,
+        tool_choice: None,
+    };
+    let result =
+        match tokio::time::timeout(SAMPLING_TIMEOUT, peer.create_message(request)).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(e)) => {
+                tracing::warn!("Planning by sampling failed: {}", e);
+                return None;
+            }
+            Err(_) => {
+                tracing::warn!("Planning by sampling timed out");
+                return None;
+            }
+        };
+    let text: String = result
+        .message
+        .content
+        .iter()
+        .filter_map(|content| content.as_text())
+        .map(|text| text.text.as_str())
+        .collect();
+    let queries = parse_sub_queries(&text, count);
+    (!queries.is_empty()).then_some((queries, result.model))
+}
+
+/// Reads up to `count` distinct questions from a model's reply, one per
+/// line, dropping list markers, citation markers and headings.
+pub fn parse_sub_queries(text: &str, count: usize) -> Vec<String> {
+    let mut queries: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let line = line.trim().trim_start_matches(['-', '*', '•']).trim_start();
+        let line = match line.split_once(['.', ')']) {
+            Some((number, rest)) if number.bytes().all(|b| b.is_ascii_digit()) => rest,
+            _ => line,
+        };
+        let mut query = line.trim().trim_matches(['"', '*']).trim();
+        while let Some(open) = query.strip_suffix(']').and_then(|q| q.rfind('[')) {
+            if !query[open + 1..query.len() - 1].bytes().all(|b| b.is_ascii_digit()) {
+                break;
+            }
+            query = query[..open].trim_end();
+        }
+        if query.is_empty()
+            || query.ends_with(':')
+            || queries.iter().any(|known| known.eq_ignore_ascii_case(query))
+        {
+            continue;
+        }
+        queries.push(query.to_string());
+        if queries.len() == count {
+            break;
+        }
+    }
+    queries
+}
+
+/// Merges the answers to the sub-questions into one report.
+pub fn combine(
+    goal: &str,
+    answers: Vec<(String, Result<PerplexityResponse, String>)>,
+    planned_by: String,
+    mut warnings: Vec<String>,
+) -> ResearchPlanResponse {
+    let mut merger = CitationMerger::new();
+    let mut report = format!("# {}\n", goal.lines().next().unwrap_or_default().trim());
+    let mut sub_queries = Vec::with_capacity(answers.len());
+
+    for (query, result) in answers {
+        report.push_str(&format!("\n## {}\n\n", query));
+        let (answer, error) = match result {
+            Ok(response) => {
+                let results: Vec<SearchWebResult> =
+                    response.web_results.iter().map(SearchWebResult::from).collect();
+                let answer = response.answer.as_deref().unwrap_or_default().trim();
+                let answer = merger.add(answer, &results);
+                if answer.is_empty() {
+                    report.push_str("_Perplexity returned no answer._\n");
+                } else {
+                    report.push_str(&answer);
+                    report.push('\n');
+                }
+                (Some(answer), None)
+            }
+            Err(e) => {
+                report.push_str(&format!("_Not answered: {}_\n", e));
+                warnings.push(format!("{}: {}", query, e));
+                (None, Some(e))
+            }
+        };
+        sub_queries.push(SubQueryResult { query, answer, error });
+    }
+
+    let web_results: Vec<WebResultInfo> =
+        merger.into_results().into_iter().map(WebResultInfo::from).collect();
+    report.push_str(&crate::report::sources(&web_results));
+    ResearchPlanResponse {
+        goal: goal.to_string(),
+        report,
+        sub_queries,
+        web_results,
+        planned_by,
+        warnings,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_combine() {
+        let reply = "Here are the questions:\n\n1. How does Rust enforce memory safety? [1]\n\
+                     2) What is Swift's ownership model?\n- **How does Rust enforce memory \
+                     safety?**\n* \"What are C++ safety profiles?\"\n4. Extra question?";
+        assert_eq!(
+            parse_sub_queries(reply, 3),
+            [
+                "How does Rust enforce memory safety?",
+                "What is Swift's ownership model?",
+                "What are C++ safety profiles?",
+            ]
+        );
+        assert!(parse_sub_queries("Questions:\n\n", 3).is_empty());
+
+        let mut answered = PerplexityResponse::answered("Rust uses borrowing [1][2].");
+        answered.web_results = ["https://doc.rust-lang.org/book", "https://swift.org"]
+            .into_iter()
+            .map(|url| WebResultInfo {
+                name: url.to_string(),
+                url: url.to_string(),
+                snippet: String::new(),
+                score: None,
+            })
+            .collect();
+        let mut second = answered.clone();
+        second.answer = Some("Swift has ownership too [1].".to_string());
+        second.web_results.remove(0);
+
+        let plan = combine(
+            "Memory safety",
+            vec![
+                ("Rust?".to_string(), Ok(answered)),
+                ("C++?".to_string(), Err("rate limited".to_string())),
+                ("Swift?".to_string(), Ok(second)),
+            ],
+            "perplexity".to_string(),
+            Vec::new(),
+        );
+        assert_eq!(plan.web_results.len(), 2);
+        assert_eq!(
+            plan.sub_queries[2].answer.as_deref(),
+            Some("Swift has ownership too [2].")
+        );
+        assert_eq!(plan.warnings, ["C++?: rate limited"]);
+        assert!(plan.report.starts_with(
+            "# Memory safety\n\n## Rust?\n\nRust uses borrowing [1][2].\n\n## C++?\n\n\
+             _Not answered: rate limited_\n\n## Swift?\n\nSwift has ownership too [2].\n\n\
+             ## Sources\n\n1. "
+        ));
+    }
+}
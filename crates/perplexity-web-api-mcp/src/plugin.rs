@@ -0,0 +1,162 @@
+//! Post-processing plugins compiled to WebAssembly.
+//!
+//! A plugin is an [Extism](https://extism.org) module exporting a `process`
+//! function, added to the answer pipeline as a `wasm:<path>` step. The
+//! function receives the response as JSON:
+//!
+//! ```json
+//! {"answer": "…", "web_results": [{"name": "…", "url": "…", "snippet": "…"}], "warnings": []}
+//! ```
+//!
+//! and returns the same shape, with any field it leaves out kept as it was.
+//! Plugins run sandboxed, without access to the network or the file system,
+//! and within memory and time limits. A plugin that fails leaves the
+//! response unchanged and adds a warning.
+
+use extism::{Manifest, Plugin, PluginBuilder, Wasm};
+use perplexity_web_api::{Processor, SearchResponse, SearchWebResult};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Name of the function a plugin must export.
+const ENTRY_POINT: &str = "process";
+
+/// How long a plugin may run per response.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Most memory a plugin may use, in 64 KiB pages (64 MiB).
+const MAX_MEMORY_PAGES: u32 = 1024;
+
+/// The response as a plugin sees it.
+#[derive(Serialize)]
+struct PluginInput<'a> {
+    answer: Option<&'a str>,
+    web_results: &'a [SearchWebResult],
+    warnings: &'a [String],
+}
+
+/// A plugin's changes to the response.
+#[derive(Debug, Deserialize)]
+struct PluginOutput {
+    #[serde(default, deserialize_with = "some")]
+    answer: Option<Option<String>>,
+    web_results: Option<Vec<SearchWebResult>>,
+    warnings: Option<Vec<String>>,
+}
+
+/// Tells a present `null` (no answer) from a missing field (unchanged).
+fn some<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Option<String>>, D::Error> {
+    Option::deserialize(deserializer).map(Some)
+}
+
+/// A pipeline step that runs a WebAssembly plugin.
+pub struct WasmProcessor {
+    name: String,
+    plugin: Mutex<Plugin>,
+}
+
+impl WasmProcessor {
+    /// Loads the plugin at `path`.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let module = std::fs::read(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        let manifest = Manifest::new([Wasm::data(module)])
+            .with_timeout(TIMEOUT)
+            .with_memory_max(MAX_MEMORY_PAGES)
+            .disallow_all_hosts();
+        let plugin = PluginBuilder::new(manifest)
+            .with_wasi(true)
+            .build()
+            .map_err(|e| format!("cannot load {}: {}", path.display(), e))?;
+        if !plugin.function_exists(ENTRY_POINT) {
+            return Err(format!(
+                "{} does not export a `{}` function",
+                path.display(),
+                ENTRY_POINT
+            ));
+        }
+
+        let file = path.file_name().map_or_else(
+            || path.display().to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        );
+        Ok(Self { name: format!("wasm:{}", file), plugin: Mutex::new(plugin) })
+    }
+
+    fn call(&self, input: &str) -> Result<String, String> {
+        let mut plugin = self.plugin.lock().map_err(|_| "plugin poisoned".to_string())?;
+        plugin.call::<&str, String>(ENTRY_POINT, input).map_err(|e| e.to_string())
+    }
+}
+
+impl Processor for WasmProcessor {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn process(&self, response: &mut SearchResponse) {
+        let input = PluginInput {
+            answer: response.answer.as_deref(),
+            web_results: &response.web_results,
+            warnings: &response.warnings,
+        };
+        let result = serde_json::to_string(&input)
+            .map_err(|e| e.to_string())
+            .and_then(|input| self.call(&input))
+            .and_then(|output| apply(response, &output));
+        if let Err(e) = result {
+            tracing::warn!(plugin = %self.name, "Plugin failed: {}", e);
+            response.warnings.push(format!("Plugin {} failed: {}", self.name, e));
+        }
+    }
+}
+
+/// Applies a plugin's JSON `output` to `response`.
+fn apply(response: &mut SearchResponse, output: &str) -> Result<(), String> {
+    let output: PluginOutput =
+        serde_json::from_str(output).map_err(|e| format!("invalid output: {}", e))?;
+    if let Some(answer) = output.answer {
+        response.answer = answer;
+    }
+    if let Some(web_results) = output.web_results {
+        response.web_results = web_results;
+    }
+    if let Some(warnings) = output.warnings {
+        response.warnings = warnings;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use perplexity_web_api::SearchEvent;
+
+    #[test]
+    fn test_apply_keeps_missing_fields() {
+        let mut response = SearchResponse::from(SearchEvent {
+            answer: Some("Rust is fast [1].".to_string()),
+            web_results: vec![SearchWebResult {
+                name: "Rust".to_string(),
+                url: "https://www.rust-lang.org".to_string(),
+                snippet: String::new(),
+                score: None,
+            }],
+            ..SearchEvent::default()
+        });
+
+        apply(&mut response, r#"{"answer": "Rust is fast."}"#).unwrap();
+        assert_eq!(response.answer.as_deref(), Some("Rust is fast."));
+        assert_eq!(response.web_results.len(), 1);
+
+        apply(&mut response, r#"{"answer": null, "web_results": []}"#).unwrap();
+        assert_eq!(response.answer, None);
+        assert!(response.web_results.is_empty());
+
+        assert!(apply(&mut response, "not json").unwrap_err().starts_with("invalid output"));
+    }
+}
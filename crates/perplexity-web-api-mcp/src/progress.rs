@@ -0,0 +1,155 @@
+//! Streaming of partial answers as MCP progress notifications.
+//!
+//! When a tool call carries a `progressToken`, the answer text is forwarded
+//! while it is generated: each notification's `message` holds the text
+//! appended since the previous one. Updates are coalesced so that at most
+//! one notification is sent per [`INTERVAL`]. The tool result remains the
+//! authoritative answer; if Perplexity revises text it already streamed,
+//! the revision is only visible there.
+//!
+//! With a heartbeat interval, a notification without a message is also sent
+//! whenever the answer has not changed for that long, so clients can tell a
+//! Deep Research query that is still running from a dead connection.
+
+use rmcp::model::{Meta, ProgressNotificationParam, ProgressToken};
+use rmcp::{Peer, RoleServer};
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinHandle;
+
+/// Minimum time between two progress notifications.
+const INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long [`AnswerProgress::finish`] waits for the last notification.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Forwards answer text to the client that made a tool call.
+pub struct AnswerProgress {
+    answer: watch::Sender<String>,
+    done: oneshot::Sender<()>,
+    forwarder: JoinHandle<()>,
+}
+
+impl AnswerProgress {
+    /// Starts forwarding if the request asked for progress notifications,
+    /// sending a heartbeat after every `heartbeat` without one.
+    pub fn start(
+        meta: &Meta,
+        peer: Peer<RoleServer>,
+        heartbeat: Option<Duration>,
+    ) -> Option<Self> {
+        let token = meta.get_progress_token()?;
+        let (answer, answer_rx) = watch::channel(String::new());
+        let (done, done_rx) = oneshot::channel();
+        let forwarder = tokio::spawn(forward(peer, token, heartbeat, answer_rx, done_rx));
+
+        Some(Self { answer, done, forwarder })
+    }
+
+    /// Returns a handle for reporting the answer from a search callback.
+    pub fn sink(&self) -> ProgressSink {
+        ProgressSink(self.answer.clone())
+    }
+
+    /// Sends any text not yet forwarded and stops.
+    ///
+    /// Must be awaited before the tool result is returned: notifications for
+    /// a request are not allowed after its response.
+    pub async fn finish(self) {
+        let _ = self.done.send(());
+        let _ = tokio::time::timeout(FLUSH_TIMEOUT, self.forwarder).await;
+    }
+}
+
+/// Reports the answer so far to an [`AnswerProgress`].
+#[derive(Clone)]
+pub struct ProgressSink(watch::Sender<String>);
+
+impl ProgressSink {
+    /// Creates a sink whose updates are read from the returned receiver,
+    /// for streaming answers other than as MCP notifications.
+    pub fn channel() -> (Self, watch::Receiver<String>) {
+        let (answer, answer_rx) = watch::channel(String::new());
+        (Self(answer), answer_rx)
+    }
+
+    /// Records the full answer text generated so far.
+    pub fn update(&self, answer: &str) {
+        self.0.send_if_modified(|current| {
+            if current == answer {
+                return false;
+            }
+            answer.clone_into(current);
+            true
+        });
+    }
+}
+
+async fn forward(
+    peer: Peer<RoleServer>,
+    token: ProgressToken,
+    heartbeat: Option<Duration>,
+    mut answer: watch::Receiver<String>,
+    mut done: oneshot::Receiver<()>,
+) {
+    let mut sent = String::new();
+    let mut notifications = 0u32;
+    let mut last_notified = Instant::now();
+    let mut notify = async |message: Option<&str>| {
+        notifications += 1;
+        let param = ProgressNotificationParam {
+            progress_token: token.clone(),
+            progress: f64::from(notifications),
+            total: None,
+            message: message.map(String::from),
+        };
+        let result = peer.notify_progress(param).await;
+        if let Err(e) = &result {
+            tracing::debug!("Stopped streaming answer: {}", e);
+        }
+        result.is_ok()
+    };
+
+    loop {
+        let finished = tokio::select! {
+            biased;
+            _ = &mut done => true,
+            changed = answer.changed() => changed.is_err(),
+            () = silence(heartbeat, last_notified) => {
+                if !notify(None).await {
+                    return;
+                }
+                last_notified = Instant::now();
+                continue;
+            }
+        };
+
+        let text = answer.borrow_and_update().clone();
+        match text.strip_prefix(sent.as_str()) {
+            Some("") => {}
+            Some(delta) => {
+                if !notify(Some(delta)).await {
+                    return;
+                }
+                last_notified = Instant::now();
+                sent = text;
+            }
+            // Earlier text was revised; continue from the new version.
+            None => sent = text,
+        }
+
+        if finished {
+            return;
+        }
+        tokio::time::sleep(INTERVAL).await;
+    }
+}
+
+/// Completes once `heartbeat` has passed since `since`, or never without a
+/// heartbeat interval.
+async fn silence(heartbeat: Option<Duration>, since: Instant) {
+    match heartbeat {
+        Some(interval) => tokio::time::sleep_until((since + interval).into()).await,
+        None => std::future::pending().await,
+    }
+}
@@ -0,0 +1,198 @@
+//! Research providers other than Perplexity, and fallback between them.
+//!
+//! A fallback endpoint is any HTTP service that accepts a JSON `POST` of
+//!
+//! ```json
+//! { "query": "…", "mode": "pro", "sources": ["web"], "language": "en-US" }
+//! ```
+//!
+//! and responds with an answer in the shape of a Perplexity stream event:
+//! `{ "answer": "…", "web_results": [{ "name": "…", "url": "…", "snippet": "…" }] }`.
+
+use perplexity_web_api::{
+    Degradation, DegradationKind, Error, ResearchFuture, ResearchProvider, SearchEvent,
+    SearchRequest, SearchResponse,
+};
+use rquest::Client as HttpClient;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long a fallback endpoint may take to answer.
+const FALLBACK_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// A research provider behind a user-supplied HTTP endpoint.
+pub struct HttpProvider {
+    http: HttpClient,
+    url: String,
+    api_key: Option<String>,
+}
+
+#[derive(Serialize)]
+struct HttpQuery<'a> {
+    query: &'a str,
+    mode: &'static str,
+    sources: Vec<&'static str>,
+    language: &'a str,
+}
+
+impl HttpProvider {
+    /// Creates a provider posting queries to `url` with `http`,
+    /// authenticated with `api_key` as a bearer token if set.
+    pub fn new(url: String, api_key: Option<String>, http: HttpClient) -> Self {
+        Self { http, url, api_key }
+    }
+
+    async fn query(&self, request: &SearchRequest) -> perplexity_web_api::Result<SearchEvent> {
+        let body = HttpQuery {
+            query: &request.query,
+            mode: request.mode.as_str(),
+            sources: request.sources.iter().map(|s| s.as_str()).collect(),
+            language: &request.language,
+        };
+        let mut http_request = self.http.post(&self.url).json(&body);
+        if let Some(api_key) = &self.api_key {
+            http_request = http_request.bearer_auth(api_key);
+        }
+
+        let response = tokio::time::timeout(FALLBACK_TIMEOUT, http_request.send())
+            .await
+            .map_err(|_| Error::Timeout(FALLBACK_TIMEOUT))??;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(Error::Server {
+                status: status.as_u16(),
+                message: format!("Fallback provider responded with {}", status),
+            });
+        }
+        Ok(response.json().await?)
+    }
+}
+
+impl ResearchProvider for HttpProvider {
+    fn name(&self) -> &str {
+        "fallback"
+    }
+
+    fn research<'a>(
+        &'a self,
+        request: SearchRequest,
+        on_event: &'a mut (dyn FnMut(&SearchEvent) + Send),
+    ) -> ResearchFuture<'a> {
+        Box::pin(async move {
+            let event = self.query(&request).await?;
+            on_event(&event);
+            Ok(SearchResponse::from(event))
+        })
+    }
+}
+
+/// Runs queries on `primary`, retrying them on `secondary` when the primary
+/// is unavailable.
+pub struct Fallback {
+    primary: Arc<dyn ResearchProvider>,
+    secondary: Arc<dyn ResearchProvider>,
+}
+
+impl Fallback {
+    pub fn new(
+        primary: Arc<dyn ResearchProvider>,
+        secondary: Arc<dyn ResearchProvider>,
+    ) -> Self {
+        Self { primary, secondary }
+    }
+}
+
+impl ResearchProvider for Fallback {
+    fn name(&self) -> &str {
+        self.primary.name()
+    }
+
+    fn research<'a>(
+        &'a self,
+        request: SearchRequest,
+        on_event: &'a mut (dyn FnMut(&SearchEvent) + Send),
+    ) -> ResearchFuture<'a> {
+        Box::pin(async move {
+            let error = match self.primary.research(request.clone(), on_event).await {
+                Err(e) if e.is_unavailable() => e,
+                result => return result,
+            };
+
+            tracing::warn!(
+                "{} is unavailable ({}), trying {}",
+                self.primary.name(),
+                error,
+                self.secondary.name()
+            );
+            let mut response = self.secondary.research(request, on_event).await?;
+            response.warnings.push(format!(
+                "Answered by the {} provider because {} was unavailable: {}",
+                self.secondary.name(),
+                self.primary.name(),
+                error
+            ));
+            response.degradations.push(Degradation {
+                kind: DegradationKind::ProviderFallback,
+                reason: format!("{} was unavailable: {}", self.primary.name(), error),
+                count: 1,
+            });
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Canned(&'static str, fn() -> perplexity_web_api::Result<SearchResponse>);
+
+    impl ResearchProvider for Canned {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn research<'a>(
+            &'a self,
+            _request: SearchRequest,
+            _on_event: &'a mut (dyn FnMut(&SearchEvent) + Send),
+        ) -> ResearchFuture<'a> {
+            Box::pin(async move { (self.1)() })
+        }
+    }
+
+    fn answer(text: &str) -> perplexity_web_api::Result<SearchResponse> {
+        Ok(SearchResponse::from(SearchEvent {
+            answer: Some(text.to_string()),
+            ..SearchEvent::default()
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_only_when_unavailable() {
+        let secondary: Arc<dyn ResearchProvider> = Arc::new(Canned("backup", || answer("b")));
+        let research = |primary: Canned| {
+            let fallback = Fallback::new(Arc::new(primary), Arc::clone(&secondary));
+            async move { fallback.research(SearchRequest::new("q"), &mut |_| {}).await }
+        };
+
+        let ok = research(Canned("primary", || answer("a"))).await.unwrap();
+        assert_eq!(ok.answer.as_deref(), Some("a"));
+
+        let rate_limited = research(Canned("primary", || {
+            Err(Error::Server { status: 429, message: "Too Many Requests".to_string() })
+        }))
+        .await
+        .unwrap();
+        assert_eq!(rate_limited.answer.as_deref(), Some("b"));
+        assert!(rate_limited.warnings[0].contains("backup"));
+        assert_eq!(rate_limited.degradations[0].kind, DegradationKind::ProviderFallback);
+
+        let rejected = research(Canned("primary", || {
+            Err(Error::QueryTooLong { length: 9000, limit: 8000 })
+        }))
+        .await;
+        assert!(matches!(rejected, Err(Error::QueryTooLong { .. })));
+    }
+}
@@ -0,0 +1,175 @@
+//! Prioritized execution slots.
+//!
+//! [`Slots`] lets a fixed number of holders run at once, like a semaphore,
+//! but hands a freed slot to the highest-priority waiter, and among equal
+//! priorities to the one that has waited longest. The server queues
+//! Perplexity queries through one, so that when the account's rate limits
+//! force queries to run a few at a time, interactive calls overtake
+//! background jobs and schedules.
+
+use rmcp::schemars;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use tokio::sync::oneshot;
+
+/// How urgently a query should run when it has to wait for a slot.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    /// Bulk and background work.
+    Low,
+    /// Interactive calls.
+    #[default]
+    Normal,
+    /// Calls that should overtake everything else.
+    High,
+}
+
+impl Priority {
+    fn index(self) -> usize {
+        match self {
+            Self::High => 0,
+            Self::Normal => 1,
+            Self::Low => 2,
+        }
+    }
+}
+
+/// A limited number of execution slots, shared by all clones.
+#[derive(Clone)]
+pub struct Slots {
+    inner: Arc<Mutex<State>>,
+}
+
+struct State {
+    limit: usize,
+    taken: usize,
+    /// Waiters by priority, highest first.
+    waiting: [VecDeque<oneshot::Sender<()>>; 3],
+}
+
+impl Slots {
+    /// Creates `limit` slots; at least one.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(State {
+                limit: limit.max(1),
+                taken: 0,
+                waiting: Default::default(),
+            })),
+        }
+    }
+
+    /// Slots that never make anyone wait.
+    pub fn unlimited() -> Self {
+        Self::new(usize::MAX)
+    }
+
+    /// Waits for a free slot, which is held until the permit is dropped.
+    pub async fn acquire(&self, priority: Priority) -> Permit {
+        let receiver = {
+            let mut state = self.lock();
+            if state.taken < state.limit {
+                state.taken += 1;
+                return Permit { slots: self.clone() };
+            }
+            let (sender, receiver) = oneshot::channel();
+            state.waiting[priority.index()].push_back(sender);
+            receiver
+        };
+
+        let mut waiting = Waiting { slots: self, receiver };
+        // The slot is handed over with the message; the sender is only
+        // dropped without sending if the slots are gone.
+        let _ = (&mut waiting.receiver).await;
+        Permit { slots: self.clone() }
+    }
+
+    /// Passes a freed slot to the next waiter, or returns it.
+    fn release(&self) {
+        let mut state = self.lock();
+        for queue in &mut state.waiting {
+            while let Some(sender) = queue.pop_front() {
+                if sender.send(()).is_ok() {
+                    return;
+                }
+            }
+        }
+        state.taken -= 1;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A held slot, released when dropped.
+pub struct Permit {
+    slots: Slots,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.slots.release();
+    }
+}
+
+/// A queued [`Slots::acquire`] call. If it is cancelled after a slot was
+/// handed to it, the slot is passed on.
+struct Waiting<'a> {
+    slots: &'a Slots,
+    receiver: oneshot::Receiver<()>,
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.receiver.close();
+        if self.receiver.try_recv().is_ok() {
+            self.slots.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_freed_slots_go_to_higher_priority_first() {
+        let slots = Slots::new(1);
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let held = slots.acquire(Priority::Normal).await;
+
+        let mut tasks = Vec::new();
+        for (name, priority) in
+            [("low", Priority::Low), ("normal", Priority::Normal), ("high", Priority::High)]
+        {
+            let (slots, order) = (slots.clone(), Arc::clone(&order));
+            tasks.push(tokio::spawn(async move {
+                let _permit = slots.acquire(priority).await;
+                order.lock().unwrap().push(name);
+            }));
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // A waiter that gives up does not keep its place.
+        let cancelled =
+            tokio::time::timeout(Duration::from_millis(10), slots.acquire(Priority::High));
+        assert!(cancelled.await.is_err());
+
+        drop(held);
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), ["high", "normal", "low"]);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(10), slots.acquire(Priority::Low))
+                .await
+                .is_ok()
+        );
+    }
+}
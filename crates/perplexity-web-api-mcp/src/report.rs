@@ -0,0 +1,62 @@
+//! Markdown reports of answers, as written by `perplexity_ask_to_file`.
+//!
+//! The report is the query as a heading, the answer, and its web results as
+//! a numbered list, so the answer's `[1]`-style citations point at the
+//! right source.
+
+use crate::server::{PerplexityResponse, WebResultInfo};
+
+/// Renders `response` to `query` as a Markdown document.
+pub fn markdown(query: &str, response: &PerplexityResponse) -> String {
+    let title = query.lines().next().unwrap_or_default().trim();
+    let mut report = format!("# {}\n\n", title);
+    match response.answer.as_deref().map(str::trim) {
+        Some(answer) if !answer.is_empty() => report.push_str(answer),
+        _ => report.push_str("_Perplexity returned no answer._"),
+    }
+    report.push('\n');
+    report.push_str(&sources(&response.web_results));
+    report
+}
+
+/// Lists `results` under a Sources heading, numbered for their citation
+/// markers. Empty if there are none.
+pub fn sources(results: &[WebResultInfo]) -> String {
+    if results.is_empty() {
+        return String::new();
+    }
+    let mut section = "\n## Sources\n\n".to_string();
+    for (i, result) in results.iter().enumerate() {
+        let name = if result.name.is_empty() { &result.url } else { &result.name };
+        let name = name.replace('[', "\\[").replace(']', "\\]");
+        section.push_str(&format!("{}. [{}](<{}>)\n", i + 1, name, result.url));
+    }
+    section
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markdown_lists_sources_in_citation_order() {
+        let source = |name: &str, url: &str| WebResultInfo {
+            name: name.to_string(),
+            url: url.to_string(),
+            snippet: String::new(),
+            score: None,
+        };
+        let mut response = PerplexityResponse::answered("Rust 1.0 shipped in 2015 [1]. [2]\n");
+        response.web_results = vec![
+            source("Announcing Rust 1.0", "https://blog.rust-lang.org/2015/05/15/Rust-1.0"),
+            source("", "https://example.com/a b"),
+        ];
+
+        assert_eq!(
+            markdown("When did Rust 1.0 ship?\nBe brief.", &response),
+            "# When did Rust 1.0 ship?\n\nRust 1.0 shipped in 2015 [1]. [2]\n\n## Sources\n\n\
+             1. [Announcing Rust 1.0](<https://blog.rust-lang.org/2015/05/15/Rust-1.0>)\n\
+             2. [https://example.com/a b](<https://example.com/a b>)\n"
+        );
+    }
+}
@@ -0,0 +1,329 @@
+//! Resolution of file paths against the client's workspace roots.
+//!
+//! Search tools accept files to attach by path. Relative paths are resolved
+//! against the roots the client reports through MCP `roots/list`, trying
+//! each in order; absolute paths must lie inside one of them. Paths are
+//! canonicalized before the check, so neither `..` components nor symlinks
+//! can reach outside a root. Clients that do not support roots get the
+//! server's working directory as their only root.
+//!
+//! Reports written by `perplexity_ask_to_file` go to the same roots, or to
+//! the configured output directory instead. A relative output path is taken
+//! from the first root.
+//!
+//! Paths from the client are read the same way on every platform: `/` and
+//! `\` both separate components, and names are normalized to NFC. A file
+//! whose name is stored decomposed, as macOS used to write them, is still
+//! found. Roots and files with names that are not valid UTF-8 are handled as
+//! raw paths and only converted lossily for display and upload.
+
+use perplexity_web_api::UploadFile;
+use rmcp::{ErrorData as McpError, Peer, RoleServer};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+use unicode_normalization::UnicodeNormalization;
+
+/// Largest file that can be attached, matching the client's default upload
+/// limit.
+const MAX_FILE_SIZE: u64 = 50 * 1024 * 1024;
+
+/// How long the client may take to list its roots.
+const LIST_ROOTS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A file read from inside a workspace root.
+pub struct ResolvedFile {
+    /// Canonical path of the file.
+    pub path: PathBuf,
+    pub file: UploadFile,
+}
+
+/// Resolves `paths` against `peer`'s workspace roots and reads the files.
+pub async fn read_files(
+    peer: &Peer<RoleServer>,
+    paths: Vec<String>,
+) -> Result<Vec<ResolvedFile>, McpError> {
+    let roots = client_roots(peer).await?;
+    tokio::task::spawn_blocking(move || {
+        let roots: Vec<PathBuf> =
+            roots.iter().filter_map(|root| root.canonicalize().ok()).collect();
+        paths.iter().map(|path| read_file(&roots, path)).collect()
+    })
+    .await
+    .map_err(|e| McpError::internal_error(format!("Reading files failed: {}", e), None))?
+}
+
+/// Resolves `path` for writing against `output_dir`, or `peer`'s workspace
+/// roots if there is none.
+pub async fn output_path(
+    peer: &Peer<RoleServer>,
+    output_dir: Option<&Path>,
+    path: &str,
+) -> Result<PathBuf, McpError> {
+    let roots = match output_dir {
+        Some(dir) => vec![dir.to_path_buf()],
+        None => client_roots(peer).await?,
+    };
+    let path = client_path(path);
+    tokio::task::spawn_blocking(move || {
+        let roots: Vec<PathBuf> =
+            roots.iter().filter_map(|root| root.canonicalize().ok()).collect();
+        resolve_output(&roots, &path)
+    })
+    .await
+    .map_err(|e| McpError::internal_error(format!("Resolving the path failed: {}", e), None))?
+    .map_err(|message| McpError::invalid_params(message, None))
+}
+
+/// Shows `path` as the user would write it. Canonical paths on Windows carry
+/// the `\\?\` verbatim prefix, which most programs do not accept.
+pub fn display(path: &Path) -> String {
+    let text = path.to_string_lossy();
+    if cfg!(windows) {
+        if let Some(share) = text.strip_prefix(r"\\?\UNC\") {
+            return format!(r"\\{}", share);
+        }
+        if let Some(local) = text.strip_prefix(r"\\?\") {
+            return local.to_string();
+        }
+    }
+    text.into_owned()
+}
+
+/// Reads a path sent by the client: NFC-normalized, with `\` taken as a
+/// separator also outside Windows, since clients there send either.
+fn client_path(path: &str) -> PathBuf {
+    let path: String = path.nfc().collect();
+    if cfg!(windows) { PathBuf::from(path) } else { PathBuf::from(path.replace('\\', "/")) }
+}
+
+/// Writes `contents` to `path`, creating its directory. Fails if the file
+/// exists, unless `overwrite`.
+pub fn write_file(path: &Path, contents: &[u8], overwrite: bool) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true);
+    if overwrite {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    options.open(path)?.write_all(contents)
+}
+
+/// Directories of the client's roots, or the working directory if it has
+/// none.
+async fn client_roots(peer: &Peer<RoleServer>) -> Result<Vec<PathBuf>, McpError> {
+    let mut roots = Vec::new();
+    if peer.peer_info().is_some_and(|info| info.capabilities.roots.is_some()) {
+        let listed = tokio::time::timeout(LIST_ROOTS_TIMEOUT, peer.list_roots())
+            .await
+            .map_err(|_| McpError::internal_error("Listing workspace roots timed out", None))?
+            .map_err(|e| {
+                McpError::internal_error(
+                    format!("Listing workspace roots failed: {}", e),
+                    None,
+                )
+            })?;
+        for root in listed.roots {
+            match url::Url::parse(&root.uri).ok().and_then(|uri| uri.to_file_path().ok()) {
+                Some(path) => roots.push(path),
+                None => tracing::debug!(uri = %root.uri, "Ignoring non-file workspace root"),
+            }
+        }
+    }
+    if roots.is_empty() {
+        let cwd = std::env::current_dir().map_err(|e| {
+            McpError::internal_error(format!("Cannot read working directory: {}", e), None)
+        })?;
+        roots.push(cwd);
+    }
+    Ok(roots)
+}
+
+fn read_file(roots: &[PathBuf], path: &str) -> Result<ResolvedFile, McpError> {
+    let resolved = resolve(roots, &client_path(path))
+        .map_err(|message| McpError::invalid_params(message, None))?;
+    let unreadable = |e: std::io::Error| {
+        McpError::invalid_params(format!("Cannot read {}: {}", path, e), None)
+    };
+
+    let size = std::fs::metadata(&resolved).map_err(unreadable)?.len();
+    if size > MAX_FILE_SIZE {
+        return Err(McpError::invalid_params(
+            format!("{} is {} bytes (limit {})", path, size, MAX_FILE_SIZE),
+            None,
+        ));
+    }
+    let data = std::fs::read(&resolved).map_err(unreadable)?;
+    let filename = resolved
+        .file_name()
+        .map_or_else(|| path.to_string(), |name| name.to_string_lossy().nfc().collect());
+
+    Ok(ResolvedFile { path: resolved, file: UploadFile::from_bytes(filename, data) })
+}
+
+/// Finds `path` inside one of the canonical `roots`, as spelled or, failing
+/// that, decomposed.
+fn resolve(roots: &[PathBuf], path: &Path) -> Result<PathBuf, String> {
+    let mut spellings = vec![path.to_path_buf()];
+    if let Some(text) = path.to_str() {
+        let decomposed: String = text.nfd().collect();
+        if decomposed != text {
+            spellings.push(PathBuf::from(decomposed));
+        }
+    }
+    let candidates: Vec<PathBuf> = if path.is_absolute() {
+        spellings
+    } else {
+        roots
+            .iter()
+            .flat_map(|root| spellings.iter().map(|spelling| root.join(spelling)))
+            .collect()
+    };
+
+    for candidate in candidates {
+        let Ok(canonical) = candidate.canonicalize() else {
+            continue;
+        };
+        if !roots.iter().any(|root| canonical.starts_with(root)) {
+            return Err(format!("{} is outside the workspace roots", path.display()));
+        }
+        if !canonical.is_file() {
+            return Err(format!("{} is not a file", path.display()));
+        }
+        return Ok(canonical);
+    }
+    Err(format!("File not found in the workspace roots: {}", path.display()))
+}
+
+/// Places `path`, which need not exist yet, inside one of the canonical
+/// `roots`.
+fn resolve_output(roots: &[PathBuf], path: &Path) -> Result<PathBuf, String> {
+    if path.components().any(|c| c == Component::ParentDir) {
+        return Err(format!("{} must not contain `..`", path.display()));
+    }
+    let outside = || format!("{} is outside the workspace roots", path.display());
+    let candidate = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        roots.first().ok_or_else(outside)?.join(path)
+    };
+
+    // The missing part of the path cannot hold symlinks, so checking the
+    // nearest existing directory is enough.
+    let existing = candidate.ancestors().find(|dir| dir.exists()).ok_or_else(outside)?;
+    let canonical = existing.canonicalize().map_err(|e| e.to_string())?;
+    let resolved = match candidate.strip_prefix(existing) {
+        Ok(rest) if !rest.as_os_str().is_empty() => canonical.join(rest),
+        _ => canonical,
+    };
+    if !roots.iter().any(|root| resolved.starts_with(root) && resolved != *root) {
+        return Err(outside());
+    }
+    if resolved.is_dir() {
+        return Err(format!("{} is a directory", path.display()));
+    }
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_stays_inside_roots() {
+        let base = std::env::temp_dir().join(format!("pplx-roots-{}", uuid::Uuid::new_v4()));
+        let first = base.join("first");
+        let second = base.join("second");
+        std::fs::create_dir_all(first.join("src")).unwrap();
+        std::fs::create_dir_all(&second).unwrap();
+        std::fs::write(first.join("src/main.rs"), "fn main() {}").unwrap();
+        std::fs::write(second.join("notes.md"), "# Notes").unwrap();
+        std::fs::write(base.join("secret.txt"), "secret").unwrap();
+        let roots = [first.canonicalize().unwrap(), second.canonicalize().unwrap()];
+
+        assert_eq!(
+            resolve(&roots, Path::new("src/main.rs")).unwrap(),
+            roots[0].join("src/main.rs")
+        );
+        assert_eq!(resolve(&roots, Path::new("notes.md")).unwrap(), roots[1].join("notes.md"));
+        assert_eq!(
+            resolve(&roots, &roots[1].join("notes.md")).unwrap(),
+            roots[1].join("notes.md")
+        );
+
+        assert!(resolve(&roots, Path::new("../secret.txt")).unwrap_err().contains("outside"));
+        assert!(resolve(&roots, &base.join("secret.txt")).unwrap_err().contains("outside"));
+        assert!(resolve(&roots, Path::new("src")).unwrap_err().contains("not a file"));
+        assert!(resolve(&roots, Path::new("missing.rs")).unwrap_err().contains("not found"));
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_output_stays_inside_roots() {
+        let base = std::env::temp_dir().join(format!("pplx-output-{}", uuid::Uuid::new_v4()));
+        let root = base.join("root");
+        std::fs::create_dir_all(root.join("docs")).unwrap();
+        let roots = [root.canonicalize().unwrap()];
+
+        let report = resolve_output(&roots, Path::new("docs/new/report.md")).unwrap();
+        assert_eq!(report, roots[0].join("docs/new/report.md"));
+        write_file(&report, b"one", false).unwrap();
+        assert!(write_file(&report, b"two", false).is_err());
+        write_file(&report, b"two", true).unwrap();
+        assert_eq!(std::fs::read(&report).unwrap(), b"two");
+
+        assert!(resolve_output(&roots, Path::new("../x.md")).unwrap_err().contains(".."));
+        assert!(resolve_output(&roots, &base.join("x.md")).unwrap_err().contains("outside"));
+        assert!(resolve_output(&roots, Path::new("docs")).unwrap_err().contains("directory"));
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&base, root.join("escape")).unwrap();
+            let escaped = resolve_output(&roots, Path::new("escape/x.md"));
+            assert!(escaped.unwrap_err().contains("outside"));
+        }
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_client_paths_are_portable() {
+        let base = std::env::temp_dir().join(format!("pplx-paths-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(base.join("docs")).unwrap();
+        // A decomposed name: "e" followed by a combining acute accent.
+        std::fs::write(base.join("docs").join("cafe\u{301}.md"), "# Café").unwrap();
+        let roots = [base.canonicalize().unwrap()];
+
+        let resolved = read_file(&roots, "docs\\caf\u{e9}.md").unwrap();
+        assert!(resolved.path.starts_with(&roots[0]));
+        match resolved.file {
+            UploadFile::Binary { filename, .. } => assert_eq!(filename, "caf\u{e9}.md"),
+            UploadFile::Text { .. } => panic!("files are read as bytes"),
+        }
+        assert!(!display(&resolved.path).starts_with(r"\\?\"));
+
+        let output =
+            resolve_output(&roots, &client_path("docs\\new\\resume\u{301}.md")).unwrap();
+        assert_eq!(output, roots[0].join("docs").join("new").join("resum\u{e9}.md"));
+        assert!(resolve_output(&roots, &client_path("docs\\..\\..\\x.md")).is_err());
+
+        // Linux file names are bytes; macOS and Windows reject invalid UTF-8.
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            let raw = base.join(std::ffi::OsStr::from_bytes(b"raw-\xff"));
+            std::fs::create_dir_all(&raw).unwrap();
+            std::fs::write(raw.join("notes.md"), "# Notes").unwrap();
+            let roots = [raw.canonicalize().unwrap()];
+            let resolved = read_file(&roots, "notes.md").unwrap();
+            assert_eq!(resolved.path, roots[0].join("notes.md"));
+            assert!(display(&resolved.path).contains("raw-\u{fffd}"));
+        }
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,550 @@
+//! Recurring queries run on a cron schedule.
+//!
+//! The scheduler only decides *when* a query is due; each run is submitted
+//! as a background job, so it shares the job queue's concurrency limit, is
+//! recorded in the history store and is delivered to the webhook like any
+//! other job. Runs missed while the server was not running are skipped.
+
+use perplexity_web_api::SearchMode;
+use rmcp::schemars;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+use crate::digest::NewsDigestRequest;
+use crate::server::PerplexityRequest;
+use crate::storage::{self, StorageKey};
+use crate::util::{civil_from_days, read_json, unix_now, write_atomically};
+
+/// Longest the scheduler sleeps before re-checking for due schedules.
+const MAX_IDLE: Duration = Duration::from_secs(60);
+
+/// A five-field cron expression (`minute hour day-of-month month day-of-week`)
+/// evaluated in UTC.
+///
+/// Fields accept `*`, single values, ranges (`1-5`), lists (`1,15`) and steps
+/// (`*/15`, `0-30/10`). Day-of-week runs from 0 (Sunday) to 7 (also Sunday).
+/// `@hourly`, `@daily`, `@weekly` and `@monthly` are accepted as shorthands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether day-of-month and day-of-week were both restricted, in which
+    /// case a day matching either one fires (as in standard cron).
+    either_day: bool,
+}
+
+impl FromStr for Cron {
+    type Err = String;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let expression = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            other => other,
+        };
+
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!(
+                "Expected 5 cron fields (minute hour day month weekday), got {}",
+                fields.len()
+            ));
+        };
+
+        let mut weekdays = parse_field(weekday, 0, 7)?;
+        // 7 is an alias for Sunday.
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays & !(1 << 7)) | 1;
+        }
+
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59)?,
+            hours: parse_field(hour, 0, 23)?,
+            days: parse_field(day, 1, 31)?,
+            months: parse_field(month, 1, 12)?,
+            weekdays,
+            either_day: day != "*" && weekday != "*",
+        })
+    }
+}
+
+impl Cron {
+    /// Returns the first minute strictly after `after` (a Unix timestamp in
+    /// seconds) at which the expression fires, or `None` if it never does
+    /// (e.g. `0 0 30 2 *`).
+    pub fn next_after(&self, after: i64) -> Option<i64> {
+        let mut t = (after.div_euclid(60) + 1) * 60;
+
+        // Each step skips at least a minute and at most a month; this bounds
+        // the search to a few years.
+        for _ in 0..100_000 {
+            let days = t.div_euclid(86_400);
+            let (year, month, day) = civil_from_days(days);
+            let weekday = (days + 4).rem_euclid(7);
+            let seconds = t.rem_euclid(86_400);
+            let (hour, minute) = (seconds / 3600, seconds % 3600 / 60);
+
+            if !has(self.months, month) {
+                let (year, month) =
+                    if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+                t = days_from_civil(year, month, 1) * 86_400;
+            } else if !self.day_matches(day, weekday) {
+                t = (days + 1) * 86_400;
+            } else if !has(self.hours, hour) {
+                t = days * 86_400 + (hour + 1) * 3600;
+            } else if !has(self.minutes, minute) {
+                t += 60;
+            } else {
+                return Some(t);
+            }
+        }
+
+        None
+    }
+
+    fn day_matches(&self, day: i64, weekday: i64) -> bool {
+        let by_day = has(self.days, day);
+        let by_weekday = has(self.weekdays, weekday);
+        if self.either_day { by_day || by_weekday } else { by_day && by_weekday }
+    }
+}
+
+fn has(mask: u64, value: i64) -> bool {
+    u32::try_from(value).is_ok_and(|v| v < 64 && mask & (1 << v) != 0)
+}
+
+/// Parses one cron field into a bitmask of the values it matches.
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut mask = 0u64;
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .ok()
+                    .filter(|&s| s > 0)
+                    .ok_or_else(|| format!("Invalid step in cron field '{}'", field))?;
+                (range, step)
+            }
+            None => (part, 1),
+        };
+
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (parse_value(start, field)?, parse_value(end, field)?),
+                None => {
+                    let value = parse_value(range, field)?;
+                    // "5/10" means "from 5 to the maximum, every 10".
+                    (value, if step > 1 { max } else { value })
+                }
+            },
+        };
+
+        if start < min || end > max || start > end {
+            return Err(format!("Cron field '{}' is out of range ({}-{})", field, min, max));
+        }
+
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+
+    Ok(mask)
+}
+
+fn parse_value(value: &str, field: &str) -> Result<u32, String> {
+    value.parse().map_err(|_| format!("Invalid value '{}' in cron field '{}'", value, field))
+}
+
+/// Converts a (year, month, day) date to days since the Unix epoch.
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// A registered recurring query.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct ScheduleRecord {
+    /// Identifier of the schedule.
+    pub id: String,
+
+    /// Cron expression the query runs on, in UTC.
+    pub cron: String,
+
+    /// Search mode the query runs in.
+    pub mode: String,
+
+    /// The query to run.
+    pub request: PerplexityRequest,
+
+    /// Unix timestamp (seconds) when the schedule was created.
+    pub created_at: i64,
+
+    /// Unix timestamp (seconds) of the next run.
+    pub next_run_at: i64,
+
+    /// Unix timestamp (seconds) of the most recent run.
+    pub last_run_at: Option<i64>,
+
+    /// Background job of the most recent run, for use with the job tools.
+    pub last_job_id: Option<String>,
+
+    /// The news digest to build, for schedules registered with
+    /// perplexity_schedule_digest. `request` then only describes it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<NewsDigestRequest>,
+}
+
+/// Settings for a [`Scheduler`].
+pub struct ScheduleOptions {
+    /// Maximum number of registered schedules.
+    pub capacity: usize,
+    /// File the schedules are persisted to.
+    pub state_file: Option<PathBuf>,
+    /// Key the state file is encrypted with.
+    pub storage_key: Option<StorageKey>,
+}
+
+impl Default for ScheduleOptions {
+    fn default() -> Self {
+        Self { capacity: 50, state_file: None, storage_key: None }
+    }
+}
+
+/// Keeps track of recurring queries and starts them when they are due.
+#[derive(Clone)]
+pub struct Scheduler {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    schedules: Mutex<Vec<ScheduleRecord>>,
+    capacity: usize,
+    state_file: Option<PathBuf>,
+    storage_key: Option<StorageKey>,
+    /// Serializes state file writes so that they land in order.
+    write_lock: tokio::sync::Mutex<()>,
+    /// Wakes the run loop when the set of schedules changes.
+    changed: Notify,
+}
+
+impl Scheduler {
+    /// Creates a scheduler, loading any schedules persisted in the state file.
+    ///
+    /// Runs that fell due while the server was not running are skipped.
+    pub fn load(options: ScheduleOptions) -> std::io::Result<Self> {
+        let mut schedules: Vec<ScheduleRecord> = match &options.state_file {
+            Some(path) => read_json(path, options.storage_key.as_ref())?.unwrap_or_default(),
+            None => Vec::new(),
+        };
+
+        let now = unix_now();
+        schedules.retain_mut(|schedule| {
+            if schedule.next_run_at > now {
+                return true;
+            }
+            let next = schedule.cron.parse::<Cron>().ok().and_then(|c| c.next_after(now));
+            match next {
+                Some(next) => {
+                    schedule.next_run_at = next;
+                    true
+                }
+                None => {
+                    tracing::warn!(schedule_id = %schedule.id, "Dropping schedule that never fires");
+                    false
+                }
+            }
+        });
+
+        Ok(Self {
+            inner: Arc::new(Inner {
+                schedules: Mutex::new(schedules),
+                capacity: options.capacity.max(1),
+                state_file: options.state_file,
+                storage_key: options.storage_key,
+                write_lock: tokio::sync::Mutex::new(()),
+                changed: Notify::new(),
+            }),
+        })
+    }
+
+    /// Registers a query, or with `digest` a news digest, to run whenever
+    /// `cron` fires.
+    pub async fn add(
+        &self,
+        cron: &str,
+        request: PerplexityRequest,
+        mode: SearchMode,
+        digest: Option<NewsDigestRequest>,
+    ) -> Result<ScheduleRecord, String> {
+        let now = unix_now();
+        let next_run_at = cron
+            .parse::<Cron>()?
+            .next_after(now)
+            .ok_or_else(|| format!("Cron expression '{}' never fires", cron))?;
+
+        let record = ScheduleRecord {
+            id: Uuid::new_v4().to_string(),
+            cron: cron.trim().to_string(),
+            mode: mode.as_str().to_string(),
+            request,
+            created_at: now,
+            next_run_at,
+            last_run_at: None,
+            last_job_id: None,
+            digest,
+        };
+
+        {
+            let mut schedules = self.lock();
+            if schedules.len() >= self.inner.capacity {
+                return Err(format!(
+                    "Too many schedules ({}); cancel one before adding another",
+                    schedules.len()
+                ));
+            }
+            schedules.push(record.clone());
+        }
+
+        self.inner.changed.notify_one();
+        self.persist().await;
+        Ok(record)
+    }
+
+    /// Returns all schedules, ordered by creation time.
+    pub fn list(&self) -> Vec<ScheduleRecord> {
+        self.lock().clone()
+    }
+
+    /// Removes a schedule. Returns false if no schedule has the given id.
+    pub async fn cancel(&self, id: &str) -> bool {
+        let removed = {
+            let mut schedules = self.lock();
+            let before = schedules.len();
+            schedules.retain(|s| s.id != id);
+            schedules.len() < before
+        };
+
+        if removed {
+            self.inner.changed.notify_one();
+            self.persist().await;
+        }
+        removed
+    }
+
+    /// Starts the run loop, calling `run` for each schedule as it falls due.
+    ///
+    /// `run` starts the query and returns the id of the job running it, or
+    /// an error if it could not be started. The loop runs for the lifetime
+    /// of the process.
+    pub fn start<F>(&self, run: F)
+    where
+        F: Fn(&ScheduleRecord) -> Result<String, String> + Send + 'static,
+    {
+        let scheduler = self.clone();
+        tokio::spawn(async move {
+            loop {
+                for schedule in scheduler.take_due(unix_now()) {
+                    match run(&schedule) {
+                        Ok(job_id) => {
+                            tracing::info!(schedule_id = %schedule.id, job_id = %job_id, "Started scheduled query");
+                            scheduler.set_last_job(&schedule.id, job_id);
+                        }
+                        Err(e) => {
+                            tracing::warn!(schedule_id = %schedule.id, "Failed to start scheduled query: {}", e);
+                        }
+                    }
+                }
+                scheduler.persist().await;
+
+                let idle = scheduler.lock().iter().map(|s| s.next_run_at).min().map_or(
+                    MAX_IDLE,
+                    |next| {
+                        let secs = u64::try_from(next - unix_now()).unwrap_or_default();
+                        Duration::from_secs(secs).min(MAX_IDLE)
+                    },
+                );
+                tokio::select! {
+                    () = tokio::time::sleep(idle) => {}
+                    () = scheduler.inner.changed.notified() => {}
+                }
+            }
+        });
+    }
+
+    /// Returns the schedules due at `now` and advances them to their next run.
+    fn take_due(&self, now: i64) -> Vec<ScheduleRecord> {
+        let mut due = Vec::new();
+        let mut schedules = self.lock();
+
+        schedules.retain_mut(|schedule| {
+            if schedule.next_run_at > now {
+                return true;
+            }
+            due.push(schedule.clone());
+            schedule.last_run_at = Some(now);
+
+            let next = schedule.cron.parse::<Cron>().ok().and_then(|c| c.next_after(now));
+            match next {
+                Some(next) => {
+                    schedule.next_run_at = next;
+                    true
+                }
+                None => false,
+            }
+        });
+
+        due
+    }
+
+    fn set_last_job(&self, id: &str, job_id: String) {
+        if let Some(schedule) = self.lock().iter_mut().find(|s| s.id == id) {
+            schedule.last_job_id = Some(job_id);
+        }
+    }
+
+    /// Writes the schedules to the state file, if one is configured.
+    async fn persist(&self) {
+        let Some(path) = self.inner.state_file.clone() else {
+            return;
+        };
+
+        let _guard = self.inner.write_lock.lock().await;
+        let snapshot = match serde_json::to_vec(&*self.lock()) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                tracing::warn!("Failed to serialize schedules: {}", e);
+                return;
+            }
+        };
+
+        let key = self.inner.storage_key.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            storage::seal(key.as_ref(), snapshot)
+                .and_then(|sealed| write_atomically(&path, &sealed))
+                .map_err(|e| (path, e))
+        })
+        .await;
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err((path, e))) => {
+                tracing::warn!("Failed to persist schedules to {}: {}", path.display(), e);
+            }
+            Err(e) => tracing::warn!("Schedule persistence task failed: {}", e),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<ScheduleRecord>> {
+        self.inner.schedules.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2026-03-02 10:17:30 UTC, a Monday.
+    const MONDAY: i64 = 1_772_446_650;
+
+    fn cron(expression: &str) -> Cron {
+        expression.parse().unwrap()
+    }
+
+    fn request(query: &str) -> PerplexityRequest {
+        PerplexityRequest::plain(query.to_string(), None)
+    }
+
+    #[test]
+    fn test_civil_round_trip() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(MONDAY / 86_400), (2026, 3, 2));
+        assert_eq!(days_from_civil(2024, 2, 29), 19_782);
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+    }
+
+    #[test]
+    fn test_next_after() {
+        // Next quarter hour.
+        assert_eq!(cron("*/15 * * * *").next_after(MONDAY), Some(MONDAY - 1050 + 1800));
+        // Daily at 09:00 has passed today, so tomorrow.
+        assert_eq!(
+            cron("0 9 * * *").next_after(MONDAY),
+            Some(MONDAY - 37_050 + 86_400 + 32_400)
+        );
+        // Fridays at 08:30.
+        assert_eq!(
+            cron("30 8 * * 5").next_after(MONDAY),
+            Some(MONDAY - 37_050 + 4 * 86_400 + 30_600)
+        );
+        // First of the month, via the shorthand.
+        assert_eq!(
+            cron("@monthly").next_after(MONDAY),
+            Some(days_from_civil(2026, 4, 1) * 86_400)
+        );
+        // Sunday may be written as 7.
+        assert_eq!(cron("0 0 * * 7"), cron("0 0 * * 0"));
+    }
+
+    #[test]
+    fn test_restricted_day_fields_match_either() {
+        // The 15th (a Sunday in March 2026) or any Wednesday, whichever is first.
+        let next = cron("0 12 15 * 3").next_after(MONDAY).unwrap();
+        assert_eq!(civil_from_days(next / 86_400), (2026, 3, 4));
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        assert!("* * * *".parse::<Cron>().is_err());
+        assert!("60 * * * *".parse::<Cron>().is_err());
+        assert!("*/0 * * * *".parse::<Cron>().is_err());
+        assert!("5-1 * * * *".parse::<Cron>().is_err());
+        assert_eq!(cron("0 0 30 2 *").next_after(MONDAY), None);
+    }
+
+    #[tokio::test]
+    async fn test_take_due_advances_schedule() {
+        let scheduler = Scheduler::load(ScheduleOptions::default()).unwrap();
+        let schedule =
+            scheduler.add("*/5 * * * *", request("q"), SearchMode::Pro, None).await.unwrap();
+
+        assert!(scheduler.take_due(schedule.next_run_at - 1).is_empty());
+        let due = scheduler.take_due(schedule.next_run_at);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].request.query, "q");
+
+        let updated = &scheduler.list()[0];
+        assert_eq!(updated.last_run_at, Some(schedule.next_run_at));
+        assert_eq!(updated.next_run_at, schedule.next_run_at + 300);
+    }
+
+    #[tokio::test]
+    async fn test_capacity_and_cancel() {
+        let scheduler =
+            Scheduler::load(ScheduleOptions { capacity: 1, ..ScheduleOptions::default() })
+                .unwrap();
+        let first =
+            scheduler.add("@daily", request("a"), SearchMode::Auto, None).await.unwrap();
+        assert!(scheduler.add("@daily", request("b"), SearchMode::Auto, None).await.is_err());
+
+        assert!(scheduler.cancel(&first.id).await);
+        assert!(!scheduler.cancel(&first.id).await);
+        assert!(scheduler.list().is_empty());
+    }
+}
@@ -0,0 +1,79 @@
+//! Secrets read from the environment or the OS keyring.
+//!
+//! Each secret is looked up as an environment variable first. When built
+//! with the `keyring` feature, a secret that is not set in the environment
+//! is read from the OS keyring (Keychain, Windows Credential Manager or the
+//! Secret Service), stored under the service [`SERVICE`] with the
+//! variable's name as the account. `--store-secret` saves a secret there,
+//! and `--import-secrets` moves the secrets out of a plaintext `.env` file.
+
+use std::env;
+
+/// Keyring service name the secrets are stored under.
+#[cfg(feature = "keyring")]
+const SERVICE: &str = "perplexity-web-api-mcp";
+
+/// Environment variables holding secrets.
+pub const SECRETS: &[&str] = &[
+    "PERPLEXITY_SESSION_TOKEN",
+    "PERPLEXITY_CSRF_TOKEN",
+    "PERPLEXITY_WEBHOOK_SECRET",
+    "PERPLEXITY_FALLBACK_API_KEY",
+    "PERPLEXITY_OPENAI_API_KEY",
+    "PERPLEXITY_HTTP_API_KEY",
+    "PERPLEXITY_SIGNING_KEY",
+    "PERPLEXITY_STORAGE_KEY",
+];
+
+/// Returns the secret `name` from the environment or, failing that, the
+/// keyring.
+pub fn secret(name: &str) -> Option<String> {
+    if let Ok(value) = env::var(name) {
+        return Some(value);
+    }
+    #[cfg(feature = "keyring")]
+    match keyring::Entry::new(SERVICE, name).and_then(|entry| entry.get_password()) {
+        Ok(value) => return Some(value),
+        Err(keyring::Error::NoEntry) => {}
+        Err(e) => tracing::warn!("Cannot read {} from the keyring: {}", name, e),
+    }
+    None
+}
+
+/// Saves `value` as the secret `name` in the keyring.
+#[cfg(feature = "keyring")]
+pub fn store(name: &str, value: &str) -> Result<(), String> {
+    if !SECRETS.contains(&name) {
+        return Err(format!(
+            "{} is not a secret; expected one of {}",
+            name,
+            SECRETS.join(", ")
+        ));
+    }
+    keyring::Entry::new(SERVICE, name)
+        .and_then(|entry| entry.set_password(value))
+        .map_err(|e| e.to_string())
+}
+
+/// Saves the secrets set in the `.env` style file at `path` in the keyring
+/// and removes them from the file, returning their names. Other variables
+/// in the file are left alone.
+#[cfg(feature = "keyring")]
+pub fn import(path: &std::path::Path) -> Result<Vec<String>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut imported = Vec::new();
+    for (name, value) in crate::config::parse_env_file(&text) {
+        if SECRETS.contains(&name.as_str()) {
+            store(&name, &value).map_err(|e| format!("{}: {}", name, e))?;
+            imported.push(name);
+        }
+    }
+    if !imported.is_empty() {
+        let removed: Vec<(&str, Option<String>)> =
+            imported.iter().map(|name| (name.as_str(), None)).collect();
+        crate::config::save(path, &removed).map_err(|e| {
+            format!("stored in the keyring, but cannot update the file: {}", e)
+        })?;
+    }
+    Ok(imported)
+}
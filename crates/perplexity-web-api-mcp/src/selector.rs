@@ -0,0 +1,137 @@
+//! Virtual model names resolved when a query runs.
+//!
+//! A call may ask for `model: "best"`, `"fastest"` or `"cheapest"` instead
+//! of naming a model. The selector ranks the models of the tool's mode by
+//! their capability metadata and picks the first one that has not recently
+//! been rate limited, so a model whose quota ran out is skipped until it
+//! has had time to recover. Modes without a model choice run with
+//! Perplexity's default.
+
+use perplexity_web_api::{Model, SearchMode, SpeedTier};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// How long a rate-limited model is skipped by selectors.
+const QUOTA_COOLDOWN: Duration = Duration::from_secs(15 * 60);
+
+/// A virtual model name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Selector {
+    /// The most thorough model of the mode.
+    Best,
+    /// The quickest model of the mode.
+    Fastest,
+    /// Perplexity's own model if the mode has one, otherwise the quickest.
+    Cheapest,
+}
+
+impl Selector {
+    /// Recognizes a selector name, ignoring case and surrounding whitespace.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "best" => Some(Self::Best),
+            "fastest" => Some(Self::Fastest),
+            "cheapest" => Some(Self::Cheapest),
+            _ => None,
+        }
+    }
+
+    /// Picks the model to run `mode` with, or `None` if the mode has no
+    /// model choice.
+    ///
+    /// Models rate limited within the cooldown are passed over, unless all
+    /// of the mode's models are.
+    pub fn resolve(self, mode: SearchMode, quota: &ModelQuota) -> Option<Model> {
+        let candidates = self.ranked(mode);
+        candidates
+            .iter()
+            .copied()
+            .find(|model| quota.available(*model))
+            .or_else(|| candidates.first().copied())
+    }
+
+    /// The models [`resolve`](Self::resolve) ranks ahead of its pick but
+    /// passed over because they were rate limited.
+    pub fn passed_over(self, mode: SearchMode, quota: &ModelQuota) -> Vec<Model> {
+        let candidates = self.ranked(mode);
+        let skipped = candidates.iter().take_while(|model| !quota.available(**model)).count();
+        if skipped == candidates.len() {
+            return Vec::new();
+        }
+        candidates[..skipped].to_vec()
+    }
+
+    /// The models of `mode`, most preferred first.
+    fn ranked(self, mode: SearchMode) -> Vec<Model> {
+        let mut candidates: Vec<Model> =
+            Model::ALL.iter().copied().filter(|model| model.mode() == mode).collect();
+        match self {
+            Self::Best => candidates.sort_by_key(|model| std::cmp::Reverse(speed(*model))),
+            Self::Fastest => candidates.sort_by_key(|model| speed(*model)),
+            Self::Cheapest => candidates
+                .sort_by_key(|model| (model.provider() != "Perplexity", speed(*model))),
+        }
+        candidates
+    }
+}
+
+/// Orders speed tiers from fastest to slowest.
+fn speed(model: Model) -> u8 {
+    match model.speed_tier() {
+        SpeedTier::Fast => 0,
+        SpeedTier::Standard => 1,
+        SpeedTier::Slow => 2,
+    }
+}
+
+/// Models that were recently rate limited, shared by all clones.
+#[derive(Debug, Clone, Default)]
+pub struct ModelQuota {
+    exhausted: Arc<Mutex<Vec<(Model, Instant)>>>,
+}
+
+impl ModelQuota {
+    /// Records that `model` was rate limited just now.
+    pub fn exhaust(&self, model: Model) {
+        let mut exhausted = self.exhausted.lock().unwrap_or_else(PoisonError::into_inner);
+        exhausted.retain(|(m, _)| *m != model);
+        exhausted.push((model, Instant::now()));
+    }
+
+    /// Whether `model` has not been rate limited within the cooldown.
+    pub fn available(&self, model: Model) -> bool {
+        let exhausted = self.exhausted.lock().unwrap_or_else(PoisonError::into_inner);
+        !exhausted.iter().any(|(m, at)| *m == model && at.elapsed() < QUOTA_COOLDOWN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selectors_rank_models_and_skip_exhausted() {
+        let quota = ModelQuota::default();
+        assert_eq!(Selector::parse(" Fastest "), Some(Selector::Fastest));
+        assert_eq!(Selector::parse("gpt-5.2"), None);
+
+        assert_eq!(Selector::Fastest.resolve(SearchMode::Pro, &quota), Some(Model::Sonar));
+        assert_eq!(Selector::Best.resolve(SearchMode::Pro, &quota), Some(Model::Gpt52));
+        assert_eq!(Selector::Cheapest.resolve(SearchMode::Pro, &quota), Some(Model::Sonar));
+        assert_eq!(
+            Selector::Fastest.resolve(SearchMode::Reasoning, &quota),
+            Some(Model::Gemini30Pro)
+        );
+        assert_eq!(Selector::Best.resolve(SearchMode::Auto, &quota), None);
+
+        quota.exhaust(Model::Sonar);
+        assert_eq!(Selector::Fastest.resolve(SearchMode::Pro, &quota), Some(Model::Grok41));
+        assert_eq!(Selector::Fastest.passed_over(SearchMode::Pro, &quota), [Model::Sonar]);
+        assert!(Selector::Best.passed_over(SearchMode::Pro, &quota).is_empty());
+        for model in Model::ALL {
+            quota.exhaust(*model);
+        }
+        assert_eq!(Selector::Fastest.resolve(SearchMode::Pro, &quota), Some(Model::Sonar));
+        assert!(Selector::Fastest.passed_over(SearchMode::Pro, &quota).is_empty());
+    }
+}
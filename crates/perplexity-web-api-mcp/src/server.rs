@@ -1,12 +1,69 @@
-use perplexity_web_api::{Client, SearchMode, SearchRequest, Source};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use futures_util::StreamExt;
+use perplexity_web_api::{
+    AnswerStyle, Client, ContextPack, Degradation, GeneratedImage, HostAllowlist, Model,
+    Pipeline, QueryOverflow, Recency, ResearchProvider, SearchEvent, SearchMetrics,
+    SearchMode, SearchRequest, SearchResponse, SearchWebResult, Source, StreamErrorKind,
+    ThreadSummary, UploadFile, estimate_tokens,
+};
 use rmcp::{
-    ErrorData as McpError, ServerHandler,
-    handler::server::{router::tool::ToolRouter, wrapper::Parameters},
-    model::{CallToolResult, Content, ServerCapabilities, ServerInfo},
-    schemars, tool, tool_handler, tool_router,
+    ErrorData as McpError, Peer, RoleServer, ServerHandler,
+    handler::server::{
+        router::tool::{ToolRoute, ToolRouter},
+        tool::ToolCallContext,
+        wrapper::Parameters,
+    },
+    model::{
+        AnnotateAble, CallToolRequestParams, CallToolResult, Content, ErrorCode,
+        Implementation, ListResourcesResult, ListToolsResult, Meta, PaginatedRequestParams,
+        RawResource, ReadResourceRequestParams, ReadResourceResult, Resource,
+        ResourceContents, ServerCapabilities, ServerInfo, SetLevelRequestMethod,
+        SetLevelRequestParams, Tool,
+    },
+    schemars,
+    service::RequestContext,
+    tool, tool_router,
 };
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::{JoinError, JoinHandle};
+use tracing::Instrument;
+
+use crate::about::{AboutResponse, Transport};
+use crate::breaker::CircuitBreaker;
+use crate::cache::{AnswerCache, CacheKey};
+use crate::claims::Claim;
+use crate::code::{CodeSnippet, extract_code_blocks};
+use crate::compare::{self, CompareRequest};
+use crate::condense::Condenser;
+use crate::confidence::Confidence;
+use crate::diff::AnswerDiff;
+use crate::digest::{self, NewsDigestRequest, NewsDigestResponse};
+use crate::failure;
+use crate::fetch::SourceFetcher;
+use crate::jobs::{JobManager, JobOptions, JobStatus};
+use crate::locale::Locale;
+use crate::logging::ClientLog;
+use crate::naming::{ToolNames, ToolNaming};
+use crate::pagecache::PageCache;
+use crate::plan::{self, ResearchPlanRequest};
+use crate::progress::{AnswerProgress, ProgressSink};
+use crate::provider::Fallback;
+use crate::queue::{Priority, Slots};
+use crate::schedule::{ScheduleRecord, Scheduler};
+use crate::selector::{ModelQuota, Selector};
+use crate::settings::{self, EffectiveConfig};
+use crate::signing::{ResultSigner, Signature};
+use crate::template::QueryTemplate;
+use crate::toolset::ToolFilter;
+use crate::usage::{Outcome, UsageStats};
+use crate::util::rfc3339;
+
+#[cfg(feature = "store-sqlite")]
+use crate::history::{HistoryEntry, HistoryMatch, HistoryStore, NewEntry};
 
 /// Request parameters shared by all Perplexity tools.
 #[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
@@ -14,23 +71,137 @@ pub struct PerplexityRequest {
     /// The search query or question to ask.
     pub query: String,
 
-    /// Information sources to search. Valid values: "web", "scholar", "social".
-    /// Defaults to ["web"] if not specified.
+    /// Information sources to search. Valid values: "web", "scholar", "social"
+    /// (case-insensitive). Defaults to ["web"] if not specified.
     #[serde(default)]
     pub sources: Option<Vec<String>>,
 
     /// Language code (ISO 639), e.g., "en-US". Defaults to "en-US".
     #[serde(default)]
     pub language: Option<String>,
+
+    /// Seconds to wait for the complete answer. If exceeded, the best answer
+    /// so far is returned with `partial: true` and a `job_id`; the query keeps
+    /// running in the background and its final result can be fetched with
+    /// `perplexity_job_result`. Waits for the complete answer if not specified.
+    /// Must be shorter than the server's hard deadline.
+    #[serde(default)]
+    pub soft_deadline_secs: Option<u64>,
+
+    /// Include a link to a spoken (text-to-speech) version of the answer as
+    /// `audio_url`, when Perplexity provides one. Defaults to false.
+    #[serde(default)]
+    pub include_audio: Option<bool>,
+
+    /// Also return the answer split into `claims`, each with the citation
+    /// numbers that support it. Defaults to false.
+    #[serde(default)]
+    pub extract_claims: Option<bool>,
+
+    /// Check each claim's citations against the cited web results and list
+    /// those sharing too few words with the claim as `weak_citations`.
+    /// Implies `extract_claims`. Defaults to false.
+    #[serde(default)]
+    pub verify_citations: Option<bool>,
+
+    /// Also return `context_pack`: the web results' distinct snippets as
+    /// one block of text with source headers, at most this many estimated
+    /// tokens, for injecting into another model's prompt.
+    #[serde(default)]
+    pub context_pack_tokens: Option<usize>,
+
+    /// Include the model's thinking before it answered as `reasoning`, for
+    /// Reasoning mode answers. Defaults to the server's setting.
+    #[serde(default)]
+    pub include_reasoning: Option<bool>,
+
+    /// Return at most this many web results, the highest ranked first.
+    /// Defaults to the server's limit, which it cannot exceed.
+    #[serde(default)]
+    pub max_sources: Option<usize>,
+
+    /// Answer length: "concise", "balanced", or "detailed". Defaults to the
+    /// server's configured style for the tool's mode, or Perplexity's default.
+    #[serde(default)]
+    pub answer_style: Option<String>,
+
+    /// Only search sources published within this period: "day", "week",
+    /// "month", or "year". Searches sources of any age if not specified.
+    #[serde(default)]
+    pub recency: Option<String>,
+
+    /// Model to answer with: one listed by perplexity_list_models for the
+    /// tool's mode, or "best", "fastest" or "cheapest" to let the server
+    /// pick one. Defaults to Perplexity's choice for the mode.
+    #[serde(default)]
+    pub model: Option<String>,
+
+    /// Accept a cached answer to the same query (same mode, sources and
+    /// language) if it is at most this many seconds old. Always runs a fresh
+    /// query if not specified.
+    #[serde(default)]
+    pub max_age_secs: Option<u64>,
+
+    /// With `max_age_secs`, return an older cached answer immediately with
+    /// `stale: true` and refresh it in the background, instead of waiting
+    /// for a fresh one. Defaults to false.
+    #[serde(default)]
+    pub stale_while_revalidate: Option<bool>,
+
+    /// Labels for the query, e.g. ["competitors"]. Its answer is kept in the
+    /// cache and the history with them, so perplexity_cache_invalidate can
+    /// purge a topic's answers by tag.
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+
+    /// How urgently to run the query while the server's concurrent query
+    /// limit makes queries wait: "high", "normal", or "low". Defaults to
+    /// "normal", or "low" for background jobs.
+    #[serde(default)]
+    pub priority: Option<Priority>,
+
+    /// Paths of files to attach to the query, such as source files or
+    /// documents to ask about. Relative paths are resolved against the
+    /// client's workspace roots; files outside them are rejected.
+    #[serde(default)]
+    pub files: Option<Vec<String>>,
+
+    /// Confirms running a Deep Research query on servers that require
+    /// confirmation. If omitted there, the user is asked through the client
+    /// when it supports elicitation.
+    #[serde(default)]
+    pub confirm: Option<bool>,
+
+    /// Contents of `files`, read when the tool call is received.
+    #[serde(skip)]
+    pub uploads: Vec<UploadFile>,
 }
 
-/// Parses a source string into a Source enum.
-fn parse_source(s: &str) -> Option<Source> {
-    match s {
-        "web" => Some(Source::Web),
-        "scholar" => Some(Source::Scholar),
-        "social" => Some(Source::Social),
-        _ => None,
+impl PerplexityRequest {
+    /// A request for `query` with every option left at its default.
+    pub fn plain(query: String, sources: Option<Vec<String>>) -> Self {
+        Self {
+            query,
+            sources,
+            language: None,
+            soft_deadline_secs: None,
+            include_audio: None,
+            extract_claims: None,
+            verify_citations: None,
+            context_pack_tokens: None,
+            include_reasoning: None,
+            max_sources: None,
+            answer_style: None,
+            recency: None,
+            model: None,
+            max_age_secs: None,
+            stale_while_revalidate: None,
+            tags: None,
+            priority: None,
+            files: None,
+            confirm: None,
+            uploads: Vec::new(),
+        }
     }
 }
 
@@ -43,8 +214,15 @@ pub struct WebResultInfo {
     /// URL of the web result.
     pub url: String,
 
-    /// Snippet/excerpt from the web result.
+    /// Snippet/excerpt from the web result. Empty (and omitted) in stored
+    /// results when snippet redaction is enabled.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub snippet: String,
+
+    /// Relevance score Perplexity gave the result, when it sent one; higher
+    /// is more relevant.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
 }
 
 /// Response from Perplexity tools.
@@ -58,139 +236,2888 @@ pub struct PerplexityResponse {
 
     /// Context for making follow-up queries.
     pub follow_up: FollowUpInfo,
+
+    /// True if this is an intermediate answer returned at the soft deadline.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub partial: bool,
+
+    /// Background job that will hold the complete answer, when `partial` is true.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<String>,
+
+    /// Changes made to the query before it was sent, e.g. moving text beyond
+    /// Perplexity's length limit into an attachment.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+
+    /// Retries, fallbacks and substitutions that happened while answering,
+    /// so the answer came from a degraded path. Empty if there were none.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub degradations: Vec<DegradationInfo>,
+
+    /// URL of a spoken version of the answer, when requested with `include_audio`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_url: Option<String>,
+
+    /// Search queries Perplexity ran to find the sources, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub search_queries: Vec<String>,
+
+    /// The model that served the answer, as reported by Perplexity.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub served_model: Option<String>,
+
+    /// The model picked for a `model` selector such as "fastest".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved_model: Option<String>,
+
+    /// The model's thinking before it answered, when requested with
+    /// `include_reasoning`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<String>,
+
+    /// The answer's sentences and their citations, when requested with
+    /// `extract_claims`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub claims: Option<Vec<Claim>>,
+
+    /// The web results' snippets packed for another model's prompt, when
+    /// requested with `context_pack_tokens`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_pack: Option<ContextPackInfo>,
+
+    /// Heuristic reliability estimate from hedging language in the answer and
+    /// uncertainty in the reasoning steps. Absent on partial answers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<Confidence>,
+
+    /// Timing and volume statistics for the query. Absent on partial answers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<MetricsInfo>,
+
+    /// True if this cached answer is older than `max_age_secs`; a fresh one
+    /// is being fetched in the background, unless Perplexity is unavailable.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stale: bool,
+
+    /// Age in seconds of the answer, when it was served from the cache.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_age_secs: Option<u64>,
+
+    /// Signature over the rest of the response, when the server has a
+    /// signing key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Signature>,
 }
 
-/// Follow-up context information.
+/// Response from `perplexity_code_search`.
+#[derive(Debug, Serialize)]
+pub struct CodeSearchResponse {
+    #[serde(flatten)]
+    pub response: PerplexityResponse,
+
+    /// Fenced code blocks from the answer, in order.
+    pub code_snippets: Vec<CodeSnippet>,
+}
+
+/// Appended to `perplexity_code_search` queries to steer the answer toward
+/// programming sources and fenced, language-tagged code.
+const CODE_FOCUS: &str = "\n\nFocus on programming sources such as official \
+    documentation, source repositories and developer Q&A sites. Put code in fenced \
+    Markdown blocks tagged with their language.";
+
+/// Latency breakdown and event counts for a completed query.
+///
+/// Times are milliseconds since the query started.
 #[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
-pub struct FollowUpInfo {
-    /// Backend UUID for follow-up queries.
-    pub backend_uuid: Option<String>,
+pub struct MetricsInfo {
+    /// Search mode the query ran in.
+    pub mode: String,
 
-    /// Attachment URLs from the response.
-    pub attachments: Vec<String>,
+    /// Model preference sent to Perplexity, e.g. "pplx_pro".
+    pub model: String,
+
+    /// Time spent uploading attachments.
+    pub upload_ms: u64,
+
+    /// Time until Perplexity accepted the request.
+    pub connect_ms: u64,
+
+    /// Time until the first streamed event.
+    pub first_event_ms: Option<u64>,
+
+    /// Time until the first answer text.
+    pub first_answer_ms: Option<u64>,
+
+    /// Time until the answer was complete.
+    pub total_ms: u64,
+
+    /// Number of streamed events received.
+    pub event_count: usize,
+
+    /// Perplexity's id for the request, when it reported one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_request_id: Option<String>,
+
+    /// When the query started, as an RFC 3339 UTC timestamp.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<String>,
+
+    /// When the answer was complete, as an RFC 3339 UTC timestamp.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<String>,
+
+    /// Time zone the query's relative dates were interpreted in, when the
+    /// server sets one; otherwise the account's own setting applies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
 }
 
-/// MCP server wrapping Perplexity AI client.
-#[derive(Clone)]
-pub struct PerplexityServer {
-    client: Arc<Client>,
-    tool_router: ToolRouter<Self>,
+/// Web result snippets packed into prompt text.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct ContextPackInfo {
+    /// Each distinct snippet under a `[n] title` line and its URL, where `n`
+    /// is the source's citation number.
+    pub text: String,
+
+    /// Citation numbers of the sources in the pack.
+    pub sources: Vec<usize>,
+
+    /// Estimated size of `text` in tokens, erring high.
+    pub estimated_tokens: usize,
+
+    /// True if sources were left out or cut short to fit the budget.
+    pub truncated: bool,
 }
 
-/// Converts a `PerplexityResponse` into a `CallToolResult`.
-fn response_to_tool_result(response: PerplexityResponse) -> Result<CallToolResult, McpError> {
-    let json = serde_json::to_string_pretty(&response).map_err(|e| {
-        McpError::internal_error(format!("JSON serialization error: {}", e), None)
-    })?;
-    Ok(CallToolResult::success(vec![Content::text(json)]))
+/// Something that went wrong while answering and was worked around.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct DegradationInfo {
+    /// What happened: "upload_retry", "session_retry", "provider_fallback",
+    /// "model_substitution", "partial_answer", or "circuit_open".
+    pub kind: String,
+
+    /// Why it happened, typically the error that was worked around.
+    pub reason: String,
+
+    /// How many times it happened.
+    pub count: u32,
 }
 
-impl PerplexityServer {
-    /// Creates a new server instance with the given Perplexity client.
-    pub fn new(client: Client) -> Self {
-        Self { client: Arc::new(client), tool_router: Self::tool_router() }
+impl DegradationInfo {
+    /// A selector picked `model` because the models it prefers were rate
+    /// limited.
+    fn model_substitution(model: Model, passed_over: &[Model]) -> Self {
+        let names: Vec<String> = passed_over.iter().map(ToString::to_string).collect();
+        Self {
+            kind: "model_substitution".to_string(),
+            reason: format!("Ran {} because {} was rate limited", model, names.join(", ")),
+            count: 1,
+        }
     }
+}
 
-    /// Helper to execute a search with the given mode.
-    async fn do_search(
-        &self,
-        params: PerplexityRequest,
-        mode: SearchMode,
-    ) -> Result<PerplexityResponse, McpError> {
-        let mut request = SearchRequest::new(&params.query).mode(mode).incognito(true);
+impl From<Degradation> for DegradationInfo {
+    fn from(degradation: Degradation) -> Self {
+        Self {
+            kind: degradation.kind.as_str().to_string(),
+            reason: degradation.reason,
+            count: degradation.count,
+        }
+    }
+}
 
-        if let Some(sources) = params.sources
-            && !sources.is_empty()
-        {
-            let parsed_sources: Vec<Source> =
-                sources.iter().filter_map(|s| parse_source(s.as_str())).collect();
-            if !parsed_sources.is_empty() {
-                request = request.sources(parsed_sources);
-            }
+impl From<SearchWebResult> for WebResultInfo {
+    fn from(result: SearchWebResult) -> Self {
+        Self {
+            name: result.name,
+            url: result.url,
+            snippet: result.snippet,
+            score: result.score,
         }
+    }
+}
 
-        if let Some(language) = params.language {
-            request = request.language(language);
+impl From<&WebResultInfo> for SearchWebResult {
+    fn from(result: &WebResultInfo) -> Self {
+        Self {
+            name: result.name.clone(),
+            url: result.url.clone(),
+            snippet: result.snippet.clone(),
+            score: result.score,
         }
+    }
+}
 
-        let response = self.client.search(request).await.map_err(|e| {
-            McpError::internal_error(format!("Perplexity API error: {}", e), None)
-        })?;
+impl From<ContextPack> for ContextPackInfo {
+    fn from(pack: ContextPack) -> Self {
+        Self {
+            text: pack.text,
+            sources: pack.sources,
+            estimated_tokens: pack.estimated_tokens,
+            truncated: pack.truncated,
+        }
+    }
+}
+
+impl From<SearchMetrics> for MetricsInfo {
+    fn from(metrics: SearchMetrics) -> Self {
+        Self {
+            mode: metrics.mode.as_str().to_string(),
+            model: metrics.model_preference.to_string(),
+            upload_ms: millis(metrics.upload),
+            connect_ms: millis(metrics.connect),
+            first_event_ms: metrics.first_event.map(millis),
+            first_answer_ms: metrics.first_answer.map(millis),
+            total_ms: millis(metrics.total),
+            event_count: metrics.event_count,
+            server_request_id: metrics.server_request_id,
+            started_at: metrics.started_at.map(rfc3339),
+            finished_at: metrics.finished_at.map(rfc3339),
+            timezone: metrics.timezone,
+        }
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+impl From<SearchResponse> for PerplexityResponse {
+    fn from(response: SearchResponse) -> Self {
+        // Responses built from a single event carry no measurements.
+        let metrics =
+            (response.metrics.event_count > 0).then(|| MetricsInfo::from(response.metrics));
+        let confidence = response.answer.as_deref().map(|answer| {
+            Confidence::assess(answer, &response.raw, response.web_results.len())
+        });
 
-        Ok(PerplexityResponse {
+        Self {
             answer: response.answer,
-            web_results: response
-                .web_results
-                .into_iter()
-                .map(|r| WebResultInfo { name: r.name, url: r.url, snippet: r.snippet })
-                .collect(),
+            web_results: response.web_results.into_iter().map(WebResultInfo::from).collect(),
             follow_up: FollowUpInfo {
                 backend_uuid: response.follow_up.backend_uuid,
                 attachments: response.follow_up.attachments,
             },
-        })
+            partial: false,
+            job_id: None,
+            warnings: response.warnings,
+            degradations: response
+                .degradations
+                .into_iter()
+                .map(DegradationInfo::from)
+                .collect(),
+            audio_url: response.audio.map(|audio| audio.url),
+            search_queries: response.search_queries,
+            served_model: response.served_model,
+            resolved_model: None,
+            reasoning: response.reasoning,
+            claims: None,
+            context_pack: None,
+            confidence,
+            metrics,
+            stale: false,
+            cache_age_secs: None,
+            signature: None,
+        }
     }
 }
 
-#[tool_router]
-impl PerplexityServer {
-    /// Quick web search using Perplexity's turbo model.
-    ///
-    /// Best for: Quick questions, everyday searches, and conversational queries
-    /// that benefit from web context.
-    #[tool(
-        name = "perplexity_search",
-        description = "Quick web search using Perplexity AI. Best for: Quick questions, everyday searches, and conversational queries that benefit from web context."
-    )]
-    pub async fn perplexity_search(
-        &self,
-        Parameters(params): Parameters<PerplexityRequest>,
-    ) -> Result<CallToolResult, McpError> {
-        response_to_tool_result(self.do_search(params, SearchMode::Auto).await?)
+#[cfg(test)]
+impl PerplexityResponse {
+    /// A response with `answer` and no sources, for tests.
+    pub(crate) fn answered(answer: &str) -> Self {
+        Self {
+            answer: Some(answer.to_string()),
+            web_results: Vec::new(),
+            follow_up: FollowUpInfo { backend_uuid: None, attachments: Vec::new() },
+            partial: false,
+            job_id: None,
+            warnings: Vec::new(),
+            degradations: Vec::new(),
+            audio_url: None,
+            search_queries: Vec::new(),
+            served_model: None,
+            resolved_model: None,
+            reasoning: None,
+            claims: None,
+            context_pack: None,
+            confidence: None,
+            metrics: None,
+            stale: false,
+            cache_age_secs: None,
+            signature: None,
+        }
     }
+}
 
-    /// Deep, comprehensive research using Perplexity's sonar-deep-research model.
-    ///
-    /// Best for: Complex topics requiring detailed investigation, comprehensive reports,
-    /// and in-depth analysis. Provides thorough analysis with citations.
-    #[tool(
-        name = "perplexity_research",
-        description = "Deep, comprehensive research using Perplexity AI's sonar-deep-research model. Provides thorough analysis with citations. Best for: Complex topics requiring detailed investigation, comprehensive reports, and in-depth analysis."
-    )]
-    pub async fn perplexity_research(
-        &self,
-        Parameters(params): Parameters<PerplexityRequest>,
-    ) -> Result<CallToolResult, McpError> {
-        response_to_tool_result(self.do_search(params, SearchMode::DeepResearch).await?)
+impl PerplexityResponse {
+    /// Removes snippet text from the web results, keeping their names and URLs,
+    /// and drops the context pack built from it.
+    pub fn redact_snippets(&mut self) {
+        for result in &mut self.web_results {
+            result.snippet.clear();
+        }
+        self.context_pack = None;
     }
 
-    /// Advanced reasoning and problem-solving using Perplexity's sonar-reasoning-pro model.
-    ///
-    /// Best for: Logical problems, complex analysis, decision-making,
-    /// and tasks requiring step-by-step reasoning.
-    #[tool(
-        name = "perplexity_reason",
-        description = "Advanced reasoning and problem-solving using Perplexity AI's sonar-reasoning-pro model. Best for: Logical problems, complex analysis, decision-making, and tasks requiring step-by-step reasoning."
-    )]
-    pub async fn perplexity_reason(
-        &self,
-        Parameters(params): Parameters<PerplexityRequest>,
-    ) -> Result<CallToolResult, McpError> {
-        response_to_tool_result(self.do_search(params, SearchMode::Reasoning).await?)
+    /// Signs the response's current content, replacing any earlier
+    /// signature.
+    pub fn sign(&mut self, signer: &ResultSigner) {
+        self.signature = None;
+        match signer.sign(self) {
+            Ok(signature) => self.signature = Some(signature),
+            Err(e) => tracing::warn!("Failed to sign result: {}", e),
+        }
+    }
+
+    /// Applies the response options of a call: drops what it did not ask
+    /// for and adds the claims and context pack it did.
+    fn shaped(self, shape: ResponseShape) -> Self {
+        self.keep_audio(shape.include_audio)
+            .with_claims(shape.extract_claims, shape.verify_citations)
+            .keep_reasoning(shape.include_reasoning)
+            .limit_sources(shape.max_sources)
+            .with_context_pack(shape.context_pack_tokens)
+    }
+
+    /// Drops the audio link unless the caller asked for it.
+    fn keep_audio(mut self, include_audio: Option<bool>) -> Self {
+        if include_audio != Some(true) {
+            self.audio_url = None;
+        }
+        self
+    }
+
+    /// Drops the model's thinking unless the caller asked for it.
+    fn keep_reasoning(mut self, include_reasoning: bool) -> Self {
+        if !include_reasoning {
+            self.reasoning = None;
+        }
+        self
+    }
+
+    /// Keeps only the first `max_sources` web results, with a warning if
+    /// any were dropped.
+    fn limit_sources(mut self, max_sources: Option<usize>) -> Self {
+        if let Some(max) = max_sources
+            && self.web_results.len() > max
+        {
+            self.warnings.push(format!(
+                "Showing the first {} of {} sources",
+                max,
+                self.web_results.len()
+            ));
+            self.web_results.truncate(max);
+        }
+        self
+    }
+
+    /// Splits the answer into claims if the request asked for them, and
+    /// checks their citations against the web results if asked to.
+    fn with_claims(mut self, extract_claims: Option<bool>, verify: Option<bool>) -> Self {
+        let verify = verify == Some(true);
+        if extract_claims == Some(true) || verify {
+            let mut claims =
+                self.answer.as_deref().map(crate::claims::extract_claims).unwrap_or_default();
+            if verify {
+                let sources: Vec<String> = self
+                    .web_results
+                    .iter()
+                    .map(|result| format!("{} {}", result.name, result.snippet))
+                    .collect();
+                crate::claims::verify_citations(&mut claims, &sources);
+            }
+            self.claims = Some(claims);
+        }
+        self
+    }
+
+    /// Packs the web results for another model's prompt if the request
+    /// asked for it.
+    fn with_context_pack(mut self, max_tokens: Option<usize>) -> Self {
+        if let Some(max_tokens) = max_tokens {
+            let results: Vec<SearchWebResult> =
+                self.web_results.iter().map(SearchWebResult::from).collect();
+            self.context_pack = Some(ContextPack::build(&results, max_tokens).into());
+        }
+        self
     }
 }
 
-#[tool_handler]
-impl ServerHandler for PerplexityServer {
-    fn get_info(&self) -> ServerInfo {
-        ServerInfo {
-            instructions: Some(
-                "Perplexity AI MCP server providing web search, deep research, and reasoning tools. \
-                 Use perplexity_search for quick queries, perplexity_research for comprehensive analysis, \
-                 and perplexity_reason for logical problem-solving."
-                    .into(),
-            ),
-            capabilities: ServerCapabilities::builder().enable_tools().build(),
+/// A call's response options, resolved against the server's settings.
+#[derive(Debug, Clone, Copy)]
+struct ResponseShape {
+    include_audio: Option<bool>,
+    extract_claims: Option<bool>,
+    verify_citations: Option<bool>,
+    include_reasoning: bool,
+    max_sources: Option<usize>,
+    context_pack_tokens: Option<usize>,
+}
+
+/// Follow-up context information.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct FollowUpInfo {
+    /// Backend UUID for follow-up queries.
+    pub backend_uuid: Option<String>,
+
+    /// Attachment URLs from the response.
+    pub attachments: Vec<String>,
+}
+
+/// Request parameters for generating an image.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct GenerateImageRequest {
+    /// Description of the image to generate.
+    pub prompt: String,
+}
+
+/// Request parameters for sharing a thread.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct ShareRequest {
+    /// Thread id (`follow_up.backend_uuid` from a previous answer).
+    pub backend_uuid: String,
+
+    /// Revoke the public link instead of creating it. Defaults to false.
+    #[serde(default)]
+    pub revoke: Option<bool>,
+}
+
+/// Outcome of sharing or unsharing a thread.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct ShareResponse {
+    /// Thread id the request applied to.
+    pub backend_uuid: String,
+
+    /// Public link to the thread; absent when the link was revoked.
+    pub url: Option<String>,
+
+    /// Whether the thread is now publicly viewable.
+    pub shared: bool,
+}
+
+/// Request parameters for reading or renaming a thread.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct ThreadRequest {
+    /// Thread id (`follow_up.backend_uuid` from a previous answer).
+    pub backend_uuid: String,
+
+    /// New title for the thread. Leaves the title unchanged if not specified.
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+/// Request parameters for bookmarking a thread.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct BookmarkRequest {
+    /// Thread id (`follow_up.backend_uuid` from a previous answer).
+    pub backend_uuid: String,
+
+    /// Whether the thread should be bookmarked. Set to false to remove the
+    /// bookmark. Defaults to true.
+    #[serde(default)]
+    pub bookmarked: Option<bool>,
+}
+
+/// Outcome of bookmarking or unbookmarking a thread.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct BookmarkResponse {
+    /// Thread id the request applied to.
+    pub backend_uuid: String,
+
+    /// Whether the thread is now bookmarked.
+    pub bookmarked: bool,
+}
+
+/// Request parameters for listing bookmarked threads.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct BookmarksRequest {
+    /// Maximum number of threads to return (1-100). Defaults to 20.
+    #[serde(default)]
+    pub limit: Option<u32>,
+
+    /// Number of threads to skip, for paging. Defaults to 0.
+    #[serde(default)]
+    pub offset: Option<u32>,
+}
+
+/// Bookmarked threads.
+#[derive(Debug, Clone, Serialize)]
+pub struct BookmarksResponse {
+    /// Threads ordered from most to least recently updated.
+    pub threads: Vec<ThreadSummary>,
+}
+
+/// An answer to compare with [`DiffAnswersRequest`].
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct AnswerSnapshot {
+    /// The answer text.
+    pub answer: String,
+
+    /// URLs of the answer's web results.
+    #[serde(default)]
+    pub citations: Vec<String>,
+}
+
+/// Request parameters for comparing two answers.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct DiffAnswersRequest {
+    /// The earlier answer.
+    pub before: AnswerSnapshot,
+
+    /// The later answer.
+    pub after: AnswerSnapshot,
+}
+
+/// Request parameters for writing an answer to a file.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct AskToFileRequest {
+    #[serde(flatten)]
+    pub request: PerplexityRequest,
+
+    /// File to write the report to. Relative paths are taken from the first
+    /// workspace root, or the server's output directory if it has one.
+    pub path: String,
+
+    /// Search mode: "auto", "pro", "reasoning", or "deep research".
+    /// Defaults to "pro".
+    #[serde(default)]
+    pub mode: Option<String>,
+
+    /// Replace the file if it exists. Defaults to false.
+    #[serde(default)]
+    pub overwrite: Option<bool>,
+}
+
+/// A report written to a file.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct AskToFileResponse {
+    /// Absolute path of the written file.
+    pub path: String,
+
+    /// Size of the report in bytes.
+    pub bytes: usize,
+
+    /// Number of sources listed in the report.
+    pub sources: usize,
+
+    /// Context for making follow-up queries.
+    pub follow_up: FollowUpInfo,
+
+    /// Changes made to the query before it was sent.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// Request parameters for registering a recurring query.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct ScheduleQueryRequest {
+    #[serde(flatten)]
+    pub request: PerplexityRequest,
+
+    /// When to run, as a five-field cron expression in UTC
+    /// ("minute hour day month weekday"), e.g. "0 9 * * 1" for Mondays at
+    /// 09:00. "@hourly", "@daily", "@weekly" and "@monthly" are also accepted.
+    pub cron: String,
+
+    /// Search mode: "auto", "pro", "reasoning", or "deep research".
+    /// Defaults to "pro".
+    #[serde(default)]
+    pub mode: Option<String>,
+}
+
+/// Request parameters for registering a recurring news digest.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct ScheduleDigestRequest {
+    #[serde(flatten)]
+    pub digest: NewsDigestRequest,
+
+    /// When to run, as for perplexity_schedule_query, e.g. "@daily" or
+    /// "0 7 * * 1-5" for weekdays at 07:00 UTC.
+    pub cron: String,
+}
+
+/// Request parameters for cancelling a recurring query.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct ScheduleRequest {
+    /// Identifier returned by perplexity_schedule_query.
+    pub schedule_id: String,
+}
+
+/// Registered recurring queries.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct ScheduleListResponse {
+    /// Schedules ordered by creation time.
+    pub schedules: Vec<ScheduleRecord>,
+}
+
+/// Request parameters for fetching an external source.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct FetchSourceRequest {
+    /// URL to fetch, typically one of an answer's `web_results`.
+    pub url: String,
+
+    /// Return at most this many characters of the page text. Defaults to
+    /// 20000.
+    #[serde(default)]
+    pub max_chars: Option<usize>,
+}
+
+/// A model that can be requested with `model`.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct ModelInfo {
+    /// Name to pass as `model`.
+    pub name: String,
+    /// Search mode the model is available in: "pro" or "reasoning".
+    pub mode: String,
+    /// Company behind the model.
+    pub provider: String,
+    /// Whether the model thinks step by step before answering.
+    pub reasoning: bool,
+    /// How quickly the model typically answers: "fast", "standard" or "slow".
+    pub speed_tier: String,
+}
+
+impl From<Model> for ModelInfo {
+    fn from(model: Model) -> Self {
+        Self {
+            name: model.as_str().to_string(),
+            mode: model.mode().as_str().to_string(),
+            provider: model.provider().to_string(),
+            reasoning: model.is_reasoning(),
+            speed_tier: model.speed_tier().as_str().to_string(),
+        }
+    }
+}
+
+/// Models that can be requested.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct ModelListResponse {
+    /// Models in Perplexity's menu order, pro mode first.
+    pub models: Vec<ModelInfo>,
+}
+
+/// Outcome of cancelling a recurring query.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct ScheduleCancelled {
+    /// Identifier of the cancelled schedule.
+    pub schedule_id: String,
+}
+
+/// Request parameters for starting a background query.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct AsyncAskRequest {
+    #[serde(flatten)]
+    pub request: PerplexityRequest,
+
+    /// Search mode: "auto", "pro", "reasoning", or "deep research".
+    /// Defaults to "deep research".
+    #[serde(default)]
+    pub mode: Option<String>,
+}
+
+/// Acknowledgement of a started background query.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct JobSubmitted {
+    /// Identifier of the job, used with the job status/result tools and
+    /// included in the webhook notification.
+    pub job_id: String,
+
+    /// Initial status of the job.
+    pub status: JobStatus,
+}
+
+/// Request parameters identifying a background job.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct JobRequest {
+    /// Job id returned by `perplexity_ask_async`.
+    pub job_id: String,
+}
+
+/// Progress of a background job.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct JobStatusResponse {
+    /// Identifier of the job.
+    pub job_id: String,
+
+    /// Current status: "queued", "running", "completed", or "failed".
+    pub status: JobStatus,
+
+    /// Search mode the job runs in.
+    pub mode: String,
+
+    /// The query being researched.
+    pub query: String,
+
+    /// Unix timestamp (seconds) when the job was submitted.
+    pub created_at: i64,
+
+    /// Unix timestamp (seconds) when the job started running.
+    pub started_at: Option<i64>,
+
+    /// Unix timestamp (seconds) when the job finished.
+    pub finished_at: Option<i64>,
+
+    /// Error message if the job failed.
+    pub error: Option<String>,
+}
+
+/// Outcome of a background job.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct JobResultResponse {
+    /// Identifier of the job.
+    pub job_id: String,
+
+    /// Current status. `result` is only present once this is "completed".
+    pub status: JobStatus,
+
+    /// The query response, once the job has completed.
+    pub result: Option<PerplexityResponse>,
+
+    /// Error message if the job failed.
+    pub error: Option<String>,
+}
+
+/// Request parameters for purging cached answers.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct CacheInvalidateRequest {
+    /// Purge answers to queries asked with any of these tags. Purges answers
+    /// regardless of tags if not specified, which needs `older_than_secs`.
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+
+    /// Purge only answers older than this many seconds.
+    #[serde(default)]
+    pub older_than_secs: Option<u64>,
+
+    /// Also delete the matching entries from the query history. Defaults to
+    /// false.
+    #[serde(default)]
+    pub include_history: Option<bool>,
+}
+
+/// How many answers were purged.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct CacheInvalidateResponse {
+    /// Answers removed from the cache.
+    pub cache_entries: usize,
+
+    /// Entries deleted from the query history, if it was included.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history_entries: Option<usize>,
+}
+
+/// Request parameters for searching the local query history.
+#[cfg(feature = "store-sqlite")]
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct HistorySearchRequest {
+    /// Text to look for in past queries and answers. Returns the most recent
+    /// entries if not specified.
+    #[serde(default)]
+    pub query: Option<String>,
+
+    /// Maximum number of entries to return (1-100). Defaults to 10.
+    #[serde(default)]
+    pub limit: Option<u32>,
+}
+
+/// Matching entries from the local query history.
+#[cfg(feature = "store-sqlite")]
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct HistorySearchResponse {
+    /// Entries ordered from newest to oldest.
+    pub entries: Vec<HistoryEntry>,
+}
+
+/// Request parameters for looking up prior research on a topic.
+#[cfg(feature = "store-sqlite")]
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct HistoryLookupRequest {
+    /// The question or topic to look for, in natural language.
+    pub question: String,
+
+    /// Maximum number of entries to return (1-20). Defaults to 5.
+    #[serde(default)]
+    pub limit: Option<u32>,
+}
+
+/// Past entries relevant to a lookup question.
+#[cfg(feature = "store-sqlite")]
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct HistoryLookupResponse {
+    /// Matching entries, most relevant first.
+    pub matches: Vec<HistoryMatch>,
+}
+
+/// MCP server wrapping Perplexity AI client.
+#[derive(Clone)]
+pub struct PerplexityServer {
+    client: Arc<Client>,
+    tool_router: ToolRouter<Self>,
+    jobs: JobManager,
+    hard_deadline: Duration,
+    cache: AnswerCache,
+    redact_snippets: bool,
+    research_condenser: Option<Condenser>,
+    confirm_research: bool,
+    heartbeat: Option<Duration>,
+    max_sources: Option<usize>,
+    include_reasoning: bool,
+    quota: ModelQuota,
+    fetcher: SourceFetcher,
+    signer: Option<ResultSigner>,
+    timezone: Option<String>,
+    search_mode: SearchMode,
+    default_model: Option<String>,
+    usage: UsageStats,
+    output_dir: Option<std::path::PathBuf>,
+    pipeline: Pipeline,
+    answer_styles: Vec<(SearchMode, AnswerStyle)>,
+    scheduler: Option<Scheduler>,
+    client_log: Option<ClientLog>,
+    tool_names: ToolNames,
+    locale: Locale,
+    provider: Arc<dyn ResearchProvider>,
+    breaker: Option<Arc<CircuitBreaker>>,
+    cached_on_outage: bool,
+    queries: Slots,
+    transport: Transport,
+    config: Option<Arc<EffectiveConfig>>,
+    #[cfg(feature = "store-sqlite")]
+    history: Option<HistoryStore>,
+}
+
+/// Default limit on how long a query overrunning its soft deadline may keep running.
+const DEFAULT_HARD_DEADLINE: Duration = Duration::from_secs(20 * 60);
+
+/// Default number of answers kept for `max_age_secs` lookups.
+const DEFAULT_CACHE_CAPACITY: usize = 128;
+
+/// Default number of characters `perplexity_fetch_source` returns.
+const DEFAULT_FETCH_CHARS: usize = 20_000;
+
+/// Serializes a tool response into a `CallToolResult` with pretty-printed JSON.
+fn response_to_tool_result(response: impl Serialize) -> Result<CallToolResult, McpError> {
+    let json = serde_json::to_string_pretty(&response).map_err(|e| {
+        McpError::internal_error(format!("JSON serialization error: {}", e), None)
+    })?;
+    Ok(CallToolResult::success(vec![Content::text(json)]))
+}
+
+impl PerplexityServer {
+    /// Creates a new server instance with the given Perplexity client.
+    pub fn new(client: Client) -> Self {
+        let client = Arc::new(client);
+        Self {
+            provider: Arc::clone(&client) as Arc<dyn ResearchProvider>,
+            client,
+            tool_router: Self::tool_router() + Self::jobs_router(),
+            jobs: JobManager::new(JobOptions::default()),
+            hard_deadline: DEFAULT_HARD_DEADLINE,
+            cache: AnswerCache::new(DEFAULT_CACHE_CAPACITY),
+            redact_snippets: false,
+            research_condenser: None,
+            confirm_research: false,
+            heartbeat: None,
+            max_sources: None,
+            include_reasoning: false,
+            quota: ModelQuota::default(),
+            fetcher: SourceFetcher::default(),
+            signer: None,
+            timezone: None,
+            search_mode: SearchMode::Auto,
+            default_model: None,
+            usage: UsageStats::default(),
+            output_dir: None,
+            pipeline: Pipeline::new(),
+            answer_styles: Vec::new(),
+            scheduler: None,
+            client_log: None,
+            tool_names: ToolNames::default(),
+            locale: Locale::default(),
+            breaker: None,
+            cached_on_outage: false,
+            queries: Slots::unlimited(),
+            transport: Transport::default(),
+            config: None,
+            #[cfg(feature = "store-sqlite")]
+            history: None,
+        }
+    }
+
+    /// Answers searches through `provider` instead of the Perplexity client.
+    ///
+    /// Tools that act on the account, such as sharing threads or generating
+    /// images, still use the client; disable them with
+    /// [`with_tool_filter`](Self::with_tool_filter) if it is not usable.
+    pub fn with_provider(mut self, provider: Arc<dyn ResearchProvider>) -> Self {
+        self.provider = provider;
+        self
+    }
+
+    /// Fails searches fast after `failures` consecutive outage errors of the
+    /// current provider, probing it again `cooldown` later. With
+    /// `serve_cached`, queries arriving meanwhile are answered from the
+    /// cache at any age when it has an answer.
+    ///
+    /// Apply before [`with_fallback`](Self::with_fallback), so the fallback
+    /// answers while the breaker is open.
+    pub fn with_circuit_breaker(
+        mut self,
+        failures: u32,
+        cooldown: Duration,
+        serve_cached: bool,
+    ) -> Self {
+        let breaker = Arc::new(CircuitBreaker::new(self.provider, failures, cooldown));
+        self.provider = Arc::clone(&breaker) as Arc<dyn ResearchProvider>;
+        self.breaker = Some(breaker);
+        self.cached_on_outage = serve_cached;
+        self
+    }
+
+    /// Runs at most `limit` Perplexity queries at once. Further queries wait,
+    /// and a freed slot goes to the waiting query of highest `priority`.
+    pub fn with_query_limit(mut self, limit: usize) -> Self {
+        self.queries = Slots::new(limit);
+        self
+    }
+
+    /// Retries searches on `fallback` when the current provider is down or
+    /// rate-limited.
+    pub fn with_fallback(mut self, fallback: Arc<dyn ResearchProvider>) -> Self {
+        self.provider = Arc::new(Fallback::new(self.provider, fallback));
+        self
+    }
+
+    /// Records the transport the server is served over, as reported by
+    /// `perplexity_about`.
+    pub fn with_transport(mut self, transport: Transport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Serves `config` as the `perplexity://config` resource.
+    pub fn with_config(mut self, config: EffectiveConfig) -> Self {
+        self.config = Some(Arc::new(config));
+        self
+    }
+
+    /// Enables the MCP `logging` capability, forwarding records from
+    /// `client_log`'s tracing layer at the level the client sets.
+    pub fn with_client_log(mut self, client_log: ClientLog) -> Self {
+        self.client_log = Some(client_log);
+        self
+    }
+
+    /// Replaces the default in-memory job manager.
+    pub fn with_jobs(mut self, jobs: JobManager) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    /// Sets how long a query that overran its soft deadline may keep running.
+    ///
+    /// Default is 20 minutes.
+    pub fn with_hard_deadline(mut self, hard_deadline: Duration) -> Self {
+        self.hard_deadline = hard_deadline;
+        self
+    }
+
+    /// Sets how many answers are kept for `max_age_secs` lookups. 0 disables
+    /// the cache.
+    ///
+    /// Default is 128.
+    pub fn with_cache_capacity(mut self, capacity: usize) -> Self {
+        self.cache = AnswerCache::new(capacity);
+        self
+    }
+
+    /// Omits snippet text from answers kept in the cache and the history
+    /// store, keeping only result names and URLs.
+    ///
+    /// Background job results are redacted through
+    /// [`JobOptions::redact_snippets`].
+    pub fn with_redacted_snippets(mut self) -> Self {
+        self.redact_snippets = true;
+        self
+    }
+
+    /// Asks clients that support MCP sampling to condense Deep Research
+    /// answers longer than `max_chars` characters.
+    pub fn with_research_max_chars(mut self, max_chars: usize) -> Self {
+        self.research_condenser = Some(Condenser::new(max_chars));
+        self
+    }
+
+    /// Sends a progress notification without a message when a streamed
+    /// answer has not changed for `interval`, to show the query is still
+    /// running.
+    pub fn with_heartbeat(mut self, interval: Duration) -> Self {
+        self.heartbeat = Some(interval);
+        self
+    }
+
+    /// The interval set by [`with_heartbeat`](Self::with_heartbeat).
+    pub fn heartbeat(&self) -> Option<Duration> {
+        self.heartbeat
+    }
+
+    /// Returns at most `max` web results per answer, and no more than that
+    /// when a call asks for more with `max_sources`.
+    pub fn with_max_sources(mut self, max: usize) -> Self {
+        self.max_sources = Some(max);
+        self
+    }
+
+    /// Includes the model's thinking in Reasoning mode answers unless a call
+    /// sets `include_reasoning: false`.
+    pub fn with_reasoning_output(mut self) -> Self {
+        self.include_reasoning = true;
+        self
+    }
+
+    /// Signs answers returned by the search tools with `signer`.
+    ///
+    /// Background job results and history entries are signed by the job
+    /// manager and the history store, which get their own copy of the key.
+    pub fn with_signer(mut self, signer: ResultSigner) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Signs `response` if the server has a signing key.
+    fn signed(&self, mut response: PerplexityResponse) -> PerplexityResponse {
+        if let Some(signer) = &self.signer {
+            response.sign(signer);
+        }
+        response
+    }
+
+    /// Makes `perplexity_fetch_source` refuse hosts not in `hosts` and
+    /// follow at most `max_redirects` redirects.
+    pub fn with_fetch_limits(mut self, hosts: HostAllowlist, max_redirects: usize) -> Self {
+        self.fetcher = self.fetcher.limited_to(hosts, max_redirects);
+        self
+    }
+
+    /// Makes `perplexity_fetch_source` honor each site's robots.txt.
+    pub fn with_robots_txt(mut self) -> Self {
+        self.fetcher = self.fetcher.respecting_robots();
+        self
+    }
+
+    /// Makes `perplexity_fetch_source` keep pages in `cache` and revalidate
+    /// them instead of downloading them again.
+    pub fn with_fetch_cache(mut self, cache: PageCache) -> Self {
+        self.fetcher = self.fetcher.caching_in(cache);
+        self
+    }
+
+    /// Sends `timezone`, an IANA name such as "Europe/Berlin", with every
+    /// query, so that "today" means the same wherever the server runs.
+    pub fn with_timezone(mut self, timezone: String) -> Self {
+        self.timezone = Some(timezone);
+        self
+    }
+
+    /// Answers `perplexity_search` in `mode` instead of Auto.
+    pub fn with_search_mode(mut self, mode: SearchMode) -> Self {
+        self.search_mode = mode;
+        self
+    }
+
+    /// Runs queries that do not name a model with `model`, a model name or
+    /// a selector such as "fastest". A model name only applies to queries
+    /// in the mode it belongs to.
+    pub fn with_default_model(mut self, model: String) -> Self {
+        self.default_model = Some(model);
+        self
+    }
+
+    /// Counts queries in `usage` instead of only in memory.
+    pub fn with_usage_stats(mut self, usage: UsageStats) -> Self {
+        self.usage = usage;
+        self
+    }
+
+    /// Writes the reports of `perplexity_ask_to_file` under `dir` instead of
+    /// the client's workspace roots.
+    pub fn with_output_dir(mut self, dir: std::path::PathBuf) -> Self {
+        self.output_dir = Some(dir);
+        self
+    }
+
+    /// Post-processes every answer with `pipeline` before it is returned,
+    /// cached or recorded.
+    pub fn with_pipeline(mut self, pipeline: Pipeline) -> Self {
+        self.pipeline = pipeline;
+        self
+    }
+
+    /// Requires confirmation before Deep Research queries, which take
+    /// several minutes: either `confirm: true` in the call or the user's
+    /// answer to an elicitation request.
+    pub fn with_research_confirmation(mut self) -> Self {
+        self.confirm_research = true;
+        self
+    }
+
+    /// Sets the answer style used for queries in `mode` that do not request one.
+    pub fn with_answer_style(mut self, mode: SearchMode, style: AnswerStyle) -> Self {
+        self.answer_styles.retain(|(m, _)| *m != mode);
+        self.answer_styles.push((mode, style));
+        self
+    }
+
+    /// Warms up the Perplexity session in the background, so the first
+    /// query does not pay for connection setup. The returned handle
+    /// completes when the warm-up has finished, successfully or not.
+    pub fn warm_up(&self) -> tokio::task::JoinHandle<()> {
+        let client = Arc::clone(&self.client);
+        tokio::spawn(async move {
+            let started = std::time::Instant::now();
+            match client.warm_up().await {
+                Ok(()) => tracing::info!(
+                    elapsed_ms = millis(started.elapsed()),
+                    "Perplexity session warmed up"
+                ),
+                Err(e) => tracing::warn!("Session warm-up failed: {}", e),
+            }
+        })
+    }
+
+    /// Restarts jobs that were still unfinished when the server last exited.
+    pub fn resume_jobs(&self) {
+        for (id, request, mode) in self.jobs.pending() {
+            tracing::info!(job_id = %id, "Resuming job");
+            let server = self.clone();
+            self.jobs.resume(id, async move {
+                server
+                    .complete_search(background(request), mode, None)
+                    .await
+                    .map_err(|e| e.message.into_owned())
+            });
+        }
+    }
+
+    /// Enables the schedule tools, running due queries as background jobs.
+    ///
+    /// Call [`start_scheduler`](Self::start_scheduler) once the server is
+    /// fully configured to begin running them.
+    pub fn with_scheduler(mut self, scheduler: Scheduler) -> Self {
+        self.tool_router += Self::schedule_router();
+        self.scheduler = Some(scheduler);
+        self
+    }
+
+    /// Starts running scheduled queries, if the scheduler is enabled.
+    pub fn start_scheduler(&self) {
+        let Some(scheduler) = &self.scheduler else {
+            return;
+        };
+
+        let server = self.clone();
+        scheduler.start(move |schedule| {
+            let mode = schedule.mode.parse().unwrap_or_default();
+            let (worker, request) = (server.clone(), background(schedule.request.clone()));
+            let digest = schedule.digest.clone().map(|mut digest| {
+                digest.priority.get_or_insert(Priority::Low);
+                digest
+            });
+            server.jobs.submit(schedule.request.clone(), mode, async move {
+                match digest {
+                    Some(digest) => {
+                        worker.news_digest(digest).await.map(NewsDigestResponse::into_response)
+                    }
+                    None => worker.complete_search(request, mode, None).await,
+                }
+                .map_err(|e| e.message.into_owned())
+            })
+        });
+    }
+
+    fn scheduler(&self) -> Result<&Scheduler, McpError> {
+        self.scheduler
+            .as_ref()
+            .ok_or_else(|| McpError::internal_error("Scheduler is not configured", None))
+    }
+
+    fn find_job(&self, job_id: &str) -> Result<crate::jobs::JobRecord, McpError> {
+        self.jobs.get(job_id).ok_or_else(|| {
+            McpError::invalid_params(
+                format!("Unknown job id '{}'; it may have expired", job_id),
+                None,
+            )
+        })
+    }
+
+    /// Records every answered query in `store` and enables the history tools.
+    #[cfg(feature = "store-sqlite")]
+    pub fn with_history(mut self, store: HistoryStore) -> Self {
+        self.tool_router += Self::history_router();
+        self.history = Some(store);
+        self
+    }
+
+    /// Exposes each template as a tool that asks its filled-in query in
+    /// the template's mode.
+    ///
+    /// Like the optional tools, add templates before filtering and renaming.
+    pub fn with_templates(
+        mut self,
+        templates: Vec<(QueryTemplate, SearchMode)>,
+    ) -> Result<Self, String> {
+        for (template, mode) in templates {
+            if self.tool_router.has_route(&template.name) {
+                return Err(format!(
+                    "template {} has the name of another tool",
+                    template.name
+                ));
+            }
+            let tool = template.tool();
+            let template = Arc::new(template);
+            self.tool_router.add_route(ToolRoute::new_dyn(tool, move |context| {
+                let template = template.clone();
+                Box::pin(async move { template_call(&template, mode, context).await })
+            }));
+        }
+        Ok(self)
+    }
+
+    /// Removes the tools excluded by `filter` from the server.
+    ///
+    /// Like [`with_tool_naming`](Self::with_tool_naming), call this after
+    /// enabling optional tools, and before renaming: the filter uses the
+    /// default names.
+    pub fn with_tool_filter(mut self, filter: &ToolFilter) -> Result<Self, String> {
+        let removed = filter.removed(self.tool_router.map.keys().map(AsRef::as_ref))?;
+        for tool in removed {
+            self.tool_router.remove_route(&tool);
+        }
+        Ok(self)
+    }
+
+    /// Shows tool descriptions, the server instructions and error messages
+    /// in `locale`. Tools without a translation, such as templates, keep
+    /// their descriptions.
+    ///
+    /// Call this before [`with_tool_naming`](Self::with_tool_naming): the
+    /// translations mention the default tool names.
+    pub fn with_locale(mut self, locale: Locale) -> Self {
+        for route in self.tool_router.map.values_mut() {
+            if let Some(description) = locale.description(&route.attr.name) {
+                route.attr.description = Some(description.into());
+            }
+        }
+        self.locale = locale;
+        self
+    }
+
+    /// Exposes the tools under the names chosen by `naming`.
+    ///
+    /// Call this after enabling optional tools such as the scheduler and
+    /// history, so that they are renamed too.
+    pub fn with_tool_naming(mut self, naming: &ToolNaming) -> Result<Self, String> {
+        let names = naming.resolve(self.tool_router.map.keys().map(AsRef::as_ref))?;
+        let routes: Vec<_> = self.tool_router.map.drain().map(|(_, route)| route).collect();
+        for mut route in routes {
+            names.rename_tool(&mut route.attr);
+            self.tool_router.add_route(route);
+        }
+        self.tool_names = names;
+        Ok(self)
+    }
+
+    /// The tools the server exposes, sorted by name.
+    pub fn tools(&self) -> Vec<Tool> {
+        let mut tools = self.tool_router.list_all();
+        tools.sort_by(|a, b| a.name.cmp(&b.name));
+        tools
+    }
+
+    /// Everything the server advertises to an MCP client: its name, version,
+    /// instructions and capabilities, and its tool, prompt and resource
+    /// definitions.
+    pub fn definitions(&self) -> serde_json::Value {
+        let info = self.get_info();
+        serde_json::json!({
+            "server": info.server_info,
+            "protocolVersion": info.protocol_version,
+            "instructions": info.instructions,
+            "capabilities": info.capabilities,
+            "tools": self.tools(),
+            "prompts": [],
+            "resources": self.resources(),
+            "resourceTemplates": [],
+        })
+    }
+
+    /// The resources the server exposes.
+    fn resources(&self) -> Vec<Resource> {
+        if self.config.is_none() {
+            return Vec::new();
+        }
+        let mut config = RawResource::new(settings::URI, "config");
+        config.description = Some(
+            "The settings this server started with and where each came from, with secrets \
+             redacted."
+                .to_string(),
+        );
+        config.mime_type = Some("application/json".to_string());
+        vec![config.no_annotation()]
+    }
+
+    /// Helper to execute a search with the given mode.
+    ///
+    /// Honors `soft_deadline_secs` by handing the query off to a background
+    /// job when it overruns.
+    async fn do_search(
+        &self,
+        mut params: PerplexityRequest,
+        mode: SearchMode,
+        progress: Option<AnswerProgress>,
+    ) -> Result<PerplexityResponse, McpError> {
+        // A background job would have no time left to finish the search.
+        if let Some(secs) = params.soft_deadline_secs
+            && Duration::from_secs(secs) >= self.hard_deadline
+        {
+            return Err(McpError::invalid_params(
+                format!(
+                    "soft_deadline_secs must be less than the hard deadline of {}s",
+                    self.hard_deadline.as_secs()
+                ),
+                None,
+            ));
+        }
+        let (resolved_model, substitution) = self.resolve_model(&mut params, mode);
+        let cached =
+            self.cached_search(&params, mode).or_else(|| self.outage_answer(&params, mode));
+        if let Some(mut response) = cached {
+            if let Some(progress) = progress {
+                progress.finish().await;
+            }
+            self.record_usage(mode, Outcome::Cached, response.answer.as_deref());
+            response.resolved_model = resolved_model;
+            return Ok(response);
+        }
+
+        #[cfg(feature = "otel")]
+        let started = std::time::Instant::now();
+
+        let sink = progress.as_ref().map(AnswerProgress::sink);
+        let result = match params.soft_deadline_secs {
+            Some(secs) => {
+                self.search_with_deadline(params, mode, Duration::from_secs(secs), sink).await
+            }
+            None => self.complete_search(params, mode, sink.as_ref()).await,
+        };
+        if let Some(progress) = progress {
+            progress.finish().await;
+        }
+
+        #[cfg(feature = "otel")]
+        crate::otel::record_query(
+            mode.as_str(),
+            match &result {
+                Ok(response) if response.partial => "partial",
+                Ok(_) => "complete",
+                Err(_) => "error",
+            },
+            started.elapsed(),
+        );
+
+        result.map(|mut response| {
+            response.resolved_model = resolved_model;
+            response.degradations.extend(substitution);
+            response
+        })
+    }
+
+    /// Answers from the cache at any age while the circuit breaker fails
+    /// calls fast, if enabled.
+    fn outage_answer(
+        &self,
+        params: &PerplexityRequest,
+        mode: SearchMode,
+    ) -> Option<PerplexityResponse> {
+        if !self.cached_on_outage {
+            return None;
+        }
+        let retry_after = self.breaker.as_ref()?.retry_after()?;
+        let (response, age) = self.cache.get(&CacheKey::new(params, mode))?;
+        tracing::info!(
+            age_secs = age.as_secs(),
+            "Provider unavailable, serving cached answer"
+        );
+
+        let mut response = response.shaped(self.shape(params));
+        response.stale = true;
+        response.cache_age_secs = Some(age.as_secs());
+        response.degradations.push(DegradationInfo {
+            kind: "circuit_open".to_string(),
+            reason: format!(
+                "{} is unavailable; answered from the cache, retry after {}s",
+                self.provider.name(),
+                retry_after.as_secs().max(1)
+            ),
+            count: 1,
+        });
+        Some(response)
+    }
+
+    /// Fills in the default model if the call names none, replaces a
+    /// selector such as "fastest" in the call's `model` with the model it
+    /// picks for `mode`, and returns the picked model's name, with a
+    /// degradation if rate limits made the selector pass over models it
+    /// prefers.
+    fn resolve_model(
+        &self,
+        params: &mut PerplexityRequest,
+        mode: SearchMode,
+    ) -> (Option<String>, Option<DegradationInfo>) {
+        if params.model.is_none() {
+            params.model = self.default_model.clone().filter(|model| {
+                model.parse::<Model>().map_or(true, |model| model.mode() == mode)
+            });
+        }
+        let Some(selector) = params.model.as_deref().and_then(Selector::parse) else {
+            return (None, None);
+        };
+        let model = selector.resolve(mode, &self.quota);
+        params.model = model.map(|model| model.to_string());
+        tracing::debug!(?selector, model = ?params.model, "Resolved model selector");
+        let passed_over = selector.passed_over(mode, &self.quota);
+        let substitution = model
+            .filter(|_| !passed_over.is_empty())
+            .map(|model| DegradationInfo::model_substitution(model, &passed_over));
+        (params.model.clone(), substitution)
+    }
+
+    /// Keeps selectors away from the call's model for a while if Perplexity
+    /// rate limited it.
+    fn note_rate_limit(&self, params: &PerplexityRequest, error: &perplexity_web_api::Error) {
+        if error.is_rate_limited()
+            && let Some(model) = params.model.as_deref().and_then(|m| m.parse::<Model>().ok())
+        {
+            tracing::info!(%model, "Model rate limited, skipping it in selectors");
+            self.quota.exhaust(model);
+        }
+    }
+
+    /// Returns the copy of `response` to keep in the cache, with snippets
+    /// removed if redaction is enabled.
+    fn stored_copy(&self, response: &PerplexityResponse) -> PerplexityResponse {
+        let mut stored = response.clone();
+        if self.redact_snippets {
+            stored.redact_snippets();
+        }
+        stored
+    }
+
+    /// Answers from the cache if the request allows it.
+    ///
+    /// Returns a fresh-enough answer as is. With stale-while-revalidate, an
+    /// older answer is returned flagged as stale, and a refresh is started
+    /// unless one is already running.
+    fn cached_search(
+        &self,
+        params: &PerplexityRequest,
+        mode: SearchMode,
+    ) -> Option<PerplexityResponse> {
+        let max_age = Duration::from_secs(params.max_age_secs?);
+        let key = CacheKey::new(params, mode);
+        let (mut response, age) = self.cache.get(&key)?;
+
+        response.cache_age_secs = Some(age.as_secs());
+        if age <= max_age {
+            self.cache.tag(&key, crate::cache::tags(params));
+            return Some(response.shaped(self.shape(params)));
+        }
+        if params.stale_while_revalidate != Some(true) {
+            return None;
+        }
+
+        if self.cache.begin_refresh(&key) {
+            tracing::info!(age_secs = age.as_secs(), "Serving stale answer, refreshing");
+            let server = self.clone();
+            let params = background(params.clone());
+            tokio::spawn(
+                async move {
+                    let refreshed = tokio::time::timeout(
+                        server.hard_deadline,
+                        server.complete_search(params, mode, None),
+                    )
+                    .await;
+                    match refreshed {
+                        Ok(Ok(_)) => {}
+                        Ok(Err(e)) => {
+                            tracing::warn!("Background refresh failed: {}", e.message);
+                            server.cache.end_refresh(&key);
+                        }
+                        Err(_) => {
+                            tracing::warn!("Background refresh exceeded the hard deadline");
+                            server.cache.end_refresh(&key);
+                        }
+                    }
+                }
+                .in_current_span(),
+            );
+        }
+
+        response.stale = true;
+        Some(response.shaped(self.shape(params)))
+    }
+
+    /// Web results to return for a call: its `max_sources`, capped by the
+    /// server's limit.
+    fn max_sources(&self, params: &PerplexityRequest) -> Option<usize> {
+        match (params.max_sources, self.max_sources) {
+            (Some(requested), Some(limit)) => Some(requested.min(limit)),
+            (requested, limit) => requested.or(limit),
+        }
+    }
+
+    /// Whether a call's answer keeps the model's thinking: its
+    /// `include_reasoning`, or the server's setting.
+    fn include_reasoning(&self, params: &PerplexityRequest) -> bool {
+        params.include_reasoning.unwrap_or(self.include_reasoning)
+    }
+
+    /// The response options of a call with `params`.
+    fn shape(&self, params: &PerplexityRequest) -> ResponseShape {
+        ResponseShape {
+            include_audio: params.include_audio,
+            extract_claims: params.extract_claims,
+            verify_citations: params.verify_citations,
+            include_reasoning: self.include_reasoning(params),
+            max_sources: self.max_sources(params),
+            context_pack_tokens: params.context_pack_tokens,
+        }
+    }
+
+    /// Executes a search and waits for the complete answer.
+    pub async fn complete_search(
+        &self,
+        params: PerplexityRequest,
+        mode: SearchMode,
+        progress: Option<&ProgressSink>,
+    ) -> Result<PerplexityResponse, McpError> {
+        let request = self.build_request(&params, mode)?;
+
+        let permit = self.queries.acquire(params.priority.unwrap_or_default()).await;
+        let mut response = self
+            .provider
+            .research(request, &mut |event| {
+                if let (Some(progress), Some(answer)) = (progress, &event.answer) {
+                    progress.update(answer);
+                }
+            })
+            .await
+            .map_err(|e| {
+                self.note_rate_limit(&params, &e);
+                self.record_usage(mode, Outcome::Failed, None);
+                api_error(e)
+            })?;
+        drop(permit);
+        self.record_usage(mode, Outcome::Answered, response.answer.as_deref());
+        self.pipeline.run(&mut response);
+        let response = PerplexityResponse::from(response);
+        let tags = crate::cache::tags(&params);
+        self.cache.insert(CacheKey::new(&params, mode), self.stored_copy(&response), tags);
+        let response = response.shaped(self.shape(&params));
+
+        #[cfg(feature = "store-sqlite")]
+        self.record_history(mode, &params, &response).await;
+
+        Ok(response)
+    }
+
+    /// Executes a search, returning the best answer so far if it takes longer
+    /// than `soft_deadline`.
+    async fn search_with_deadline(
+        &self,
+        params: PerplexityRequest,
+        mode: SearchMode,
+        soft_deadline: Duration,
+        progress: Option<ProgressSink>,
+    ) -> Result<PerplexityResponse, McpError> {
+        let request = self.build_request(&params, mode)?;
+        let (best_tx, best_rx) = watch::channel(None);
+        let provider = Arc::clone(&self.provider);
+        let pipeline = self.pipeline.clone();
+        let (queries, priority) = (self.queries.clone(), params.priority.unwrap_or_default());
+
+        let mut task = tokio::spawn(
+            async move {
+                let permit = queries.acquire(priority).await;
+                let mut response = provider
+                    .research(request, &mut |event| {
+                        if let Some(answer) = &event.answer {
+                            if let Some(progress) = &progress {
+                                progress.update(answer);
+                            }
+                            best_tx.send_replace(Some(event.clone()));
+                        }
+                    })
+                    .await?;
+                drop(permit);
+                pipeline.run(&mut response);
+                Ok(response)
+            }
+            .in_current_span(),
+        );
+
+        let Ok(joined) = tokio::time::timeout(soft_deadline, &mut task).await else {
+            return Ok(self.hand_off(params, mode, soft_deadline, task, &best_rx));
+        };
+        if let Ok(Err(e)) = &joined {
+            self.note_rate_limit(&params, e);
+        }
+        match &joined {
+            Ok(Ok(response)) => {
+                self.record_usage(mode, Outcome::Answered, response.answer.as_deref());
+            }
+            _ => self.record_usage(mode, Outcome::Failed, None),
+        }
+
+        let response = PerplexityResponse::from(join_search(joined)?);
+        let tags = crate::cache::tags(&params);
+        self.cache.insert(CacheKey::new(&params, mode), self.stored_copy(&response), tags);
+        let response = response.shaped(self.shape(&params));
+
+        #[cfg(feature = "store-sqlite")]
+        self.record_history(mode, &params, &response).await;
+
+        Ok(response)
+    }
+
+    /// Moves an overrunning search into a background job and returns the
+    /// latest intermediate answer, flagged as partial.
+    fn hand_off(
+        &self,
+        params: PerplexityRequest,
+        mode: SearchMode,
+        soft_deadline: Duration,
+        task: JoinHandle<perplexity_web_api::Result<SearchResponse>>,
+        progress: &watch::Receiver<Option<SearchEvent>>,
+    ) -> PerplexityResponse {
+        let remaining = self.hard_deadline.saturating_sub(soft_deadline);
+        let hard_deadline = self.hard_deadline;
+        let shape = self.shape(&params);
+        let cache_key = CacheKey::new(&params, mode);
+        let tags = crate::cache::tags(&params);
+        let server = self.clone();
+        #[cfg(feature = "store-sqlite")]
+        let request = params.clone();
+
+        let job_id = self.jobs.adopt(params, mode, async move {
+            let abort = task.abort_handle();
+            let Ok(joined) = tokio::time::timeout(remaining, task).await else {
+                abort.abort();
+                server.record_usage(mode, Outcome::Failed, None);
+                return Err(format!("Hard deadline of {:?} exceeded", hard_deadline));
+            };
+            match &joined {
+                Ok(Ok(response)) => {
+                    server.record_usage(mode, Outcome::Answered, response.answer.as_deref());
+                }
+                _ => server.record_usage(mode, Outcome::Failed, None),
+            }
+
+            let response = PerplexityResponse::from(
+                join_search(joined).map_err(|e| e.message.into_owned())?,
+            );
+            server.cache.insert(cache_key, server.stored_copy(&response), tags);
+            let response = response.shaped(shape);
+
+            #[cfg(feature = "store-sqlite")]
+            server.record_history(mode, &request, &response).await;
+
+            Ok(response)
+        });
+
+        tracing::info!(job_id = %job_id, "Soft deadline exceeded, continuing in background");
+
+        let best = progress.borrow().clone();
+        let mut partial = SearchResponse::from(best.unwrap_or_default());
+        self.pipeline.run(&mut partial);
+        let mut response = PerplexityResponse::from(partial).shaped(shape);
+        response.partial = true;
+        response.confidence = None;
+        response.degradations.push(DegradationInfo {
+            kind: "partial_answer".to_string(),
+            reason: format!(
+                "Soft deadline of {}s exceeded; the complete answer is job {}",
+                soft_deadline.as_secs(),
+                job_id
+            ),
+            count: 1,
+        });
+        response.job_id = Some(job_id);
+        response
+    }
+
+    /// Builds a library search request from tool parameters.
+    fn build_request(
+        &self,
+        params: &PerplexityRequest,
+        mode: SearchMode,
+    ) -> Result<SearchRequest, McpError> {
+        let mut request = SearchRequest::new(&params.query)
+            .mode(mode)
+            .incognito(true)
+            .on_overflow(QueryOverflow::Attach);
+
+        if let Some(sources) = &params.sources
+            && !sources.is_empty()
+        {
+            let parsed_sources = sources
+                .iter()
+                .map(|s| s.parse::<Source>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
+            request = request.sources(parsed_sources);
+        }
+
+        if let Some(language) = &params.language {
+            request = request.language(language);
+        }
+        if let Some(timezone) = &self.timezone {
+            request = request.timezone(timezone);
+        }
+
+        let style = match &params.answer_style {
+            Some(style) => Some(
+                style
+                    .parse::<AnswerStyle>()
+                    .map_err(|e| McpError::invalid_params(e.to_string(), None))?,
+            ),
+            None => {
+                self.answer_styles.iter().find(|(m, _)| *m == mode).map(|(_, style)| *style)
+            }
+        };
+        if let Some(style) = style {
+            request = request.answer_style(style);
+        }
+        if let Some(recency) = &params.recency {
+            let recency = recency
+                .parse::<Recency>()
+                .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
+            request = request.recency(recency);
+        }
+        if let Some(model) = &params.model {
+            let model = model
+                .parse::<Model>()
+                .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
+            request = request.model(model);
+        }
+        if let Some(id) = crate::correlation::current() {
+            request = request.request_id(id);
+        }
+
+        if params.files.as_ref().is_some_and(|files| files.len() != params.uploads.len()) {
+            return Err(McpError::invalid_params(
+                "Attached files are no longer available; repeat the query",
+                None,
+            ));
+        }
+        for file in &params.uploads {
+            request = request.file(file.clone());
+        }
+
+        Ok(request)
+    }
+
+    /// Reads the files named in `params` from the client's workspace roots.
+    async fn attach_files(
+        &self,
+        mut params: PerplexityRequest,
+        peer: &Peer<RoleServer>,
+    ) -> Result<PerplexityRequest, McpError> {
+        let Some(paths) = params.files.take().filter(|paths| !paths.is_empty()) else {
+            return Ok(params);
+        };
+        let files = crate::roots::read_files(peer, paths).await?;
+        params.files = Some(files.iter().map(|f| crate::roots::display(&f.path)).collect());
+        params.uploads = files.into_iter().map(|f| f.file).collect();
+        Ok(params)
+    }
+
+    /// Asks one query per item, a few at a time, pairing each item with its
+    /// answer or the reason it failed. Fails only if every query does.
+    async fn ask_all(
+        &self,
+        items: Vec<String>,
+        mode: SearchMode,
+        request: impl Fn(&str) -> PerplexityRequest,
+    ) -> Result<Vec<(String, Result<PerplexityResponse, String>)>, McpError> {
+        let answers: Vec<(String, Result<PerplexityResponse, String>)> =
+            futures_util::stream::iter(items)
+                .map(|item| {
+                    let params = request(&item);
+                    async move {
+                        let result = self
+                            .do_search(params, mode, None)
+                            .await
+                            .map_err(|e| e.message.to_string());
+                        (item, result)
+                    }
+                })
+                .buffered(plan::CONCURRENCY)
+                .collect()
+                .await;
+        if answers.iter().all(|(_, result)| result.is_err()) {
+            let reason =
+                answers.into_iter().find_map(|(_, result)| result.err()).unwrap_or_default();
+            return Err(McpError::internal_error(
+                format!("No query could be answered: {}", reason),
+                None,
+            ));
+        }
+        Ok(answers)
+    }
+
+    /// Runs a news digest, for the tool and for scheduled digests.
+    async fn news_digest(
+        &self,
+        params: NewsDigestRequest,
+    ) -> Result<NewsDigestResponse, McpError> {
+        let window =
+            digest::validate(&params).map_err(|e| McpError::invalid_params(e, None))?;
+        let mode = match params.mode {
+            Some(mode) => mode
+                .parse::<SearchMode>()
+                .map_err(|e| McpError::invalid_params(e.to_string(), None))?,
+            None => SearchMode::Pro,
+        };
+        let answers = self
+            .ask_all(params.topics, mode, |topic| PerplexityRequest {
+                recency: Some(window.as_str().to_string()),
+                priority: params.priority,
+                ..PerplexityRequest::plain(
+                    digest::topic_query(topic, window),
+                    params.sources.clone(),
+                )
+            })
+            .await?;
+        Ok(digest::assemble(window, answers))
+    }
+
+    /// Counts a query in the usage statistics, off the async runtime since
+    /// they may be kept in a file.
+    fn record_usage(&self, mode: SearchMode, outcome: Outcome, answer: Option<&str>) {
+        let usage = self.usage.clone();
+        let tokens = answer.map_or(0, |answer| estimate_tokens(answer) as u64);
+        tokio::task::spawn_blocking(move || usage.record(mode, outcome, tokens));
+    }
+
+    /// Deletes history entries matching `tags` recorded more than
+    /// `older_than_secs` ago.
+    #[cfg(feature = "store-sqlite")]
+    async fn purge_history(
+        &self,
+        tags: Vec<String>,
+        older_than_secs: Option<u64>,
+    ) -> Result<usize, McpError> {
+        let Some(store) = self.history.clone() else {
+            return Err(McpError::invalid_params("The query history is not enabled", None));
+        };
+        let before = older_than_secs
+            .map(|secs| crate::util::unix_now() - i64::try_from(secs).unwrap_or(i64::MAX));
+        tokio::task::spawn_blocking(move || store.purge(&tags, before))
+            .await
+            .map_err(|e| {
+                McpError::internal_error(format!("History task failed: {}", e), None)
+            })?
+            .map_err(|e| {
+                McpError::internal_error(format!("History purge failed: {}", e), None)
+            })
+    }
+
+    #[cfg(not(feature = "store-sqlite"))]
+    async fn purge_history(
+        &self,
+        _tags: Vec<String>,
+        _older_than_secs: Option<u64>,
+    ) -> Result<usize, McpError> {
+        Err(McpError::invalid_params("The query history is not enabled", None))
+    }
+
+    /// Stores a completed query in the history database, if one is configured.
+    ///
+    /// Failures are logged rather than returned so that a broken store never
+    /// costs the caller an answer they already paid for.
+    #[cfg(feature = "store-sqlite")]
+    async fn record_history(
+        &self,
+        mode: SearchMode,
+        params: &PerplexityRequest,
+        response: &PerplexityResponse,
+    ) {
+        let Some(store) = self.history.clone() else {
+            return;
+        };
+
+        let answer = response.answer.clone();
+        let mut web_results = response.web_results.clone();
+        if self.redact_snippets {
+            web_results.iter_mut().for_each(|result| result.snippet.clear());
+        }
+        let backend_uuid = response.follow_up.backend_uuid.clone();
+        let query = params.query.clone();
+        let tags = crate::cache::tags(params);
+
+        let result = tokio::task::spawn_blocking(move || {
+            store.record(&NewEntry {
+                mode: mode.as_str(),
+                query: &query,
+                answer: answer.as_deref(),
+                web_results: &web_results,
+                backend_uuid: backend_uuid.as_deref(),
+                tags: &tags,
+            })
+        })
+        .await;
+
+        match result {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => tracing::warn!("Failed to record query history: {}", e),
+            Err(e) => tracing::warn!("History task failed: {}", e),
+        }
+    }
+}
+
+fn api_error(e: perplexity_web_api::Error) -> McpError {
+    let (code, kind) = match e {
+        perplexity_web_api::Error::StreamEvent { kind: StreamErrorKind::Rejected, .. } => {
+            (ErrorCode::INVALID_REQUEST, failure::QUERY_REJECTED)
+        }
+        perplexity_web_api::Error::Refused { .. } => {
+            (ErrorCode::INVALID_REQUEST, failure::REFUSED)
+        }
+        perplexity_web_api::Error::InvalidParameter { .. }
+        | perplexity_web_api::Error::InvalidModelForMode { .. }
+        | perplexity_web_api::Error::UnsupportedAttachmentType { .. }
+        | perplexity_web_api::Error::AttachmentTypeMismatch { .. } => {
+            (ErrorCode::INVALID_PARAMS, failure::INVALID_PARAMS)
+        }
+        perplexity_web_api::Error::ProviderUnavailable { retry_after, .. } => {
+            return McpError::internal_error(
+                format!("Perplexity API error: {}", e),
+                Some(serde_json::json!({
+                    failure::CODE_KEY: failure::UNAVAILABLE,
+                    "retry_after_secs": retry_after.as_secs().max(1),
+                })),
+            );
+        }
+        _ => (ErrorCode::INTERNAL_ERROR, failure::UPSTREAM),
+    };
+    failure::coded(code, format!("Perplexity API error: {}", e), kind)
+}
+
+/// Marks `request` as background work, unless it asks for a priority.
+fn background(mut request: PerplexityRequest) -> PerplexityRequest {
+    request.priority.get_or_insert(Priority::Low);
+    request
+}
+
+/// Unwraps the outcome of a spawned search task.
+fn join_search(
+    joined: Result<perplexity_web_api::Result<SearchResponse>, JoinError>,
+) -> Result<SearchResponse, McpError> {
+    joined
+        .map_err(|e| {
+            if e.is_panic() {
+                tracing::error!("Search task panicked: {}", e);
+                failure::coded(
+                    ErrorCode::INTERNAL_ERROR,
+                    "Search task panicked",
+                    failure::PANIC,
+                )
+            } else {
+                McpError::internal_error(format!("Search task failed: {}", e), None)
+            }
+        })?
+        .map_err(api_error)
+}
+
+/// Answers a call to a template's tool.
+async fn template_call(
+    template: &QueryTemplate,
+    mode: SearchMode,
+    context: ToolCallContext<'_, PerplexityServer>,
+) -> Result<CallToolResult, McpError> {
+    let query = template
+        .render(context.arguments.as_ref())
+        .map_err(|e| McpError::invalid_params(e, None))?;
+    let params = PerplexityRequest {
+        answer_style: template.answer_style.clone(),
+        model: template.model.clone(),
+        ..PerplexityRequest::plain(query, template.sources.clone())
+    };
+    let server = context.service;
+    let RequestContext { meta, peer, .. } = context.request_context;
+    let progress = AnswerProgress::start(&meta, peer, server.heartbeat);
+    let response = server.do_search(params, mode, progress).await?;
+    response_to_tool_result(server.signed(response))
+}
+
+#[tool_router]
+impl PerplexityServer {
+    /// Quick web search using Perplexity's turbo model.
+    ///
+    /// Best for: Quick questions, everyday searches, and conversational queries
+    /// that benefit from web context.
+    #[tool(
+        name = "perplexity_search",
+        description = "Quick web search using Perplexity AI. Best for: Quick questions, everyday searches, and conversational queries that benefit from web context."
+    )]
+    pub async fn perplexity_search(
+        &self,
+        Parameters(params): Parameters<PerplexityRequest>,
+        meta: Meta,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let params = self.attach_files(params, &peer).await?;
+        let progress = AnswerProgress::start(&meta, peer, self.heartbeat);
+        let response = self.do_search(params, self.search_mode, progress).await?;
+        response_to_tool_result(self.signed(response))
+    }
+
+    /// Deep, comprehensive research using Perplexity's sonar-deep-research model.
+    ///
+    /// Best for: Complex topics requiring detailed investigation, comprehensive reports,
+    /// and in-depth analysis. Provides thorough analysis with citations.
+    #[tool(
+        name = "perplexity_research",
+        description = "Deep, comprehensive research using Perplexity AI's sonar-deep-research model. Provides thorough analysis with citations. Best for: Complex topics requiring detailed investigation, comprehensive reports, and in-depth analysis."
+    )]
+    pub async fn perplexity_research(
+        &self,
+        Parameters(params): Parameters<PerplexityRequest>,
+        meta: Meta,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        if self.confirm_research {
+            crate::confirm::research(&peer, &params).await?;
+        }
+        let params = self.attach_files(params, &peer).await?;
+        let progress = AnswerProgress::start(&meta, peer.clone(), self.heartbeat);
+        let mut response = self.do_search(params, SearchMode::DeepResearch, progress).await?;
+        if let Some(condenser) = &self.research_condenser {
+            condenser.condense(&peer, &mut response).await;
+        }
+        response_to_tool_result(self.signed(response))
+    }
+
+    /// Advanced reasoning and problem-solving using Perplexity's sonar-reasoning-pro model.
+    ///
+    /// Best for: Logical problems, complex analysis, decision-making,
+    /// and tasks requiring step-by-step reasoning.
+    #[tool(
+        name = "perplexity_reason",
+        description = "Advanced reasoning and problem-solving using Perplexity AI's sonar-reasoning-pro model. Best for: Logical problems, complex analysis, decision-making, and tasks requiring step-by-step reasoning."
+    )]
+    pub async fn perplexity_reason(
+        &self,
+        Parameters(params): Parameters<PerplexityRequest>,
+        meta: Meta,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let params = self.attach_files(params, &peer).await?;
+        let progress = AnswerProgress::start(&meta, peer, self.heartbeat);
+        let response = self.do_search(params, SearchMode::Reasoning, progress).await?;
+        response_to_tool_result(self.signed(response))
+    }
+
+    /// Programming-focused search that also returns the answer's code blocks.
+    #[tool(
+        name = "perplexity_code_search",
+        description = "Programming-focused search using Perplexity AI's pro mode, biased toward documentation, source repositories and developer Q&A. Returns the answer plus `code_snippets`: its fenced code blocks with language tags. Best for: API usage, error messages, and code examples."
+    )]
+    pub async fn perplexity_code_search(
+        &self,
+        Parameters(mut params): Parameters<PerplexityRequest>,
+        meta: Meta,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        params.query.push_str(CODE_FOCUS);
+        let params = self.attach_files(params, &peer).await?;
+        let progress = AnswerProgress::start(&meta, peer, self.heartbeat);
+        let response = self.do_search(params, SearchMode::Pro, progress).await?;
+        let code_snippets =
+            response.answer.as_deref().map(extract_code_blocks).unwrap_or_default();
+        let mut result = CodeSearchResponse { response, code_snippets };
+        if let Some(signer) = &self.signer {
+            // Sign the snippets too, since they are part of the same object.
+            result.response.signature = None;
+            match signer.sign(&result) {
+                Ok(signature) => result.response.signature = Some(signature),
+                Err(e) => tracing::warn!("Failed to sign result: {}", e),
+            }
+        }
+        response_to_tool_result(result)
+    }
+
+    /// Runs a query and writes the answer as a Markdown report to a file.
+    #[tool(
+        name = "perplexity_ask_to_file",
+        description = "Run a Perplexity query (pro mode by default) and write the answer with its numbered sources as a Markdown report to `path` inside the workspace, returning the path instead of the answer. Fails if the file exists unless overwrite=true. Best for: building documentation or notes from research without copying the answer through the conversation."
+    )]
+    pub async fn perplexity_ask_to_file(
+        &self,
+        Parameters(params): Parameters<AskToFileRequest>,
+        meta: Meta,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let mode = match params.mode {
+            Some(mode) => mode
+                .parse::<SearchMode>()
+                .map_err(|e| McpError::invalid_params(e.to_string(), None))?,
+            None => SearchMode::Pro,
+        };
+        let path =
+            crate::roots::output_path(&peer, self.output_dir.as_deref(), &params.path).await?;
+        let overwrite = params.overwrite.unwrap_or(false);
+        if !overwrite && path.exists() {
+            return Err(McpError::invalid_params(
+                format!("{} exists; pass overwrite=true to replace it", params.path),
+                None,
+            ));
+        }
+
+        // A partial answer would leave a truncated report behind.
+        let mut request = self.attach_files(params.request, &peer).await?;
+        request.soft_deadline_secs = None;
+        let query = request.query.clone();
+        let progress = AnswerProgress::start(&meta, peer, self.heartbeat);
+        let response = self.do_search(request, mode, progress).await?;
+
+        let report = crate::report::markdown(&query, &response);
+        let bytes = report.len();
+        let target = path.clone();
+        tokio::task::spawn_blocking(move || {
+            crate::roots::write_file(&target, report.as_bytes(), overwrite)
+        })
+        .await
+        .map_err(|e| {
+            McpError::internal_error(format!("Writing the report failed: {}", e), None)
+        })?
+        .map_err(|e| {
+            McpError::internal_error(
+                format!("Cannot write {}: {}", crate::roots::display(&path), e),
+                None,
+            )
+        })?;
+
+        response_to_tool_result(AskToFileResponse {
+            path: crate::roots::display(&path),
+            bytes,
+            sources: response.web_results.len(),
+            follow_up: response.follow_up,
+            warnings: response.warnings,
+        })
+    }
+
+    /// Breaks a research goal into sub-questions, asks them and merges the
+    /// answers into one report.
+    #[tool(
+        name = "perplexity_research_plan",
+        description = "Research a broad goal: break it into specific sub-questions (written by your model through sampling when supported, otherwise by a quick Perplexity query), ask them in parallel (pro mode by default), and combine the answers into one Markdown report whose [n] citations refer to a single deduplicated source list. Best for: surveys and comparisons too broad for one query, when deep research is too slow."
+    )]
+    pub async fn perplexity_research_plan(
+        &self,
+        Parameters(params): Parameters<ResearchPlanRequest>,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let mode = match params.mode {
+            Some(mode) => mode
+                .parse::<SearchMode>()
+                .map_err(|e| McpError::invalid_params(e.to_string(), None))?,
+            None => SearchMode::Pro,
+        };
+        let count = params
+            .max_sub_queries
+            .unwrap_or(plan::DEFAULT_SUB_QUERIES)
+            .clamp(1, plan::MAX_SUB_QUERIES);
+        let mut warnings = Vec::new();
+        let (queries, planned_by) =
+            match plan::sample_sub_queries(&peer, &params.goal, count).await {
+                Some((queries, model)) => (queries, format!("sampling:{}", model)),
+                None => {
+                    let planning = PerplexityRequest::plain(
+                        plan::planning_query(&params.goal, count),
+                        params.sources.clone(),
+                    );
+                    let response = self.do_search(planning, SearchMode::Auto, None).await?;
+                    let queries = plan::parse_sub_queries(
+                        response.answer.as_deref().unwrap_or_default(),
+                        count,
+                    );
+                    if queries.is_empty() {
+                        warnings.push(
+                            "Perplexity suggested no sub-questions; asked the goal itself"
+                                .to_string(),
+                        );
+                    }
+                    (queries, "perplexity".to_string())
+                }
+            };
+        let queries = if queries.is_empty() { vec![params.goal.clone()] } else { queries };
+
+        let answers = self
+            .ask_all(queries, mode, |query| {
+                PerplexityRequest::plain(query.to_string(), params.sources.clone())
+            })
+            .await?;
+        response_to_tool_result(plan::combine(&params.goal, answers, planned_by, warnings))
+    }
+
+    /// Compares entities on a list of criteria.
+    #[tool(
+        name = "perplexity_compare",
+        description = "Compare 2-8 entities (products, libraries, companies...) on 1-10 criteria. Runs one Perplexity query per entity (pro mode by default) and returns a comparison matrix: JSON rows with a cell per criterion and the citations supporting it, and a Markdown table whose [n] markers refer to a single deduplicated source list. Best for: analyst-style comparisons and evaluation tables."
+    )]
+    pub async fn perplexity_compare(
+        &self,
+        Parameters(params): Parameters<CompareRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        compare::validate(&params).map_err(|e| McpError::invalid_params(e, None))?;
+        let mode = match params.mode {
+            Some(mode) => mode
+                .parse::<SearchMode>()
+                .map_err(|e| McpError::invalid_params(e.to_string(), None))?,
+            None => SearchMode::Pro,
+        };
+        let criteria = params.criteria;
+        let answers = self
+            .ask_all(params.entities, mode, |entity| {
+                PerplexityRequest::plain(
+                    compare::entity_query(entity, &criteria),
+                    params.sources.clone(),
+                )
+            })
+            .await?;
+        response_to_tool_result(compare::assemble(criteria, answers))
+    }
+
+    /// Collects a topic-by-topic digest of recent news.
+    #[tool(
+        name = "perplexity_news_digest",
+        description = "Build a news digest: for each of 1-10 topics, ask Perplexity (pro mode by default) for the main stories of the window (day, week, month or year; only sources published within it are searched), list stories reported under several topics once, and return one Markdown digest whose [n] citations refer to a single deduplicated source list. Best for: daily or weekly briefings; register one with perplexity_schedule_digest when scheduling is enabled."
+    )]
+    pub async fn perplexity_news_digest(
+        &self,
+        Parameters(params): Parameters<NewsDigestRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        response_to_tool_result(self.news_digest(params).await?)
+    }
+
+    /// Purges cached answers by tag or age.
+    #[tool(
+        name = "perplexity_cache_invalidate",
+        description = "Purge cached answers to queries asked with any of the given tags, and/or older than older_than_secs, so the next query for that topic fetches fresh data; with include_history=true also delete the matching entries from the query history. Answers without a matching tag are kept."
+    )]
+    pub async fn perplexity_cache_invalidate(
+        &self,
+        Parameters(params): Parameters<CacheInvalidateRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let tags = crate::cache::tags(&PerplexityRequest {
+            tags: params.tags,
+            ..PerplexityRequest::plain(String::new(), None)
+        });
+        if tags.is_empty() && params.older_than_secs.is_none() {
+            return Err(McpError::invalid_params(
+                "Give tags or older_than_secs to choose which answers to purge",
+                None,
+            ));
+        }
+        let older_than = params.older_than_secs.map(Duration::from_secs);
+        let cache_entries = self.cache.invalidate(&tags, older_than);
+        tracing::info!(cache_entries, tags = ?tags, "Invalidated cached answers");
+
+        let history_entries = if params.include_history == Some(true) {
+            Some(self.purge_history(tags, params.older_than_secs).await?)
+        } else {
+            None
+        };
+        response_to_tool_result(CacheInvalidateResponse { cache_entries, history_entries })
+    }
+
+    /// Creates or revokes a public link to a thread.
+    #[tool(
+        name = "perplexity_share",
+        description = "Create a public link to a Perplexity thread so a human can read the full answer and research trail, or revoke it with revoke=true. Takes the follow_up.backend_uuid of a previous answer."
+    )]
+    pub async fn perplexity_share(
+        &self,
+        Parameters(params): Parameters<ShareRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let response = if params.revoke == Some(true) {
+            self.client.unshare_thread(&params.backend_uuid).await.map_err(api_error)?;
+            ShareResponse { backend_uuid: params.backend_uuid, url: None, shared: false }
+        } else {
+            let link =
+                self.client.share_thread(&params.backend_uuid).await.map_err(api_error)?;
+            ShareResponse {
+                backend_uuid: link.backend_uuid,
+                url: Some(link.url),
+                shared: true,
+            }
+        };
+
+        response_to_tool_result(response)
+    }
+
+    /// Returns a thread's metadata, optionally renaming it first.
+    #[tool(
+        name = "perplexity_thread",
+        description = "Get a Perplexity thread's title, mode, model, creation and update times, and number of turns. Pass title to rename the thread first. Takes the follow_up.backend_uuid of a previous answer."
+    )]
+    pub async fn perplexity_thread(
+        &self,
+        Parameters(params): Parameters<ThreadRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        if let Some(title) = params.title.as_deref() {
+            let title = title.trim();
+            if title.is_empty() {
+                return Err(McpError::invalid_params("title must not be empty", None));
+            }
+            self.client.rename_thread(&params.backend_uuid, title).await.map_err(api_error)?;
+        }
+
+        let info = self.client.thread_info(&params.backend_uuid).await.map_err(api_error)?;
+        response_to_tool_result(info)
+    }
+
+    /// Bookmarks or unbookmarks a thread.
+    #[tool(
+        name = "perplexity_bookmark",
+        description = "Pin an important Perplexity thread to the account's bookmarks, or remove it with bookmarked=false. Takes the follow_up.backend_uuid of a previous answer."
+    )]
+    pub async fn perplexity_bookmark(
+        &self,
+        Parameters(params): Parameters<BookmarkRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let bookmarked = params.bookmarked.unwrap_or(true);
+        self.client
+            .bookmark_thread(&params.backend_uuid, bookmarked)
+            .await
+            .map_err(api_error)?;
+
+        response_to_tool_result(BookmarkResponse {
+            backend_uuid: params.backend_uuid,
+            bookmarked,
+        })
+    }
+
+    /// Lists bookmarked threads.
+    #[tool(
+        name = "perplexity_bookmarks",
+        description = "List the account's bookmarked Perplexity threads with their ids, titles and last update times, most recent first."
+    )]
+    pub async fn perplexity_bookmarks(
+        &self,
+        Parameters(params): Parameters<BookmarksRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let limit = params.limit.unwrap_or(20).clamp(1, 100) as usize;
+        let offset = params.offset.unwrap_or(0) as usize;
+        let threads =
+            self.client.bookmarked_threads(limit, offset).await.map_err(api_error)?;
+
+        response_to_tool_result(BookmarksResponse { threads })
+    }
+
+    /// Compares two answers claim by claim.
+    #[tool(
+        name = "perplexity_diff_answers",
+        description = "Compare two answers, e.g. today's and last week's answer to the same query. Returns claims (sentences) that were added or removed, the number unchanged, and added or removed citation URLs. Best for: monitoring how answers to a recurring question change."
+    )]
+    pub async fn perplexity_diff_answers(
+        &self,
+        Parameters(params): Parameters<DiffAnswersRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let DiffAnswersRequest { before, after } = params;
+        response_to_tool_result(AnswerDiff::between(
+            &before.answer,
+            &before.citations,
+            &after.answer,
+            &after.citations,
+        ))
+    }
+
+    /// Generates images from a text prompt.
+    #[tool(
+        name = "perplexity_generate_image",
+        description = "Generate an image from a text description using Perplexity AI. Returns the generated images, plus any text Perplexity replied with."
+    )]
+    pub async fn perplexity_generate_image(
+        &self,
+        Parameters(params): Parameters<GenerateImageRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let mut request = SearchRequest::new(params.prompt)
+            .mode(SearchMode::Pro)
+            .incognito(true)
+            .generate_image(true);
+        if let Some(id) = crate::correlation::current() {
+            request = request.request_id(id);
+        }
+        let response = self.client.search(request).await.map_err(api_error)?;
+
+        if response.images.is_empty() {
+            return Err(McpError::internal_error(
+                format!(
+                    "Perplexity did not return an image: {}",
+                    response.answer.as_deref().unwrap_or("no answer")
+                ),
+                None,
+            ));
+        }
+
+        let mut content: Vec<Content> =
+            response.answer.into_iter().map(Content::text).collect();
+        for (i, image) in response.images.into_iter().enumerate() {
+            content.extend(self.image_content(image, i).await);
+        }
+        Ok(CallToolResult::success(content))
+    }
+
+    /// Fetches the text of an external source.
+    #[tool(
+        name = "perplexity_fetch_source",
+        description = "Fetch a web page cited by an answer, such as a web_results URL, and return its text with markup removed. The page is fetched directly, without Perplexity and without its session cookies. Best for: reading a source in full to verify a claim."
+    )]
+    pub async fn perplexity_fetch_source(
+        &self,
+        Parameters(params): Parameters<FetchSourceRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let max_chars = params.max_chars.unwrap_or(DEFAULT_FETCH_CHARS);
+        let source = self.fetcher.fetch(&params.url, max_chars).await.map_err(|e| {
+            failure::coded(
+                ErrorCode::INTERNAL_ERROR,
+                format!("Could not fetch {}: {}", params.url, e),
+                failure::FETCH_FAILED,
+            )
+        })?;
+        response_to_tool_result(source)
+    }
+
+    /// Lists the models that can be requested, with their capabilities.
+    #[tool(
+        name = "perplexity_list_models",
+        description = "List the models that can be passed as `model` to perplexity_code_search (pro mode) and perplexity_reason (reasoning mode), with their provider, whether they reason step by step, and their speed tier (fast, standard or slow). Best for: picking a model to suit the task."
+    )]
+    pub async fn perplexity_list_models(&self) -> Result<CallToolResult, McpError> {
+        let models = Model::ALL.iter().copied().map(ModelInfo::from).collect();
+        response_to_tool_result(ModelListResponse { models })
+    }
+
+    /// Describes the build and configuration of this server.
+    #[tool(
+        name = "perplexity_about",
+        description = "Show the version and git commit of this server, the Perplexity API version it speaks, its optional features, the MCP transport it is served over and the models it knows. Best for: including in bug reports."
+    )]
+    pub async fn perplexity_about(&self) -> Result<CallToolResult, McpError> {
+        response_to_tool_result(AboutResponse::new(self.transport, self.client.api_version()))
+    }
+
+    /// Summarizes local usage statistics.
+    #[tool(
+        name = "perplexity_usage_stats",
+        description = "Show how many queries this server has answered, failed and served from its cache in each search mode, and the estimated tokens of its answers, since the statistics were last reset. The counts are kept locally and never sent anywhere."
+    )]
+    pub async fn perplexity_usage_stats(&self) -> Result<CallToolResult, McpError> {
+        let usage = self.usage.clone();
+        let summary = tokio::task::spawn_blocking(move || usage.summary())
+            .await
+            .map_err(|e| McpError::internal_error(format!("Usage task failed: {}", e), None))?
+            .map_err(|e| {
+                McpError::internal_error(format!("Cannot read usage statistics: {}", e), None)
+            })?;
+        response_to_tool_result(summary)
+    }
+}
+
+impl PerplexityServer {
+    /// Converts a generated image into MCP image content.
+    ///
+    /// Images returned only as a URL are downloaded with the session; if that
+    /// fails, a resource link to the URL is returned instead.
+    async fn image_content(&self, image: GeneratedImage, index: usize) -> Option<Content> {
+        if let Some(data) = image.data {
+            let mime_type = image.mime_type.unwrap_or_else(|| "image/png".to_string());
+            return Some(Content::image(BASE64.encode(data), mime_type));
+        }
+
+        let url = image.url?;
+        match self.client.download_attachment_as(&url, "image/*").await {
+            Ok((data, mime_type)) => Some(Content::image(BASE64.encode(data), mime_type)),
+            Err(e) => {
+                tracing::warn!("Failed to download generated image: {}", e);
+                let mut resource =
+                    RawResource::new(url, format!("generated-image-{}", index + 1));
+                resource.mime_type = image.mime_type;
+                Some(Content::resource_link(resource))
+            }
+        }
+    }
+}
+
+#[tool_router(router = jobs_router)]
+impl PerplexityServer {
+    /// Starts a query in the background and returns a job id immediately.
+    #[tool(
+        name = "perplexity_ask_async",
+        description = "Start a Perplexity query in the background and return a job id immediately. Poll perplexity_job_status and fetch the answer with perplexity_job_result. Defaults to deep research mode. Best for: long Deep Research jobs that exceed tool-call timeouts."
+    )]
+    pub async fn perplexity_ask_async(
+        &self,
+        Parameters(mut params): Parameters<AsyncAskRequest>,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        params.request = self.attach_files(params.request, &peer).await?;
+        let mode = match params.mode {
+            Some(mode) => mode
+                .parse::<SearchMode>()
+                .map_err(|e| McpError::invalid_params(e.to_string(), None))?,
+            None => SearchMode::DeepResearch,
+        };
+        if self.confirm_research && mode == SearchMode::DeepResearch {
+            crate::confirm::research(&peer, &params.request).await?;
+        }
+        let (resolved_model, substitution) = self.resolve_model(&mut params.request, mode);
+
+        let server = self.clone();
+        let request = background(params.request.clone());
+        let job_id = self
+            .jobs
+            .submit(params.request, mode, async move {
+                let mut response = server
+                    .complete_search(request, mode, None)
+                    .await
+                    .map_err(|e| e.message.into_owned())?;
+                response.resolved_model = resolved_model;
+                response.degradations.extend(substitution);
+                Ok(response)
+            })
+            .map_err(|e| McpError::internal_error(e, None))?;
+
+        response_to_tool_result(JobSubmitted { job_id, status: JobStatus::Queued })
+    }
+
+    /// Reports the progress of a background job.
+    #[tool(
+        name = "perplexity_job_status",
+        description = "Check the status of a background job started with perplexity_ask_async: queued, running, completed, or failed."
+    )]
+    pub async fn perplexity_job_status(
+        &self,
+        Parameters(params): Parameters<JobRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let job = self.find_job(&params.job_id)?;
+
+        response_to_tool_result(JobStatusResponse {
+            job_id: job.id,
+            status: job.status,
+            mode: job.mode,
+            query: job.request.query,
+            created_at: job.created_at,
+            started_at: job.started_at,
+            finished_at: job.finished_at,
+            error: job.error,
+        })
+    }
+
+    /// Returns the result of a background job.
+    #[tool(
+        name = "perplexity_job_result",
+        description = "Fetch the result of a background job started with perplexity_ask_async. Returns the full answer once the job is completed, otherwise its current status."
+    )]
+    pub async fn perplexity_job_result(
+        &self,
+        Parameters(params): Parameters<JobRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let job = self.find_job(&params.job_id)?;
+
+        response_to_tool_result(JobResultResponse {
+            job_id: job.id,
+            status: job.status,
+            result: job.result,
+            error: job.error,
+        })
+    }
+}
+
+#[tool_router(router = schedule_router)]
+impl PerplexityServer {
+    /// Registers a query to run on a cron schedule.
+    #[tool(
+        name = "perplexity_schedule_query",
+        description = "Register a Perplexity query to run repeatedly on a cron schedule (UTC), e.g. \"0 9 * * 1\" for Mondays at 09:00. Each run is a background job whose id is reported as last_job_id by perplexity_list_schedules; results are also stored in the history and sent to the webhook when configured. Defaults to pro mode. Best for: monitoring a topic over time."
+    )]
+    pub async fn perplexity_schedule_query(
+        &self,
+        Parameters(params): Parameters<ScheduleQueryRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        if params.request.files.as_ref().is_some_and(|files| !files.is_empty()) {
+            return Err(McpError::invalid_params(
+                "Scheduled queries cannot attach files",
+                None,
+            ));
+        }
+        let mode = match params.mode {
+            Some(mode) => mode
+                .parse::<SearchMode>()
+                .map_err(|e| McpError::invalid_params(e.to_string(), None))?,
+            None => SearchMode::Pro,
+        };
+
+        let schedule = self
+            .scheduler()?
+            .add(&params.cron, params.request, mode, None)
+            .await
+            .map_err(|e| McpError::invalid_params(e, None))?;
+
+        response_to_tool_result(schedule)
+    }
+
+    /// Registers a news digest to run on a cron schedule.
+    #[tool(
+        name = "perplexity_schedule_digest",
+        description = "Register a news digest, as built by perplexity_news_digest, to run repeatedly on a cron schedule (UTC), e.g. \"@daily\" with window \"day\" for a daily briefing. Each run is a background job whose result has the digest as its answer; it is reported as last_job_id by perplexity_list_schedules and sent to the webhook when configured."
+    )]
+    pub async fn perplexity_schedule_digest(
+        &self,
+        Parameters(params): Parameters<ScheduleDigestRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let digest = params.digest;
+        digest::validate(&digest).map_err(|e| McpError::invalid_params(e, None))?;
+        let mode = match &digest.mode {
+            Some(mode) => mode
+                .parse::<SearchMode>()
+                .map_err(|e| McpError::invalid_params(e.to_string(), None))?,
+            None => SearchMode::Pro,
+        };
+        let request =
+            PerplexityRequest::plain(digest::title(&digest.topics), digest.sources.clone());
+
+        let schedule = self
+            .scheduler()?
+            .add(&params.cron, request, mode, Some(digest))
+            .await
+            .map_err(|e| McpError::invalid_params(e, None))?;
+
+        response_to_tool_result(schedule)
+    }
+
+    /// Lists registered recurring queries.
+    #[tool(
+        name = "perplexity_list_schedules",
+        description = "List recurring Perplexity queries registered with perplexity_schedule_query, with their next and last run times and the job id of the last run."
+    )]
+    pub async fn perplexity_list_schedules(&self) -> Result<CallToolResult, McpError> {
+        response_to_tool_result(ScheduleListResponse { schedules: self.scheduler()?.list() })
+    }
+
+    /// Cancels a recurring query.
+    #[tool(
+        name = "perplexity_cancel_schedule",
+        description = "Cancel a recurring Perplexity query registered with perplexity_schedule_query. Runs already started finish as usual."
+    )]
+    pub async fn perplexity_cancel_schedule(
+        &self,
+        Parameters(params): Parameters<ScheduleRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        if !self.scheduler()?.cancel(&params.schedule_id).await {
+            return Err(McpError::invalid_params(
+                format!("Unknown schedule id '{}'", params.schedule_id),
+                None,
+            ));
+        }
+
+        response_to_tool_result(ScheduleCancelled { schedule_id: params.schedule_id })
+    }
+}
+
+#[cfg(feature = "store-sqlite")]
+#[tool_router(router = history_router)]
+impl PerplexityServer {
+    /// Searches previously answered queries stored in the local history database.
+    #[tool(
+        name = "perplexity_history_search",
+        description = "Search previously answered Perplexity queries stored locally. Returns past queries, answers, citations, and thread ids, newest first. Use before asking a new question to reuse earlier research."
+    )]
+    pub async fn perplexity_history_search(
+        &self,
+        Parameters(params): Parameters<HistorySearchRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let Some(store) = self.history.clone() else {
+            return Err(McpError::internal_error("History store is not configured", None));
+        };
+
+        let limit = params.limit.unwrap_or(10).clamp(1, 100);
+        let entries =
+            tokio::task::spawn_blocking(move || store.search(params.query.as_deref(), limit))
+                .await
+                .map_err(|e| {
+                    McpError::internal_error(format!("History task failed: {}", e), None)
+                })?
+                .map_err(|e| {
+                    McpError::internal_error(format!("History query failed: {}", e), None)
+                })?;
+
+        response_to_tool_result(HistorySearchResponse { entries })
+    }
+
+    /// Finds prior research relevant to a question using full-text ranking.
+    #[tool(
+        name = "perplexity_history_lookup",
+        description = "Check whether a topic was already researched. Ranks locally stored Perplexity answers by relevance to a natural-language question. Use before asking a new question to avoid repeating research."
+    )]
+    pub async fn perplexity_history_lookup(
+        &self,
+        Parameters(params): Parameters<HistoryLookupRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let Some(store) = self.history.clone() else {
+            return Err(McpError::internal_error("History store is not configured", None));
+        };
+
+        let limit = params.limit.unwrap_or(5).clamp(1, 20);
+        let matches =
+            tokio::task::spawn_blocking(move || store.lookup(&params.question, limit))
+                .await
+                .map_err(|e| {
+                    McpError::internal_error(format!("History task failed: {}", e), None)
+                })?
+                .map_err(|e| {
+                    McpError::internal_error(format!("History query failed: {}", e), None)
+                })?;
+
+        response_to_tool_result(HistoryLookupResponse { matches })
+    }
+}
+
+impl ServerHandler for PerplexityServer {
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParams,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let request_id = crate::correlation::new_id();
+        let tool_name = request.name.clone();
+        let span =
+            tracing::info_span!("tool_call", tool = %tool_name, request_id = %request_id);
+
+        #[cfg(feature = "otel")]
+        {
+            use tracing_opentelemetry::OpenTelemetrySpanExt;
+            // Only fails when the span is disabled, in which case there is
+            // nothing to attach the caller's trace to.
+            let _ = span.set_parent(crate::otel::parent_context(&context.meta));
+        }
+
+        let tcc = ToolCallContext::new(self, request, context);
+        let started = std::time::Instant::now();
+        let call = failure::contain(&tool_name, self.tool_router.call(tcc));
+        let result = failure::classify(
+            crate::correlation::scope(request_id.clone(), call).instrument(span.clone()).await,
+        );
+
+        span.in_scope(|| match &result {
+            Ok(_) => {
+                tracing::info!(elapsed_ms = millis(started.elapsed()), "Tool call finished")
+            }
+            Err(e) => tracing::warn!(
+                elapsed_ms = millis(started.elapsed()),
+                "Tool call failed: {}",
+                e.message
+            ),
+        });
+        let result = result.map_err(|error| self.locale.localize_error(error));
+        crate::correlation::tag(result, &request_id)
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParams>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        Ok(ListToolsResult {
+            tools: self.tool_router.list_all(),
+            meta: None,
+            next_cursor: None,
+        })
+    }
+
+    fn get_tool(&self, name: &str) -> Option<Tool> {
+        self.tool_router.get(name).cloned()
+    }
+
+    async fn list_resources(
+        &self,
+        _request: Option<PaginatedRequestParams>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListResourcesResult, McpError> {
+        Ok(ListResourcesResult::with_all_items(self.resources()))
+    }
+
+    async fn read_resource(
+        &self,
+        request: ReadResourceRequestParams,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ReadResourceResult, McpError> {
+        let config = self.config.as_ref().filter(|_| request.uri == settings::URI);
+        let Some(config) = config else {
+            return Err(McpError::resource_not_found(
+                format!("No resource {}", request.uri),
+                None,
+            ));
+        };
+        let json = serde_json::to_string_pretty(config.as_ref()).map_err(|e| {
+            McpError::internal_error(format!("JSON serialization error: {}", e), None)
+        })?;
+        Ok(ReadResourceResult {
+            contents: vec![ResourceContents::TextResourceContents {
+                uri: request.uri,
+                mime_type: Some("application/json".to_string()),
+                text: json,
+                meta: None,
+            }],
+        })
+    }
+
+    async fn set_level(
+        &self,
+        request: SetLevelRequestParams,
+        context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        let Some(client_log) = &self.client_log else {
+            return Err(McpError::method_not_found::<SetLevelRequestMethod>());
+        };
+        client_log.set_level(request.level);
+        client_log.attach(context.peer);
+        Ok(())
+    }
+
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            server_info: Implementation {
+                name: env!("CARGO_PKG_NAME").to_owned(),
+                version: env!("CARGO_PKG_VERSION").to_owned(),
+                ..Implementation::default()
+            },
+            instructions: Some(self.tool_names.rename_text(self.locale.instructions())),
+            capabilities: {
+                let mut capabilities = ServerCapabilities::builder().enable_tools().build();
+                if self.client_log.is_some() {
+                    capabilities.logging = Some(Default::default());
+                }
+                if self.config.is_some() {
+                    capabilities.resources = Some(Default::default());
+                }
+                capabilities
+            },
             ..Default::default()
         }
     }
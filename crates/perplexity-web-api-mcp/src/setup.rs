@@ -0,0 +1,289 @@
+//! The `setup` wizard.
+//!
+//! Walks a first-time user through signing in to Perplexity and copying the
+//! session cookies, checks them, picks the mode of `perplexity_search` and
+//! a default model, runs a test query, and writes the configuration file so
+//! that MCP clients can start the server without any environment variables.
+
+use perplexity_web_api::{Client, HeaderStrategy, Model, SearchMode, SearchRequest};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use crate::config;
+use crate::selector::{ModelQuota, Selector};
+
+/// Page the user signs in on.
+const SIGN_IN_URL: &str = "https://www.perplexity.ai/";
+
+/// Query run to check the configuration.
+const TEST_QUERY: &str = "What is the Model Context Protocol? Answer in one sentence.";
+
+/// Runs the wizard on the terminal and saves the result to `path`.
+pub async fn run(path: &Path, headers: HeaderStrategy) -> Result<(), Box<dyn Error>> {
+    let mut prompt = Prompt { input: io::stdin().lock(), output: io::stdout() };
+    wizard(&mut prompt, path, headers).await
+}
+
+async fn wizard<R: BufRead, W: Write>(
+    prompt: &mut Prompt<R, W>,
+    path: &Path,
+    headers: HeaderStrategy,
+) -> Result<(), Box<dyn Error>> {
+    prompt.say(&format!(
+        "This sets up perplexity-web-api-mcp and saves the configuration to {}.\n",
+        path.display()
+    ))?;
+
+    let (client, session_token, csrf_token) = loop {
+        let (session_token, csrf_token) = ask_tokens(prompt)?;
+        let cookies = HashMap::from([
+            ("next-auth.session-token".to_string(), session_token.clone()),
+            ("next-auth.csrf-token".to_string(), csrf_token.clone()),
+        ]);
+        let client = Client::builder()
+            .cookies(cookies)
+            .header_strategy(headers.clone())
+            .warm_up(false)
+            .build()
+            .await?;
+        match client.session().await {
+            Ok(Some(session)) => {
+                let account = session.email.or(session.name).unwrap_or_else(|| "?".into());
+                prompt.say(&format!("Signed in as {}.\n", account))?;
+                break (client, session_token, csrf_token);
+            }
+            Ok(None) => prompt.say("Perplexity did not accept these tokens.")?,
+            Err(e) => prompt.say(&format!("Cannot check the tokens: {}", e))?,
+        }
+        if !prompt.confirm("Try again?", true)? {
+            return Err("setup cancelled".into());
+        }
+    };
+
+    let mode = ask_mode(prompt)?;
+    let model = ask_model(prompt)?;
+
+    if prompt.confirm("Run a test query?", true)? {
+        let mut request = SearchRequest::new(TEST_QUERY).mode(mode);
+        if let Some(model) = model.as_deref().and_then(|model| model_for(model, mode)) {
+            request = request.model(model);
+        }
+        match client.search(request).await {
+            Ok(response) => prompt.say(&format!(
+                "{}\n",
+                response.answer.as_deref().unwrap_or("(Perplexity returned no answer)").trim()
+            ))?,
+            Err(e) => prompt.say(&format!(
+                "The test query failed: {}. The configuration is saved anyway; \
+                 check it later with `test-auth`.\n",
+                e
+            ))?,
+        }
+    }
+
+    #[cfg(feature = "keyring")]
+    let in_keyring =
+        prompt.confirm("Store the tokens in the OS keyring instead of the file?", true)?;
+    #[cfg(not(feature = "keyring"))]
+    let in_keyring = false;
+    #[cfg(feature = "keyring")]
+    if in_keyring {
+        crate::secrets::store("PERPLEXITY_SESSION_TOKEN", &session_token)?;
+        crate::secrets::store("PERPLEXITY_CSRF_TOKEN", &csrf_token)?;
+    }
+
+    config::save(
+        path,
+        &[
+            ("PERPLEXITY_SESSION_TOKEN", (!in_keyring).then_some(session_token)),
+            ("PERPLEXITY_CSRF_TOKEN", (!in_keyring).then_some(csrf_token)),
+            ("PERPLEXITY_SEARCH_MODE", Some(mode.as_str().to_string())),
+            ("PERPLEXITY_DEFAULT_MODEL", model),
+        ],
+    )?;
+    prompt.say(&format!(
+        "Saved {}. The server reads it on startup, so MCP clients only need to run \
+         `perplexity-web-api-mcp`; check it any time with `perplexity-web-api-mcp validate-config`.",
+        path.display()
+    ))?;
+    Ok(())
+}
+
+/// Has the user sign in and copy the session and CSRF tokens.
+fn ask_tokens<R: BufRead, W: Write>(
+    prompt: &mut Prompt<R, W>,
+) -> io::Result<(String, String)> {
+    if prompt.confirm("Open perplexity.ai in your browser to sign in?", true)?
+        && let Err(e) = open_browser(SIGN_IN_URL)
+    {
+        prompt
+            .say(&format!("Cannot open a browser ({}); visit {} yourself.", e, SIGN_IN_URL))?;
+    }
+    prompt.say(
+        "Once signed in, open Developer Tools (F12) and find the cookies of \
+         https://www.perplexity.ai (Application → Cookies). Paste the Cookie header of \
+         a request to perplexity.ai, or press Enter to paste the two tokens one by one.",
+    )?;
+    let header = prompt.ask("Cookie header")?;
+    if !header.is_empty() {
+        if let Some(tokens) = tokens_from_cookies(&header) {
+            return Ok(tokens);
+        }
+        prompt.say("That header has no session and CSRF cookies.")?;
+    }
+    let session_token = prompt.ask_required("__Secure-next-auth.session-token")?;
+    let csrf_token = prompt.ask_required("next-auth.csrf-token")?;
+    Ok((session_token, csrf_token))
+}
+
+/// Asks for the mode `perplexity_search` answers in.
+fn ask_mode<R: BufRead, W: Write>(prompt: &mut Prompt<R, W>) -> io::Result<SearchMode> {
+    loop {
+        let answer = prompt.ask("Mode for perplexity_search: auto (quick) or pro [auto]")?;
+        if answer.is_empty() {
+            return Ok(SearchMode::Auto);
+        }
+        match answer.parse() {
+            Ok(mode @ (SearchMode::Auto | SearchMode::Pro)) => return Ok(mode),
+            _ => prompt.say("Answer auto or pro.")?,
+        }
+    }
+}
+
+/// Asks for the default model, returning `None` for Perplexity's default.
+fn ask_model<R: BufRead, W: Write>(prompt: &mut Prompt<R, W>) -> io::Result<Option<String>> {
+    prompt.say("\nModels for Pro searches and reasoning:")?;
+    for model in Model::ALL {
+        prompt.say(&format!("  {} ({}, {})", model, model.mode(), model.provider()))?;
+    }
+    prompt.say("or a selector: best, fastest or cheapest.")?;
+    loop {
+        let answer = prompt.ask("Default model [Perplexity's default]")?;
+        if answer.is_empty() {
+            return Ok(None);
+        }
+        if Selector::parse(&answer).is_some() || answer.parse::<Model>().is_ok() {
+            return Ok(Some(answer));
+        }
+        prompt.say(&format!("Unknown model {:?}.", answer))?;
+    }
+}
+
+/// The model `default` runs queries in `mode` with, if any.
+fn model_for(default: &str, mode: SearchMode) -> Option<Model> {
+    match Selector::parse(default) {
+        Some(selector) => selector.resolve(mode, &ModelQuota::default()),
+        None => default.parse::<Model>().ok().filter(|model| model.mode() == mode),
+    }
+}
+
+/// Picks the session and CSRF tokens out of a `Cookie` header.
+fn tokens_from_cookies(header: &str) -> Option<(String, String)> {
+    let header = header.trim();
+    let header = header
+        .get(..7)
+        .filter(|name| name.eq_ignore_ascii_case("cookie:"))
+        .map_or(header, |_| &header[7..]);
+    let mut session_token = None;
+    let mut csrf_token = None;
+    for (name, value) in header.split(';').filter_map(|cookie| cookie.trim().split_once('=')) {
+        match name.trim_start_matches("__Secure-").trim_start_matches("__Host-") {
+            "next-auth.session-token" => session_token = Some(value.to_string()),
+            "next-auth.csrf-token" => csrf_token = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    Some((session_token?, csrf_token?))
+}
+
+fn open_browser(url: &str) -> io::Result<()> {
+    let mut command = if cfg!(windows) {
+        let mut command = std::process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else if cfg!(target_os = "macos") {
+        std::process::Command::new("open")
+    } else {
+        std::process::Command::new("xdg-open")
+    };
+    command.arg(url).spawn().map(drop)
+}
+
+/// Questions and answers on a pair of streams.
+struct Prompt<R, W> {
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> Prompt<R, W> {
+    fn say(&mut self, text: &str) -> io::Result<()> {
+        writeln!(self.output, "{}", text)
+    }
+
+    /// Asks `question` and returns the trimmed answer.
+    fn ask(&mut self, question: &str) -> io::Result<String> {
+        write!(self.output, "{}: ", question)?;
+        self.output.flush()?;
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "setup cancelled"));
+        }
+        Ok(line.trim().to_string())
+    }
+
+    /// Asks `question` until the answer is not empty.
+    fn ask_required(&mut self, question: &str) -> io::Result<String> {
+        loop {
+            let answer = self.ask(question)?;
+            if !answer.is_empty() {
+                return Ok(answer);
+            }
+        }
+    }
+
+    /// Asks a yes or no `question`, with `default` for an empty answer.
+    fn confirm(&mut self, question: &str, default: bool) -> io::Result<bool> {
+        let hint = if default { "Y/n" } else { "y/N" };
+        loop {
+            match self.ask(&format!("{} [{}]", question, hint))?.to_ascii_lowercase().as_str()
+            {
+                "" => return Ok(default),
+                "y" | "yes" => return Ok(true),
+                "n" | "no" => return Ok(false),
+                _ => self.say("Answer yes or no.")?,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens_and_answers() {
+        assert_eq!(
+            tokens_from_cookies(
+                "Cookie: pplx.visitor-id=1; __Secure-next-auth.session-token=abc.def; \
+                 next-auth.csrf-token=x%7Cy"
+            ),
+            Some(("abc.def".to_string(), "x%7Cy".to_string()))
+        );
+        assert_eq!(tokens_from_cookies("next-auth.csrf-token=x"), None);
+
+        let mut prompt = Prompt {
+            input: &b"maybe\n\nreasoning\npro\nbogus\nfastest\n"[..],
+            output: Vec::new(),
+        };
+        assert!(prompt.confirm("Go?", true).unwrap());
+        assert_eq!(ask_mode(&mut prompt).unwrap(), SearchMode::Pro);
+        assert_eq!(ask_model(&mut prompt).unwrap().as_deref(), Some("fastest"));
+        let output = String::from_utf8(prompt.output).unwrap();
+        assert!(output.contains("Answer yes or no."));
+        assert!(output.contains("Answer auto or pro."));
+        assert!(output.contains("Unknown model \"bogus\"."));
+        assert!(prompt.input.is_empty());
+    }
+}
@@ -0,0 +1,157 @@
+//! Ed25519 signatures over stored and exported results.
+//!
+//! With a signing key configured, complete answers, background job results
+//! and query history entries carry a `signature` over their content, so a
+//! result kept elsewhere can later be checked for tampering against the
+//! server's public key.
+//!
+//! The signed message is the record's JSON without its `signature` field,
+//! and for history entries without the `id` and `relevance` the database
+//! assigns, serialized compactly with object keys sorted.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use ed25519_dalek::{Signer, SigningKey, Verifier, VerifyingKey};
+use rmcp::schemars;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Name of the only supported signature algorithm.
+const ALGORITHM: &str = "ed25519";
+
+/// Fields left out of the signed message.
+const UNSIGNED_FIELDS: &[&str] = &["signature", "id", "relevance"];
+
+/// A signature attached to a result.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct Signature {
+    /// Signature algorithm, always "ed25519".
+    pub algorithm: String,
+    /// Hex-encoded public key of the signing server.
+    pub public_key: String,
+    /// Base64-encoded signature.
+    pub value: String,
+}
+
+/// Signs results with the server's Ed25519 key.
+#[derive(Clone)]
+pub struct ResultSigner {
+    key: SigningKey,
+}
+
+impl ResultSigner {
+    /// Creates a signer from a hex-encoded 32-byte private key.
+    pub fn from_hex(key: &str) -> Result<Self, String> {
+        let bytes = hex::decode(key.trim()).map_err(|e| format!("not hex: {}", e))?;
+        let seed: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| "expected a 32-byte key (64 hex digits)".to_string())?;
+        Ok(Self { key: SigningKey::from_bytes(&seed) })
+    }
+
+    /// Hex-encoded public key that verifies this signer's signatures.
+    pub fn public_key(&self) -> String {
+        hex::encode(self.key.verifying_key().as_bytes())
+    }
+
+    /// Signs the JSON form of `record`.
+    pub fn sign(&self, record: &impl Serialize) -> Result<Signature, String> {
+        let value = serde_json::to_value(record).map_err(|e| e.to_string())?;
+        let signature = self.key.sign(message(value).as_bytes());
+        Ok(Signature {
+            algorithm: ALGORITHM.to_string(),
+            public_key: self.public_key(),
+            value: BASE64.encode(signature.to_bytes()),
+        })
+    }
+}
+
+/// Checks the `signature` of a signed record, returning the public key
+/// that made it.
+///
+/// Callers should compare the key with the one they trust: any key can
+/// produce a valid signature.
+pub fn verify(record: &Value) -> Result<String, String> {
+    let signature = record.get("signature").ok_or("the record has no signature")?;
+    let signature: Signature = serde_json::from_value(signature.clone())
+        .map_err(|e| format!("bad signature: {}", e))?;
+    if signature.algorithm != ALGORITHM {
+        return Err(format!("unsupported algorithm '{}'", signature.algorithm));
+    }
+
+    let key: [u8; 32] = hex::decode(&signature.public_key)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or("bad public key")?;
+    let key = VerifyingKey::from_bytes(&key).map_err(|e| format!("bad public key: {}", e))?;
+    let value: [u8; 64] = BASE64
+        .decode(&signature.value)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or("bad signature value")?;
+
+    key.verify(
+        message(record.clone()).as_bytes(),
+        &ed25519_dalek::Signature::from_bytes(&value),
+    )
+    .map_err(|_| "the signature does not match the record".to_string())?;
+    Ok(signature.public_key)
+}
+
+/// The signed message for `record`: compact JSON, keys sorted, without the
+/// unsigned fields.
+fn message(mut record: Value) -> String {
+    if let Value::Object(fields) = &mut record {
+        for field in UNSIGNED_FIELDS {
+            fields.remove(*field);
+        }
+    }
+    canonical(&record)
+}
+
+fn canonical(value: &Value) -> String {
+    match value {
+        Value::Object(fields) => {
+            let mut keys: Vec<&String> = fields.keys().collect();
+            keys.sort();
+            let fields: Vec<String> = keys
+                .into_iter()
+                .map(|key| {
+                    format!("{}:{}", Value::from(key.as_str()), canonical(&fields[key]))
+                })
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        }
+        Value::Array(items) => {
+            format!("[{}]", items.iter().map(canonical).collect::<Vec<_>>().join(","))
+        }
+        scalar => scalar.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let signer = ResultSigner::from_hex(&"07".repeat(32)).unwrap();
+        let mut record = json!({"query": "q", "answer": "a", "web_results": [{"url": "u"}]});
+        let signature = signer.sign(&record).unwrap();
+        assert_eq!(signature.public_key, signer.public_key());
+
+        record["signature"] = serde_json::to_value(&signature).unwrap();
+        record["id"] = json!(42);
+        assert_eq!(verify(&record).unwrap(), signer.public_key());
+
+        record["answer"] = json!("tampered");
+        assert!(verify(&record).is_err());
+        assert!(verify(&json!({"answer": "a"})).is_err());
+        assert!(ResultSigner::from_hex("abcd").is_err());
+        assert_eq!(
+            canonical(&json!({"b": [1, {"d": 2, "c": "x"}], "a": null})),
+            r#"{"a":null,"b":[1,{"c":"x","d":2}]}"#
+        );
+    }
+}
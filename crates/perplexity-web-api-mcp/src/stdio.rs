@@ -0,0 +1,243 @@
+//! Fault-tolerant newline-delimited JSON-RPC transport for stdio.
+//!
+//! rmcp's own stdio transport ends the session on the first line it cannot
+//! decode, which an editor experiences as the server crashing. This one
+//! answers a malformed line with a JSON-RPC error and keeps reading, so the
+//! session only ends when the input reaches EOF or keeps failing to read.
+
+use rmcp::RoleServer;
+use rmcp::service::{RxJsonRpcMessage, TxJsonRpcMessage};
+use rmcp::transport::Transport;
+use serde_json::{Value, json};
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{
+    AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use tokio::sync::Mutex;
+
+/// Longest accepted message, in bytes. Longer lines are discarded.
+const MAX_LINE_LENGTH: usize = 16 * 1024 * 1024;
+
+/// Consecutive read errors after which the input is treated as closed.
+const MAX_READ_ERRORS: u32 = 5;
+
+/// Delay before retrying after a read error; doubled for each further error.
+const READ_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+
+/// Server transport over a reader and a writer of JSON-RPC lines.
+pub struct LineTransport<R, W> {
+    reader: BufReader<R>,
+    writer: Arc<Mutex<W>>,
+    line: Vec<u8>,
+}
+
+/// Transport over the process's stdin and stdout.
+pub fn stdio() -> LineTransport<tokio::io::Stdin, tokio::io::Stdout> {
+    LineTransport::new(tokio::io::stdin(), tokio::io::stdout())
+}
+
+enum Line {
+    Message(Vec<u8>),
+    TooLong,
+    Eof,
+}
+
+impl<R, W> LineTransport<R, W>
+where
+    R: AsyncRead + Unpin + Send,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    pub fn new(reader: R, writer: W) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            writer: Arc::new(Mutex::new(writer)),
+            line: Vec::new(),
+        }
+    }
+
+    /// Reads the next line, retrying transient read errors.
+    async fn read_line(&mut self) -> Line {
+        let mut errors = 0;
+        loop {
+            self.line.clear();
+            let limit = u64::try_from(MAX_LINE_LENGTH).unwrap_or(u64::MAX) + 1;
+            match (&mut self.reader).take(limit).read_until(b'\n', &mut self.line).await {
+                Ok(0) => return Line::Eof,
+                Ok(_) if self.line.len() > MAX_LINE_LENGTH && !self.line.ends_with(b"\n") => {
+                    return match self.skip_line().await {
+                        Ok(()) => Line::TooLong,
+                        Err(_) => Line::Eof,
+                    };
+                }
+                Ok(_) => return Line::Message(std::mem::take(&mut self.line)),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    errors += 1;
+                    if errors >= MAX_READ_ERRORS {
+                        tracing::error!(
+                            "Giving up on input after {} read errors: {}",
+                            errors,
+                            e
+                        );
+                        return Line::Eof;
+                    }
+                    tracing::warn!("Error reading input, retrying: {}", e);
+                    tokio::time::sleep(READ_RETRY_DELAY * 2u32.pow(errors - 1)).await;
+                }
+            }
+        }
+    }
+
+    /// Discards input up to and including the next newline.
+    async fn skip_line(&mut self) -> io::Result<()> {
+        loop {
+            let buffer = self.reader.fill_buf().await?;
+            if buffer.is_empty() {
+                return Ok(());
+            }
+            match buffer.iter().position(|&b| b == b'\n') {
+                Some(end) => {
+                    self.reader.consume(end + 1);
+                    return Ok(());
+                }
+                None => {
+                    let len = buffer.len();
+                    self.reader.consume(len);
+                }
+            }
+        }
+    }
+}
+
+impl<R, W> Transport<RoleServer> for LineTransport<R, W>
+where
+    R: AsyncRead + Unpin + Send,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    type Error = io::Error;
+
+    fn send(
+        &mut self,
+        item: TxJsonRpcMessage<RoleServer>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'static {
+        let writer = Arc::clone(&self.writer);
+        async move { write_line(&writer, &item).await }
+    }
+
+    async fn receive(&mut self) -> Option<RxJsonRpcMessage<RoleServer>> {
+        loop {
+            let line = match self.read_line().await {
+                Line::Message(line) => line,
+                Line::TooLong => {
+                    tracing::warn!(
+                        "Discarded a message longer than {} bytes",
+                        MAX_LINE_LENGTH
+                    );
+                    reject(&self.writer, Value::Null, INVALID_REQUEST, "Message too long")
+                        .await;
+                    continue;
+                }
+                Line::Eof => return None,
+            };
+            if line.trim_ascii().is_empty() {
+                continue;
+            }
+
+            match serde_json::from_slice(&line) {
+                Ok(message) => return Some(message),
+                Err(e) => match serde_json::from_slice::<Value>(&line) {
+                    // Notifications get no response, even invalid ones.
+                    Ok(value) if value.get("id").is_none_or(Value::is_null) => {
+                        tracing::warn!("Ignoring invalid notification: {}", e);
+                    }
+                    Ok(value) => {
+                        tracing::warn!("Rejecting invalid request: {}", e);
+                        let id = value.get("id").cloned().unwrap_or(Value::Null);
+                        reject(&self.writer, id, INVALID_REQUEST, "Invalid request").await;
+                    }
+                    Err(_) => {
+                        tracing::warn!("Rejecting malformed message: {}", e);
+                        reject(&self.writer, Value::Null, PARSE_ERROR, "Parse error").await;
+                    }
+                },
+            }
+        }
+    }
+
+    async fn close(&mut self) -> Result<(), Self::Error> {
+        self.writer.lock().await.flush().await
+    }
+}
+
+/// Reports an undecodable line to the client.
+async fn reject<W: AsyncWrite + Unpin>(
+    writer: &Mutex<W>,
+    id: Value,
+    code: i64,
+    message: &str,
+) {
+    let error = json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    });
+    if let Err(e) = write_line(writer, &error).await {
+        tracing::warn!("Failed to report malformed message: {}", e);
+    }
+}
+
+async fn write_line<W, T>(writer: &Mutex<W>, item: &T) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+    T: serde::Serialize,
+{
+    let mut bytes = serde_json::to_vec(item)?;
+    bytes.push(b'\n');
+
+    let mut writer = writer.lock().await;
+    writer.write_all(&bytes).await?;
+    writer.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, duplex};
+
+    #[tokio::test]
+    async fn test_survives_malformed_lines() {
+        let (mut client_in, server_in) = duplex(4096);
+        let (server_out, client_out) = duplex(4096);
+        let mut transport = LineTransport::new(server_in, server_out);
+        let mut responses = BufReader::new(client_out).lines();
+
+        client_in
+            .write_all(
+                b"{not json\n\n{\"jsonrpc\":\"2.0\",\"id\":7,\"method\":42}\n\
+                  {\"jsonrpc\":\"2.0\",\"method\":7}\n\
+                  {\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"ping\"}\n",
+            )
+            .await
+            .unwrap();
+        drop(client_in);
+
+        let message = transport.receive().await.expect("valid message after bad ones");
+        assert!(matches!(message, rmcp::model::JsonRpcMessage::Request(_)));
+        assert!(transport.receive().await.is_none());
+
+        let parse_error: Value =
+            serde_json::from_str(&responses.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(parse_error["error"]["code"], PARSE_ERROR);
+        assert!(parse_error["id"].is_null());
+
+        let invalid: Value =
+            serde_json::from_str(&responses.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(invalid["error"]["code"], INVALID_REQUEST);
+        assert_eq!(invalid["id"], 7);
+    }
+}
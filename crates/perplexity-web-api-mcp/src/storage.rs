@@ -0,0 +1,198 @@
+//! Encryption of persisted state at rest.
+//!
+//! Headless deployments often have no keyring to keep secrets in, and the
+//! job and schedule state files and the query history database hold every
+//! question asked. With a storage key configured, these are written
+//! encrypted with XChaCha20-Poly1305 and decrypted when read back. Files
+//! written before the key was set are still read, and are encrypted the
+//! next time they are written.
+//!
+//! The key comes from `PERPLEXITY_STORAGE_KEY`, or from the output of the
+//! `PERPLEXITY_STORAGE_KEY_COMMAND` hook for keys held in a KMS. A value of
+//! 64 hex digits is used as the key itself; anything else is a passphrase,
+//! stretched with Argon2id and a random salt stored in the file.
+
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use std::io;
+use std::process::Command;
+use std::sync::Arc;
+
+use crate::secrets;
+
+/// Marks an encrypted file.
+const MAGIC: &[u8; 8] = b"PWMENC1\0";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = MAGIC.len() + SALT_LEN + NONCE_LEN;
+
+/// Key encrypting persisted state.
+#[derive(Clone)]
+pub struct StorageKey {
+    /// The passphrase the key was derived from, kept to derive the keys of
+    /// files written with another salt. `None` for raw keys.
+    passphrase: Option<Arc<str>>,
+    /// Salt of files written by this process; zero for raw keys.
+    salt: [u8; SALT_LEN],
+    key: Key,
+}
+
+impl StorageKey {
+    /// Creates a key from 64 hex digits or a passphrase.
+    pub fn new(secret: &str) -> Result<Self, String> {
+        let secret = secret.trim();
+        if secret.is_empty() {
+            return Err("the storage key is empty".to_string());
+        }
+        if secret.len() == 64
+            && let Ok(bytes) = hex::decode(secret)
+        {
+            return Ok(Self {
+                passphrase: None,
+                salt: [0; SALT_LEN],
+                key: *Key::from_slice(&bytes),
+            });
+        }
+
+        let mut salt = [0; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Ok(Self { passphrase: Some(Arc::from(secret)), salt, key: derive(secret, &salt)? })
+    }
+
+    /// Reads the key from `PERPLEXITY_STORAGE_KEY` or the output of
+    /// `PERPLEXITY_STORAGE_KEY_COMMAND`, if either is set.
+    pub fn from_env() -> Result<Option<Self>, String> {
+        if let Some(secret) = secrets::secret("PERPLEXITY_STORAGE_KEY") {
+            return Self::new(&secret).map(Some);
+        }
+        let Ok(command) = std::env::var("PERPLEXITY_STORAGE_KEY_COMMAND") else {
+            return Ok(None);
+        };
+
+        let output = shell(&command)
+            .output()
+            .map_err(|e| format!("cannot run the storage key command: {}", e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "the storage key command failed ({}): {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        let secret = String::from_utf8(output.stdout)
+            .map_err(|_| "the storage key command printed invalid UTF-8".to_string())?;
+        Self::new(&secret).map(Some)
+    }
+
+    /// Encrypts `plaintext` into the contents of an encrypted file.
+    pub fn seal(&self, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = XChaCha20Poly1305::new(&self.key)
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| invalid("encryption failed"))?;
+
+        let mut sealed = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+        sealed.extend_from_slice(MAGIC);
+        sealed.extend_from_slice(&self.salt);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    /// Decrypts the contents of an encrypted file.
+    fn unseal(&self, sealed: &[u8]) -> io::Result<Vec<u8>> {
+        if sealed.len() < HEADER_LEN {
+            return Err(invalid("the encrypted file is truncated"));
+        }
+        let (salt, rest) = sealed[MAGIC.len()..].split_at(SALT_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+        let key = match &self.passphrase {
+            Some(passphrase) if salt != self.salt => {
+                derive(passphrase, salt).map_err(invalid)?
+            }
+            _ => self.key,
+        };
+        XChaCha20Poly1305::new(&key)
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| invalid("cannot decrypt the file; is the storage key right?"))
+    }
+}
+
+/// Whether `bytes` are the contents of an encrypted file.
+pub fn is_sealed(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Encrypts `bytes` if a key is configured.
+pub fn seal(key: Option<&StorageKey>, bytes: Vec<u8>) -> io::Result<Vec<u8>> {
+    match key {
+        Some(key) => key.seal(&bytes),
+        None => Ok(bytes),
+    }
+}
+
+/// Decrypts `bytes` read from a file if they are encrypted, and returns
+/// them unchanged otherwise.
+pub fn unseal(key: Option<&StorageKey>, bytes: Vec<u8>) -> io::Result<Vec<u8>> {
+    if !is_sealed(&bytes) {
+        return Ok(bytes);
+    }
+    match key {
+        Some(key) => key.unseal(&bytes),
+        None => Err(invalid(
+            "the file is encrypted; set PERPLEXITY_STORAGE_KEY or \
+             PERPLEXITY_STORAGE_KEY_COMMAND",
+        )),
+    }
+}
+
+fn derive(passphrase: &str, salt: &[u8]) -> Result<Key, String> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("cannot derive the storage key: {}", e))?;
+    Ok(key)
+}
+
+fn shell(command: &str) -> Command {
+    #[cfg(windows)]
+    let (program, flag) = ("cmd", "/C");
+    #[cfg(not(windows))]
+    let (program, flag) = ("sh", "-c");
+    let mut shell = Command::new(program);
+    shell.arg(flag).arg(command);
+    shell
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_unseal() {
+        let raw = StorageKey::new(&"ab".repeat(32)).unwrap();
+        let sealed = raw.seal(b"{\"jobs\":[]}").unwrap();
+        assert!(is_sealed(&sealed));
+        assert_eq!(unseal(Some(&raw), sealed.clone()).unwrap(), b"{\"jobs\":[]}");
+        assert!(unseal(None, sealed.clone()).is_err());
+        assert!(unseal(Some(&StorageKey::new(&"cd".repeat(32)).unwrap()), sealed).is_err());
+
+        // Another process with the same passphrase uses another salt.
+        let written = StorageKey::new("correct horse").unwrap();
+        let reader = StorageKey::new("correct horse").unwrap();
+        assert_ne!(written.salt, reader.salt);
+        let sealed = seal(Some(&written), b"history".to_vec()).unwrap();
+        assert_eq!(unseal(Some(&reader), sealed.clone()).unwrap(), b"history");
+        assert!(unseal(Some(&StorageKey::new("wrong").unwrap()), sealed).is_err());
+
+        assert_eq!(unseal(None, b"[]".to_vec()).unwrap(), b"[]");
+        assert!(StorageKey::new("  ").is_err());
+    }
+}
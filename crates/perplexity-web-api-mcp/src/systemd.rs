@@ -0,0 +1,48 @@
+//! Integration with systemd service units.
+//!
+//! When started by a `.socket` unit, the server takes over the listening
+//! socket systemd passes it instead of binding its own, so the address is
+//! configured in the unit and connections arriving during a restart are
+//! queued rather than refused. The service manager is told through
+//! `sd_notify` when the server is ready and when it stops, and is pinged at
+//! half the watchdog interval if the unit sets `WatchdogSec`.
+
+use sd_notify::NotifyState;
+use std::io;
+use std::net::TcpListener;
+
+/// Takes the first listening socket passed by systemd, if any.
+pub fn take_listener() -> io::Result<Option<TcpListener>> {
+    let mut fds = listenfd::ListenFd::from_env();
+    let Some(listener) = fds.take_tcp_listener(0)? else {
+        return Ok(None);
+    };
+    listener.set_nonblocking(true)?;
+    Ok(Some(listener))
+}
+
+/// Tells systemd the server is ready, and starts the watchdog pings.
+pub fn ready(status: &str) {
+    notify(&[NotifyState::Ready, NotifyState::Status(status)]);
+
+    if let Some(timeout) = sd_notify::watchdog_enabled() {
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(timeout / 2);
+            loop {
+                ticks.tick().await;
+                notify(&[NotifyState::Watchdog]);
+            }
+        });
+    }
+}
+
+/// Tells systemd the server is shutting down.
+pub fn stopping() {
+    notify(&[NotifyState::Stopping]);
+}
+
+fn notify(state: &[NotifyState<'_>]) {
+    if let Err(e) = sd_notify::notify(state) {
+        tracing::warn!("Cannot notify systemd: {}", e);
+    }
+}
@@ -0,0 +1,327 @@
+//! Query templates exposed as tools.
+//!
+//! Operators can encode research recipes as templates in a JSON file: a
+//! query with `{placeholders}`, the typed arguments that fill them, and
+//! optionally the mode, sources, answer style and model to ask with. Each
+//! template becomes an MCP tool whose input schema lists its arguments, so
+//! agents call `security_advisories(product, since)` instead of composing
+//! the query themselves. `{{` and `}}` stand for literal braces.
+
+use perplexity_web_api::SearchMode;
+use rmcp::model::{JsonObject, Tool, ToolAnnotations};
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+/// The templates file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TemplatesFile {
+    templates: Vec<QueryTemplate>,
+}
+
+/// A query template.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QueryTemplate {
+    /// Name of the tool.
+    pub name: String,
+    /// Description of the tool shown to agents.
+    pub description: String,
+    /// The query, with `{argument}` placeholders.
+    pub query: String,
+    /// Search mode to ask in.
+    #[serde(default = "default_mode")]
+    pub mode: String,
+    /// Arguments, by placeholder name.
+    #[serde(default)]
+    pub arguments: BTreeMap<String, Argument>,
+    /// Sources to search, as for the search tools.
+    #[serde(default)]
+    pub sources: Option<Vec<String>>,
+    /// Answer style, as for the search tools.
+    #[serde(default)]
+    pub answer_style: Option<String>,
+    /// Model or model selector, as for the search tools.
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+fn default_mode() -> String {
+    SearchMode::Auto.as_str().to_string()
+}
+
+/// A template argument.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Argument {
+    /// Type of the value.
+    #[serde(rename = "type", default)]
+    pub kind: ArgumentType,
+    /// Description shown to agents.
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Value used when the argument is left out. Arguments without one are
+    /// required.
+    #[serde(default)]
+    pub default: Option<Value>,
+    /// Values a string argument is limited to.
+    #[serde(default, rename = "enum")]
+    pub choices: Option<Vec<String>>,
+}
+
+/// Type of a template argument.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArgumentType {
+    #[default]
+    String,
+    Integer,
+    Number,
+    Boolean,
+    /// A calendar date, `YYYY-MM-DD`.
+    Date,
+}
+
+impl ArgumentType {
+    /// Formats `value` for the query, if it has this type.
+    fn format(self, value: &Value) -> Option<String> {
+        match (self, value) {
+            (Self::String, Value::String(s)) => Some(s.clone()),
+            (Self::Date, Value::String(s)) if is_date(s) => Some(s.clone()),
+            (Self::Integer, Value::Number(n)) if n.is_i64() || n.is_u64() => {
+                Some(n.to_string())
+            }
+            (Self::Number, Value::Number(n)) => Some(n.to_string()),
+            (Self::Boolean, Value::Bool(b)) => Some(b.to_string()),
+            _ => None,
+        }
+    }
+
+    fn schema(self) -> Map<String, Value> {
+        let mut schema = Map::new();
+        let kind = match self {
+            Self::String | Self::Date => "string",
+            Self::Integer => "integer",
+            Self::Number => "number",
+            Self::Boolean => "boolean",
+        };
+        schema.insert("type".into(), kind.into());
+        if self == Self::Date {
+            schema.insert("format".into(), "date".into());
+        }
+        schema
+    }
+}
+
+/// Whether `s` is a `YYYY-MM-DD` date.
+fn is_date(s: &str) -> bool {
+    let parts: Vec<&str> = s.split('-').collect();
+    let [year, month, day] = parts[..] else {
+        return false;
+    };
+    let number = |part: &str, len: usize, range: std::ops::RangeInclusive<u32>| {
+        part.len() == len
+            && part.bytes().all(|b| b.is_ascii_digit())
+            && part.parse().is_ok_and(|n| range.contains(&n))
+    };
+    number(year, 4, 0..=9999) && number(month, 2, 1..=12) && number(day, 2, 1..=31)
+}
+
+/// A piece of a parsed query.
+enum Piece<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+/// Splits `query` into text and placeholders.
+fn pieces(query: &str) -> Result<Vec<Piece<'_>>, String> {
+    let mut pieces = Vec::new();
+    let mut rest = query;
+    while let Some(i) = rest.find(['{', '}']) {
+        pieces.push(Piece::Text(&rest[..i]));
+        let tail = &rest[i..];
+        if tail.starts_with("{{") || tail.starts_with("}}") {
+            pieces.push(Piece::Text(&tail[..1]));
+            rest = &tail[2..];
+        } else if tail.starts_with('}') {
+            return Err("unmatched `}`; write `}}` for a literal brace".into());
+        } else {
+            let end = tail.find('}').ok_or("unclosed `{`; write `{{` for a literal brace")?;
+            pieces.push(Piece::Placeholder(tail[1..end].trim()));
+            rest = &tail[end + 1..];
+        }
+    }
+    pieces.push(Piece::Text(rest));
+    Ok(pieces)
+}
+
+impl QueryTemplate {
+    /// Checks that the template is usable, returning its search mode.
+    pub fn validate(&self) -> Result<SearchMode, String> {
+        if self.name.is_empty()
+            || !self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+        {
+            return Err("the name may only contain letters, digits, `_`, `-` and `.`".into());
+        }
+        let mode = self.mode.parse::<SearchMode>().map_err(|e| e.to_string())?;
+        let mut used = Vec::new();
+        for piece in pieces(&self.query)? {
+            if let Piece::Placeholder(name) = piece {
+                if !self.arguments.contains_key(name) {
+                    return Err(format!("`{{{}}}` is not one of the arguments", name));
+                }
+                used.push(name);
+            }
+        }
+        for (name, argument) in &self.arguments {
+            if !used.contains(&name.as_str()) {
+                return Err(format!("argument `{}` is not used in the query", name));
+            }
+            if argument.choices.is_some() && argument.kind != ArgumentType::String {
+                return Err(format!("argument `{}`: `enum` needs type string", name));
+            }
+            if let Some(default) = &argument.default
+                && argument.kind.format(default).is_none()
+            {
+                return Err(format!(
+                    "argument `{}`: the default is not a {:?}",
+                    name, argument.kind
+                ));
+            }
+        }
+        Ok(mode)
+    }
+
+    /// The tool definition for the template.
+    pub fn tool(&self) -> Tool {
+        let mut properties = Map::new();
+        let mut required = Vec::new();
+        for (name, argument) in &self.arguments {
+            let mut schema = argument.kind.schema();
+            if let Some(description) = &argument.description {
+                schema.insert("description".into(), description.clone().into());
+            }
+            if let Some(choices) = &argument.choices {
+                schema.insert("enum".into(), json!(choices));
+            }
+            match &argument.default {
+                Some(default) => {
+                    schema.insert("default".into(), default.clone());
+                }
+                None => required.push(name.clone()),
+            }
+            properties.insert(name.clone(), Value::Object(schema));
+        }
+        let mut schema = JsonObject::new();
+        schema.insert("type".into(), "object".into());
+        schema.insert("properties".into(), Value::Object(properties));
+        schema.insert("required".into(), json!(required));
+        schema.insert("additionalProperties".into(), false.into());
+
+        Tool::new(self.name.clone(), self.description.clone(), Arc::new(schema)).annotate(
+            ToolAnnotations::new().read_only(true).destructive(false).open_world(true),
+        )
+    }
+
+    /// Fills in the query from a call's arguments.
+    pub fn render(&self, arguments: Option<&JsonObject>) -> Result<String, String> {
+        let empty = JsonObject::new();
+        let arguments = arguments.unwrap_or(&empty);
+        if let Some(unknown) =
+            arguments.keys().find(|name| !self.arguments.contains_key(*name))
+        {
+            return Err(format!("unknown argument `{}`", unknown));
+        }
+
+        let mut query = String::new();
+        for piece in pieces(&self.query)? {
+            match piece {
+                Piece::Text(text) => query.push_str(text),
+                Piece::Placeholder(name) => {
+                    let argument = &self.arguments[name];
+                    let value = arguments
+                        .get(name)
+                        .or(argument.default.as_ref())
+                        .ok_or_else(|| format!("missing argument `{}`", name))?;
+                    let text = argument.kind.format(value).ok_or_else(|| {
+                        format!("argument `{}` must be a {:?}", name, argument.kind)
+                    })?;
+                    if let Some(choices) = &argument.choices
+                        && !choices.contains(&text)
+                    {
+                        return Err(format!(
+                            "argument `{}` must be one of {}",
+                            name,
+                            choices.join(", ")
+                        ));
+                    }
+                    query.push_str(&text);
+                }
+            }
+        }
+        Ok(query)
+    }
+}
+
+/// Reads and checks the templates in the file at `path`.
+pub fn load(path: &Path) -> Result<Vec<(QueryTemplate, SearchMode)>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let file: TemplatesFile = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+    file.templates
+        .into_iter()
+        .map(|template| match template.validate() {
+            Ok(mode) => Ok((template, mode)),
+            Err(e) => Err(format!("template {}: {}", template.name, e)),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_renders_typed_arguments() {
+        let template: QueryTemplate = serde_json::from_value(json!({
+            "name": "security_advisories",
+            "description": "Recent advisories",
+            "query": "Summarize {severity} security advisories for {product} since {since} {{max {limit}}}",
+            "mode": "pro",
+            "arguments": {
+                "product": { "description": "Product name" },
+                "since": { "type": "date" },
+                "severity": { "enum": ["all", "critical"], "default": "all" },
+                "limit": { "type": "integer", "default": 5 }
+            }
+        }))
+        .unwrap();
+        assert_eq!(template.validate(), Ok(SearchMode::Pro));
+
+        let tool = template.tool();
+        assert_eq!(tool.input_schema["required"], json!(["product", "since"]));
+        assert_eq!(tool.input_schema["properties"]["since"]["format"], "date");
+
+        let args = json!({ "product": "OpenSSL", "since": "2026-01-01" });
+        assert_eq!(
+            template.render(args.as_object()).unwrap(),
+            "Summarize all security advisories for OpenSSL since 2026-01-01 {max 5}"
+        );
+        let bad_date = json!({ "product": "OpenSSL", "since": "January" });
+        assert!(template.render(bad_date.as_object()).is_err());
+        let bad_choice = json!({ "product": "x", "since": "2026-01-01", "severity": "low" });
+        assert!(template.render(bad_choice.as_object()).is_err());
+        assert!(template.render(None).unwrap_err().contains("missing argument"));
+
+        let mut unused = template.clone();
+        unused.query = "Advisories for {product} since {since}".into();
+        assert!(unused.validate().unwrap_err().contains("not used"));
+        unused.query = "Advisories for {vendor}".into();
+        assert!(unused.validate().unwrap_err().contains("not one of the arguments"));
+    }
+}
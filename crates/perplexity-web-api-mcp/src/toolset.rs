@@ -0,0 +1,79 @@
+//! Selection of the exposed tools.
+//!
+//! Shared deployments may not want every tool available, for example the
+//! ones that change account state. A [`ToolFilter`] keeps tools out of
+//! `tools/list`, and calls to them fail as calls to unknown tools do.
+
+/// Tools to expose, by default name.
+#[derive(Debug, Clone, Default)]
+pub struct ToolFilter {
+    allow: Option<Vec<String>>,
+    deny: Vec<String>,
+}
+
+impl ToolFilter {
+    /// Exposes only the listed tools (less any denied ones).
+    pub fn allow<S: Into<String>>(mut self, tools: impl IntoIterator<Item = S>) -> Self {
+        self.allow.get_or_insert_with(Vec::new).extend(tools.into_iter().map(Into::into));
+        self
+    }
+
+    /// Hides the listed tools.
+    pub fn deny<S: Into<String>>(mut self, tools: impl IntoIterator<Item = S>) -> Self {
+        self.deny.extend(tools.into_iter().map(Into::into));
+        self
+    }
+
+    /// Returns the tools among `tools` to remove, rejecting filters that
+    /// name unknown tools.
+    pub fn removed<'a>(
+        &self,
+        tools: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Vec<String>, String> {
+        let tools: Vec<&str> = tools.into_iter().collect();
+        let listed = self.allow.iter().flatten().chain(&self.deny);
+        if let Some(unknown) = listed.into_iter().find(|tool| !tools.contains(&tool.as_str()))
+        {
+            return Err(format!("Unknown tool '{}'", unknown));
+        }
+
+        Ok(tools
+            .into_iter()
+            .filter(|&tool| {
+                let allowed =
+                    self.allow.as_ref().is_none_or(|allow| allow.iter().any(|t| t == tool));
+                !allowed || self.deny.iter().any(|t| t == tool)
+            })
+            .map(String::from)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOOLS: &[&str] =
+        &["perplexity_search", "perplexity_research", "perplexity_bookmark"];
+
+    #[test]
+    fn test_removed_tools() {
+        let removed = |filter: ToolFilter| filter.removed(TOOLS.iter().copied());
+
+        assert!(removed(ToolFilter::default()).unwrap().is_empty());
+        assert_eq!(
+            removed(ToolFilter::default().deny(["perplexity_bookmark"])).unwrap(),
+            ["perplexity_bookmark"]
+        );
+        assert_eq!(
+            removed(
+                ToolFilter::default()
+                    .allow(["perplexity_search", "perplexity_research"])
+                    .deny(["perplexity_research"])
+            )
+            .unwrap(),
+            ["perplexity_research", "perplexity_bookmark"]
+        );
+        assert!(removed(ToolFilter::default().deny(["perplexity_raw_request"])).is_err());
+    }
+}
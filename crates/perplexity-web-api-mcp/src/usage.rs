@@ -0,0 +1,211 @@
+//! Local usage statistics.
+//!
+//! Counts the queries answered, failed and served from the cache in each
+//! search mode, and estimates the tokens of the answers, so users can see how
+//! they use Perplexity. The counts are
+//! kept in a small JSON file next to the configuration file and are never
+//! sent anywhere. The file is re-read before every update, so `stats
+//! --reset` takes effect while a server is running.
+
+use perplexity_web_api::SearchMode;
+use rmcp::schemars;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::storage::{self, StorageKey};
+use crate::util::{read_json, rfc3339, unix_now, write_atomically};
+
+/// How a query ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Perplexity answered it.
+    Answered,
+    /// It failed.
+    Failed,
+    /// It was answered from the cache.
+    Cached,
+}
+
+/// Query counts for one search mode.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema,
+)]
+pub struct ModeUsage {
+    /// Queries Perplexity answered.
+    pub answered: u64,
+    /// Queries that failed.
+    pub failed: u64,
+    /// Queries answered from the cache.
+    pub cached: u64,
+    /// Estimated tokens of the answers returned, fresh or cached.
+    #[serde(default)]
+    pub answer_tokens: u64,
+}
+
+impl ModeUsage {
+    fn add(&mut self, other: &Self) {
+        self.answered += other.answered;
+        self.failed += other.failed;
+        self.cached += other.cached;
+        self.answer_tokens += other.answer_tokens;
+    }
+}
+
+/// Usage since the counts were last reset.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct UsageSummary {
+    /// When counting started (RFC 3339).
+    pub since: Option<String>,
+    /// When the last query was counted (RFC 3339).
+    pub last_used: Option<String>,
+    /// Counts per search mode.
+    pub modes: BTreeMap<String, ModeUsage>,
+    /// Counts over all modes.
+    pub total: ModeUsage,
+}
+
+/// The stored counts.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct Counts {
+    since: Option<i64>,
+    last_used: Option<i64>,
+    modes: BTreeMap<String, ModeUsage>,
+}
+
+/// Usage statistics, shared by all clones.
+#[derive(Clone, Default)]
+pub struct UsageStats {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    path: Option<PathBuf>,
+    storage_key: Option<StorageKey>,
+    counts: Mutex<Counts>,
+}
+
+impl UsageStats {
+    /// Keeps the counts in the file at `path`, or only in memory without
+    /// one, encrypting the file with `storage_key` if given.
+    pub fn open(path: Option<PathBuf>, storage_key: Option<StorageKey>) -> io::Result<Self> {
+        let counts = match &path {
+            Some(path) => read_json(path, storage_key.as_ref())?.unwrap_or_default(),
+            None => Counts::default(),
+        };
+        Ok(Self { inner: Arc::new(Inner { path, storage_key, counts: Mutex::new(counts) }) })
+    }
+
+    /// The default location of the statistics file, next to the
+    /// configuration file.
+    pub fn default_path() -> PathBuf {
+        crate::config::path().with_file_name("stats.json")
+    }
+
+    /// Where the counts are kept, if in a file.
+    pub fn path(&self) -> Option<&Path> {
+        self.inner.path.as_deref()
+    }
+
+    /// Counts a query in `mode` whose answer took an estimated
+    /// `answer_tokens`. Failures to save are logged.
+    pub fn record(&self, mode: SearchMode, outcome: Outcome, answer_tokens: u64) {
+        let result = self.update(|counts| {
+            let now = unix_now();
+            counts.since.get_or_insert(now);
+            counts.last_used = Some(now);
+            let usage = counts.modes.entry(mode.as_str().to_string()).or_default();
+            match outcome {
+                Outcome::Answered => usage.answered += 1,
+                Outcome::Failed => usage.failed += 1,
+                Outcome::Cached => usage.cached += 1,
+            }
+            usage.answer_tokens += answer_tokens;
+        });
+        if let Err(e) = result {
+            tracing::warn!("Failed to save usage statistics: {}", e);
+        }
+    }
+
+    /// Clears the counts.
+    pub fn reset(&self) -> io::Result<()> {
+        self.update(|counts| *counts = Counts::default()).map(drop)
+    }
+
+    /// Returns the counts.
+    pub fn summary(&self) -> io::Result<UsageSummary> {
+        let counts = self.update(|_| {})?;
+        let mut total = ModeUsage::default();
+        counts.modes.values().for_each(|usage| total.add(usage));
+        let time = |secs: i64| rfc3339(UNIX_EPOCH + Duration::from_secs(secs.unsigned_abs()));
+        Ok(UsageSummary {
+            since: counts.since.map(time),
+            last_used: counts.last_used.map(time),
+            modes: counts.modes,
+            total,
+        })
+    }
+
+    /// Re-reads the file, applies `change` and saves the result if it
+    /// changed, returning the new counts.
+    fn update(&self, change: impl FnOnce(&mut Counts)) -> io::Result<Counts> {
+        let inner = &self.inner;
+        let mut counts = inner.counts.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(path) = &inner.path {
+            *counts = read_json(path, inner.storage_key.as_ref())?.unwrap_or_default();
+        }
+        let before = counts.clone();
+        change(&mut counts);
+        if let Some(path) = &inner.path
+            && *counts != before
+        {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            let bytes = serde_json::to_vec(&*counts)?;
+            write_atomically(path, &storage::seal(inner.storage_key.as_ref(), bytes)?)?;
+        }
+        Ok(counts.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_persist_and_reset() {
+        let dir = std::env::temp_dir().join(format!("pplx-usage-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("stats.json");
+        let stats = UsageStats::open(Some(path.clone()), None).unwrap();
+        stats.record(SearchMode::Pro, Outcome::Answered, 120);
+        stats.record(SearchMode::Pro, Outcome::Cached, 80);
+        stats.record(SearchMode::Auto, Outcome::Failed, 0);
+
+        let summary = UsageStats::open(Some(path.clone()), None).unwrap().summary().unwrap();
+        assert_eq!(
+            summary.modes["pro"],
+            ModeUsage { answered: 1, failed: 0, cached: 1, answer_tokens: 200 }
+        );
+        assert_eq!(
+            summary.total,
+            ModeUsage { answered: 1, failed: 1, cached: 1, answer_tokens: 200 }
+        );
+        assert!(summary.since.is_some());
+
+        // A reset from another process is seen by the next update.
+        UsageStats::open(Some(path), None).unwrap().reset().unwrap();
+        stats.record(SearchMode::Auto, Outcome::Answered, 5);
+        let summary = stats.summary().unwrap();
+        assert_eq!(
+            summary.total,
+            ModeUsage { answered: 1, failed: 0, cached: 0, answer_tokens: 5 }
+        );
+        assert_eq!(summary.modes.len(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,102 @@
+//! Delivery of completed job results to a user-configured webhook.
+
+use hmac::{Hmac, KeyInit, Mac};
+use rquest::Client as HttpClient;
+use serde::Serialize;
+use sha2::Sha256;
+use std::time::Duration;
+
+use crate::util::unix_now;
+
+/// Header carrying the hex-encoded HMAC-SHA256 of the timestamp and body.
+pub const SIGNATURE_HEADER: &str = "X-Perplexity-Signature-256";
+
+/// Header carrying the Unix time, in seconds, at which a delivery was signed.
+pub const TIMESTAMP_HEADER: &str = "X-Perplexity-Timestamp";
+
+/// How long a single webhook delivery may take.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A webhook endpoint that receives job results as JSON `POST` requests.
+#[derive(Clone)]
+pub struct Webhook {
+    http: HttpClient,
+    url: String,
+    secret: Option<String>,
+}
+
+impl Webhook {
+    /// Creates a webhook posting to `url` with `http`, signing bodies with
+    /// `secret` if set.
+    pub fn new(url: String, secret: Option<String>, http: HttpClient) -> Self {
+        Self { http, url, secret }
+    }
+
+    /// Posts `payload` to the webhook.
+    ///
+    /// When a secret is configured, the current time is sent in
+    /// [`TIMESTAMP_HEADER`], and the signature of `<timestamp>.<body>` is sent
+    /// in [`SIGNATURE_HEADER`] as `sha256=<hex>`, so receivers can refuse
+    /// stale deliveries replayed later.
+    pub async fn deliver(&self, payload: &impl Serialize) -> Result<(), String> {
+        let body = serde_json::to_vec(payload).map_err(|e| e.to_string())?;
+
+        let mut request = self.http.post(&self.url).header("Content-Type", "application/json");
+        if let Some(secret) = &self.secret {
+            let timestamp = unix_now();
+            request = request
+                .header(TIMESTAMP_HEADER, timestamp.to_string())
+                .header(SIGNATURE_HEADER, sign(secret.as_bytes(), timestamp, &body));
+        }
+
+        let response = tokio::time::timeout(DELIVERY_TIMEOUT, request.body(body).send())
+            .await
+            .map_err(|_| format!("timed out after {:?}", DELIVERY_TIMEOUT))?
+            .map_err(|e| e.to_string())?;
+
+        if !response.status().is_success() {
+            return Err(format!("webhook responded with {}", response.status()));
+        }
+
+        Ok(())
+    }
+}
+
+/// Computes the `sha256=<hex>` HMAC signature of `<timestamp>.<body>`.
+fn sign(secret: &[u8], timestamp: i64, body: &[u8]) -> String {
+    let mut message = format!("{}.", timestamp).into_bytes();
+    message.extend_from_slice(body);
+    format!("sha256={}", hmac_hex(secret, &message))
+}
+
+/// Hex-encoded HMAC-SHA256 of `message`.
+fn hmac_hex(secret: &[u8], message: &[u8]) -> String {
+    // HMAC accepts keys of any length, so this cannot fail.
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(message);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hmac_matches_reference_vector() {
+        // RFC 4231 test case 2.
+        assert_eq!(
+            hmac_hex(b"Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_sign_covers_timestamp() {
+        let body = br#"{"job_id":"1"}"#;
+        assert_eq!(
+            sign(b"secret", 1_700_000_000, body),
+            format!("sha256={}", hmac_hex(b"secret", br#"1700000000.{"job_id":"1"}"#))
+        );
+        assert_ne!(sign(b"secret", 1_700_000_000, body), sign(b"secret", 1_700_000_001, body));
+    }
+}
@@ -0,0 +1,318 @@
+//! Running the HTTP-mode server as a Windows service.
+//!
+//! `--install-service` registers an automatically started service that runs
+//! this executable with the HTTP options it was given, and copies the
+//! server's `PERPLEXITY_*` environment variables into the service's
+//! environment, since services do not inherit the installing user's.
+//! `--uninstall-service` stops and removes it again.
+//!
+//! The service control manager starts the executable with `--run-service`.
+//! The server then connects to the service control dispatcher on a
+//! separate thread, reports itself running, shuts down when the service is
+//! stopped, and writes its logs to the Windows Application event log.
+
+use std::ffi::{OsStr, OsString};
+use std::fmt::Write as _;
+use std::os::windows::ffi::OsStrExt;
+use std::sync::{Condvar, Mutex, PoisonError, mpsc};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::Layer;
+use tracing_subscriber::layer::Context;
+use windows_service::service::{
+    ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+    ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{
+    self, ServiceControlHandlerResult, ServiceStatusHandle,
+};
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+use windows_service::{define_windows_service, service_dispatcher};
+use windows_sys::Win32::Foundation::HANDLE;
+use windows_sys::Win32::System::EventLog::{
+    EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE, EVENTLOG_WARNING_TYPE,
+    RegisterEventSourceW, ReportEventW,
+};
+use winreg::RegKey;
+use winreg::enums::{HKEY_LOCAL_MACHINE, KEY_SET_VALUE};
+
+/// Name of the service and of its event log source.
+pub const SERVICE_NAME: &str = "perplexity-web-api-mcp";
+const DISPLAY_NAME: &str = "Perplexity MCP Server";
+const DESCRIPTION: &str = "Serves Perplexity AI search tools over the Model Context Protocol.";
+
+/// Registry key of the service's settings.
+const SERVICE_KEY: &str = r"SYSTEM\CurrentControlSet\Services\perplexity-web-api-mcp";
+/// Registry key registering the event log source.
+const EVENT_SOURCE_KEY: &str =
+    r"SYSTEM\CurrentControlSet\Services\EventLog\Application\perplexity-web-api-mcp";
+/// Message file whose messages print their text as is, shipped with .NET.
+const EVENT_MESSAGE_FILE: &str = r"Microsoft.NET\Framework64\v4.0.30319\EventLogMessages.dll";
+
+/// Options only meaningful to the installing process, left out of the
+/// service's command line.
+const INSTALL_OPTIONS: &[&str] = &["--install-service", "--uninstall-service"];
+
+/// Completes the stop request of the service control manager.
+static STOP: Notify = Notify::const_new();
+/// Status handle, set once the service is registered.
+static STATUS: Mutex<Option<ServiceStatusHandle>> = Mutex::new(None);
+/// Receives whether the service registered, from the dispatcher thread.
+static STARTED: Mutex<Option<mpsc::Sender<Result<(), String>>>> = Mutex::new(None);
+/// Set when the server has shut down, releasing the service thread.
+static STOPPED: Mutex<bool> = Mutex::new(false);
+static STOPPED_CHANGED: Condvar = Condvar::new();
+
+define_windows_service!(ffi_service_main, service_main);
+
+/// Installs the service, running this executable with `--run-service` and
+/// the current command-line options.
+pub fn install() -> Result<String, String> {
+    let executable = std::env::current_exe().map_err(|e| e.to_string())?;
+    let mut launch_arguments = vec![OsString::from("--run-service")];
+    launch_arguments.extend(
+        std::env::args_os()
+            .skip(1)
+            .filter(|arg| !INSTALL_OPTIONS.iter().any(|option| arg == option)),
+    );
+
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )
+    .map_err(|e| format!("cannot connect to the service manager: {}", e))?;
+    let service = manager
+        .create_service(
+            &ServiceInfo {
+                name: SERVICE_NAME.into(),
+                display_name: DISPLAY_NAME.into(),
+                service_type: ServiceType::OWN_PROCESS,
+                start_type: ServiceStartType::AutoStart,
+                error_control: ServiceErrorControl::Normal,
+                executable_path: executable,
+                launch_arguments,
+                dependencies: Vec::new(),
+                account_name: None,
+                account_password: None,
+            },
+            ServiceAccess::CHANGE_CONFIG,
+        )
+        .map_err(|e| format!("cannot create the service: {}", e))?;
+    service.set_description(DESCRIPTION).map_err(|e| e.to_string())?;
+
+    let environment: Vec<String> = std::env::vars()
+        .filter(|(name, _)| name.starts_with("PERPLEXITY_") || name == "RUST_LOG")
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect();
+    let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
+    if !environment.is_empty() {
+        hklm.open_subkey_with_flags(SERVICE_KEY, KEY_SET_VALUE)
+            .and_then(|key| key.set_value("Environment", &environment))
+            .map_err(|e| format!("cannot set the service environment: {}", e))?;
+    }
+
+    let system_root = std::env::var("SystemRoot").unwrap_or_else(|_| r"C:\Windows".into());
+    let (source, _) = hklm
+        .create_subkey(EVENT_SOURCE_KEY)
+        .map_err(|e| format!("cannot register the event log source: {}", e))?;
+    source
+        .set_value("EventMessageFile", &format!(r"{}\{}", system_root, EVENT_MESSAGE_FILE))
+        .and_then(|()| source.set_value("TypesSupported", &7u32))
+        .map_err(|e| format!("cannot register the event log source: {}", e))?;
+
+    Ok(format!(
+        "Installed the {} service with {} environment variables; start it with `sc start {}`",
+        SERVICE_NAME,
+        environment.len(),
+        SERVICE_NAME
+    ))
+}
+
+/// Stops the service if it is running and removes it.
+pub fn uninstall() -> Result<String, String> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+        .map_err(|e| format!("cannot connect to the service manager: {}", e))?;
+    let service = manager
+        .open_service(
+            SERVICE_NAME,
+            ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
+        )
+        .map_err(|e| format!("cannot open the service: {}", e))?;
+    let running = service
+        .query_status()
+        .is_ok_and(|status| status.current_state != ServiceState::Stopped);
+    if running {
+        service.stop().map_err(|e| format!("cannot stop the service: {}", e))?;
+    }
+    service.delete().map_err(|e| format!("cannot delete the service: {}", e))?;
+
+    let _ = RegKey::predef(HKEY_LOCAL_MACHINE).delete_subkey_all(EVENT_SOURCE_KEY);
+    Ok(format!("Removed the {} service", SERVICE_NAME))
+}
+
+/// Connects to the service control dispatcher, returning once the service
+/// is reported running.
+pub fn start() -> Result<(), String> {
+    let (sender, receiver) = mpsc::channel();
+    *STARTED.lock().unwrap_or_else(PoisonError::into_inner) = Some(sender);
+    std::thread::spawn(|| {
+        if let Err(e) = service_dispatcher::start(SERVICE_NAME, ffi_service_main) {
+            report_start(Err(format!(
+                "cannot connect to the service control dispatcher ({}); \
+                 --run-service is only used by the service manager",
+                e
+            )));
+        }
+    });
+    receiver.recv().map_err(|_| "the service did not start".to_string())?
+}
+
+/// Completes when the service control manager asks the service to stop.
+pub async fn stop_requested() {
+    STOP.notified().await;
+}
+
+/// Reports that the service is shutting down.
+pub fn stopping() {
+    set_status(ServiceState::StopPending, ServiceControlAccept::empty());
+}
+
+/// Reports that the service has stopped.
+pub fn stopped() {
+    set_status(ServiceState::Stopped, ServiceControlAccept::empty());
+    *STOPPED.lock().unwrap_or_else(PoisonError::into_inner) = true;
+    STOPPED_CHANGED.notify_all();
+}
+
+fn service_main(_arguments: Vec<OsString>) {
+    let registered =
+        service_control_handler::register(SERVICE_NAME, |control| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                STOP.notify_one();
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        });
+    let handle = match registered {
+        Ok(handle) => handle,
+        Err(e) => {
+            report_start(Err(format!("cannot register the service: {}", e)));
+            return;
+        }
+    };
+    *STATUS.lock().unwrap_or_else(PoisonError::into_inner) = Some(handle);
+    set_status(
+        ServiceState::Running,
+        ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+    );
+    report_start(Ok(()));
+
+    // The dispatcher ends the service when this returns, so wait for the
+    // server to finish shutting down.
+    let mut stopped = STOPPED.lock().unwrap_or_else(PoisonError::into_inner);
+    while !*stopped {
+        stopped = STOPPED_CHANGED.wait(stopped).unwrap_or_else(PoisonError::into_inner);
+    }
+}
+
+fn report_start(result: Result<(), String>) {
+    if let Some(sender) = STARTED.lock().unwrap_or_else(PoisonError::into_inner).take() {
+        let _ = sender.send(result);
+    }
+}
+
+fn set_status(state: ServiceState, controls_accepted: ServiceControlAccept) {
+    let Some(handle) = *STATUS.lock().unwrap_or_else(PoisonError::into_inner) else {
+        return;
+    };
+    let status = ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state: state,
+        controls_accepted,
+        exit_code: ServiceExitCode::Win32(0),
+        checkpoint: 0,
+        wait_hint: Duration::from_secs(30),
+        process_id: None,
+    };
+    if let Err(e) = handle.set_service_status(status) {
+        tracing::warn!("Cannot report the service status: {}", e);
+    }
+}
+
+/// Tracing layer writing info, warning and error records to the Windows
+/// Application event log.
+pub struct EventLogLayer {
+    source: HANDLE,
+}
+
+// Event log handles may be used from any thread.
+unsafe impl Send for EventLogLayer {}
+unsafe impl Sync for EventLogLayer {}
+
+impl EventLogLayer {
+    /// Opens the service's event log source.
+    pub fn new() -> Option<Self> {
+        let name = wide(OsStr::new(SERVICE_NAME));
+        // SAFETY: `name` is a NUL-terminated UTF-16 string.
+        let source = unsafe { RegisterEventSourceW(std::ptr::null(), name.as_ptr()) };
+        (!source.is_null()).then_some(Self { source })
+    }
+}
+
+impl<S: Subscriber> Layer<S> for EventLogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let kind = match *event.metadata().level() {
+            Level::ERROR => EVENTLOG_ERROR_TYPE,
+            Level::WARN => EVENTLOG_WARNING_TYPE,
+            Level::INFO => EVENTLOG_INFORMATION_TYPE,
+            Level::DEBUG | Level::TRACE => return,
+        };
+        let mut message = Message(String::new());
+        event.record(&mut message);
+        let text = wide(OsStr::new(&message.0));
+        let strings = [text.as_ptr()];
+        // SAFETY: `source` is an open event source, and `strings` holds one
+        // NUL-terminated UTF-16 string that outlives the call.
+        unsafe {
+            ReportEventW(
+                self.source,
+                kind,
+                0,
+                0,
+                std::ptr::null_mut(),
+                1,
+                0,
+                strings.as_ptr(),
+                std::ptr::null(),
+            );
+        }
+    }
+}
+
+/// Formats a record as its message followed by `name=value` fields.
+struct Message(String);
+
+impl Visit for Message {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, "{:?}", value);
+        } else {
+            let _ = write!(self.0, " {}={:?}", field.name(), value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.0.push_str(value);
+        } else {
+            let _ = write!(self.0, " {}={}", field.name(), value);
+        }
+    }
+}
+
+fn wide(text: &OsStr) -> Vec<u16> {
+    text.encode_wide().chain(Some(0)).collect()
+}
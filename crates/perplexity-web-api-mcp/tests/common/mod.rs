@@ -0,0 +1,142 @@
+//! A minimal MCP client for driving the server binary over stdio.
+//!
+//! [`Client::start`] runs the server in offline mode against the fixtures in
+//! `tests/fixtures`, with its configuration and state confined to a fresh
+//! directory, and performs the MCP handshake. Requests are then sent as
+//! newline-delimited JSON-RPC messages; notifications from the server are
+//! skipped while waiting for the response.
+
+use serde_json::{Value, json};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+/// Protocol version the client asks for.
+const PROTOCOL_VERSION: &str = "2025-06-18";
+
+/// How long to wait for a response before failing the test.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A running server and the client end of its stdio transport.
+pub struct Client {
+    child: Child,
+    stdin: ChildStdin,
+    messages: Receiver<Value>,
+    next_id: u64,
+    dir: PathBuf,
+}
+
+impl Client {
+    /// Starts the server in offline mode and initializes the session.
+    pub fn start() -> Self {
+        Self::start_with_env(&[])
+    }
+
+    /// Like [`Client::start`], with extra environment variables set for the
+    /// server.
+    pub fn start_with_env(vars: &[(&str, &str)]) -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("perplexity-mcp-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).expect("create state directory");
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures");
+
+        // A clean environment keeps the developer's tokens, configuration
+        // file and state files out of the run. The server logs to stderr,
+        // which would interleave with the test output.
+        let mut child = Command::new(env!("CARGO_BIN_EXE_perplexity-web-api-mcp"))
+            .arg("--offline")
+            .arg("--fixtures")
+            .arg(&fixtures)
+            .env_clear()
+            .env("HOME", &dir)
+            .env("PERPLEXITY_CONFIG", dir.join("config.env"))
+            .envs(vars.iter().copied())
+            .current_dir(&dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("start server");
+        let stdin = child.stdin.take().expect("server stdin");
+        let stdout = child.stdout.take().expect("server stdout");
+
+        let (sender, messages) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                let message = serde_json::from_str(&line)
+                    .unwrap_or_else(|e| panic!("server sent invalid JSON ({}): {}", e, line));
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut client = Self { child, stdin, messages, next_id: 1, dir };
+        let result = client
+            .request(
+                "initialize",
+                json!({
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": {},
+                    "clientInfo": { "name": "stdio-test", "version": "0" },
+                }),
+            )
+            .expect("initialize");
+        assert_eq!(result["serverInfo"]["name"], "perplexity-web-api-mcp");
+        client.notify("notifications/initialized", json!({}));
+        client
+    }
+
+    /// Sends a request and waits for its response: the result, or the
+    /// JSON-RPC error object.
+    pub fn request(&mut self, method: &str, params: Value) -> Result<Value, Value> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(&json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+        loop {
+            let message = self
+                .messages
+                .recv_timeout(TIMEOUT)
+                .unwrap_or_else(|e| panic!("no response to {}: {}", method, e));
+            if message.get("method").is_some() || message["id"] != id {
+                continue;
+            }
+            return match message.get("error") {
+                Some(error) => Err(error.clone()),
+                None => Ok(message["result"].clone()),
+            };
+        }
+    }
+
+    /// Sends a notification.
+    pub fn notify(&mut self, method: &str, params: Value) {
+        self.send(&json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    /// Calls a tool and returns the JSON document of its text content.
+    /// Fails the test if the call fails or reports a tool error.
+    pub fn call_tool(&mut self, name: &str, arguments: Value) -> Value {
+        let result = self
+            .request("tools/call", json!({ "name": name, "arguments": arguments }))
+            .unwrap_or_else(|e| panic!("{} failed: {}", name, e));
+        assert_eq!(result["isError"], false, "{} reported an error: {}", name, result);
+        let text = result["content"][0]["text"].as_str().expect("text content");
+        serde_json::from_str(text).expect("tool result is JSON")
+    }
+
+    fn send(&mut self, message: &Value) {
+        writeln!(self.stdin, "{}", message).expect("write to server");
+        self.stdin.flush().expect("flush server stdin");
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
@@ -0,0 +1,149 @@
+//! End-to-end tests of the MCP tool layer.
+//!
+//! Each test runs the server binary in offline mode and drives it over
+//! stdio, so tool registration, input schemas, argument parsing and
+//! response formatting are covered as a client sees them. Answers are
+//! replayed from the fixtures in `tests/fixtures`.
+
+mod common;
+
+use common::Client;
+use serde_json::{Value, json};
+
+/// Query answered by the fixture in `tests/fixtures`.
+const FIXTURE_QUERY: &str = "What is the Rust borrow checker?";
+
+#[test]
+fn test_list_tools() {
+    let mut client = Client::start();
+    let result = client.request("tools/list", json!({})).expect("tools/list");
+    let tools = result["tools"].as_array().expect("tool array");
+    let tool = |name: &str| tools.iter().find(|tool| tool["name"] == name);
+
+    for name in ["perplexity_search", "perplexity_research", "perplexity_list_models"] {
+        assert!(tool(name).is_some(), "{} is not listed", name);
+    }
+    // Tools that always need Perplexity are hidden offline.
+    assert!(tool("perplexity_generate_image").is_none());
+
+    for tool in tools {
+        assert!(tool["description"].as_str().is_some_and(|d| !d.is_empty()), "{}", tool);
+        assert_eq!(tool["inputSchema"]["type"], "object", "{}", tool["name"]);
+    }
+
+    let schema = &tool("perplexity_search").expect("perplexity_search")["inputSchema"];
+    assert_eq!(schema["required"], json!(["query"]));
+    assert_eq!(schema["properties"]["query"]["type"], "string");
+    assert!(schema["properties"]["sources"].is_object());
+}
+
+#[test]
+fn test_search_replays_fixture() {
+    let mut client = Client::start();
+    let response = client.call_tool("perplexity_search", json!({ "query": FIXTURE_QUERY }));
+
+    let answer = response["answer"].as_str().expect("answer");
+    assert!(answer.starts_with("The borrow checker is the part of the Rust compiler"));
+    let urls: Vec<&Value> = response["web_results"]
+        .as_array()
+        .expect("web results")
+        .iter()
+        .map(|result| &result["url"])
+        .collect();
+    assert_eq!(
+        urls,
+        [
+            "https://doc.rust-lang.org/book/ch04-02-references-and-borrowing.html",
+            "https://doc.rust-lang.org/book/ch10-03-lifetime-syntax.html",
+        ]
+    );
+    assert_eq!(response["follow_up"]["backend_uuid"], "00000000-0000-4000-8000-000000000001");
+    assert!(response.get("warnings").is_none(), "{}", response);
+
+    // Without a fixture the placeholder answer says which file to create.
+    let response = client.call_tool("perplexity_search", json!({ "query": "Unrecorded?" }));
+    assert_eq!(response["answer"], "Offline answer for: Unrecorded?");
+    assert!(response["warnings"][0].as_str().is_some_and(|w| w.contains("no fixture")));
+}
+
+#[test]
+fn test_invalid_arguments_are_rejected() {
+    let mut client = Client::start();
+    let error = client
+        .request("tools/call", json!({ "name": "perplexity_search", "arguments": {} }))
+        .expect_err("a search without a query must fail");
+    assert_eq!(error["code"], -32602, "{}", error);
+
+    let error = client
+        .request("tools/call", json!({ "name": "perplexity_nonexistent", "arguments": {} }))
+        .expect_err("an unknown tool must fail");
+    assert!(error["message"].as_str().is_some_and(|m| !m.is_empty()), "{}", error);
+}
+
+#[test]
+fn test_soft_deadline_must_precede_hard_deadline() {
+    let mut client = Client::start_with_env(&[("PERPLEXITY_HARD_DEADLINE_SECS", "60")]);
+    let arguments =
+        |secs: u64| json!({ "query": "What is Rust?", "soft_deadline_secs": secs });
+    let error = client
+        .request(
+            "tools/call",
+            json!({ "name": "perplexity_search", "arguments": arguments(60) }),
+        )
+        .expect_err("a soft deadline at the hard deadline must fail");
+    assert_eq!(error["code"], -32602, "{}", error);
+
+    let response = client.call_tool("perplexity_search", arguments(59));
+    assert!(response.get("partial").is_none(), "{}", response);
+}
+
+#[test]
+fn test_about_reports_build() {
+    let mut client = Client::start();
+    let about = client.call_tool("perplexity_about", json!({}));
+    assert_eq!(about["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(about["transport"], "stdio");
+    assert!(about["models"].as_array().is_some_and(|models| !models.is_empty()), "{}", about);
+}
+
+#[test]
+fn test_config_resource() {
+    let mut client = Client::start();
+    let result = client.request("resources/list", json!({})).expect("resources/list");
+    assert_eq!(result["resources"][0]["uri"], "perplexity://config", "{}", result);
+
+    let result = client
+        .request("resources/read", json!({ "uri": "perplexity://config" }))
+        .expect("resources/read");
+    let text = result["contents"][0]["text"].as_str().expect("text contents");
+    let config: Value = serde_json::from_str(text).expect("config is JSON");
+    assert!(
+        config["arguments"].as_array().is_some_and(|args| args.contains(&json!("--offline")))
+    );
+    let settings = config["settings"].as_array().expect("settings");
+    let setting = settings.iter().find(|s| s["name"] == "PERPLEXITY_CONFIG").expect("setting");
+    assert_eq!(setting["source"], "environment");
+
+    let error = client
+        .request("resources/read", json!({ "uri": "perplexity://nothing" }))
+        .expect_err("an unknown resource must fail");
+    assert_eq!(error["code"], -32002, "{}", error);
+}
+
+#[test]
+fn test_indonesian_tool_descriptions() {
+    let descriptions = |locale: &str| -> Vec<(Value, Value)> {
+        let mut client = Client::start_with_env(&[("PERPLEXITY_LOCALE", locale)]);
+        let result = client.request("tools/list", json!({})).expect("tools/list");
+        let tools = result["tools"].as_array().expect("tool array");
+        tools.iter().map(|tool| (tool["name"].clone(), tool["description"].clone())).collect()
+    };
+
+    let english = descriptions("en");
+    let indonesian = descriptions("id");
+    assert_eq!(english.len(), indonesian.len());
+    for ((name, english), (_, indonesian)) in english.iter().zip(&indonesian) {
+        assert!(indonesian.as_str().is_some_and(|d| !d.is_empty()), "{}", name);
+        assert_ne!(english, indonesian, "{} is not translated", name);
+    }
+}
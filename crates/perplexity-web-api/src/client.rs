@@ -1,33 +1,136 @@
+use crate::compat::{API_VERSIONS, ApiVersion};
 use crate::config::{
-    API_BASE_URL, API_VERSION, ENDPOINT_AUTH_SESSION, ENDPOINT_SSE_ASK, model_preference,
+    API_BASE_URL, ENDPOINT_AUTH_SESSION, ENDPOINT_BOOKMARKED_THREADS, ENDPOINT_SSE_ASK,
+    ENDPOINT_THREAD, ENDPOINT_THREAD_ACCESS, ENDPOINT_THREAD_BOOKMARK, ENDPOINT_THREAD_TITLE,
+    MAX_QUERY_LENGTH, THREAD_ACCESS_PRIVATE, THREAD_ACCESS_PUBLIC,
 };
-use crate::error::{Error, Result};
-use crate::sse::SseStream;
+use crate::download::download_file;
+use crate::error::{Error, Result, StreamErrorKind};
+use crate::headers::{HeaderStrategy, Headers};
+use crate::heartbeat::{StreamItem, with_heartbeats};
+use crate::hook::{OutgoingRequest, RequestHook};
+use crate::hosts::{DEFAULT_MAX_REDIRECTS, HostAllowlist};
+use crate::payload::AskPayload;
+use crate::pipeline::Pipeline;
+#[cfg(feature = "image")]
+use crate::preprocess::{ImagePreprocessing, preprocess_image};
+use crate::raw::{RawEventSink, RawSinks};
+use crate::refusal::refusal_reason;
+use crate::rt;
+use crate::sse::{SseStream, StreamLimits};
 use crate::types::SearchMode;
-use crate::types::{AskParams, AskPayload, SearchEvent, SearchRequest, SearchResponse};
-use crate::upload::upload_file;
+use crate::types::{
+    AccountSession, Attachment, ConnectionCheck, Degradation, DegradationKind, SearchEvent,
+    SearchMetrics, SearchRequest, SearchResponse, SessionResponse, ShareLink,
+    ThreadAccessRequest, ThreadAccessResponse, ThreadBookmarkRequest, ThreadInfo,
+    ThreadListResponse, ThreadResponse, ThreadSummary, ThreadTitleRequest, UploadFile,
+    UploadProgress,
+};
+use crate::upload::{UploadRetries, upload_file};
+use bytes::Bytes;
 use futures_util::{Stream, StreamExt};
+use rquest::header::HeaderMap;
 use rquest::{Client as HttpClient, cookie::Jar};
 use rquest_util::Emulation;
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Duration;
-use uuid::Uuid;
+use std::time::{Duration, Instant, SystemTime};
 
 /// Default request timeout (30 seconds).
 const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
 
+/// Default limit on the size of downloaded attachments (50 MiB).
+const DEFAULT_MAX_DOWNLOAD_SIZE: usize = 50 * 1024 * 1024;
+
+/// Default limit on the size of a single event of the answer stream (32 MiB).
+const DEFAULT_MAX_EVENT_SIZE: usize = 32 * 1024 * 1024;
+
+/// Default limit on the total size of one answer stream (512 MiB).
+const DEFAULT_MAX_STREAM_SIZE: usize = 512 * 1024 * 1024;
+
+/// Default limit on the size of a single uploaded file (50 MiB).
+const DEFAULT_MAX_UPLOAD_SIZE: usize = 50 * 1024 * 1024;
+
+/// Default limit on the number of files attached to one query.
+const DEFAULT_MAX_ATTACHMENTS: usize = 10;
+
+/// Default time an idle pooled connection is kept open (5 minutes).
+const DEFAULT_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Default TCP keep-alive interval (60 seconds).
+const DEFAULT_TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+
+/// Default interval of HTTP/2 keep-alive pings (30 seconds).
+const DEFAULT_HTTP2_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Default time to wait for a keep-alive ping to be acknowledged (20
+/// seconds).
+const DEFAULT_HTTP2_KEEPALIVE_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Default number of queries sent on one anonymous session before it is
+/// replaced.
+const DEFAULT_ANONYMOUS_SESSION_QUERIES: usize = 5;
+
 /// Builder for creating a configured [`Client`] instance.
 pub struct ClientBuilder {
     cookies: HashMap<String, String>,
     http_client: Option<HttpClient>,
+    header_strategy: HeaderStrategy,
     timeout: Duration,
+    pool_idle_timeout: Option<Duration>,
+    pool_max_idle_per_host: Option<usize>,
+    tcp_keepalive: Option<Duration>,
+    http2_keepalive: Http2KeepAlive,
+    allowed_hosts: HostAllowlist,
+    max_redirects: usize,
+    max_download_size: usize,
+    stream_limits: StreamLimits,
+    max_upload_size: usize,
+    max_attachments: usize,
+    api_version: ApiVersion,
+    detect_api_version: bool,
+    warm_up: bool,
+    detect_refusals: bool,
+    anonymous: bool,
+    anonymous_session_queries: usize,
+    request_hooks: Vec<RequestHook>,
+    raw_sinks: RawSinks,
+    #[cfg(feature = "image")]
+    image_preprocessing: Option<ImagePreprocessing>,
 }
 
 impl ClientBuilder {
     /// Creates a new builder with default settings.
     pub fn new() -> Self {
-        Self { cookies: HashMap::new(), http_client: None, timeout: DEFAULT_TIMEOUT }
+        Self {
+            cookies: HashMap::new(),
+            http_client: None,
+            header_strategy: HeaderStrategy::default(),
+            timeout: DEFAULT_TIMEOUT,
+            pool_idle_timeout: Some(DEFAULT_POOL_IDLE_TIMEOUT),
+            pool_max_idle_per_host: None,
+            tcp_keepalive: Some(DEFAULT_TCP_KEEPALIVE),
+            http2_keepalive: Http2KeepAlive::default(),
+            allowed_hosts: HostAllowlist::perplexity(),
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            max_download_size: DEFAULT_MAX_DOWNLOAD_SIZE,
+            stream_limits: StreamLimits {
+                max_event_size: DEFAULT_MAX_EVENT_SIZE,
+                max_stream_size: DEFAULT_MAX_STREAM_SIZE,
+            },
+            max_upload_size: DEFAULT_MAX_UPLOAD_SIZE,
+            max_attachments: DEFAULT_MAX_ATTACHMENTS,
+            api_version: ApiVersion::current(),
+            detect_api_version: false,
+            warm_up: true,
+            detect_refusals: false,
+            anonymous: false,
+            anonymous_session_queries: DEFAULT_ANONYMOUS_SESSION_QUERIES,
+            request_hooks: Vec::new(),
+            raw_sinks: RawSinks::default(),
+            #[cfg(feature = "image")]
+            image_preprocessing: None,
+        }
     }
 
     /// Sets authentication cookies for the client.
@@ -46,6 +149,15 @@ impl ClientBuilder {
         self
     }
 
+    /// Sets the user agent and client hints sent to Perplexity.
+    ///
+    /// Default is [`HeaderStrategy::Emulated`], the headers of the built-in
+    /// Chrome emulation.
+    pub fn header_strategy(mut self, strategy: HeaderStrategy) -> Self {
+        self.header_strategy = strategy;
+        self
+    }
+
     /// Sets the request timeout.
     ///
     /// Default is 30 seconds.
@@ -54,40 +166,365 @@ impl ClientBuilder {
         self
     }
 
+    /// Sets how long an idle pooled connection is kept for reuse. `None`
+    /// keeps idle connections until the server closes them.
+    ///
+    /// Default is 5 minutes, so that bursts of queries a few minutes apart
+    /// reuse one connection. Like the other connection settings, this has no
+    /// effect with a custom [`http_client`](Self::http_client).
+    pub fn pool_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.pool_idle_timeout = timeout;
+        self
+    }
+
+    /// Sets the maximum number of idle connections kept per host.
+    ///
+    /// Default is unlimited.
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    /// Sets the TCP keep-alive interval of the client's sockets. `None`
+    /// leaves the system default.
+    ///
+    /// The probes keep proxies and NATs from dropping a connection while a
+    /// slow answer streams with long pauses, and detect dead connections
+    /// before they are reused. Default is 60 seconds.
+    pub fn tcp_keepalive(mut self, interval: Option<Duration>) -> Self {
+        self.tcp_keepalive = interval;
+        self
+    }
+
+    /// Sets the interval of the HTTP/2 ping frames sent on open connections.
+    /// `None` sends none.
+    ///
+    /// Unlike TCP probes, pings travel end to end, so they also keep proxies
+    /// that only watch HTTP traffic from closing a stream that pauses while
+    /// Perplexity thinks. Default is 30 seconds.
+    pub fn http2_keep_alive_interval(mut self, interval: Option<Duration>) -> Self {
+        self.http2_keepalive.interval = interval;
+        self
+    }
+
+    /// Sets how long to wait for a ping to be acknowledged before the
+    /// connection is closed as dead.
+    ///
+    /// Default is 20 seconds.
+    pub fn http2_keep_alive_timeout(mut self, timeout: Duration) -> Self {
+        self.http2_keepalive.timeout = timeout;
+        self
+    }
+
+    /// Sets whether pings are also sent on idle pooled connections, not only
+    /// while a request is in flight, so they stay usable for the next burst
+    /// of queries.
+    ///
+    /// Default is false.
+    pub fn http2_keep_alive_while_idle(mut self, enabled: bool) -> Self {
+        self.http2_keepalive.while_idle = enabled;
+        self
+    }
+
+    /// Sets the hosts the client may contact. Upload and download URLs
+    /// taken from responses, and redirects, leading anywhere else fail with
+    /// [`Error::HostNotAllowed`] before anything is sent.
+    ///
+    /// Default is [`HostAllowlist::perplexity`]. Redirects of a custom
+    /// [`http_client`](Self::http_client) are not checked.
+    pub fn allowed_hosts(mut self, hosts: HostAllowlist) -> Self {
+        self.allowed_hosts = hosts;
+        self
+    }
+
+    /// Sets how many redirects a request follows before failing; `0`
+    /// follows none.
+    ///
+    /// Default is 5. Like the connection settings, this has no effect with
+    /// a custom [`http_client`](Self::http_client).
+    pub fn max_redirects(mut self, max: usize) -> Self {
+        self.max_redirects = max;
+        self
+    }
+
+    /// Sets the maximum size of attachments fetched with
+    /// [`Client::download_attachment`].
+    ///
+    /// Default is 50 MiB.
+    pub fn max_download_size(mut self, bytes: usize) -> Self {
+        self.max_download_size = bytes;
+        self
+    }
+
+    /// Sets the maximum size of a single event of the answer stream. A
+    /// larger event fails the search with [`Error::EventTooLarge`].
+    ///
+    /// Default is 32 MiB.
+    pub fn max_event_size(mut self, bytes: usize) -> Self {
+        self.stream_limits.max_event_size = bytes;
+        self
+    }
+
+    /// Sets the maximum number of bytes read from one answer stream. A
+    /// longer stream fails the search with [`Error::StreamTooLarge`].
+    ///
+    /// Default is 512 MiB.
+    pub fn max_stream_size(mut self, bytes: usize) -> Self {
+        self.stream_limits.max_stream_size = bytes;
+        self
+    }
+
+    /// Sets the maximum size of a single uploaded file.
+    ///
+    /// Default is 50 MiB.
+    pub fn max_upload_size(mut self, bytes: usize) -> Self {
+        self.max_upload_size = bytes;
+        self
+    }
+
+    /// Sets the maximum number of files attached to one query, counting both
+    /// new uploads and previously uploaded attachments.
+    ///
+    /// Default is 10.
+    pub fn max_attachments(mut self, count: usize) -> Self {
+        self.max_attachments = count;
+        self
+    }
+
+    /// Sets the version of the Perplexity web API the client speaks, one of
+    /// [`API_VERSIONS`]: the version sent with requests, and the shape of
+    /// the payloads sent and of the answer events parsed.
+    ///
+    /// Default is [`ApiVersion::current`].
+    pub fn api_version(mut self, version: ApiVersion) -> Self {
+        self.api_version = version;
+        self
+    }
+
+    /// Sets whether [`build`](Self::build) asks Perplexity which API version
+    /// to speak, see [`Client::detect_api_version`].
+    ///
+    /// Disabled by default. Detection needs [`cookies`](Self::cookies) and
+    /// is skipped without them. If Perplexity cannot be reached, the version
+    /// set with [`api_version`](Self::api_version) is kept; if it rejects
+    /// every supported version, `build` fails with
+    /// [`Error::ApiVersionRejected`].
+    pub fn detect_api_version(mut self, enabled: bool) -> Self {
+        self.detect_api_version = enabled;
+        self
+    }
+
+    /// Sets whether [`build`](Self::build) warms up the session.
+    ///
+    /// Enabled by default. When disabled, the first request pays for the
+    /// connection setup unless [`Client::warm_up`] is called.
+    pub fn warm_up(mut self, enabled: bool) -> Self {
+        self.warm_up = enabled;
+        self
+    }
+
+    /// Sets whether searches return [`Error::Refused`] when Perplexity
+    /// declines to answer, instead of the refusal as the answer.
+    ///
+    /// Disabled by default. Refusals are recognized by their wording; see
+    /// [`refusal_reason`].
+    pub fn detect_refusals(mut self, enabled: bool) -> Self {
+        self.detect_refusals = enabled;
+        self
+    }
+
+    /// Queries as an anonymous guest instead of an account.
+    ///
+    /// Cookies set with [`cookies`](Self::cookies) are ignored, and every
+    /// guest session gets an HTTP client with a fresh cookie jar rather than
+    /// the one set with [`http_client`](Self::http_client). Guest
+    /// sessions only search in [`SearchMode::Auto`] and allow a few free
+    /// queries each, so the client starts a fresh session after every
+    /// [`anonymous_session_queries`](Self::anonymous_session_queries)
+    /// queries, and early when Perplexity reports the allowance exhausted.
+    /// Disabled by default.
+    pub fn anonymous(mut self, enabled: bool) -> Self {
+        self.anonymous = enabled;
+        self
+    }
+
+    /// Sets how many queries are sent on one anonymous session before it is
+    /// replaced (default 5).
+    pub fn anonymous_session_queries(mut self, count: usize) -> Self {
+        self.anonymous_session_queries = count.max(1);
+        self
+    }
+
+    /// Registers a hook that may change the headers and JSON payload of
+    /// every query just before it is sent, e.g. to add a flag Perplexity
+    /// gates a feature behind. Hooks run in the order they were registered.
+    ///
+    /// ```no_run
+    /// # async fn example() -> perplexity_web_api::Result<()> {
+    /// let client = perplexity_web_api::Client::builder()
+    ///     .on_request(|request| request.set_param("use_schematized_api", true))
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn on_request(
+        mut self,
+        hook: impl Fn(&mut OutgoingRequest) + Send + Sync + 'static,
+    ) -> Self {
+        self.request_hooks.push(RequestHook::new(hook));
+        self
+    }
+
+    /// Registers a sink that receives every event of every answer stream as
+    /// Perplexity sent it, before it is parsed, e.g. to archive streams or
+    /// to spot fields the parser does not model. Sinks are called in the
+    /// order they were registered.
+    ///
+    /// ```no_run
+    /// # async fn example() -> perplexity_web_api::Result<()> {
+    /// let client = perplexity_web_api::Client::builder()
+    ///     .on_raw_event(|event: perplexity_web_api::RawEvent<'_>| {
+    ///         eprintln!("{}: {}", event.event, event.data);
+    ///     })
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn on_raw_event(mut self, sink: impl RawEventSink + 'static) -> Self {
+        self.raw_sinks.push(Arc::new(sink));
+        self
+    }
+
+    /// Resizes and re-encodes image attachments before they are uploaded,
+    /// stripping their metadata.
+    ///
+    /// Disabled by default.
+    #[cfg(feature = "image")]
+    pub fn image_preprocessing(mut self, options: ImagePreprocessing) -> Self {
+        self.image_preprocessing = Some(options);
+        self
+    }
+
     /// Builds the client and performs initial session warm-up.
     ///
     /// This mirrors the Python client's behavior of making an initial
-    /// GET request to `/api/auth/session` to establish a session.
-    pub async fn build(self) -> Result<Client> {
+    /// GET request to `/api/auth/session` to establish a session. See
+    /// [`Client::warm_up`].
+    pub async fn build(mut self) -> Result<Client> {
+        rt::enter()?;
         let timeout = self.timeout;
+        let headers = Headers::new(&self.header_strategy)?;
+        let settings = ConnectionSettings {
+            pool_idle_timeout: self.pool_idle_timeout,
+            pool_max_idle_per_host: self.pool_max_idle_per_host,
+            tcp_keepalive: self.tcp_keepalive,
+            http2_keepalive: self.http2_keepalive,
+            allowed_hosts: self.allowed_hosts.clone(),
+            max_redirects: self.max_redirects,
+        };
+        if self.anonymous {
+            self.cookies.clear();
+        }
         let http = match self.http_client {
             Some(client) => client,
-            None => {
-                let jar = Arc::new(Jar::default());
-                let url = API_BASE_URL.parse().expect("Invalid API base URL");
-
-                for (name, value) in &self.cookies {
-                    let cookie =
-                        format!("{}={}; Domain=www.perplexity.ai; Path=/", name, value);
-                    jar.add_cookie_str(&cookie, &url);
-                }
+            None => settings.http_client(&self.cookies)?,
+        };
 
-                HttpClient::builder()
-                    .emulation(Emulation::Chrome131)
-                    .cookie_provider(jar)
-                    .build()
-                    .map_err(Error::Http)?
-            }
+        let mut client = Client {
+            http,
+            headers,
+            has_cookies: !self.cookies.is_empty(),
+            allowed_hosts: self.allowed_hosts,
+            timeout,
+            max_download_size: self.max_download_size,
+            stream_limits: self.stream_limits,
+            max_upload_size: self.max_upload_size,
+            max_attachments: self.max_attachments,
+            api_version: self.api_version,
+            detect_refusals: self.detect_refusals,
+            request_hooks: self.request_hooks,
+            raw_sinks: self.raw_sinks,
+            guest: self.anonymous.then(|| GuestSessions {
+                settings,
+                limit: self.anonymous_session_queries,
+                current: rt::Mutex::new(None),
+            }),
+            #[cfg(feature = "image")]
+            image_preprocessing: self.image_preprocessing,
         };
+        if self.detect_api_version && client.has_cookies {
+            match client.detect_api_version().await {
+                Ok(version) => client.api_version = version,
+                Err(e @ Error::ApiVersionRejected { .. }) => return Err(e),
+                // Perplexity could not be asked; the configured version stays.
+                Err(_) => {}
+            }
+        }
+        if self.warm_up {
+            client.warm_up().await?;
+        }
+        Ok(client)
+    }
+}
 
-        let session_fut =
-            http.get(format!("{}{}", API_BASE_URL, ENDPOINT_AUTH_SESSION)).send();
-        tokio::time::timeout(timeout, session_fut)
-            .await
-            .map_err(|_| Error::Timeout(timeout))?
-            .map_err(Error::Http)?;
+/// Connection settings shared by the HTTP clients a [`Client`] creates.
+#[derive(Clone)]
+struct ConnectionSettings {
+    pool_idle_timeout: Option<Duration>,
+    pool_max_idle_per_host: Option<usize>,
+    tcp_keepalive: Option<Duration>,
+    http2_keepalive: Http2KeepAlive,
+    allowed_hosts: HostAllowlist,
+    max_redirects: usize,
+}
+
+/// HTTP/2 keep-alive ping settings.
+#[derive(Debug, Clone, Copy)]
+struct Http2KeepAlive {
+    interval: Option<Duration>,
+    timeout: Duration,
+    while_idle: bool,
+}
 
-        Ok(Client { http, has_cookies: !self.cookies.is_empty(), timeout })
+impl Default for Http2KeepAlive {
+    fn default() -> Self {
+        Self {
+            interval: Some(DEFAULT_HTTP2_KEEPALIVE_INTERVAL),
+            timeout: DEFAULT_HTTP2_KEEPALIVE_TIMEOUT,
+            while_idle: false,
+        }
+    }
+}
+
+impl ConnectionSettings {
+    /// Creates an HTTP client with its own cookie jar, holding `cookies`.
+    fn http_client(&self, cookies: &HashMap<String, String>) -> Result<HttpClient> {
+        let jar = Arc::new(Jar::default());
+        let url = API_BASE_URL.parse().expect("Invalid API base URL");
+
+        for (name, value) in cookies {
+            let cookie = format!("{}={}; Domain=www.perplexity.ai; Path=/", name, value);
+            jar.add_cookie_str(&cookie, &url);
+        }
+
+        let mut builder = HttpClient::builder()
+            .emulation(Emulation::Chrome131)
+            .cookie_provider(jar)
+            .redirect(self.allowed_hosts.redirect_policy(self.max_redirects))
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .tcp_keepalive(self.tcp_keepalive)
+            .http2(|mut h2| {
+                let keepalive = self.http2_keepalive;
+                h2.keep_alive_interval(keepalive.interval)
+                    .keep_alive_timeout(keepalive.timeout)
+                    .keep_alive_while_idle(keepalive.while_idle);
+            });
+        if let Some(max) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        builder.build().map_err(Error::Http)
     }
 }
 
@@ -119,8 +556,37 @@ impl Default for ClientBuilder {
 /// ```
 pub struct Client {
     http: HttpClient,
+    headers: Headers,
     has_cookies: bool,
+    allowed_hosts: HostAllowlist,
     timeout: Duration,
+    max_download_size: usize,
+    stream_limits: StreamLimits,
+    max_upload_size: usize,
+    max_attachments: usize,
+    api_version: ApiVersion,
+    detect_refusals: bool,
+    request_hooks: Vec<RequestHook>,
+    raw_sinks: RawSinks,
+    guest: Option<GuestSessions>,
+    #[cfg(feature = "image")]
+    image_preprocessing: Option<ImagePreprocessing>,
+}
+
+/// Rotation state of anonymous guest sessions.
+struct GuestSessions {
+    settings: ConnectionSettings,
+    /// Queries sent on one session before it is replaced.
+    limit: usize,
+    /// The session in use, or `None` before the first query or once retired.
+    current: rt::Mutex<Option<GuestSession>>,
+}
+
+/// An anonymous guest session: an HTTP client with its own cookie jar.
+struct GuestSession {
+    http: HttpClient,
+    /// Queries sent on this session so far.
+    used: usize,
 }
 
 impl Client {
@@ -129,29 +595,217 @@ impl Client {
         ClientBuilder::new()
     }
 
+    /// The version of the Perplexity web API the client speaks.
+    pub fn api_version(&self) -> ApiVersion {
+        self.api_version
+    }
+
+    /// Finds the newest of [`API_VERSIONS`] Perplexity accepts, by listing
+    /// bookmarked threads with each in turn, newest first. Fails with
+    /// [`Error::ApiVersionRejected`] if it accepts none of them.
+    ///
+    /// Requires authentication cookies, like the thread endpoints.
+    pub async fn detect_api_version(&self) -> Result<ApiVersion> {
+        for &version in API_VERSIONS {
+            let request = self
+                .http
+                .get(format!("{}{}", API_BASE_URL, ENDPOINT_BOOKMARKED_THREADS))
+                .query(&[("limit", 1), ("offset", 0)]);
+            match self.send_thread_request_as::<ThreadListResponse>(request, version).await {
+                Ok(_) => return Ok(version),
+                Err(Error::Server { status: 400 | 404 | 410 | 426, .. }) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Err(Error::ApiVersionRejected {
+            tried: API_VERSIONS.iter().map(ApiVersion::as_str).collect(),
+        })
+    }
+
+    /// Fetches the auth session, priming the session cookies, the DNS
+    /// lookup and a pooled keep-alive connection so that the next request
+    /// skips connection setup.
+    ///
+    /// [`ClientBuilder::build`] calls this unless disabled with
+    /// [`ClientBuilder::warm_up`]. Calling it again later is harmless. In
+    /// anonymous mode it establishes a guest session.
+    pub async fn warm_up(&self) -> Result<()> {
+        match &self.guest {
+            Some(guest) => self.guest_http(guest, 0).await.map(drop),
+            None => self.fetch_session(&self.http).await,
+        }
+    }
+
+    /// Returns the account the session cookies sign in to, or `None` if
+    /// Perplexity does not accept them.
+    ///
+    /// Use this to check the tokens before relying on them. In anonymous
+    /// mode there is no account, and this returns `None`.
+    pub async fn session(&self) -> Result<Option<AccountSession>> {
+        if !self.has_cookies {
+            return Ok(None);
+        }
+        let request_fut = self
+            .headers
+            .apply(self.http.get(format!("{}{}", API_BASE_URL, ENDPOINT_AUTH_SESSION)))
+            .send();
+        let response: SessionResponse = rt::timeout(self.timeout, request_fut)
+            .await?
+            .map_err(Error::Http)?
+            .error_for_status()
+            .map_err(|e| Error::Server {
+                status: e.status().map(|s| s.as_u16()).unwrap_or(0),
+                message: e.to_string(),
+            })?
+            .json()
+            .await
+            .map_err(Error::Http)?;
+
+        Ok(response.into_session())
+    }
+
+    /// Sends one request for the auth session to Perplexity and reports how
+    /// it answered, whatever the status.
+    ///
+    /// The request goes through the client's own HTTP client, so it carries
+    /// the session cookies when the client has any. Use this to tell network,
+    /// proxy and TLS problems apart from problems with the account: only
+    /// failing to get any answer is an error.
+    pub async fn check_connection(&self) -> Result<ConnectionCheck> {
+        let started = std::time::Instant::now();
+        let check_fut = async {
+            let response = self
+                .headers
+                .apply(self.http.get(format!("{}{}", API_BASE_URL, ENDPOINT_AUTH_SESSION)))
+                .send()
+                .await?;
+            let status = response.status().as_u16();
+            let date = response
+                .headers()
+                .get(rquest::header::DATE)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+            response.bytes().await?;
+            Ok::<_, rquest::Error>((status, date))
+        };
+        let (status, date) =
+            rt::timeout(self.timeout, check_fut).await?.map_err(Error::Http)?;
+
+        Ok(ConnectionCheck { status, round_trip: started.elapsed(), date })
+    }
+
+    /// Fetches the auth session with `http`.
+    async fn fetch_session(&self, http: &HttpClient) -> Result<()> {
+        let session_fut = async {
+            let response = self
+                .headers
+                .apply(http.get(format!("{}{}", API_BASE_URL, ENDPOINT_AUTH_SESSION)))
+                .send()
+                .await?;
+            // Reading the body to the end returns the connection to the pool.
+            response.bytes().await
+        };
+        rt::timeout(self.timeout, session_fut).await?.map_err(Error::Http)?;
+        Ok(())
+    }
+
     /// Performs a search query and returns the final response.
     ///
     /// This method consumes the entire SSE stream and returns the final result.
     /// For streaming responses, use [`search_stream`](Self::search_stream) instead.
     pub async fn search(&self, request: SearchRequest) -> Result<SearchResponse> {
-        let mut stream = Box::pin(self.search_stream(request).await?);
+        self.search_with_progress(request, |_| {}).await
+    }
+
+    /// Asks a question about `context`, such as a document or a long log.
+    ///
+    /// Short context is sent inline; longer context is uploaded as a file and
+    /// referenced from the query. See [`SearchRequest::context`].
+    pub async fn ask_with_context(
+        &self,
+        request: SearchRequest,
+        context: &str,
+    ) -> Result<SearchResponse> {
+        self.search(request.context(context)).await
+    }
+
+    /// Performs a search query and runs the response through `pipeline`.
+    pub async fn ask_with_pipeline(
+        &self,
+        request: SearchRequest,
+        pipeline: &Pipeline,
+    ) -> Result<SearchResponse> {
+        let mut response = self.search(request).await?;
+        pipeline.run(&mut response);
+        Ok(response)
+    }
+
+    /// Performs a search query, calling `on_event` for every event received,
+    /// and returns the final response.
+    ///
+    /// Useful to observe intermediate answers while still getting the
+    /// [`SearchMetrics`] collected by [`search`](Self::search).
+    pub async fn search_with_progress(
+        &self,
+        request: SearchRequest,
+        mut on_event: impl FnMut(&SearchEvent),
+    ) -> Result<SearchResponse> {
+        let started = Instant::now();
+        let mut metrics = SearchMetrics {
+            mode: request.mode,
+            model: request.model,
+            started_at: Some(SystemTime::now()),
+            timezone: request.timezone.clone(),
+            ..Default::default()
+        };
+
+        let (stream, setup) = self.open_stream(request).await?;
+        metrics.model_preference = setup.model_preference;
+        metrics.upload = setup.upload;
+        metrics.connect = started.elapsed();
+        metrics.server_request_id = setup.server_request_id;
+        let (uploads, warnings, degradations) =
+            (setup.uploads, setup.warnings, setup.degradations);
+
+        let mut stream = std::pin::pin!(stream);
         let mut last_event: Option<SearchEvent> = None;
 
         while let Some(result) = stream.next().await {
-            match result {
-                Ok(event) => last_event = Some(event),
-                Err(e) => return Err(e),
+            let event = match result {
+                Ok(event) => event,
+                Err(e) => {
+                    if let Error::StreamEvent { kind: StreamErrorKind::RateLimited, .. } = e {
+                        self.retire_guest_session().await;
+                    }
+                    return Err(e);
+                }
+            };
+
+            metrics.event_count += 1;
+            metrics.first_event.get_or_insert_with(|| started.elapsed());
+            if event.answer.as_deref().is_some_and(|a| !a.is_empty()) {
+                metrics.first_answer.get_or_insert_with(|| started.elapsed());
             }
+
+            on_event(&event);
+            last_event = Some(event);
         }
 
+        metrics.total = started.elapsed();
+        metrics.finished_at = Some(SystemTime::now());
         let event = last_event.ok_or(Error::UnexpectedEndOfStream)?;
 
-        Ok(SearchResponse {
-            answer: event.answer.clone(),
-            web_results: event.web_results.clone(),
-            follow_up: event.as_follow_up(),
-            raw: serde_json::to_value(&event).map_err(Error::Json)?,
-        })
+        let mut response = SearchResponse::from(event);
+        if self.detect_refusals
+            && let Some(reason) = refusal_reason(&response)
+        {
+            return Err(Error::Refused { reason });
+        }
+        response.uploads = uploads;
+        response.warnings = warnings;
+        response.degradations = degradations;
+        response.metrics = metrics;
+        Ok(response)
     }
 
     /// Performs a search query and returns a stream of events.
@@ -162,77 +816,450 @@ impl Client {
         &self,
         request: SearchRequest,
     ) -> Result<impl Stream<Item = Result<SearchEvent>>> {
+        Ok(self.open_stream(request).await?.0)
+    }
+
+    /// Performs a search query and returns a stream of events, with a
+    /// [`StreamItem::Heartbeat`] after every `interval` in which no event
+    /// arrived.
+    ///
+    /// Heartbeats are generated locally and say nothing about the
+    /// connection beyond its silence; the stream itself never times out.
+    pub async fn search_stream_with_heartbeats(
+        &self,
+        request: SearchRequest,
+        interval: Duration,
+    ) -> Result<impl Stream<Item = Result<StreamItem>>> {
+        Ok(with_heartbeats(self.open_stream(request).await?.0, interval))
+    }
+
+    /// Uploads attachments, sends the ask request, and returns the event
+    /// stream together with details of how it was set up.
+    async fn open_stream(
+        &self,
+        mut request: SearchRequest,
+    ) -> Result<(impl Stream<Item = Result<SearchEvent>> + use<>, StreamSetup)> {
+        let warnings = request.fit_query(MAX_QUERY_LENGTH)?.into_iter().collect();
         self.validate_request(&request)?;
 
-        let mut attachments = Vec::new();
+        let upload_started = Instant::now();
+        let files = self.prepare_files(&request.files).await?;
+        let total_bytes: usize = files.iter().map(UploadFile::len).sum();
+        let mut uploaded_bytes = 0;
+        let mut uploads = Vec::with_capacity(files.len());
+        let mut upload_retries = UploadRetries::default();
 
-        for file in &request.files {
-            let url = upload_file(&self.http, file, self.timeout).await?;
-            attachments.push(url);
-        }
+        for (index, file) in files.iter().enumerate() {
+            let progress = |file_uploaded: usize, attachment: Option<&Attachment>| {
+                if let Some(callback) = &request.on_upload_progress {
+                    callback.call(&UploadProgress {
+                        index,
+                        count: files.len(),
+                        filename: file.filename().to_string(),
+                        file_uploaded,
+                        file_size: file.len(),
+                        uploaded_bytes: uploaded_bytes + file_uploaded,
+                        total_bytes,
+                        attachment: attachment.cloned(),
+                    });
+                }
+            };
+
+            let (attachment, retries) = upload_file(
+                &self.http,
+                &self.headers,
+                &self.allowed_hosts,
+                self.api_version,
+                file,
+                self.timeout,
+                &mut |sent| progress(sent, None),
+            )
+            .await?;
+            progress(attachment.size, Some(&attachment));
+            upload_retries.merge(retries);
 
-        if let Some(ref follow_up) = request.follow_up {
-            attachments.extend(follow_up.attachments.clone());
+            uploaded_bytes += attachment.size;
+            uploads.push(attachment);
         }
+        let upload = upload_started.elapsed();
+
+        let payload = AskPayload::builder(&request)
+            .uploads(&uploads)
+            .api_version(self.api_version)
+            .build()?;
+        let model_preference = payload.params.model_preference;
 
-        let mode_str: &'static str = match request.mode {
-            SearchMode::Auto => "concise",
-            _ => "copilot",
+        let mut outgoing = OutgoingRequest {
+            headers: HeaderMap::new(),
+            payload: serde_json::to_value(&payload)?,
         };
+        if let Some(id) = request.request_id.as_deref().and_then(|id| id.parse().ok()) {
+            outgoing.headers.insert("x-request-id", id);
+        }
+        for hook in &self.request_hooks {
+            hook.call(&mut outgoing);
+        }
 
-        let model_pref = model_preference(request.mode, request.model).ok_or_else(|| {
-            Error::InvalidModelForMode {
-                model: request.model.map(|m| m.as_str()).unwrap_or("default").to_string(),
-                mode: request.mode.to_string(),
+        // An anonymous session whose allowance ran out is answered with 429;
+        // one retry on a fresh session covers an allowance smaller than the
+        // configured rotation.
+        let mut retried = None;
+        let response = loop {
+            let http = match &self.guest {
+                Some(guest) => self.guest_http(guest, 1).await?,
+                None => self.http.clone(),
+            };
+            match self.send_ask(&http, &outgoing).await {
+                Err(e @ Error::Server { status: 429, .. })
+                    if self.guest.is_some() && retried.is_none() =>
+                {
+                    self.retire_guest_session().await;
+                    retried = Some(e);
+                }
+                result => break result?,
             }
-        })?;
-
-        let sources_str: Vec<&'static str> =
-            request.sources.iter().map(|s| s.as_str()).collect();
-
-        let payload = AskPayload {
-            query_str: &request.query,
-            params: AskParams {
-                attachments,
-                frontend_context_uuid: Uuid::new_v4().to_string(),
-                frontend_uuid: Uuid::new_v4().to_string(),
-                is_incognito: request.incognito,
-                language: &request.language,
-                last_backend_uuid: request.follow_up.and_then(|f| f.backend_uuid),
-                mode: mode_str,
-                model_preference: model_pref,
-                source: "default",
-                sources: sources_str,
-                version: API_VERSION,
-            },
         };
 
+        let server_request_id = ["x-request-id", "cf-ray"].iter().find_map(|name| {
+            response.headers().get(*name).and_then(|v| v.to_str().ok()).map(String::from)
+        });
+        let mut degradations = Vec::new();
+        if let Some(reason) = upload_retries.last_reason {
+            degradations.push(Degradation {
+                kind: DegradationKind::UploadRetry,
+                reason,
+                count: upload_retries.count,
+            });
+        }
+        if let Some(error) = retried {
+            degradations.push(Degradation {
+                kind: DegradationKind::SessionRetry,
+                reason: error.to_string(),
+                count: 1,
+            });
+        }
+        let setup = StreamSetup {
+            model_preference,
+            server_request_id,
+            upload,
+            uploads,
+            warnings,
+            degradations,
+        };
+        let events =
+            SseStream::new(response.bytes_stream(), self.stream_limits, self.api_version)
+                .tee(self.raw_sinks.clone());
+        Ok((events, setup))
+    }
+
+    /// Sends the ask request and checks its status.
+    async fn send_ask(
+        &self,
+        http: &HttpClient,
+        outgoing: &OutgoingRequest,
+    ) -> Result<rquest::Response> {
         let request_fut = self
+            .headers
+            .apply(http.post(format!("{}{}", API_BASE_URL, ENDPOINT_SSE_ASK)))
+            .headers(outgoing.headers.clone())
+            .json(&outgoing.payload)
+            .send();
+
+        rt::timeout(self.timeout, request_fut)
+            .await?
+            .map_err(Error::Http)?
+            .error_for_status()
+            .map_err(|e| Error::Server {
+                status: e.status().map(|s| s.as_u16()).unwrap_or(0),
+                message: e.to_string(),
+            })
+    }
+
+    /// Counts `queries` against the current guest session and returns its
+    /// HTTP client, first starting a fresh session if there is none or its
+    /// allowance is used up.
+    async fn guest_http(&self, guest: &GuestSessions, queries: usize) -> Result<HttpClient> {
+        let mut current = guest.current.lock().await;
+        let session = match current.take() {
+            Some(session) if session.used < guest.limit => session,
+            _ => {
+                let http = guest.settings.http_client(&HashMap::new())?;
+                self.fetch_session(&http).await?;
+                GuestSession { http, used: 0 }
+            }
+        };
+        let http = session.http.clone();
+        *current = Some(GuestSession { used: session.used + queries, ..session });
+        Ok(http)
+    }
+
+    /// In anonymous mode, makes the next query start a fresh guest session.
+    async fn retire_guest_session(&self) {
+        if let Some(guest) = &self.guest {
+            *guest.current.lock().await = None;
+        }
+    }
+
+    /// Downloads an asset referenced by a response, such as an attachment or
+    /// generated image, using the client's session cookies.
+    ///
+    /// Fails with [`Error::DownloadTooLarge`] if the body exceeds the limit set
+    /// by [`ClientBuilder::max_download_size`].
+    pub async fn download_attachment(&self, url: &str) -> Result<Bytes> {
+        self.allowed_hosts.check(url)?;
+        let (body, _) =
+            download_file(&self.http, url, self.timeout, self.max_download_size, None).await?;
+        Ok(body)
+    }
+
+    /// Like [`download_attachment`](Self::download_attachment), but also
+    /// requires the content type to match `accept` (e.g. `"image/*"` or
+    /// `"application/pdf"`) and returns it alongside the body.
+    ///
+    /// Useful to notice a login page served in place of the asset when the
+    /// session is not accepted.
+    pub async fn download_attachment_as(
+        &self,
+        url: &str,
+        accept: &str,
+    ) -> Result<(Bytes, String)> {
+        self.allowed_hosts.check(url)?;
+        let (body, content_type) =
+            download_file(&self.http, url, self.timeout, self.max_download_size, Some(accept))
+                .await?;
+        Ok((body, content_type.unwrap_or_else(|| "application/octet-stream".to_string())))
+    }
+
+    /// Makes a thread viewable by anyone with its link and returns the link.
+    ///
+    /// `backend_uuid` is the thread id from [`FollowUpContext::backend_uuid`](crate::FollowUpContext::backend_uuid).
+    pub async fn share_thread(&self, backend_uuid: &str) -> Result<ShareLink> {
+        let response = self.set_thread_access(backend_uuid, THREAD_ACCESS_PUBLIC).await?;
+        let slug = response.thread_url_slug.unwrap_or_else(|| backend_uuid.to_string());
+
+        Ok(ShareLink {
+            backend_uuid: backend_uuid.to_string(),
+            url: format!("{}/search/{}", API_BASE_URL, slug),
+        })
+    }
+
+    /// Revokes a thread's public link, making it private again.
+    pub async fn unshare_thread(&self, backend_uuid: &str) -> Result<()> {
+        self.set_thread_access(backend_uuid, THREAD_ACCESS_PRIVATE).await?;
+        Ok(())
+    }
+
+    async fn set_thread_access(
+        &self,
+        backend_uuid: &str,
+        access: u8,
+    ) -> Result<ThreadAccessResponse> {
+        let request = self
+            .http
+            .post(format!("{}{}", API_BASE_URL, ENDPOINT_THREAD_ACCESS))
+            .json(&ThreadAccessRequest { backend_uuid, updated_access: access });
+
+        self.send_thread_request(request).await
+    }
+
+    /// Fetches a thread's title, mode, timestamps and number of turns.
+    pub async fn thread_info(&self, backend_uuid: &str) -> Result<ThreadInfo> {
+        let request =
+            self.http.get(format!("{}{}/{}", API_BASE_URL, ENDPOINT_THREAD, backend_uuid));
+        let response: ThreadResponse = self.send_thread_request(request).await?;
+
+        Ok(ThreadInfo::from_entries(backend_uuid, &response.entries))
+    }
+
+    /// Renames a thread in the Perplexity library.
+    pub async fn rename_thread(&self, backend_uuid: &str, title: &str) -> Result<()> {
+        let request = self
+            .http
+            .post(format!("{}{}", API_BASE_URL, ENDPOINT_THREAD_TITLE))
+            .json(&ThreadTitleRequest { backend_uuid, title });
+
+        let _: serde_json::Value = self.send_thread_request(request).await?;
+        Ok(())
+    }
+
+    /// Adds a thread to, or removes it from, the account's bookmarks.
+    pub async fn bookmark_thread(&self, backend_uuid: &str, bookmarked: bool) -> Result<()> {
+        let request = self
             .http
-            .post(format!("{}{}", API_BASE_URL, ENDPOINT_SSE_ASK))
-            .json(&payload)
+            .post(format!("{}{}", API_BASE_URL, ENDPOINT_THREAD_BOOKMARK))
+            .json(&ThreadBookmarkRequest { backend_uuid, bookmarked });
+
+        let _: serde_json::Value = self.send_thread_request(request).await?;
+        Ok(())
+    }
+
+    /// Lists bookmarked threads, most recently updated first.
+    ///
+    /// Returns at most `limit` threads, skipping the first `offset`.
+    pub async fn bookmarked_threads(
+        &self,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<ThreadSummary>> {
+        let request = self
+            .http
+            .get(format!("{}{}", API_BASE_URL, ENDPOINT_BOOKMARKED_THREADS))
+            .query(&[("limit", limit), ("offset", offset)]);
+        let response: ThreadListResponse = self.send_thread_request(request).await?;
+
+        Ok(response.threads)
+    }
+
+    /// Sends an authenticated request to one of the thread endpoints.
+    async fn send_thread_request<T: serde::de::DeserializeOwned>(
+        &self,
+        request: rquest::RequestBuilder,
+    ) -> Result<T> {
+        self.send_thread_request_as(request, self.api_version).await
+    }
+
+    /// Like [`send_thread_request`](Self::send_thread_request), sending
+    /// `version` as the API version.
+    async fn send_thread_request_as<T: serde::de::DeserializeOwned>(
+        &self,
+        request: rquest::RequestBuilder,
+        version: ApiVersion,
+    ) -> Result<T> {
+        if !self.has_cookies {
+            return Err(Error::ThreadsRequireAuth);
+        }
+
+        let request_fut = self
+            .headers
+            .apply(request)
+            .query(&[("version", version.as_str()), ("source", "default")])
             .send();
 
-        let response = tokio::time::timeout(self.timeout, request_fut)
-            .await
-            .map_err(|_| Error::Timeout(self.timeout))?
+        rt::timeout(self.timeout, request_fut)
+            .await?
             .map_err(Error::Http)?
             .error_for_status()
             .map_err(|e| Error::Server {
                 status: e.status().map(|s| s.as_u16()).unwrap_or(0),
                 message: e.to_string(),
-            })?;
+            })?
+            .json()
+            .await
+            .map_err(Error::Http)
+    }
+
+    /// Uploads a file so it can be attached to later queries with
+    /// [`SearchRequest::attachment`].
+    pub async fn upload(&self, file: &UploadFile) -> Result<Attachment> {
+        self.upload_with_progress(file, |_| {}).await
+    }
+
+    /// Like [`upload`](Self::upload), calling `on_progress` with the number of
+    /// bytes sent so far.
+    ///
+    /// Large images are sent in parts, each retried on its own, so progress is
+    /// reported per part. Other files, such as PDFs and videos, go to storage
+    /// that takes them in one request only: they are sent whole, retried from
+    /// the start, and report progress once they are complete.
+    pub async fn upload_with_progress(
+        &self,
+        file: &UploadFile,
+        mut on_progress: impl FnMut(usize) + Send,
+    ) -> Result<Attachment> {
+        if !self.has_cookies {
+            return Err(Error::FileUploadRequiresAuth);
+        }
+        let file = self.prepare_file(file).await?;
+
+        upload_file(
+            &self.http,
+            &self.headers,
+            &self.allowed_hosts,
+            self.api_version,
+            &file,
+            self.timeout,
+            &mut on_progress,
+        )
+        .await
+        .map(|(attachment, _)| attachment)
+    }
+
+    /// Runs [`prepare_file`](Self::prepare_file) on every file before any
+    /// of them is uploaded.
+    async fn prepare_files(&self, files: &[UploadFile]) -> Result<Vec<UploadFile>> {
+        let mut prepared = Vec::with_capacity(files.len());
+        for file in files {
+            prepared.push(self.prepare_file(file).await?);
+        }
+        Ok(prepared)
+    }
+
+    /// Checks the type of `file`, applies image preprocessing, if
+    /// configured, and checks the result against the upload size limit.
+    async fn prepare_file(&self, file: &UploadFile) -> Result<UploadFile> {
+        crate::filetype::content_type(file)?;
+        #[cfg(feature = "image")]
+        let file = match &self.image_preprocessing {
+            Some(options) => {
+                let (original, options) = (file.clone(), options.clone());
+                rt::spawn_blocking(move || {
+                    Ok::<_, Error>(preprocess_image(&original, &options)?.unwrap_or(original))
+                })
+                .await
+                .map_err(|e| Error::ImageProcessing(e.to_string()))??
+            }
+            None => file.clone(),
+        };
+        #[cfg(not(feature = "image"))]
+        let file = file.clone();
 
-        Ok(SseStream::new(response.bytes_stream()))
+        self.check_upload_size(&file)?;
+        Ok(file)
+    }
+
+    fn check_upload_size(&self, file: &UploadFile) -> Result<()> {
+        if file.len() > self.max_upload_size {
+            return Err(Error::AttachmentTooLarge {
+                filename: file.filename().to_string(),
+                size: file.len(),
+                limit: self.max_upload_size,
+            });
+        }
+        Ok(())
     }
 
     fn validate_request(&self, request: &SearchRequest) -> Result<()> {
         // Mode and sources are now validated at compile time via enums.
-        // Only runtime validation needed is for file uploads requiring auth.
+        // Runtime validation covers file uploads: they need auth and must fit
+        // the attachment count limit. Sizes are checked after preprocessing,
+        // still before anything is sent.
         if !request.files.is_empty() && !self.has_cookies {
             return Err(Error::FileUploadRequiresAuth);
         }
 
+        let count = request.files.len() + request.attachments.len();
+        if count > self.max_attachments {
+            return Err(Error::TooManyAttachments { count, limit: self.max_attachments });
+        }
+
+        if request.generate_image && !self.has_cookies {
+            return Err(Error::ImageGenerationRequiresAuth);
+        }
+
+        if self.guest.is_some() && request.mode != SearchMode::Auto {
+            return Err(Error::ModeRequiresAuth(request.mode));
+        }
+
         Ok(())
     }
 }
+
+/// Details about how a search stream was opened.
+struct StreamSetup {
+    model_preference: &'static str,
+    server_request_id: Option<String>,
+    upload: Duration,
+    uploads: Vec<Attachment>,
+    warnings: Vec<String>,
+    degradations: Vec<Degradation>,
+}
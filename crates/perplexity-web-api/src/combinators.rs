@@ -0,0 +1,280 @@
+//! Combinators for answer event streams.
+//!
+//! Perplexity resends the whole answer with every event, so most consumers
+//! of [`Client::search_stream`](crate::Client::search_stream) end up writing
+//! the same loops: skip events without an answer, keep only the last one,
+//! redraw at most a few times a second, or work out which text is new.
+//! [`SearchStreamExt`] provides those as adapters on any stream of
+//! [`SearchEvent`]s.
+
+use crate::error::{Error, Result};
+use crate::rt::{Instant, Sleep};
+use crate::types::SearchEvent;
+use futures_util::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// An event together with the answer text it added.
+#[derive(Debug, Clone)]
+pub struct AnswerDelta {
+    /// The event as received.
+    pub event: SearchEvent,
+    /// Answer text added since the previous event. Empty for events
+    /// without an answer or with an unchanged one.
+    pub delta: String,
+    /// Whether the event revised earlier answer text instead of appending
+    /// to it, in which case `delta` is the whole answer.
+    pub revised: bool,
+}
+
+/// Adapters for streams of [`SearchEvent`]s.
+pub trait SearchStreamExt: Stream<Item = Result<SearchEvent>> + Sized {
+    /// Yields the answer text of every event that changed it.
+    fn answers_only(self) -> AnswersOnly<Self> {
+        AnswersOnly { inner: self, last: None }
+    }
+
+    /// Yields only the last event, once the stream ends. Fails with
+    /// [`Error::UnexpectedEndOfStream`] if the stream ends without events.
+    fn final_only(self) -> FinalOnly<Self> {
+        FinalOnly { inner: self, last: None, done: false }
+    }
+
+    /// Yields at most one event per `interval`: the latest one received,
+    /// dropping those it replaces. The last event is always yielded.
+    fn throttle(self, interval: Duration) -> Throttle<Self> {
+        Throttle {
+            inner: self,
+            interval,
+            sleep: crate::rt::sleep(Duration::ZERO),
+            last_sent: None,
+            pending: None,
+            done: false,
+        }
+    }
+
+    /// Pairs every event with the answer text it added.
+    fn with_deltas(self) -> WithDeltas<Self> {
+        WithDeltas { inner: self, sent: String::new() }
+    }
+}
+
+impl<S> SearchStreamExt for S where S: Stream<Item = Result<SearchEvent>> {}
+
+pin_project_lite::pin_project! {
+    /// Stream for [`SearchStreamExt::answers_only`].
+    #[derive(Debug)]
+    #[must_use = "streams do nothing unless polled"]
+    pub struct AnswersOnly<S> {
+        #[pin]
+        inner: S,
+        last: Option<String>,
+    }
+}
+
+impl<S> Stream for AnswersOnly<S>
+where
+    S: Stream<Item = Result<SearchEvent>>,
+{
+    type Item = Result<String>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            let event = match std::task::ready!(this.inner.as_mut().poll_next(cx)) {
+                Some(Ok(event)) => event,
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => return Poll::Ready(None),
+            };
+            match event.answer {
+                Some(answer) if !answer.is_empty() && this.last.as_ref() != Some(&answer) => {
+                    *this.last = Some(answer.clone());
+                    return Poll::Ready(Some(Ok(answer)));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// Stream for [`SearchStreamExt::final_only`].
+    #[derive(Debug)]
+    #[must_use = "streams do nothing unless polled"]
+    pub struct FinalOnly<S> {
+        #[pin]
+        inner: S,
+        last: Option<SearchEvent>,
+        done: bool,
+    }
+}
+
+impl<S> Stream for FinalOnly<S>
+where
+    S: Stream<Item = Result<SearchEvent>>,
+{
+    type Item = Result<SearchEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        while !*this.done {
+            match std::task::ready!(this.inner.as_mut().poll_next(cx)) {
+                Some(Ok(event)) => *this.last = Some(event),
+                Some(Err(e)) => {
+                    *this.done = true;
+                    return Poll::Ready(Some(Err(e)));
+                }
+                None => {
+                    *this.done = true;
+                    let last = this.last.take().ok_or(Error::UnexpectedEndOfStream);
+                    return Poll::Ready(Some(last));
+                }
+            }
+        }
+        Poll::Ready(None)
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// Stream for [`SearchStreamExt::throttle`].
+    #[derive(Debug)]
+    #[must_use = "streams do nothing unless polled"]
+    pub struct Throttle<S> {
+        #[pin]
+        inner: S,
+        interval: Duration,
+        #[pin]
+        sleep: Sleep,
+        last_sent: Option<Instant>,
+        pending: Option<SearchEvent>,
+        done: bool,
+    }
+}
+
+impl<S> Stream for Throttle<S>
+where
+    S: Stream<Item = Result<SearchEvent>>,
+{
+    type Item = Result<SearchEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        while !*this.done {
+            match this.inner.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(event))) => {
+                    let due = this.last_sent.map(|sent| sent + *this.interval);
+                    match due {
+                        Some(due) if due > Instant::now() => {
+                            if this.pending.replace(event).is_none() {
+                                this.sleep.as_mut().reset(due);
+                            }
+                        }
+                        _ => {
+                            *this.last_sent = Some(Instant::now());
+                            *this.pending = None;
+                            return Poll::Ready(Some(Ok(event)));
+                        }
+                    }
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => *this.done = true,
+                Poll::Pending => {
+                    if this.pending.is_some() && this.sleep.as_mut().poll(cx).is_ready() {
+                        *this.last_sent = Some(Instant::now());
+                        return Poll::Ready(this.pending.take().map(Ok));
+                    }
+                    return Poll::Pending;
+                }
+            }
+        }
+        Poll::Ready(this.pending.take().map(Ok))
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// Stream for [`SearchStreamExt::with_deltas`].
+    #[derive(Debug)]
+    #[must_use = "streams do nothing unless polled"]
+    pub struct WithDeltas<S> {
+        #[pin]
+        inner: S,
+        sent: String,
+    }
+}
+
+impl<S> Stream for WithDeltas<S>
+where
+    S: Stream<Item = Result<SearchEvent>>,
+{
+    type Item = Result<AnswerDelta>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let event = match std::task::ready!(this.inner.poll_next(cx)) {
+            Some(Ok(event)) => event,
+            Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+            None => return Poll::Ready(None),
+        };
+        let (delta, revised) = match event.answer.as_deref() {
+            None => (String::new(), false),
+            Some(answer) => {
+                let added = answer.strip_prefix(this.sent.as_str());
+                let delta = (added.unwrap_or(answer).to_string(), added.is_none());
+                answer.clone_into(this.sent);
+                delta
+            }
+        };
+        Poll::Ready(Some(Ok(AnswerDelta { event, delta, revised })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+
+    fn events(answers: &[Option<&str>]) -> impl Stream<Item = Result<SearchEvent>> + use<> {
+        let events: Vec<Result<SearchEvent>> = answers
+            .iter()
+            .map(|answer| {
+                Ok(SearchEvent { answer: answer.map(String::from), ..SearchEvent::default() })
+            })
+            .collect();
+        futures_util::stream::iter(events)
+    }
+
+    #[tokio::test]
+    async fn test_stream_combinators() {
+        let answers = [None, Some("The"), Some("The"), Some("The sky"), Some("A sky")];
+
+        let texts: Vec<String> =
+            events(&answers).answers_only().map(Result::unwrap).collect().await;
+        assert_eq!(texts, ["The", "The sky", "A sky"]);
+
+        let last: Vec<_> = events(&answers).final_only().collect().await;
+        assert!(matches!(&last[..], [Ok(event)] if event.answer.as_deref() == Some("A sky")));
+        let empty: Vec<_> = events(&[]).final_only().collect().await;
+        assert!(matches!(&empty[..], [Err(Error::UnexpectedEndOfStream)]));
+
+        let throttled: Vec<SearchEvent> = events(&answers)
+            .throttle(Duration::from_secs(60))
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert_eq!(throttled.len(), 2);
+        assert_eq!(throttled[0].answer, None);
+        assert_eq!(throttled[1].answer.as_deref(), Some("A sky"));
+
+        let deltas: Vec<(String, bool)> = events(&answers)
+            .with_deltas()
+            .map(|delta| delta.map(|d| (d.delta, d.revised)).unwrap())
+            .collect()
+            .await;
+        let deltas: Vec<(&str, bool)> = deltas.iter().map(|(d, r)| (d.as_str(), *r)).collect();
+        assert_eq!(
+            deltas,
+            [("", false), ("The", false), ("", false), (" sky", false), ("A sky", true)]
+        );
+    }
+}
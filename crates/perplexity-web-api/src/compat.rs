@@ -0,0 +1,120 @@
+//! Versions of the Perplexity web API the client can speak.
+//!
+//! The web app sends its version with every request, and Perplexity has
+//! changed the shape of the ask payload and of the answer events between
+//! versions. Each [`ApiVersion`] in [`API_VERSIONS`] records how one version
+//! differs, so that one release of this crate supports a window of
+//! frontends: the payload builder and the event parser consult it, and
+//! [`ClientBuilder::api_version`](crate::ClientBuilder::api_version) or
+//! [`ClientBuilder::detect_api_version`](crate::ClientBuilder::detect_api_version)
+//! choose which one a client speaks.
+
+use crate::config::API_VERSION;
+use crate::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// How the ask payload selects the sources to search.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SourcesShape {
+    /// A `sources` list, e.g. `["web", "scholar"]`.
+    List,
+    /// A single `search_focus`, e.g. `"internet"`.
+    Focus,
+}
+
+/// Where answer events carry the answer and its web results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AnswerShape {
+    /// In the `FINAL` step of the `text` field, as JSON.
+    FinalStep,
+    /// In top-level `answer` and `web_results` fields.
+    TopLevel,
+}
+
+/// A version of the Perplexity web API and the payload and event shapes it
+/// uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ApiVersion {
+    version: &'static str,
+    pub(crate) sources: SourcesShape,
+    pub(crate) answer: AnswerShape,
+}
+
+/// The supported versions, newest first. The first is the one the current
+/// web app speaks, [`API_VERSION`].
+pub const API_VERSIONS: &[ApiVersion] = &[
+    ApiVersion {
+        version: API_VERSION,
+        sources: SourcesShape::List,
+        answer: AnswerShape::FinalStep,
+    },
+    // Multiple sources already, answers still at the top level.
+    ApiVersion { version: "2.16", sources: SourcesShape::List, answer: AnswerShape::TopLevel },
+    // One focus per query.
+    ApiVersion {
+        version: "2.13",
+        sources: SourcesShape::Focus,
+        answer: AnswerShape::TopLevel,
+    },
+];
+
+impl ApiVersion {
+    /// The version the current web app speaks.
+    pub fn current() -> Self {
+        API_VERSIONS[0]
+    }
+
+    /// Returns the version string sent to Perplexity, e.g. `"2.18"`.
+    pub fn as_str(&self) -> &'static str {
+        self.version
+    }
+}
+
+impl Default for ApiVersion {
+    fn default() -> Self {
+        Self::current()
+    }
+}
+
+impl fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.version)
+    }
+}
+
+impl FromStr for ApiVersion {
+    type Err = Error;
+
+    /// Parses one of the supported version strings.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        API_VERSIONS.iter().find(|version| version.version == s.trim()).copied().ok_or_else(
+            || Error::UnknownVariant {
+                kind: "API version",
+                value: s.to_string(),
+                suggestions: API_VERSIONS.iter().map(ApiVersion::as_str).collect(),
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_versions() {
+        assert_eq!(ApiVersion::current().as_str(), API_VERSION);
+        assert_eq!(ApiVersion::default(), ApiVersion::current());
+        assert_eq!(" 2.13 ".parse::<ApiVersion>().unwrap().sources, SourcesShape::Focus);
+
+        let err = "3.0".parse::<ApiVersion>().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Unknown API version '3.0', did you mean one of: {}, 2.16, 2.13",
+                API_VERSION
+            )
+        );
+    }
+}
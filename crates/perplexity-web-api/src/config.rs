@@ -3,9 +3,22 @@ use crate::types::{Model, SearchMode};
 pub const API_BASE_URL: &str = "https://www.perplexity.ai";
 pub const API_VERSION: &str = "2.18";
 
+/// Longest query, in characters, the ask endpoint accepts.
+pub const MAX_QUERY_LENGTH: usize = 8_000;
+
 pub const ENDPOINT_AUTH_SESSION: &str = "/api/auth/session";
 pub const ENDPOINT_SSE_ASK: &str = "/rest/sse/perplexity_ask";
 pub const ENDPOINT_UPLOAD_URL: &str = "/rest/uploads/create_upload_url";
+pub const ENDPOINT_THREAD: &str = "/rest/thread";
+pub const ENDPOINT_THREAD_ACCESS: &str = "/rest/thread/update_thread_access";
+pub const ENDPOINT_THREAD_TITLE: &str = "/rest/thread/set_thread_title";
+pub const ENDPOINT_THREAD_BOOKMARK: &str = "/rest/thread/bookmark";
+pub const ENDPOINT_BOOKMARKED_THREADS: &str = "/rest/thread/list_bookmarked_threads";
+
+/// Thread access level that only the owner can view.
+pub const THREAD_ACCESS_PRIVATE: u8 = 1;
+/// Thread access level that anyone with the link can view.
+pub const THREAD_ACCESS_PUBLIC: u8 = 2;
 
 /// Returns the model preference string for the API payload.
 ///
@@ -0,0 +1,139 @@
+//! Context packs for retrieval-augmented generation.
+//!
+//! A [`ContextPack`] condenses a response's web results into one block of
+//! text to inject into another model's prompt: each distinct snippet under
+//! a header with its citation number, title and URL, with repeated pages
+//! and repeated snippets left out, cut off at a token budget.
+
+use crate::pipeline::normalize_url;
+use crate::tokens::{estimate_tokens, token_limit};
+use crate::types::SearchWebResult;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Shortest snippet worth cutting down to fit the budget, in tokens; below
+/// this the source is left out instead.
+const MIN_SNIPPET_TOKENS: usize = 20;
+
+/// Web result snippets packed into one block of prompt text.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContextPack {
+    /// The packed snippets.
+    pub text: String,
+    /// Citation numbers of the sources in the pack, in order.
+    pub sources: Vec<usize>,
+    /// Estimated size of `text`; see [`estimate_tokens`](crate::estimate_tokens).
+    pub estimated_tokens: usize,
+    /// True if sources were left out or cut short to fit the budget.
+    pub truncated: bool,
+}
+
+impl ContextPack {
+    /// Packs the snippets of `results` into at most `max_tokens` estimated
+    /// tokens.
+    ///
+    /// Sources keep their citation numbers (their position in `results`
+    /// plus one), so the answer's `[n]` markers still refer to them. Results
+    /// without a snippet, or repeating an earlier URL or snippet, are
+    /// skipped.
+    pub fn build(results: &[SearchWebResult], max_tokens: usize) -> Self {
+        let mut pack = Self::default();
+        let mut used = 0;
+        let mut urls = HashSet::new();
+        let mut snippets = HashSet::new();
+
+        for (i, result) in results.iter().enumerate() {
+            let snippet = result.snippet.trim();
+            let normalized: String = snippet
+                .split_whitespace()
+                .flat_map(|word| [word, " "])
+                .collect::<String>()
+                .to_lowercase();
+            if snippet.is_empty()
+                || !urls.insert(normalize_url(&result.url))
+                || !snippets.insert(normalized)
+            {
+                continue;
+            }
+
+            let separator = if pack.text.is_empty() { "" } else { "\n\n" };
+            let title = if result.name.is_empty() { &result.url } else { &result.name };
+            let header = format!("{}[{}] {}\n{}\n", separator, i + 1, title, result.url);
+            let room = max_tokens.saturating_sub(used + estimate_tokens(&header));
+            if estimate_tokens(snippet) <= room {
+                pack.text.push_str(&header);
+                pack.text.push_str(snippet);
+                used = estimate_tokens(&pack.text);
+                pack.sources.push(i + 1);
+                continue;
+            }
+
+            pack.truncated = true;
+            if room > MIN_SNIPPET_TOKENS {
+                // Leave room for the ellipsis.
+                let limit =
+                    token_limit(snippet, room - estimate_tokens("…")).unwrap_or(snippet.len());
+                pack.text.push_str(&header);
+                pack.text.push_str(&cut(&snippet[..limit]));
+                pack.sources.push(i + 1);
+            }
+            break;
+        }
+
+        pack.estimated_tokens = estimate_tokens(&pack.text);
+        pack
+    }
+}
+
+/// Ends the start of a snippet at a word boundary, with an ellipsis.
+fn cut(head: &str) -> String {
+    let head = head.rfind(char::is_whitespace).map_or(head, |i| &head[..i]);
+    format!("{}…", head.trim_end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(name: &str, url: &str, snippet: &str) -> SearchWebResult {
+        SearchWebResult {
+            name: name.to_string(),
+            url: url.to_string(),
+            snippet: snippet.to_string(),
+            score: None,
+        }
+    }
+
+    #[test]
+    fn test_pack_dedupes_and_respects_budget() {
+        let long = "word ".repeat(100);
+        let results = [
+            result("Rust", "https://rust-lang.org/", "Rust is a systems language."),
+            result("Rust again", "https://rust-lang.org#intro", "Another snippet."),
+            result("Mirror", "https://mirror.example/rust", "Rust  is a systems\nlanguage."),
+            result("No snippet", "https://empty.example", " "),
+            result("", "https://book.example", "The book."),
+            result("Long", "https://long.example", &long),
+        ];
+
+        let pack = ContextPack::build(&results, 1000);
+        assert_eq!(pack.sources, [1, 5, 6]);
+        assert!(pack.text.starts_with(
+            "[1] Rust\nhttps://rust-lang.org/\nRust is a systems language.\n\n\
+             [5] https://book.example\nhttps://book.example\nThe book.\n\n[6] Long\n"
+        ));
+        assert!(!pack.truncated);
+        assert_eq!(pack.estimated_tokens, estimate_tokens(&pack.text));
+
+        let pack = ContextPack::build(&results, 80);
+        assert_eq!(pack.sources, [1, 5, 6]);
+        assert!(pack.truncated);
+        assert!(pack.text.ends_with("word…"));
+        assert!(pack.estimated_tokens <= 80);
+
+        let pack = ContextPack::build(&results, 60);
+        assert_eq!(pack.sources, [1, 5]);
+        assert!(pack.truncated);
+        assert!(ContextPack::build(&results, 0).text.is_empty());
+    }
+}
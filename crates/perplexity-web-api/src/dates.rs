@@ -0,0 +1,231 @@
+//! Relative dates in answers.
+//!
+//! Answers say "yesterday" or "last Tuesday" relative to when the query
+//! ran, which turns wrong once the answer is stored and read later.
+//! [`resolve_relative_dates`] appends the absolute date to each such
+//! expression, for the [`AbsoluteDates`](crate::AbsoluteDates) pipeline
+//! step. Only English expressions are recognized: `today`, `tomorrow`,
+//! `yesterday`, `last`/`this`/`next` followed by a weekday, and `<n> days
+//! ago` or `<n> weeks ago`, with `n` in digits or words up to twelve.
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+const WEEKDAYS: &[&str] =
+    &["monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday"];
+
+const NUMBERS: &[&str] = &[
+    "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten", "eleven",
+    "twelve",
+];
+
+/// A word of the text, by byte range.
+struct Word<'a> {
+    start: usize,
+    end: usize,
+    text: &'a str,
+}
+
+/// Appends ` (YYYY-MM-DD)` to every relative date in `text`, resolved
+/// against `today`, in days since the Unix epoch. Expressions already
+/// followed by a date are left alone, so resolving twice changes nothing.
+pub(crate) fn resolve_relative_dates(text: &str, today: i64) -> String {
+    let words = words(text);
+    let mut resolved = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut i = 0;
+
+    while i < words.len() {
+        let Some((length, day)) = expression(text, &words[i..], today) else {
+            i += 1;
+            continue;
+        };
+        let last = &words[i + length - 1];
+        let mut end = last.end;
+        // "today's" takes the date after the possessive.
+        for suffix in ["'s", "\u{2019}s"] {
+            if text[end..].starts_with(suffix) {
+                end += suffix.len();
+            }
+        }
+        i += length;
+        if is_dated(&text[end..]) {
+            continue;
+        }
+        resolved.push_str(&text[copied..end]);
+        resolved.push_str(&format!(" ({})", format_date(day)));
+        copied = end;
+    }
+    resolved.push_str(&text[copied..]);
+    resolved
+}
+
+/// Days since the Unix epoch of `secs`, a Unix timestamp, at a UTC offset
+/// of `offset_minutes`.
+pub(crate) fn epoch_day(secs: i64, offset_minutes: i32) -> i64 {
+    (secs + i64::from(offset_minutes) * 60).div_euclid(SECONDS_PER_DAY)
+}
+
+/// Parses a UTC offset, `Z`, `UTC`, `+HH`, `+HH:MM` or `-HHMM`, into
+/// minutes.
+pub(crate) fn parse_utc_offset(s: &str) -> Option<i32> {
+    let s = s.trim();
+    if s.eq_ignore_ascii_case("z") || s.eq_ignore_ascii_case("utc") {
+        return Some(0);
+    }
+    let s = s.strip_prefix("UTC").unwrap_or(s);
+    let (sign, rest) = match s.strip_prefix('+') {
+        Some(rest) => (1, rest),
+        None => (-1, s.strip_prefix(['-', '\u{2212}'])?),
+    };
+    let digits: String = rest.chars().filter(|c| *c != ':').collect();
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes) = match digits.len() {
+        1 | 2 => (digits.parse::<i32>().ok()?, 0),
+        4 => (digits.get(..2)?.parse::<i32>().ok()?, digits.get(2..)?.parse::<i32>().ok()?),
+        _ => return None,
+    };
+    (hours <= 14 && minutes < 60).then_some(sign * (hours * 60 + minutes))
+}
+
+/// Splits `text` into runs of ASCII letters and digits.
+fn words(text: &str) -> Vec<Word<'_>> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        match (start, c.is_ascii_alphanumeric()) {
+            (None, true) => start = Some(i),
+            (Some(s), false) => {
+                words.push(Word { start: s, end: i, text: &text[s..i] });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    words
+}
+
+/// Matches a relative date at the start of `words`, returning how many
+/// words it spans and the day it refers to.
+fn expression(text: &str, words: &[Word<'_>], today: i64) -> Option<(usize, i64)> {
+    let word = |i: usize| -> Option<String> {
+        let word = words.get(i)?;
+        // Words of an expression are separated by whitespace only.
+        if i > 0 && !text[words[i - 1].end..word.start].trim().is_empty() {
+            return None;
+        }
+        Some(word.text.to_ascii_lowercase())
+    };
+
+    match word(0)?.as_str() {
+        "today" => return Some((1, today)),
+        "tomorrow" => return Some((1, today + 1)),
+        "yesterday" => return Some((1, today - 1)),
+        which @ ("last" | "this" | "next") => {
+            let target = word(1).and_then(|day| WEEKDAYS.iter().position(|d| *d == day))?;
+            let (target, weekday) = (target as i64, weekday(today));
+            let day = match which {
+                "last" => today - (weekday - target - 1).rem_euclid(7) - 1,
+                "next" => today + (target - weekday - 1).rem_euclid(7) + 1,
+                _ => today - weekday + target,
+            };
+            return Some((2, day));
+        }
+        _ => {}
+    }
+
+    let count = number(&word(0)?)?;
+    let unit = match word(1)?.as_str() {
+        "day" | "days" => 1,
+        "week" | "weeks" => 7,
+        _ => return None,
+    };
+    (word(2)? == "ago").then_some((3, today - count * unit))
+}
+
+/// A count written in digits or as a word up to twelve.
+fn number(word: &str) -> Option<i64> {
+    match word {
+        "a" | "an" => Some(1),
+        _ => word
+            .parse()
+            .ok()
+            .filter(|n| (1..=999).contains(n))
+            .or_else(|| NUMBERS.iter().position(|n| *n == word).map(|i| i as i64 + 1)),
+    }
+}
+
+/// The weekday of `day`, Monday being 0. The epoch was a Thursday.
+fn weekday(day: i64) -> i64 {
+    (day + 3).rem_euclid(7)
+}
+
+/// Whether `rest` starts with a date in parentheses, as added before.
+fn is_dated(rest: &str) -> bool {
+    rest.strip_prefix(" (").and_then(|rest| rest.get(..11)).is_some_and(|date| {
+        date.ends_with(')')
+            && date.bytes().take(10).enumerate().all(|(i, b)| match i {
+                4 | 7 => b == b'-',
+                _ => b.is_ascii_digit(),
+            })
+    })
+}
+
+/// Formats `day`, in days since the Unix epoch, as `YYYY-MM-DD`.
+fn format_date(day: i64) -> String {
+    // Civil date from days since the epoch, after Howard Hinnant's algorithm.
+    let z = day + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_relative_dates() {
+        // Wednesday, 14 October 2026.
+        let today = epoch_day(1_791_979_200, 0);
+        assert_eq!(format_date(today), "2026-10-14");
+
+        let resolve = |text| resolve_relative_dates(text, today);
+        assert_eq!(
+            resolve("Yesterday's release follows the beta from last Tuesday."),
+            "Yesterday's (2026-10-13) release follows the beta from last Tuesday (2026-10-13)."
+        );
+        assert_eq!(
+            resolve("Shipped 3 days ago, two weeks ago, next Wednesday, this Monday."),
+            "Shipped 3 days ago (2026-10-11), two weeks ago (2026-09-30), \
+             next Wednesday (2026-10-21), this Monday (2026-10-12)."
+        );
+        assert_eq!(
+            resolve("Due tomorrow, or next week."),
+            "Due tomorrow (2026-10-15), or next week."
+        );
+        assert_eq!(
+            resolve("last, Tuesday; todays; 0 days ago"),
+            "last, Tuesday; todays; 0 days ago"
+        );
+
+        let once = resolve("It rained today.");
+        assert_eq!(resolve_relative_dates(&once, today), once);
+
+        // 22:00 UTC is already the next day at UTC+05:00.
+        assert_eq!(epoch_day(1_791_979_200 + 10 * 3600, 0), today);
+        assert_eq!(epoch_day(1_791_979_200 + 10 * 3600, 5 * 60), today + 1);
+        assert_eq!(parse_utc_offset("+05:30"), Some(330));
+        assert_eq!(parse_utc_offset("-0800"), Some(-480));
+        assert_eq!(parse_utc_offset("UTC+2"), Some(120));
+        assert_eq!(parse_utc_offset("Z"), Some(0));
+        assert_eq!(parse_utc_offset("Europe/Paris"), None);
+    }
+}
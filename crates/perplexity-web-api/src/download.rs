@@ -0,0 +1,96 @@
+use crate::error::{Error, Result};
+use bytes::{Bytes, BytesMut};
+use futures_util::StreamExt;
+use rquest::Client as HttpClient;
+use rquest::header::CONTENT_TYPE;
+use std::time::Duration;
+
+/// Fetches `url` with the client's session, rejecting bodies larger than
+/// `max_bytes` and, if `accept` is given, content types not matching it.
+///
+/// Returns the body and the response's content type.
+pub(crate) async fn download_file(
+    http: &HttpClient,
+    url: &str,
+    timeout: Duration,
+    max_bytes: usize,
+    accept: Option<&str>,
+) -> Result<(Bytes, Option<String>)> {
+    crate::rt::timeout(timeout, fetch(http, url, max_bytes, accept)).await?
+}
+
+async fn fetch(
+    http: &HttpClient,
+    url: &str,
+    max_bytes: usize,
+    accept: Option<&str>,
+) -> Result<(Bytes, Option<String>)> {
+    let response = http
+        .get(url)
+        .send()
+        .await
+        .map_err(Error::Http)?
+        .error_for_status()
+        .map_err(|e| Error::DownloadFailed(e.to_string()))?;
+
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string());
+
+    if let Some(accept) = accept {
+        let actual = content_type.as_deref().unwrap_or("application/octet-stream");
+        if !mime_matches(actual, accept) {
+            return Err(Error::UnexpectedContentType {
+                expected: accept.to_string(),
+                actual: actual.to_string(),
+            });
+        }
+    }
+
+    if response.content_length().is_some_and(|len| len > max_bytes as u64) {
+        return Err(Error::DownloadTooLarge { limit: max_bytes });
+    }
+
+    // Content-Length may be absent or wrong, so enforce the limit while reading.
+    let mut body = BytesMut::new();
+    let mut chunks = response.bytes_stream();
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk.map_err(Error::Http)?;
+        if body.len() + chunk.len() > max_bytes {
+            return Err(Error::DownloadTooLarge { limit: max_bytes });
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok((body.freeze(), content_type))
+}
+
+/// Checks a `Content-Type` value against a pattern such as `"image/png"` or
+/// `"image/*"`, ignoring case and parameters.
+fn mime_matches(content_type: &str, pattern: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+
+    match pattern.strip_suffix("/*") {
+        Some(kind) => essence
+            .split_once('/')
+            .is_some_and(|(actual, _)| actual.eq_ignore_ascii_case(kind)),
+        None => essence.eq_ignore_ascii_case(pattern),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mime_matches() {
+        assert!(mime_matches("image/png", "image/png"));
+        assert!(mime_matches("Image/PNG; charset=binary", "image/png"));
+        assert!(mime_matches("image/webp", "image/*"));
+        assert!(!mime_matches("text/html; charset=utf-8", "image/*"));
+        assert!(!mime_matches("imagex/png", "image/*"));
+        assert!(!mime_matches("application/pdf", "application/json"));
+    }
+}
@@ -1,3 +1,4 @@
+use std::fmt;
 use std::time::Duration;
 use thiserror::Error;
 
@@ -5,6 +6,7 @@ use thiserror::Error;
 #[derive(Error, Debug)]
 pub enum Error {
     /// HTTP request failed.
+    #[cfg(feature = "client")]
     #[error("HTTP error: {0}")]
     Http(#[from] rquest::Error),
 
@@ -20,10 +22,61 @@ pub enum Error {
     #[error("File uploads require authentication cookies")]
     FileUploadRequiresAuth,
 
+    /// Image generation requires authentication cookies.
+    #[error("Image generation requires authentication cookies")]
+    ImageGenerationRequiresAuth,
+
+    /// The query is longer than the API accepts.
+    #[error("Query is {length} characters long (limit {limit})")]
+    QueryTooLong { length: usize, limit: usize },
+
+    /// A query has more attachments than allowed.
+    #[error("Too many attachments: {count} (limit {limit})")]
+    TooManyAttachments { count: usize, limit: usize },
+
+    /// A file is larger than the upload size limit.
+    #[error("Attachment '{filename}' is {size} bytes (limit {limit})")]
+    AttachmentTooLarge { filename: String, size: usize, limit: usize },
+
+    /// A file is not one of the formats Perplexity accepts as an attachment.
+    #[error(
+        "Attachment '{filename}' is {detected}, which cannot be uploaded; supported formats: {}",
+        supported.join(", ")
+    )]
+    UnsupportedAttachmentType {
+        filename: String,
+        detected: &'static str,
+        supported: Vec<&'static str>,
+    },
+
+    /// A file's extension names a different format than its contents.
+    #[error("Attachment '{filename}' is named as {extension} but contains {detected}")]
+    AttachmentTypeMismatch {
+        filename: String,
+        extension: &'static str,
+        detected: &'static str,
+    },
+
+    /// A request would have gone to a host that is not allowed.
+    #[error("Refusing to contact {host}: not an allowed host")]
+    HostNotAllowed { host: String },
+
+    /// Anonymous sessions can only search in Auto mode.
+    #[error("{0} mode requires an account; anonymous sessions only support auto")]
+    ModeRequiresAuth(crate::types::SearchMode),
+
+    /// Reading, renaming or sharing threads requires authentication cookies.
+    #[error("Managing threads requires authentication cookies")]
+    ThreadsRequireAuth,
+
     /// Invalid model for the specified mode.
     #[error("Invalid model '{model}' for mode '{mode}'")]
     InvalidModelForMode { model: String, mode: String },
 
+    /// A request option has a value Perplexity would reject.
+    #[error("Invalid {name}: {reason}")]
+    InvalidParameter { name: &'static str, reason: String },
+
     /// Failed to get upload URL.
     #[error("Failed to get upload URL: {0}")]
     UploadUrlFailed(String),
@@ -36,6 +89,35 @@ pub enum Error {
     #[error("Missing secure_url in S3 response")]
     MissingSecureUrl,
 
+    /// Downloading an attachment failed.
+    #[error("Download failed: {0}")]
+    DownloadFailed(String),
+
+    /// A downloaded attachment exceeded the configured size limit.
+    #[error("Download exceeds the {limit} byte limit")]
+    DownloadTooLarge { limit: usize },
+
+    /// A single event of the answer stream exceeded the configured size limit.
+    #[error("Stream event exceeds the {limit} byte limit")]
+    EventTooLarge { limit: usize },
+
+    /// The answer stream exceeded the configured total size limit.
+    #[error("Answer stream exceeds the {limit} byte limit")]
+    StreamTooLarge { limit: usize },
+
+    /// A downloaded attachment had a different content type than required.
+    #[error("Expected content type '{expected}', got '{actual}'")]
+    UnexpectedContentType { expected: String, actual: String },
+
+    /// Preprocessing an image attachment failed.
+    #[cfg(feature = "image")]
+    #[error("Image processing failed: {0}")]
+    ImageProcessing(String),
+
+    /// A configured user agent cannot be sent as a header value.
+    #[error("Invalid user agent: {0:?}")]
+    InvalidUserAgent(String),
+
     /// Invalid MIME type.
     #[error("Invalid MIME type: {0}")]
     InvalidMimeType(String),
@@ -48,9 +130,136 @@ pub enum Error {
     #[error("Server error: {status} - {message}")]
     Server { status: u16, message: String },
 
+    /// Perplexity rejected every API version the client supports; a newer
+    /// release of this crate is needed.
+    #[error("Perplexity rejected every supported API version ({})", tried.join(", "))]
+    ApiVersionRejected { tried: Vec<&'static str> },
+
+    /// The client was used outside a tokio runtime, which its HTTP stack
+    /// needs.
+    #[cfg(feature = "client")]
+    #[error(
+        "The client must run within a tokio runtime; on other executors, wrap its futures \
+         with async-compat"
+    )]
+    NoRuntime,
+
     /// Stream ended unexpectedly.
     #[error("Stream ended unexpectedly")]
     UnexpectedEndOfStream,
+
+    /// A provider failed repeatedly and is not called again until it has
+    /// had time to recover.
+    #[error(
+        "{provider} is unavailable after repeated failures; retry after {}s",
+        retry_after.as_secs().max(1)
+    )]
+    ProviderUnavailable {
+        /// Name of the provider.
+        provider: String,
+        /// How long until the provider is tried again.
+        retry_after: Duration,
+    },
+
+    /// Perplexity sent an error event in place of an answer and ended the
+    /// stream.
+    #[error("Perplexity reported an error ({kind}): {message}")]
+    StreamEvent {
+        kind: StreamErrorKind,
+        /// The error code sent with the event, if any.
+        code: Option<String>,
+        /// The message sent with the event.
+        message: String,
+    },
+
+    /// Perplexity declined to answer the query. Only returned when enabled
+    /// with `ClientBuilder::detect_refusals`.
+    #[error("Perplexity declined to answer: {reason}")]
+    Refused {
+        /// The text of the refusal.
+        reason: String,
+    },
+
+    /// A string did not match any known value of a mode, model, or source.
+    #[error("Unknown {kind} '{value}', did you mean one of: {}", suggestions.join(", "))]
+    UnknownVariant { kind: &'static str, value: String, suggestions: Vec<&'static str> },
+
+    /// A step of a pipeline specification could not be parsed.
+    #[error("Invalid pipeline step '{step}': {reason}")]
+    InvalidPipelineStep { step: String, reason: String },
+}
+
+impl Error {
+    /// Whether the error means the service could not be reached or is
+    /// turning requests away for now: connection failures, timeouts, rate
+    /// limiting and server errors. Other errors are problems with the
+    /// request that retrying elsewhere would not fix.
+    pub fn is_unavailable(&self) -> bool {
+        match self {
+            #[cfg(feature = "client")]
+            Self::Http(_) => true,
+            Self::Timeout(_)
+            | Self::UnexpectedEndOfStream
+            | Self::ProviderUnavailable { .. } => true,
+            Self::Server { status, .. } => *status == 0 || *status == 429 || *status >= 500,
+            Self::StreamEvent { kind, .. } => {
+                matches!(kind, StreamErrorKind::Overloaded | StreamErrorKind::RateLimited)
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether Perplexity turned the request away for exceeding a rate
+    /// limit or usage quota.
+    pub fn is_rate_limited(&self) -> bool {
+        matches!(
+            self,
+            Self::Server { status: 429, .. }
+                | Self::StreamEvent { kind: StreamErrorKind::RateLimited, .. }
+        )
+    }
+}
+
+/// The cause of an [`Error::StreamEvent`], as far as it can be told from
+/// the event's code and message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamErrorKind {
+    /// The query was rejected and should not be retried as is.
+    Rejected,
+    /// The model or service is overloaded; retrying later may succeed.
+    Overloaded,
+    /// Too many queries were sent.
+    RateLimited,
+    /// Any other error.
+    Other,
+}
+
+impl StreamErrorKind {
+    /// Classifies an error event by its code and message.
+    pub(crate) fn classify(code: Option<&str>, message: &str) -> Self {
+        let text = format!("{} {}", code.unwrap_or_default(), message).to_lowercase();
+        let mentions = |words: &[&str]| words.iter().any(|word| text.contains(word));
+        if mentions(&["rate limit", "rate_limit", "too many", "quota"]) {
+            Self::RateLimited
+        } else if mentions(&["overload", "capacity", "unavailable", "busy", "try again"]) {
+            Self::Overloaded
+        } else if mentions(&["reject", "invalid", "not allowed", "forbidden", "blocked"]) {
+            Self::Rejected
+        } else {
+            Self::Other
+        }
+    }
+}
+
+impl fmt::Display for StreamErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Rejected => "query rejected",
+            Self::Overloaded => "overloaded",
+            Self::RateLimited => "rate limited",
+            Self::Other => "error",
+        })
+    }
 }
 
 /// Convenience Result type for this crate.
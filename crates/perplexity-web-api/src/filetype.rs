@@ -0,0 +1,197 @@
+//! Attachment type detection.
+//!
+//! The type of a file to upload is read from its first bytes rather than
+//! trusted from its name, and given as the MIME type Perplexity's upload
+//! endpoint expects for it. Files that are none of the formats Perplexity
+//! accepts, or whose extension names a different format than they contain,
+//! are rejected before anything is sent.
+
+use crate::error::{Error, Result};
+use crate::types::UploadFile;
+
+/// A format Perplexity accepts as an attachment.
+struct Format {
+    /// Name shown in errors.
+    name: &'static str,
+    /// MIME type sent to the upload endpoint.
+    mime: &'static str,
+    /// Extensions of the format, lowercase.
+    extensions: &'static [&'static str],
+}
+
+const PDF: Format = Format { name: "PDF", mime: "application/pdf", extensions: &["pdf"] };
+const PNG: Format = Format { name: "PNG", mime: "image/png", extensions: &["png"] };
+const JPEG: Format =
+    Format { name: "JPEG", mime: "image/jpeg", extensions: &["jpg", "jpeg", "jpe", "jfif"] };
+const GIF: Format = Format { name: "GIF", mime: "image/gif", extensions: &["gif"] };
+const WEBP: Format = Format { name: "WebP", mime: "image/webp", extensions: &["webp"] };
+const DOCX: Format = Format {
+    name: "Word (.docx)",
+    mime: "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    extensions: &["docx"],
+};
+const XLSX: Format = Format {
+    name: "Excel (.xlsx)",
+    mime: "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    extensions: &["xlsx"],
+};
+const PPTX: Format = Format {
+    name: "PowerPoint (.pptx)",
+    mime: "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+    extensions: &["pptx"],
+};
+
+/// Text files, uploaded as plain text whatever their extension, except for
+/// the few text types Perplexity reads differently.
+const TEXT: Format = Format { name: "text", mime: "text/plain", extensions: &["txt", "text"] };
+const TEXT_TYPES: &[(&str, &str)] = &[
+    ("md", "text/markdown"),
+    ("markdown", "text/markdown"),
+    ("csv", "text/csv"),
+    ("json", "application/json"),
+    ("html", "text/html"),
+    ("htm", "text/html"),
+];
+
+const FORMATS: &[&Format] = &[&PDF, &PNG, &JPEG, &GIF, &WEBP, &DOCX, &XLSX, &PPTX, &TEXT];
+
+/// Bytes inspected to tell text from binary data.
+const SNIFF_LEN: usize = 8192;
+
+/// Names of the formats that can be attached, as listed in errors.
+pub(crate) fn supported_formats() -> Vec<&'static str> {
+    FORMATS.iter().map(|format| format.name).collect()
+}
+
+/// Detects the type of `file` from its contents and returns the MIME type
+/// to upload it as.
+pub(crate) fn content_type(file: &UploadFile) -> Result<&'static str> {
+    let filename = file.filename();
+    let extension = filename
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_ascii_lowercase())
+        .unwrap_or_default();
+    let data = file.as_bytes();
+
+    let format = match sniff(&data) {
+        Ok(Some(format)) => format,
+        // Office documents are ZIP archives; which one is told by the name.
+        Ok(None) => match [&DOCX, &XLSX, &PPTX]
+            .into_iter()
+            .find(|format| format.extensions.contains(&extension.as_str()))
+        {
+            Some(format) => format,
+            None => return Err(unsupported(filename, "a ZIP archive")),
+        },
+        Err(detected) => return Err(unsupported(filename, detected)),
+    };
+
+    let claimed = FORMATS.iter().find(|known| known.extensions.contains(&extension.as_str()));
+    if let Some(claimed) = claimed.filter(|claimed| claimed.name != format.name) {
+        return Err(Error::AttachmentTypeMismatch {
+            filename: filename.to_string(),
+            extension: claimed.name,
+            detected: format.name,
+        });
+    }
+
+    if format.name == TEXT.name {
+        let text_type = TEXT_TYPES.iter().find(|(known, _)| *known == extension);
+        return Ok(text_type.map_or(TEXT.mime, |(_, mime)| mime));
+    }
+    Ok(format.mime)
+}
+
+/// Identifies a format by its signature. Returns `Ok(None)` for a ZIP
+/// archive, and what the data looks like if it cannot be attached.
+fn sniff(data: &[u8]) -> std::result::Result<Option<&'static Format>, &'static str> {
+    let format = if data.starts_with(b"%PDF-") {
+        &PDF
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        &PNG
+    } else if data.starts_with(b"\xff\xd8\xff") {
+        &JPEG
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        &GIF
+    } else if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+        &WEBP
+    } else if data.starts_with(b"PK\x03\x04") {
+        return Ok(None);
+    } else if is_text(data) {
+        &TEXT
+    } else if data.starts_with(b"\x7fELF") || data.starts_with(b"MZ") {
+        return Err("an executable");
+    } else if data.starts_with(b"\x1f\x8b") {
+        return Err("a gzip archive");
+    } else {
+        return Err("unrecognized binary data");
+    };
+    Ok(Some(format))
+}
+
+/// Whether `data` starts like UTF-8 text: valid UTF-8, except perhaps for a
+/// character cut off at the end of the inspected bytes, without NUL bytes.
+fn is_text(data: &[u8]) -> bool {
+    let head = &data[..data.len().min(SNIFF_LEN)];
+    let valid = match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none() && head.len() < data.len(),
+    };
+    valid && !head.contains(&0)
+}
+
+fn unsupported(filename: &str, detected: &'static str) -> Error {
+    Error::UnsupportedAttachmentType {
+        filename: filename.to_string(),
+        detected,
+        supported: supported_formats(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_type_is_sniffed() {
+        let file = |name: &str, data: &[u8]| UploadFile::from_bytes(name, data.to_vec());
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+        assert_eq!(content_type(&file("chart.png", png)).unwrap(), "image/png");
+        // The contents decide when the name does not say.
+        assert_eq!(content_type(&file("chart", png)).unwrap(), "image/png");
+        assert_eq!(
+            content_type(&file("scan.JPG", b"\xff\xd8\xff\xe0")).unwrap(),
+            "image/jpeg"
+        );
+        assert_eq!(content_type(&file("main.rs", b"fn main() {}")).unwrap(), "text/plain");
+        assert_eq!(
+            content_type(&UploadFile::from_text("notes.md", "# Notes")).unwrap(),
+            "text/markdown"
+        );
+        assert_eq!(
+            content_type(&file("plan.docx", b"PK\x03\x04\x14\0")).unwrap(),
+            "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+        );
+
+        assert!(matches!(
+            content_type(&file("report.pdf", png)),
+            Err(Error::AttachmentTypeMismatch { extension: "PDF", detected: "PNG", .. })
+        ));
+        let err = content_type(&file("tool.exe", b"MZ\x90\0\x03\0")).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::UnsupportedAttachmentType { detected: "an executable", .. }
+        ));
+        assert!(err.to_string().contains("PDF, PNG, JPEG"));
+        assert!(matches!(
+            content_type(&file("archive.zip", b"PK\x03\x04")),
+            Err(Error::UnsupportedAttachmentType { .. })
+        ));
+
+        // A multi-byte character cut off by the sniffing limit is still text.
+        let mut text = "a".repeat(SNIFF_LEN - 1).into_bytes();
+        text.extend_from_slice("é".as_bytes());
+        assert_eq!(content_type(&file("long.txt", &text)).unwrap(), "text/plain");
+    }
+}
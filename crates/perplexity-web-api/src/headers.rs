@@ -0,0 +1,217 @@
+//! Browser identity headers sent with requests to Perplexity.
+//!
+//! By default the headers come from the client's Chrome emulation. A
+//! [`HeaderStrategy`] can instead pin one user agent or rotate through
+//! several; either way the `sec-ch-ua` client hints are derived from the user
+//! agent so the two never disagree. Browsers that do not send client hints,
+//! such as Firefox and Safari, get none.
+
+use crate::error::{Error, Result};
+use rquest::header::{HeaderMap, HeaderName, HeaderValue, USER_AGENT};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Current desktop Chromium user agents, used by [`HeaderStrategy::rotating`].
+///
+/// All are Chromium-based so that they match the TLS fingerprint of the
+/// client's Chrome emulation.
+pub const BUILTIN_USER_AGENTS: &[&str] = &[
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36",
+    "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36",
+    "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36",
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/130.0.0.0 Safari/537.36",
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36 Edg/131.0.0.0",
+];
+
+/// A user agent and the client hints a browser sending it would add.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrowserProfile {
+    /// The `user-agent` header.
+    pub user_agent: String,
+    /// The `sec-ch-ua` brand list, for Chromium-based browsers.
+    pub sec_ch_ua: Option<String>,
+    /// The `sec-ch-ua-mobile` hint.
+    pub mobile: bool,
+    /// The `sec-ch-ua-platform` hint, e.g. `"Windows"`.
+    pub platform: Option<String>,
+}
+
+impl BrowserProfile {
+    /// Derives the client hints matching `user_agent`.
+    ///
+    /// Chrome and Edge user agents get a brand list with their major version
+    /// and the platform named in the user agent; others get no hints.
+    pub fn from_user_agent(user_agent: impl Into<String>) -> Self {
+        let user_agent = user_agent.into();
+        let chrome = major_version(&user_agent, "Chrome/");
+        let edge = major_version(&user_agent, "Edg/");
+
+        let sec_ch_ua = chrome.map(|chrome| match edge {
+            Some(edge) => format!(
+                "\"Microsoft Edge\";v=\"{}\", \"Chromium\";v=\"{}\", \"Not_A Brand\";v=\"24\"",
+                edge, chrome
+            ),
+            None => format!(
+                "\"Google Chrome\";v=\"{}\", \"Chromium\";v=\"{}\", \"Not_A Brand\";v=\"24\"",
+                chrome, chrome
+            ),
+        });
+        let platform =
+            sec_ch_ua.as_ref().and_then(|_| platform(&user_agent)).map(String::from);
+        let mobile = sec_ch_ua.is_some() && user_agent.contains("Mobile");
+
+        Self { user_agent, sec_ch_ua, mobile, platform }
+    }
+
+    fn headers(&self) -> Result<HeaderMap> {
+        let value = |v: &str| {
+            HeaderValue::from_str(v)
+                .map_err(|_| Error::InvalidUserAgent(self.user_agent.clone()))
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_AGENT, value(&self.user_agent)?);
+        if let Some(sec_ch_ua) = &self.sec_ch_ua {
+            headers.insert(HeaderName::from_static("sec-ch-ua"), value(sec_ch_ua)?);
+            headers.insert(
+                HeaderName::from_static("sec-ch-ua-mobile"),
+                HeaderValue::from_static(if self.mobile { "?1" } else { "?0" }),
+            );
+            if let Some(platform) = &self.platform {
+                headers.insert(
+                    HeaderName::from_static("sec-ch-ua-platform"),
+                    value(&format!("\"{}\"", platform))?,
+                );
+            }
+        }
+        Ok(headers)
+    }
+}
+
+/// How the client presents itself in request headers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum HeaderStrategy {
+    /// Use the headers of the client's browser emulation.
+    #[default]
+    Emulated,
+    /// Send the same profile with every request.
+    Fixed(BrowserProfile),
+    /// Cycle through the profiles, one per request.
+    Rotating(Vec<BrowserProfile>),
+}
+
+impl HeaderStrategy {
+    /// Rotates through [`BUILTIN_USER_AGENTS`].
+    pub fn rotating() -> Self {
+        Self::user_agents(BUILTIN_USER_AGENTS.iter().copied())
+    }
+
+    /// Uses the given user agents: a fixed one if there is exactly one,
+    /// otherwise rotating through them.
+    pub fn user_agents<S: Into<String>>(user_agents: impl IntoIterator<Item = S>) -> Self {
+        let mut profiles: Vec<BrowserProfile> =
+            user_agents.into_iter().map(BrowserProfile::from_user_agent).collect();
+        match profiles.len() {
+            0 => Self::Emulated,
+            1 => Self::Fixed(profiles.remove(0)),
+            _ => Self::Rotating(profiles),
+        }
+    }
+}
+
+/// Header sets prepared from a [`HeaderStrategy`], applied per request.
+pub(crate) struct Headers {
+    sets: Vec<HeaderMap>,
+    next: AtomicUsize,
+}
+
+impl Headers {
+    /// Validates the strategy's header values up front.
+    pub(crate) fn new(strategy: &HeaderStrategy) -> Result<Self> {
+        let sets = match strategy {
+            HeaderStrategy::Emulated => Vec::new(),
+            HeaderStrategy::Fixed(profile) => vec![profile.headers()?],
+            HeaderStrategy::Rotating(profiles) => {
+                profiles.iter().map(BrowserProfile::headers).collect::<Result<_>>()?
+            }
+        };
+        Ok(Self { sets, next: AtomicUsize::new(0) })
+    }
+
+    /// Adds the next header set to `request`.
+    pub(crate) fn apply(&self, request: rquest::RequestBuilder) -> rquest::RequestBuilder {
+        if self.sets.is_empty() {
+            return request;
+        }
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.sets.len();
+        request.headers(self.sets[index].clone())
+    }
+}
+
+fn major_version<'a>(user_agent: &'a str, token: &str) -> Option<&'a str> {
+    let start = user_agent.find(token)? + token.len();
+    let version = &user_agent[start..];
+    let end = version.find(|c: char| !c.is_ascii_digit()).unwrap_or(version.len());
+    (end > 0).then(|| &version[..end])
+}
+
+fn platform(user_agent: &str) -> Option<&'static str> {
+    const PLATFORMS: &[(&str, &str)] = &[
+        ("Android", "Android"),
+        ("CrOS", "Chrome OS"),
+        ("Windows", "Windows"),
+        ("Macintosh", "macOS"),
+        ("Linux", "Linux"),
+    ];
+    PLATFORMS.iter().find(|(token, _)| user_agent.contains(token)).map(|(_, name)| *name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_hints_match_user_agent() {
+        let chrome = BrowserProfile::from_user_agent(BUILTIN_USER_AGENTS[1]);
+        assert_eq!(
+            chrome.sec_ch_ua.as_deref(),
+            Some(
+                "\"Google Chrome\";v=\"131\", \"Chromium\";v=\"131\", \"Not_A Brand\";v=\"24\""
+            )
+        );
+        assert_eq!(chrome.platform.as_deref(), Some("macOS"));
+        assert!(!chrome.mobile);
+
+        let edge = BrowserProfile::from_user_agent(BUILTIN_USER_AGENTS[4]);
+        assert!(edge.sec_ch_ua.unwrap().starts_with("\"Microsoft Edge\";v=\"131\""));
+
+        let android = BrowserProfile::from_user_agent(
+            "Mozilla/5.0 (Linux; Android 14) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Mobile Safari/537.36",
+        );
+        assert_eq!(android.platform.as_deref(), Some("Android"));
+        assert!(android.mobile);
+
+        let firefox = BrowserProfile::from_user_agent(
+            "Mozilla/5.0 (X11; Linux x86_64; rv:136.0) Gecko/20100101 Firefox/136.0",
+        );
+        assert_eq!((firefox.sec_ch_ua, firefox.platform), (None, None));
+    }
+
+    #[test]
+    fn test_strategy_from_user_agents() {
+        assert_eq!(
+            HeaderStrategy::user_agents(Vec::<String>::new()),
+            HeaderStrategy::Emulated
+        );
+        assert!(matches!(HeaderStrategy::user_agents(["a"]), HeaderStrategy::Fixed(_)));
+        assert!(
+            matches!(HeaderStrategy::rotating(), HeaderStrategy::Rotating(p) if p.len() == 5)
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_header_values() {
+        let strategy = HeaderStrategy::user_agents(["bad\nagent"]);
+        assert!(matches!(Headers::new(&strategy), Err(Error::InvalidUserAgent(_))));
+        assert_eq!(Headers::new(&HeaderStrategy::rotating()).unwrap().sets.len(), 5);
+    }
+}
@@ -0,0 +1,81 @@
+//! Heartbeats on quiet event streams.
+//!
+//! Perplexity can go silent for minutes while a Deep Research query runs.
+//! [`Client::search_stream_with_heartbeats`](crate::Client::search_stream_with_heartbeats)
+//! interleaves [`StreamItem::Heartbeat`]s with the events whenever nothing
+//! arrives for an interval, so consumers can tell a query that is still
+//! running from a connection that died and apply their own policies.
+
+use crate::error::Result;
+use crate::types::SearchEvent;
+use futures_util::{Stream, StreamExt};
+use std::time::{Duration, Instant};
+
+/// An item of a stream with heartbeats.
+#[derive(Debug, Clone)]
+pub enum StreamItem {
+    /// An event received from Perplexity.
+    Event(Box<SearchEvent>),
+    /// Nothing was received for at least the heartbeat interval.
+    Heartbeat {
+        /// Time since the last event, or since the stream opened.
+        idle: Duration,
+    },
+}
+
+/// Wraps `events`, yielding a heartbeat after every `interval` without an
+/// event.
+pub(crate) fn with_heartbeats<S>(
+    events: S,
+    interval: Duration,
+) -> impl Stream<Item = Result<StreamItem>>
+where
+    S: Stream<Item = Result<SearchEvent>>,
+{
+    futures_util::stream::unfold(
+        (Box::pin(events), Instant::now()),
+        move |(mut events, last)| async move {
+            match crate::rt::timeout(interval, events.next()).await {
+                Ok(Some(event)) => Some((
+                    event.map(|event| StreamItem::Event(Box::new(event))),
+                    (events, Instant::now()),
+                )),
+                Ok(None) => None,
+                Err(_) => {
+                    Some((Ok(StreamItem::Heartbeat { idle: last.elapsed() }), (events, last)))
+                }
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_heartbeats_while_silent() {
+        let events = futures_util::stream::once(async {
+            tokio::time::sleep(Duration::from_millis(75)).await;
+            Ok(SearchEvent { answer: Some("Done".to_string()), ..SearchEvent::default() })
+        });
+        let items: Vec<StreamItem> = with_heartbeats(events, Duration::from_millis(20))
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        let (last, heartbeats) = items.split_last().unwrap();
+        assert!(
+            matches!(last, StreamItem::Event(event) if event.answer.as_deref() == Some("Done"))
+        );
+        assert!(heartbeats.len() >= 2);
+        let idle: Vec<Duration> = heartbeats
+            .iter()
+            .map(|item| match item {
+                StreamItem::Heartbeat { idle } => *idle,
+                StreamItem::Event(_) => panic!("unexpected event"),
+            })
+            .collect();
+        assert!(idle.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
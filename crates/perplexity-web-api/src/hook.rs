@@ -0,0 +1,97 @@
+//! Hooks adjusting queries before they are sent.
+//!
+//! Perplexity gates some features behind request flags this crate does not
+//! model yet. A hook registered with
+//! [`ClientBuilder::on_request`](crate::ClientBuilder::on_request) sees the
+//! headers and JSON payload of every query just before dispatch and may
+//! change either, so such flags can be sent without waiting for a release.
+
+use rquest::header::HeaderMap;
+use serde_json::{Map, Value};
+use std::fmt;
+use std::sync::Arc;
+
+/// A query about to be sent to Perplexity's ask endpoint.
+#[derive(Debug, Clone)]
+pub struct OutgoingRequest {
+    /// Headers sent on top of the client's browser headers, replacing any
+    /// of the same name. Holds `x-request-id` when the query has one.
+    pub headers: HeaderMap,
+    /// The JSON payload: the query as `query_str` and its options under
+    /// `params`.
+    pub payload: Value,
+}
+
+impl OutgoingRequest {
+    /// Sets the option `name` under the payload's `params`.
+    pub fn set_param(&mut self, name: &str, value: impl Into<Value>) {
+        if !self.payload.is_object() {
+            self.payload = Value::Object(Map::new());
+        }
+        let params = self.payload.as_object_mut().and_then(|payload| {
+            let params = payload.entry("params").or_insert_with(|| Value::Object(Map::new()));
+            if !params.is_object() {
+                *params = Value::Object(Map::new());
+            }
+            params.as_object_mut()
+        });
+        if let Some(params) = params {
+            params.insert(name.to_string(), value.into());
+        }
+    }
+}
+
+/// Callback adjusting an [`OutgoingRequest`].
+#[derive(Clone)]
+pub(crate) struct RequestHook(Arc<dyn Fn(&mut OutgoingRequest) + Send + Sync>);
+
+impl RequestHook {
+    pub(crate) fn new(hook: impl Fn(&mut OutgoingRequest) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(hook))
+    }
+
+    pub(crate) fn call(&self, request: &mut OutgoingRequest) {
+        (self.0)(request);
+    }
+}
+
+impl fmt::Debug for RequestHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RequestHook")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_hooks_set_params_and_headers() {
+        let mut request = OutgoingRequest {
+            headers: HeaderMap::new(),
+            payload: json!({"query_str": "q", "params": {"mode": "concise"}}),
+        };
+        let hooks = [
+            RequestHook::new(|request| request.set_param("use_schematized_api", true)),
+            RequestHook::new(|request| {
+                request.set_param("mode", "copilot");
+                request.headers.insert("x-experiment", "on".parse().unwrap());
+            }),
+        ];
+        hooks.iter().for_each(|hook| hook.call(&mut request));
+
+        assert_eq!(
+            request.payload,
+            json!({
+                "query_str": "q",
+                "params": {"mode": "copilot", "use_schematized_api": true}
+            })
+        );
+        assert_eq!(request.headers["x-experiment"], "on");
+
+        let mut empty = OutgoingRequest { headers: HeaderMap::new(), payload: Value::Null };
+        empty.set_param("flag", 1);
+        assert_eq!(empty.payload, json!({"params": {"flag": 1}}));
+    }
+}
@@ -0,0 +1,273 @@
+//! Hosts the client may contact.
+//!
+//! The client talks to Perplexity and to the storage Perplexity hands out
+//! upload and download URLs for, and nothing else. Those URLs come from
+//! responses, so a [`HostAllowlist`] checks them, and every redirect, before
+//! a request is sent: a tampered or unexpected response cannot point the
+//! client, with its session cookies, at an internal address.
+//!
+//! Applications fetching URLs of their own, such as cited pages, use
+//! [`HostAllowlist::public`] instead, which allows every host except those
+//! on the local network. Host names are checked again once resolved, by
+//! the client's [`HostAllowlist::resolver`], so a public name pointing at
+//! `127.0.0.1` does not get through.
+
+use crate::error::{Error, Result};
+use rquest::Url;
+use rquest::dns::{Addrs, Name, Resolve, Resolving};
+use rquest::redirect::Policy;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+
+/// Hosts allowed by default: Perplexity, and the storage of uploaded files
+/// and of generated images and audio.
+pub const PERPLEXITY_HOSTS: &[&str] = &[
+    "perplexity.ai",
+    "ppl-ai-file-upload.s3.amazonaws.com",
+    "api.cloudinary.com",
+    "pplx-res.cloudinary.com",
+];
+
+/// Redirects followed per request by default.
+pub const DEFAULT_MAX_REDIRECTS: usize = 5;
+
+/// Host names requests may go to.
+///
+/// An entry allows the host and its subdomains, so `perplexity.ai` also
+/// allows `www.perplexity.ai`; `*` allows every host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostAllowlist {
+    hosts: Vec<String>,
+    /// Whether loopback, private and link-local addresses are refused even
+    /// when a host entry matches.
+    public_only: bool,
+}
+
+impl HostAllowlist {
+    /// Allows only [`PERPLEXITY_HOSTS`].
+    pub fn perplexity() -> Self {
+        Self { hosts: Vec::new(), public_only: false }
+            .with_hosts(PERPLEXITY_HOSTS.iter().copied())
+    }
+
+    /// Allows every host on the public internet: not `localhost`, and not
+    /// loopback, private, link-local or cloud metadata addresses such as
+    /// `169.254.169.254`, whether named directly or resolved from a name.
+    pub fn public() -> Self {
+        Self { hosts: vec!["*".to_string()], public_only: true }
+    }
+
+    /// Allows every host, including those on the local network.
+    pub fn any() -> Self {
+        Self { hosts: vec!["*".to_string()], public_only: false }
+    }
+
+    /// Also allows `hosts`. A leading `*.` is the same as none.
+    pub fn with_hosts<I, S>(mut self, hosts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        for host in hosts {
+            let host = host.as_ref().trim().trim_start_matches("*.").trim_matches('.');
+            let host = host.to_ascii_lowercase();
+            if !host.is_empty() && !self.hosts.contains(&host) {
+                self.hosts.push(host);
+            }
+        }
+        self
+    }
+
+    /// Whether requests may go to `host`. Names that only resolve to local
+    /// addresses are refused later, by the [`resolver`](Self::resolver).
+    pub fn allows(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        if self.public_only && is_local_host(&host) {
+            return false;
+        }
+        self.hosts.iter().any(|allowed| {
+            allowed == "*"
+                || host == *allowed
+                || host.strip_suffix(allowed.as_str()).is_some_and(|sub| sub.ends_with('.'))
+        })
+    }
+
+    /// Fails with [`Error::HostNotAllowed`] unless requests may go to `url`.
+    pub fn check(&self, url: &str) -> Result<()> {
+        let parsed = Url::parse(url).ok();
+        match parsed.as_ref().and_then(Url::host_str) {
+            Some(host) if self.allows(host) => Ok(()),
+            Some(host) => Err(Error::HostNotAllowed { host: host.to_string() }),
+            None => Err(Error::HostNotAllowed { host: url.to_string() }),
+        }
+    }
+
+    /// A redirect policy following at most `max_redirects` redirects, and
+    /// only to allowed hosts.
+    pub fn redirect_policy(&self, max_redirects: usize) -> Policy {
+        let hosts = self.clone();
+        Policy::custom(move |attempt| {
+            if attempt.previous().len() > max_redirects {
+                let message = format!("more than {} redirects", max_redirects);
+                return attempt.error(message);
+            }
+            match attempt.url().host_str() {
+                Some(host) if hosts.allows(host) => attempt.follow(),
+                host => {
+                    let host = host.unwrap_or_default().to_string();
+                    attempt.error(Error::HostNotAllowed { host })
+                }
+            }
+        })
+    }
+}
+
+impl HostAllowlist {
+    /// A DNS resolver that, for [`public`](Self::public) allowlists, drops
+    /// the local addresses a name resolves to, and fails if none is left.
+    /// Install it with `ClientBuilder::dns_resolver` on clients checked
+    /// by this allowlist.
+    pub fn resolver(&self) -> Arc<PublicResolver> {
+        Arc::new(PublicResolver { public_only: self.public_only })
+    }
+}
+
+/// Resolver returned by [`HostAllowlist::resolver`].
+#[derive(Debug, Clone, Copy)]
+pub struct PublicResolver {
+    public_only: bool,
+}
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let public_only = self.public_only;
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let resolved: Vec<SocketAddr> =
+                crate::rt::lookup_host((host.as_str(), 0)).await?.collect();
+            let addrs: Vec<SocketAddr> = resolved
+                .into_iter()
+                .filter(|addr| !public_only || is_public_ip(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(Box::new(Error::HostNotAllowed { host }) as _);
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Whether `host`, a lowercase name or address, is on the local network by
+/// its spelling alone.
+fn is_local_host(host: &str) -> bool {
+    if host == "localhost"
+        || host.ends_with(".localhost")
+        || host == "metadata.google.internal"
+    {
+        return true;
+    }
+    let literal = host.trim_start_matches('[').trim_end_matches(']');
+    literal.parse::<IpAddr>().is_ok_and(|ip| !is_public_ip(ip))
+}
+
+/// Whether `ip` is reachable on the public internet: not loopback,
+/// unspecified, private, shared (carrier-grade NAT), link-local, which
+/// includes the `169.254.169.254` metadata service, or broadcast.
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(mapped) => is_public_ipv4(mapped),
+            None => is_public_ipv6(ip),
+        },
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || a == 0
+        || (a == 100 && (64..128).contains(&b)))
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80)
+}
+
+impl Default for HostAllowlist {
+    fn default() -> Self {
+        Self::perplexity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allowlist_matches_hosts_and_subdomains() {
+        let hosts =
+            HostAllowlist::perplexity().with_hosts(["*.Example.com", " hooks.internal "]);
+        assert!(hosts.allows("www.perplexity.ai"));
+        assert!(hosts.allows("perplexity.ai."));
+        assert!(hosts.allows("a.b.example.com"));
+        assert!(hosts.allows("hooks.internal"));
+        assert!(!hosts.allows("notperplexity.ai"));
+        assert!(!hosts.allows("perplexity.ai.evil.test"));
+        assert!(!hosts.allows("169.254.169.254"));
+
+        assert!(hosts.check("https://pplx-res.cloudinary.com/image/upload/x.png").is_ok());
+        assert!(matches!(
+            hosts.check("http://localhost:8080/admin"),
+            Err(Error::HostNotAllowed { host }) if host == "localhost"
+        ));
+        assert!(hosts.check("not a url").is_err());
+        assert!(HostAllowlist::any().allows("localhost"));
+    }
+
+    #[test]
+    fn test_public_allowlist_refuses_local_addresses() {
+        let public = HostAllowlist::public();
+        assert!(public.check("https://example.com/page").is_ok());
+        assert!(public.check("http://8.8.8.8/").is_ok());
+        for url in [
+            "http://127.0.0.1/",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://localhost:8080/",
+            "http://api.localhost/",
+            "http://10.0.0.5/",
+            "http://172.16.0.1/",
+            "http://192.168.1.1/",
+            "http://100.100.100.200/",
+            "http://0.0.0.0/",
+            "http://[::1]/",
+            "http://[fd00:ec2::254]/",
+            "http://[fe80::1]/",
+            "http://[::ffff:127.0.0.1]/",
+            "http://metadata.google.internal/",
+        ] {
+            assert!(public.check(url).is_err(), "{}", url);
+        }
+        assert!(!public.allows("127.0.0.1"));
+        assert!(HostAllowlist::any().check("http://127.0.0.1/").is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_resolver_drops_local_addresses() {
+        let name = |host: &str| host.parse::<Name>().unwrap();
+        let public = HostAllowlist::public().resolver();
+        assert!(public.resolve(name("localhost")).await.is_err());
+
+        let any = HostAllowlist::any().resolver();
+        let addrs: Vec<SocketAddr> = any.resolve(name("localhost")).await.unwrap().collect();
+        assert!(addrs.iter().all(|addr| addr.ip().is_loopback()), "{:?}", addrs);
+    }
+}
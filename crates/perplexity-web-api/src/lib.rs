@@ -49,6 +49,30 @@
 //! # }
 //! ```
 //!
+//! [`SearchStreamExt`] adapts the stream for common uses: only the answer
+//! text, only the final event, at most one event per interval, or the text
+//! each event added:
+//!
+//! ```no_run
+//! use perplexity_web_api::{Client, SearchRequest, SearchStreamExt};
+//! use futures_util::StreamExt;
+//! use std::io::Write;
+//!
+//! # async fn example() -> perplexity_web_api::Result<()> {
+//! # let client = Client::builder().build().await?;
+//! let mut deltas = client
+//!     .search_stream(SearchRequest::new("Explain quantum computing"))
+//!     .await?
+//!     .with_deltas();
+//!
+//! while let Some(delta) = deltas.next().await {
+//!     print!("{}", delta?.delta);
+//!     std::io::stdout().flush().ok();
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
 //! # Authentication
 //!
 //! For enhanced features (pro mode, file uploads), provide your Perplexity cookies:
@@ -70,6 +94,16 @@
 //! # }
 //! ```
 //!
+//! # Runtime
+//!
+//! The client's HTTP stack runs on tokio, so its futures must be polled
+//! within a tokio runtime; [`ClientBuilder::build`] fails with
+//! [`Error::NoRuntime`] outside one. Applications on smol or async-std can
+//! embed the client by wrapping its futures with the `async-compat` crate,
+//! which provides a tokio context on any executor. The streams the client
+//! returns, and the types and parser without the `client` feature, work on
+//! any executor.
+//!
 //! # Search Modes
 //!
 //! - [`SearchMode::Auto`] - Default mode, uses the turbo model
@@ -82,18 +116,129 @@
 //! - [`Source::Web`] - General web search (default)
 //! - [`Source::Scholar`] - Academic papers and research
 //! - [`Source::Social`] - Social media content
+//!
+//! # Recorded Payloads
+//!
+//! [`parse_sse`] and [`parse_sse_event`] turn a saved response body or a
+//! single event's JSON into [`SearchEvent`]s, the same way the client does.
+//! They need no network access, so data pipelines can depend on the crate
+//! with `default-features = false` to get just the types and the parser,
+//! without the HTTP client or tokio. To record streams as they arrive,
+//! register a [`RawEventSink`] with [`ClientBuilder::on_raw_event`]: it sees
+//! every event, including fields the parser drops.
+//!
+//! # API Versions
+//!
+//! Perplexity changes the shape of its payloads between web app versions.
+//! The client speaks [`ApiVersion::current`] unless told otherwise with
+//! [`ClientBuilder::api_version`], and can ask Perplexity which of
+//! [`API_VERSIONS`] it accepts with [`ClientBuilder::detect_api_version`].
+//!
+//! # Normalized Results
+//!
+//! [`WebSearchResponse`] converts a response into a provider-agnostic
+//! answer/sources/images shape, and the [`WebSearch`] trait, implemented by
+//! [`Client`], lets applications swap search providers behind it.
+//!
+//! # Post-processing
+//!
+//! A [`Pipeline`] applies [`Processor`]s, such as citation rewriting,
+//! source deduplication, redaction and truncation, to responses in a chosen
+//! order; [`Client::ask_with_pipeline`] runs a search through one.
+//! [`ContextPack`] packs a response's deduplicated snippets into prompt text
+//! for another model, within a token budget. [`estimate_tokens`]
+//! approximates token counts without a tokenizer vocabulary.
+//!
+//! # Features
+//!
+//! - `client` (default) - The async HTTP client, [`Client`]
+//! - `image` - Resize, re-encode and strip metadata from image attachments
+//!   before upload; see `ClientBuilder::image_preprocessing`
+
+// Request payloads and endpoints are only used by the client.
+#![cfg_attr(not(feature = "client"), allow(dead_code))]
 
+#[cfg(feature = "client")]
 mod client;
+#[cfg(feature = "client")]
+mod combinators;
+mod compat;
 mod config;
+mod context;
+mod dates;
+#[cfg(feature = "client")]
+mod download;
 mod error;
+#[cfg(feature = "client")]
+mod filetype;
+#[cfg(feature = "client")]
+mod headers;
+#[cfg(feature = "client")]
+mod heartbeat;
+#[cfg(feature = "client")]
+mod hook;
+#[cfg(feature = "client")]
+mod hosts;
+mod normalized;
+// The parser and the payload builder handle what Perplexity and callers
+// send; malformed input must become an error, never a panic.
+#[deny(clippy::unwrap_used, clippy::expect_used, clippy::panic, clippy::indexing_slicing)]
 mod parse;
+#[cfg(feature = "client")]
+#[deny(clippy::unwrap_used, clippy::expect_used, clippy::panic, clippy::indexing_slicing)]
+mod payload;
+mod pipeline;
+#[cfg(feature = "image")]
+mod preprocess;
+mod provider;
+mod raw;
+mod refusal;
+#[cfg(feature = "client")]
+mod rt;
+#[deny(clippy::unwrap_used, clippy::expect_used, clippy::panic, clippy::indexing_slicing)]
 mod sse;
+mod tokens;
 mod types;
+#[cfg(feature = "client")]
 mod upload;
 
+#[cfg(feature = "client")]
 pub use client::{Client, ClientBuilder};
-pub use error::{Error, Result};
+#[cfg(feature = "client")]
+pub use combinators::{
+    AnswerDelta, AnswersOnly, FinalOnly, SearchStreamExt, Throttle, WithDeltas,
+};
+pub use compat::{API_VERSIONS, ApiVersion};
+pub use config::API_VERSION;
+pub use context::ContextPack;
+pub use error::{Error, Result, StreamErrorKind};
+#[cfg(feature = "client")]
+pub use headers::{BUILTIN_USER_AGENTS, BrowserProfile, HeaderStrategy};
+#[cfg(feature = "client")]
+pub use heartbeat::StreamItem;
+#[cfg(feature = "client")]
+pub use hook::OutgoingRequest;
+#[cfg(feature = "client")]
+pub use hosts::{
+    DEFAULT_MAX_REDIRECTS, HostAllowlist, PERPLEXITY_HOSTS, PublicResolver, is_public_ip,
+};
+pub use normalized::{WebImage, WebSearch, WebSearchResponse, WebSource};
+pub use parse::parse_sse_event;
+pub use pipeline::{
+    AbsoluteDates, CitationMerger, CitationRewrite, CitationStyle, Dedupe, Pipeline,
+    Processor, Redact, Truncate, TruncateTokens,
+};
+#[cfg(feature = "image")]
+pub use preprocess::{ImageEncoding, ImagePreprocessing};
+pub use provider::{ResearchFuture, ResearchProvider};
+pub use raw::{RawEvent, RawEventSink};
+pub use refusal::refusal_reason;
+pub use sse::parse_sse;
+pub use tokens::estimate_tokens;
 pub use types::{
-    FollowUpContext, Model, SearchEvent, SearchMode, SearchRequest, SearchResponse, Source,
-    UploadFile,
+    AccountSession, AnswerAudio, AnswerStyle, Attachment, ConnectionCheck, Degradation,
+    DegradationKind, FollowUpContext, GeneratedImage, Model, QueryOverflow, Recency,
+    SearchEvent, SearchMetrics, SearchMode, SearchRequest, SearchResponse, SearchWebResult,
+    ShareLink, Source, SpeedTier, ThreadInfo, ThreadSummary, UploadFile, UploadProgress,
+    UploadProgressFn,
 };
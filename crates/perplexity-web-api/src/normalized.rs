@@ -0,0 +1,147 @@
+//! Provider-agnostic search results.
+//!
+//! [`WebSearchResponse`] has the answer/sources/images shape shared by most
+//! search APIs. Applications that code against it and the [`WebSearch`]
+//! trait can swap Perplexity for another provider by implementing the trait
+//! for that provider's client.
+
+use crate::types::{GeneratedImage, SearchEvent, SearchResponse, SearchWebResult};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+
+/// A search result in a provider-agnostic shape.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebSearchResponse {
+    /// The generated answer, if the provider produces one.
+    pub answer: Option<String>,
+    /// Pages the answer is based on, in citation order.
+    pub sources: Vec<WebSource>,
+    /// Images returned with the answer.
+    pub images: Vec<WebImage>,
+}
+
+/// A web page cited by a search result.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebSource {
+    pub title: String,
+    pub url: String,
+    /// Excerpt of the page, if the provider returned one.
+    pub snippet: Option<String>,
+}
+
+/// An image returned by a search.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebImage {
+    pub url: String,
+    /// MIME type of the image, e.g. `"image/png"`.
+    pub mime_type: Option<String>,
+}
+
+/// A search provider returning [`WebSearchResponse`]s.
+pub trait WebSearch {
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Answers `query` with the provider's default settings.
+    fn web_search(
+        &self,
+        query: &str,
+    ) -> impl Future<Output = Result<WebSearchResponse, Self::Error>> + Send;
+}
+
+#[cfg(feature = "client")]
+impl WebSearch for crate::Client {
+    type Error = crate::Error;
+
+    async fn web_search(&self, query: &str) -> crate::Result<WebSearchResponse> {
+        self.search(crate::SearchRequest::new(query)).await.map(WebSearchResponse::from)
+    }
+}
+
+impl From<SearchResponse> for WebSearchResponse {
+    fn from(response: SearchResponse) -> Self {
+        Self::new(response.answer, response.web_results, &response.images)
+    }
+}
+
+impl From<SearchEvent> for WebSearchResponse {
+    fn from(event: SearchEvent) -> Self {
+        Self::new(event.answer, event.web_results, &event.images)
+    }
+}
+
+impl WebSearchResponse {
+    /// Images delivered inline, without a URL, are left out.
+    fn new(
+        answer: Option<String>,
+        web_results: Vec<SearchWebResult>,
+        images: &[GeneratedImage],
+    ) -> Self {
+        let sources = web_results
+            .into_iter()
+            .map(|result| WebSource {
+                title: result.name,
+                url: result.url,
+                snippet: Some(result.snippet).filter(|snippet| !snippet.is_empty()),
+            })
+            .collect();
+        let images = images
+            .iter()
+            .filter_map(|image| {
+                Some(WebImage { url: image.url.clone()?, mime_type: image.mime_type.clone() })
+            })
+            .collect();
+
+        Self { answer, sources, images }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_event() {
+        let event = SearchEvent {
+            answer: Some("Rust is a language [1].".to_string()),
+            web_results: vec![
+                SearchWebResult {
+                    name: "Rust".to_string(),
+                    url: "https://www.rust-lang.org".to_string(),
+                    snippet: "A language empowering everyone".to_string(),
+                    score: None,
+                },
+                SearchWebResult {
+                    name: "Wikipedia".to_string(),
+                    url: "https://en.wikipedia.org/wiki/Rust".to_string(),
+                    snippet: String::new(),
+                    score: None,
+                },
+            ],
+            images: vec![
+                GeneratedImage {
+                    url: Some("https://example.com/a.png".to_string()),
+                    mime_type: Some("image/png".to_string()),
+                    data: None,
+                },
+                GeneratedImage::default(),
+            ],
+            ..SearchEvent::default()
+        };
+
+        let normalized = WebSearchResponse::from(event);
+        assert_eq!(normalized.answer.as_deref(), Some("Rust is a language [1]."));
+        assert_eq!(normalized.sources[0].title, "Rust");
+        assert_eq!(
+            normalized.sources[0].snippet.as_deref(),
+            Some("A language empowering everyone")
+        );
+        assert_eq!(normalized.sources[1].snippet, None);
+        assert_eq!(
+            normalized.images,
+            [WebImage {
+                url: "https://example.com/a.png".to_string(),
+                mime_type: Some("image/png".to_string()),
+            }]
+        );
+    }
+}
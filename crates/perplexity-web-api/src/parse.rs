@@ -1,30 +1,116 @@
-use crate::error::{Error, Result};
-use crate::types::{SearchEvent, SearchWebResult};
+use crate::compat::{AnswerShape, ApiVersion};
+use crate::error::{Error, Result, StreamErrorKind};
+use crate::types::{AnswerAudio, GeneratedImage, SearchEvent, SearchWebResult};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
 use serde_json::{Map, Value};
 use std::collections::HashMap;
 
 /// Keys that are extracted from the raw JSON and stored in dedicated fields.
-const EXTRACTED_KEYS: &[&str] = &["answer", "backend_uuid", "attachments"];
+const EXTRACTED_KEYS: &[&str] =
+    &["answer", "backend_uuid", "attachments", "generated_images", "audio", "audio_url"];
 
-/// Parses an SSE event JSON string into a SearchEvent.
-pub(crate) fn parse_sse_event(json_str: &str) -> Result<SearchEvent> {
+/// Keys holding a web result's relevance score, in order of preference.
+const SCORE_KEYS: &[&str] = &["score", "relevance_score", "relevance"];
+
+/// Keys of a reasoning step's content holding its thought, in order of
+/// preference.
+const REASONING_KEYS: &[&str] = &["thought", "reasoning", "text"];
+
+/// Keys naming the model that served the answer, in order of preference.
+const SERVED_MODEL_KEYS: &[&str] = &["display_model", "model"];
+
+/// Parses the JSON of one event of the search stream, the `data:` line of
+/// an SSE `message` event, into a [`SearchEvent`].
+///
+/// An event reporting an error, such as a rejected query or an overloaded
+/// model, is returned as [`Error::StreamEvent`] with its original message.
+pub fn parse_sse_event(json_str: &str) -> Result<SearchEvent> {
+    parse_sse_event_as(json_str, ApiVersion::current())
+}
+
+/// Like [`parse_sse_event`], for an event in the shape of `version`.
+pub(crate) fn parse_sse_event_as(json_str: &str, version: ApiVersion) -> Result<SearchEvent> {
     let mut content: Map<String, Value> =
         serde_json::from_str(json_str).map_err(Error::Json)?;
 
+    if let Some(error) = extract_error(&content) {
+        return Err(error);
+    }
+
     // Try to parse the "text" field if it contains nested JSON
     parse_nested_text_field(&mut content);
 
-    // Extract answer and web_results from the FINAL step or fall back to top-level
-    let (answer, web_results) = extract_answer_and_web_results(&content);
+    let (answer, web_results) = extract_answer_and_web_results(&content, version.answer);
 
     // Extract other known fields
     let backend_uuid = extract_string(&content, "backend_uuid");
     let attachments = extract_string_array(&content, "attachments");
+    let images = extract_images(&content);
+    let audio = extract_audio(&content);
+    let search_queries = extract_search_queries(&content);
+    let served_model = SERVED_MODEL_KEYS.iter().find_map(|key| extract_string(&content, key));
+    let reasoning = extract_reasoning(&content);
 
     // Build raw map excluding extracted keys
     let raw = build_raw_map(content);
 
-    Ok(SearchEvent { answer, web_results, backend_uuid, attachments, raw })
+    Ok(SearchEvent {
+        answer,
+        web_results,
+        backend_uuid,
+        attachments,
+        images,
+        audio,
+        search_queries,
+        served_model,
+        reasoning,
+        raw,
+    })
+}
+
+/// Recognizes an error payload: an "error" message or object, an
+/// "error_code", or a "failed" status.
+fn extract_error(content: &Map<String, Value>) -> Option<Error> {
+    let (code, message) = match content.get("error") {
+        Some(Value::String(message)) if !message.is_empty() => {
+            (content.get("error_code").and_then(scalar_string), message.clone())
+        }
+        Some(Value::Object(error)) => {
+            let code = error.get("code").or_else(|| error.get("type")).and_then(scalar_string);
+            let message = error
+                .get("message")
+                .and_then(scalar_string)
+                .unwrap_or_else(|| Value::Object(error.clone()).to_string());
+            (code, message)
+        }
+        _ => {
+            let code = content.get("error_code").and_then(scalar_string);
+            let failed = content.get("status").and_then(Value::as_str).is_some_and(|status| {
+                status.eq_ignore_ascii_case("failed") || status.eq_ignore_ascii_case("error")
+            });
+            if code.is_none() && !failed {
+                return None;
+            }
+            let message = ["error_message", "message", "text"]
+                .iter()
+                .find_map(|key| content.get(*key).and_then(scalar_string))
+                .or_else(|| code.clone())
+                .unwrap_or_else(|| "unknown error".to_string());
+            (code, message)
+        }
+    };
+    let kind = StreamErrorKind::classify(code.as_deref(), &message);
+    Some(Error::StreamEvent { kind, code, message })
+}
+
+/// A non-empty string or a number as a string.
+fn scalar_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) if !s.is_empty() => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
 }
 
 /// If the "text" field is a JSON string, parse it and replace the field with the parsed value.
@@ -44,11 +130,25 @@ fn parse_nested_text_field(content: &mut Map<String, Value>) {
 
 /// Extracts answer and web_results from the event content.
 ///
-/// First tries to find them in a FINAL step within the "text" field,
-/// then falls back to top-level "answer" field with empty web_results.
+/// First tries where `shape` says they are, then the other place: a FINAL
+/// step within the "text" field, or the top-level "answer" and
+/// "web_results" fields. Without a FINAL step, web_results are only read at
+/// the top level for [`AnswerShape::TopLevel`].
 fn extract_answer_and_web_results(
     content: &Map<String, Value>,
+    shape: AnswerShape,
 ) -> (Option<String>, Vec<SearchWebResult>) {
+    if shape == AnswerShape::TopLevel
+        && let Some(answer) = extract_string(content, "answer")
+    {
+        let web_results = content
+            .get("web_results")
+            .and_then(Value::as_array)
+            .map(|results| results.iter().filter_map(extract_web_result).collect())
+            .unwrap_or_default();
+        return (Some(answer), web_results);
+    }
+
     // Try to extract from FINAL step in text field
     if let Some((answer, web_results)) = extract_from_final_step(content) {
         return (answer, web_results);
@@ -89,11 +189,131 @@ fn extract_from_final_step(
     Some((answer, web_results))
 }
 
+/// Extracts the queries run by the search steps in the "text" field, in
+/// order and without repeats.
+///
+/// A step's queries are its `content.queries`, given as strings or objects
+/// with a `query`, or a single `content.query`.
+fn extract_search_queries(content: &Map<String, Value>) -> Vec<String> {
+    let steps = content.get("text").and_then(Value::as_array).map(Vec::as_slice);
+    let mut queries: Vec<String> = Vec::new();
+
+    for step in steps.unwrap_or_default() {
+        let is_search = step
+            .get("step_type")
+            .and_then(Value::as_str)
+            .is_some_and(|step_type| step_type.starts_with("SEARCH"));
+        let Some(step_content) = step.get("content").filter(|_| is_search) else {
+            continue;
+        };
+
+        let listed = step_content.get("queries").and_then(Value::as_array);
+        let single = step_content.get("query").map(std::slice::from_ref);
+        for query in listed.map(Vec::as_slice).or(single).unwrap_or_default() {
+            let query = query.as_str().or_else(|| query.get("query").and_then(Value::as_str));
+            if let Some(query) = query.map(str::trim).filter(|q| !q.is_empty())
+                && !queries.iter().any(|seen| seen == query)
+            {
+                queries.push(query.to_string());
+            }
+        }
+    }
+    queries
+}
+
+/// Joins the thoughts of the reasoning steps in the "text" field, in
+/// order and without repeats.
+///
+/// A step's thought is its `content.thought`, `content.reasoning` or
+/// `content.text`.
+fn extract_reasoning(content: &Map<String, Value>) -> Option<String> {
+    let steps = content.get("text").and_then(Value::as_array)?;
+    let mut thoughts: Vec<&str> = Vec::new();
+
+    for step in steps {
+        if step.get("step_type").and_then(Value::as_str) != Some("REASONING") {
+            continue;
+        }
+        let thought = REASONING_KEYS
+            .iter()
+            .find_map(|key| step.get("content")?.get(*key)?.as_str())
+            .map(str::trim)
+            .filter(|thought| !thought.is_empty());
+        if let Some(thought) = thought
+            && !thoughts.contains(&thought)
+        {
+            thoughts.push(thought);
+        }
+    }
+    (!thoughts.is_empty()).then(|| thoughts.join("\n\n"))
+}
+
 fn extract_web_result(value: &Value) -> Option<SearchWebResult> {
     let name = value.get("name").and_then(|v| v.as_str()).map(|s| s.to_string())?;
     let url = value.get("url").and_then(|v| v.as_str()).map(|s| s.to_string())?;
     let snippet = value.get("snippet").and_then(|v| v.as_str()).map(|s| s.to_string())?;
-    Some(SearchWebResult { name, url, snippet })
+    let score = SCORE_KEYS.iter().find_map(|key| value.get(*key).and_then(Value::as_f64));
+    Some(SearchWebResult { name, url, snippet, score })
+}
+
+/// Extracts generated images from the "generated_images" field.
+///
+/// Entries are either URL strings or objects with a `url` and optional
+/// `mime_type`. `data:` URLs and base64 `data` fields are decoded inline,
+/// and entries whose data does not decode are skipped.
+fn extract_images(content: &Map<String, Value>) -> Vec<GeneratedImage> {
+    content
+        .get("generated_images")
+        .and_then(|v| v.as_array())
+        .map(|arr| arr.iter().filter_map(extract_image).collect())
+        .unwrap_or_default()
+}
+
+fn extract_image(value: &Value) -> Option<GeneratedImage> {
+    let url = value.as_str().or_else(|| value.get("url").and_then(|v| v.as_str()));
+    let mime_type = value.get("mime_type").and_then(|v| v.as_str()).map(|s| s.to_string());
+
+    if let Some(data) = value.get("data").and_then(|v| v.as_str()) {
+        let data = BASE64.decode(data).ok()?;
+        return Some(GeneratedImage {
+            url: url.map(|s| s.to_string()),
+            mime_type,
+            data: Some(data.into()),
+        });
+    }
+
+    let url = url?;
+    if url.starts_with("data:") {
+        let (mime, data) = parse_data_url(url)?;
+        return Some(GeneratedImage {
+            url: None,
+            mime_type: Some(mime),
+            data: Some(data.into()),
+        });
+    }
+
+    Some(GeneratedImage { url: Some(url.to_string()), mime_type, data: None })
+}
+
+/// Extracts the text-to-speech asset from an "audio" object (`url`,
+/// `mime_type`) or a bare "audio_url" string.
+fn extract_audio(content: &Map<String, Value>) -> Option<AnswerAudio> {
+    if let Some(audio) = content.get("audio")
+        && let Some(url) = audio.get("url").and_then(|v| v.as_str())
+    {
+        let mime_type = audio.get("mime_type").and_then(|v| v.as_str()).map(|s| s.to_string());
+        return Some(AnswerAudio { url: url.to_string(), mime_type });
+    }
+
+    let url = extract_string(content, "audio_url")?;
+    Some(AnswerAudio { url, mime_type: None })
+}
+
+/// Decodes a base64 `data:<mime>;base64,<data>` URL.
+fn parse_data_url(url: &str) -> Option<(String, Vec<u8>)> {
+    let rest = url.strip_prefix("data:")?;
+    let (mime, data) = rest.split_once(";base64,")?;
+    Some((mime.to_string(), BASE64.decode(data).ok()?))
 }
 
 /// Extracts a string value from the content map.
@@ -147,6 +367,41 @@ mod tests {
         assert_eq!(event.attachments, vec!["url1", "url2"]);
     }
 
+    #[test]
+    fn test_parse_event_with_generated_images() {
+        let json = r#"{"generated_images": [
+            "https://example.com/cat.png",
+            {"url": "https://example.com/dog.webp", "mime_type": "image/webp"},
+            {"data": "iVBORw==", "mime_type": "image/png"},
+            "data:image/jpeg;base64,/9j/"
+        ]}"#;
+        let event = parse_sse_event(json).unwrap();
+
+        assert_eq!(event.images.len(), 4);
+        assert_eq!(event.images[0].url.as_deref(), Some("https://example.com/cat.png"));
+        assert_eq!(event.images[1].mime_type.as_deref(), Some("image/webp"));
+        assert_eq!(event.images[2].data.as_deref(), Some(&[0x89, b'P', b'N', b'G'][..]));
+        assert_eq!(event.images[3].mime_type.as_deref(), Some("image/jpeg"));
+        assert_eq!(event.images[3].data.as_deref(), Some(&[0xff, 0xd8, 0xff][..]));
+        assert!(!event.raw.contains_key("generated_images"));
+    }
+
+    #[test]
+    fn test_parse_event_with_audio() {
+        let json =
+            r#"{"audio": {"url": "https://example.com/a.mp3", "mime_type": "audio/mpeg"}}"#;
+        let audio = parse_sse_event(json).unwrap().audio.unwrap();
+        assert_eq!(audio.url, "https://example.com/a.mp3");
+        assert_eq!(audio.mime_type.as_deref(), Some("audio/mpeg"));
+
+        let json = r#"{"audio_url": "https://example.com/b.mp3"}"#;
+        let event = parse_sse_event(json).unwrap();
+        assert_eq!(event.audio.unwrap().url, "https://example.com/b.mp3");
+        assert!(!event.raw.contains_key("audio_url"));
+
+        assert!(parse_sse_event(r#"{"answer": "x"}"#).unwrap().audio.is_none());
+    }
+
     #[test]
     fn test_parse_event_with_nested_text_json() {
         // Simulates the "text" field containing JSON string with steps
@@ -182,6 +437,26 @@ mod tests {
         assert!(event.raw.contains_key("some_field"));
     }
 
+    #[test]
+    fn test_parse_event_with_reasoning_steps() {
+        let steps = serde_json::json!([
+            {"step_type": "INITIAL_QUERY", "content": {"query": "q"}},
+            {"step_type": "REASONING", "content": {"thought": " Check small divisors. "}},
+            {"step_type": "SEARCH_WEB", "content": {"thought": "not a reasoning step"}},
+            {"step_type": "REASONING", "content": {"reasoning": "None divide it."}},
+            {"step_type": "REASONING", "content": {"thought": "Check small divisors."}},
+            {"step_type": "REASONING", "content": {"text": ""}}
+        ]);
+        let json = serde_json::json!({"text": steps.to_string()});
+        let event = parse_sse_event(&json.to_string()).unwrap();
+
+        assert_eq!(
+            event.reasoning.as_deref(),
+            Some("Check small divisors.\n\nNone divide it.")
+        );
+        assert!(parse_sse_event(r#"{"answer": "x"}"#).unwrap().reasoning.is_none());
+    }
+
     #[test]
     fn test_parse_event_fallback_to_top_level() {
         // When text doesn't contain FINAL step, fall back to top-level
@@ -236,9 +511,172 @@ mod tests {
         assert!(event.attachments.is_empty());
     }
 
+    #[test]
+    fn test_parse_web_result_scores_keep_order() {
+        let answer = serde_json::json!({
+            "answer": "Answer [1][2][3]",
+            "web_results": [
+                { "name": "B", "url": "https://b.example", "snippet": "", "score": 0.42 },
+                { "name": "A", "url": "https://a.example", "snippet": "", "relevance_score": 0.9 },
+                { "name": "C", "url": "https://c.example", "snippet": "" }
+            ]
+        });
+        let steps = serde_json::json!([
+            { "step_type": "FINAL", "content": { "answer": answer.to_string() } }
+        ]);
+        let json = serde_json::json!({ "text": steps.to_string() });
+        let event = parse_sse_event(&json.to_string()).unwrap();
+
+        let results: Vec<_> =
+            event.web_results.iter().map(|r| (r.name.as_str(), r.score)).collect();
+        assert_eq!(results, [("B", Some(0.42)), ("A", Some(0.9)), ("C", None)]);
+    }
+
+    #[test]
+    fn test_parse_event_provenance() {
+        let steps = serde_json::json!([
+            { "step_type": "INITIAL_QUERY", "content": { "query": "rust release" } },
+            {
+                "step_type": "SEARCH_WEB",
+                "content": { "queries": [
+                    { "engine": "web", "query": "rust latest release" },
+                    "rust 1.81 changelog"
+                ] }
+            },
+            { "step_type": "SEARCH_WEB", "content": { "query": "rust latest release" } },
+            { "step_type": "FINAL", "content": { "answer": "{\"answer\": \"1.81\"}" } }
+        ]);
+        let json = serde_json::json!({ "text": steps.to_string(), "display_model": "sonar" });
+        let event = parse_sse_event(&json.to_string()).unwrap();
+
+        assert_eq!(event.search_queries, ["rust latest release", "rust 1.81 changelog"]);
+        assert_eq!(event.served_model.as_deref(), Some("sonar"));
+        assert_eq!(event.answer.as_deref(), Some("1.81"));
+
+        let event = parse_sse_event(r#"{"answer": "x"}"#).unwrap();
+        assert!(event.search_queries.is_empty());
+        assert!(event.served_model.is_none());
+    }
+
+    #[test]
+    fn test_parse_error_events() {
+        let error = |json: &str| match parse_sse_event(json) {
+            Err(Error::StreamEvent { kind, code, message }) => (kind, code, message),
+            other => panic!("expected an error event, got {:?}", other),
+        };
+
+        assert_eq!(
+            error(r#"{"error": "Query rejected by content filter"}"#),
+            (StreamErrorKind::Rejected, None, "Query rejected by content filter".to_string())
+        );
+        assert_eq!(
+            error(r#"{"error": {"code": "model_overloaded", "message": "Try later"}}"#),
+            (
+                StreamErrorKind::Overloaded,
+                Some("model_overloaded".to_string()),
+                "Try later".to_string()
+            )
+        );
+        assert_eq!(
+            error(r#"{"status": "failed", "error_code": 429, "text": "Slow down"}"#),
+            (StreamErrorKind::Other, Some("429".to_string()), "Slow down".to_string())
+        );
+
+        let event =
+            parse_sse_event(r#"{"answer": "Hi", "status": "COMPLETED", "error": null}"#);
+        assert_eq!(event.unwrap().answer.as_deref(), Some("Hi"));
+    }
+
     #[test]
     fn test_parse_invalid_json() {
         let result = parse_sse_event("not json");
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_parse_rejects_malformed_events() {
+        for json in ["", "[]", "null", "\"answer\"", "{\"answer\": \"cut", "{\"a\": 1}}"] {
+            assert!(parse_sse_event(json).is_err(), "{json:?} was accepted");
+        }
+    }
+
+    #[test]
+    fn test_parse_malformed_fields() {
+        // Every field of the wrong type, or with malformed contents, is
+        // skipped rather than failing the event.
+        let json = serde_json::json!({
+            "answer": 42,
+            "backend_uuid": ["not", "a", "string"],
+            "attachments": ["https://a.example", 1, null, {"url": "x"}],
+            "generated_images": [
+                7,
+                {"data": "not base64!"},
+                "data:image/png;base64,%%%",
+                "data:image/png",
+                {"mime_type": "image/png"},
+                "https://img.example/ok.png"
+            ],
+            "audio": {"url": 3},
+            "audio_url": false,
+            "display_model": {},
+            "error": "",
+            "status": 5,
+            "text": [
+                null,
+                {"step_type": 1},
+                {"step_type": "SEARCH_WEB", "content": {"queries": [1, {"query": 2}, "  "]}},
+                {"step_type": "SEARCH_WEB", "content": "flat"},
+                {"step_type": "REASONING", "content": {"thought": ["x"]}},
+                {"step_type": "REASONING"},
+                {"step_type": "FINAL", "content": {"answer": "{not json"}}
+            ]
+        });
+        let event = parse_sse_event(&json.to_string()).unwrap();
+        assert_eq!(event.answer, None);
+        assert_eq!(event.backend_uuid, None);
+        assert_eq!(event.attachments, ["https://a.example"]);
+        assert_eq!(event.images.len(), 1);
+        assert_eq!(event.images[0].url.as_deref(), Some("https://img.example/ok.png"));
+        assert!(event.audio.is_none());
+        assert!(event.served_model.is_none());
+        assert!(event.search_queries.is_empty());
+        assert!(event.reasoning.is_none());
+
+        // A FINAL step whose answer is JSON of the wrong shape.
+        let answer =
+            serde_json::json!({"answer": ["x"], "web_results": [{}, {"name": "n"}, 3]});
+        let json = serde_json::json!({
+            "text": [{"step_type": "FINAL", "content": {"answer": answer.to_string()}}]
+        });
+        let event = parse_sse_event(&json.to_string()).unwrap();
+        assert_eq!(event.answer, None);
+        assert!(event.web_results.is_empty());
+
+        // A "text" field holding non-JSON or an unexpected JSON value.
+        for text in [
+            serde_json::json!("{\"unclosed"),
+            serde_json::json!("12"),
+            serde_json::json!({"step_type": "FINAL"}),
+        ] {
+            let event =
+                parse_sse_event(&serde_json::json!({"text": text}).to_string()).unwrap();
+            assert!(event.answer.is_none() && event.web_results.is_empty());
+        }
+
+        // Error payloads with unexpected types still end up as errors.
+        let error = parse_sse_event(r#"{"error": {"code": [1], "message": {}}}"#).unwrap_err();
+        assert!(matches!(error, Error::StreamEvent { code: None, .. }));
+    }
+
+    #[test]
+    fn test_parse_event_of_older_api_version() {
+        let json = r#"{"answer": "Old shape", "web_results": [{"name": "Docs", "url": "https://docs.example", "snippet": "s"}]}"#;
+        let older = "2.16".parse().unwrap();
+        let event = parse_sse_event_as(json, older).unwrap();
+        assert_eq!(event.answer.as_deref(), Some("Old shape"));
+        assert_eq!(event.web_results[0].url, "https://docs.example");
+
+        // The current version reads only the answer at the top level.
+        assert!(parse_sse_event(json).unwrap().web_results.is_empty());
+    }
 }
@@ -0,0 +1,337 @@
+//! The JSON payload of the ask endpoint.
+//!
+//! [`AskPayload::builder`] turns a [`SearchRequest`] into the payload the web
+//! app sends, checking up front the options Perplexity would otherwise
+//! reject with an unhelpful error or silently ignore. The payload takes the
+//! shape of the [`ApiVersion`] the client speaks.
+
+use crate::compat::{ApiVersion, SourcesShape};
+use crate::config::model_preference;
+use crate::error::{Error, Result};
+use crate::types::{Attachment, Recency, SearchMode, SearchRequest, Source};
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize)]
+pub(crate) struct AskPayload<'a> {
+    pub query_str: &'a str,
+    pub params: AskParams<'a>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct AskParams<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub answer_style: Option<&'static str>,
+    pub attachments: Vec<String>,
+    pub frontend_context_uuid: String,
+    pub frontend_uuid: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub image_generation: bool,
+    pub is_incognito: bool,
+    pub language: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_backend_uuid: Option<&'a str>,
+    pub mode: &'static str,
+    pub model_preference: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_focus: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_recency_filter: Option<&'static str>,
+    pub source: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sources: Option<Vec<&'static str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<&'a str>,
+    pub version: &'static str,
+}
+
+impl<'a> AskPayload<'a> {
+    /// Starts building the payload for `request`.
+    pub fn builder(request: &'a SearchRequest) -> AskPayloadBuilder<'a> {
+        AskPayloadBuilder {
+            request,
+            uploads: &[],
+            api_version: ApiVersion::current(),
+            frontend_uuid: Uuid::new_v4().to_string(),
+            frontend_context_uuid: Uuid::new_v4().to_string(),
+        }
+    }
+}
+
+/// Builder for an [`AskPayload`].
+pub(crate) struct AskPayloadBuilder<'a> {
+    request: &'a SearchRequest,
+    uploads: &'a [Attachment],
+    api_version: ApiVersion,
+    frontend_uuid: String,
+    frontend_context_uuid: String,
+}
+
+impl<'a> AskPayloadBuilder<'a> {
+    /// Attaches files uploaded for this query, after the request's own
+    /// attachments.
+    pub fn uploads(mut self, uploads: &'a [Attachment]) -> Self {
+        self.uploads = uploads;
+        self
+    }
+
+    /// Builds the payload for `version`, by default [`ApiVersion::current`].
+    pub fn api_version(mut self, version: ApiVersion) -> Self {
+        self.api_version = version;
+        self
+    }
+
+    /// Replaces the random ids the web app generates per query.
+    #[cfg(test)]
+    fn frontend_uuids(mut self, frontend_uuid: &str, frontend_context_uuid: &str) -> Self {
+        self.frontend_uuid = frontend_uuid.to_string();
+        self.frontend_context_uuid = frontend_context_uuid.to_string();
+        self
+    }
+
+    /// Validates the request and builds the payload.
+    ///
+    /// Fails with [`Error::InvalidModelForMode`] if the model is not
+    /// available in the mode, and with [`Error::InvalidParameter`] if no
+    /// source is selected, more than one is for an API version that searches
+    /// one at a time, or the language is not a language tag. Repeated
+    /// sources are sent once.
+    pub fn build(self) -> Result<AskPayload<'a>> {
+        let request = self.request;
+        let model_preference =
+            model_preference(request.mode, request.model).ok_or_else(|| {
+                Error::InvalidModelForMode {
+                    model: request.model.map_or("default", |m| m.as_str()).to_string(),
+                    mode: request.mode.to_string(),
+                }
+            })?;
+
+        let mut sources: Vec<Source> = Vec::with_capacity(request.sources.len());
+        for source in &request.sources {
+            if !sources.contains(source) {
+                sources.push(*source);
+            }
+        }
+        if sources.is_empty() {
+            return Err(Error::InvalidParameter {
+                name: "sources",
+                reason: "at least one source is required".to_string(),
+            });
+        }
+        let version = self.api_version;
+        let (sources, search_focus) = match (version.sources, sources.as_slice()) {
+            (SourcesShape::List, _) => {
+                (Some(sources.iter().map(Source::as_str).collect()), None)
+            }
+            (SourcesShape::Focus, [source]) => (None, Some(search_focus(*source))),
+            (SourcesShape::Focus, _) => {
+                return Err(Error::InvalidParameter {
+                    name: "sources",
+                    reason: format!("API version {} searches one source at a time", version),
+                });
+            }
+        };
+
+        if !is_language_tag(&request.language) {
+            return Err(Error::InvalidParameter {
+                name: "language",
+                reason: format!("'{}' is not a language tag such as en-US", request.language),
+            });
+        }
+
+        let follow_up = request.follow_up.as_ref();
+        let attachments = request
+            .attachments
+            .iter()
+            .chain(self.uploads)
+            .map(|attachment| attachment.url.clone())
+            .chain(follow_up.into_iter().flat_map(|f| f.attachments.iter().cloned()))
+            .collect();
+
+        Ok(AskPayload {
+            query_str: &request.query,
+            params: AskParams {
+                answer_style: request.answer_style.map(|style| style.as_str()),
+                attachments,
+                frontend_context_uuid: self.frontend_context_uuid,
+                frontend_uuid: self.frontend_uuid,
+                image_generation: request.generate_image,
+                is_incognito: request.incognito,
+                language: &request.language,
+                last_backend_uuid: follow_up.and_then(|f| f.backend_uuid.as_deref()),
+                mode: match request.mode {
+                    SearchMode::Auto => "concise",
+                    _ => "copilot",
+                },
+                model_preference,
+                search_focus,
+                search_recency_filter: request.recency.map(Recency::api_value),
+                source: "default",
+                sources,
+                timezone: request.timezone.as_deref(),
+                version: version.as_str(),
+            },
+        })
+    }
+}
+
+/// The `search_focus` of `source`, for API versions that search one source
+/// at a time.
+fn search_focus(source: Source) -> &'static str {
+    match source {
+        Source::Web => "internet",
+        Source::Scholar => "scholar",
+        Source::Social => "social",
+    }
+}
+
+/// Whether `tag` is shaped like a BCP 47 language tag: a two or three
+/// letter language, then alphanumeric subtags of up to eight characters.
+fn is_language_tag(tag: &str) -> bool {
+    let mut subtags = tag.split('-');
+    let language = subtags.next().unwrap_or_default();
+    (2..=3).contains(&language.len())
+        && language.bytes().all(|b| b.is_ascii_alphabetic())
+        && subtags.all(|subtag| {
+            (1..=8).contains(&subtag.len())
+                && subtag.bytes().all(|b| b.is_ascii_alphanumeric())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::API_VERSION;
+    use crate::types::{AnswerStyle, FollowUpContext, Model};
+    use serde_json::json;
+
+    const FRONTEND_UUID: &str = "6f1c2f8e-3b9a-4c51-9d2e-8a7b6c5d4e3f";
+    const CONTEXT_UUID: &str = "0b9e8d7c-6a5f-4e3d-8c2b-1a0f9e8d7c6b";
+
+    fn payload(request: &SearchRequest) -> Result<serde_json::Value> {
+        let payload = AskPayload::builder(request)
+            .frontend_uuids(FRONTEND_UUID, CONTEXT_UUID)
+            .build()?;
+        Ok(serde_json::to_value(payload)?)
+    }
+
+    #[test]
+    fn test_payload_matches_web_app() {
+        assert_eq!(
+            payload(&SearchRequest::new("What is Rust?")).unwrap(),
+            json!({
+                "query_str": "What is Rust?",
+                "params": {
+                    "attachments": [],
+                    "frontend_context_uuid": CONTEXT_UUID,
+                    "frontend_uuid": FRONTEND_UUID,
+                    "is_incognito": false,
+                    "language": "en-US",
+                    "mode": "concise",
+                    "model_preference": "turbo",
+                    "source": "default",
+                    "sources": ["web"],
+                    "version": API_VERSION
+                }
+            })
+        );
+
+        let follow_up = FollowUpContext {
+            backend_uuid: Some("b4c7e1d2-0000-4000-8000-000000000001".to_string()),
+            attachments: vec!["https://files.example/previous.pdf".to_string()],
+        };
+        let request = SearchRequest::new("Compare it with Go")
+            .mode(SearchMode::Pro)
+            .model(Model::Sonar)
+            .sources(vec![Source::Web, Source::Scholar, Source::Web])
+            .language("de-DE")
+            .timezone("Europe/Berlin")
+            .incognito(true)
+            .answer_style(AnswerStyle::Concise)
+            .recency(Recency::Week)
+            .follow_up(follow_up);
+        let uploads = [Attachment {
+            uuid: "f00d".to_string(),
+            url: "https://files.example/notes.md".to_string(),
+            filename: "notes.md".to_string(),
+            mime_type: "text/markdown".to_string(),
+            size: 12,
+        }];
+        let built = AskPayload::builder(&request)
+            .uploads(&uploads)
+            .frontend_uuids(FRONTEND_UUID, CONTEXT_UUID)
+            .build()
+            .unwrap();
+        assert_eq!(
+            serde_json::to_value(built).unwrap(),
+            json!({
+                "query_str": "Compare it with Go",
+                "params": {
+                    "answer_style": AnswerStyle::Concise.as_str(),
+                    "attachments": [
+                        "https://files.example/notes.md",
+                        "https://files.example/previous.pdf"
+                    ],
+                    "frontend_context_uuid": CONTEXT_UUID,
+                    "frontend_uuid": FRONTEND_UUID,
+                    "is_incognito": true,
+                    "language": "de-DE",
+                    "last_backend_uuid": "b4c7e1d2-0000-4000-8000-000000000001",
+                    "mode": "copilot",
+                    "model_preference": "experimental",
+                    "search_recency_filter": "WEEK",
+                    "source": "default",
+                    "sources": ["web", "scholar"],
+                    "timezone": "Europe/Berlin",
+                    "version": API_VERSION
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_builder_validation() {
+        let invalid_model = SearchRequest::new("q").model(Model::Gpt52);
+        assert!(matches!(payload(&invalid_model), Err(Error::InvalidModelForMode { .. })));
+
+        let no_sources = SearchRequest::new("q").sources(Vec::new());
+        assert!(matches!(
+            payload(&no_sources),
+            Err(Error::InvalidParameter { name: "sources", .. })
+        ));
+
+        for language in
+            ["", "-", "english", "en_US", "en-", "en--US", "en-US-toolongsubtag", "fé-FR"]
+        {
+            let result = payload(&SearchRequest::new("q").language(language));
+            assert!(
+                matches!(result, Err(Error::InvalidParameter { name: "language", .. })),
+                "{language:?} was accepted"
+            );
+        }
+        for language in ["en", "pt-BR", "zh-Hant-TW", "es-419"] {
+            assert!(payload(&SearchRequest::new("q").language(language)).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_payload_for_older_api_version() {
+        let version: ApiVersion = "2.13".parse().unwrap();
+        let request = SearchRequest::new("q").sources(vec![Source::Scholar]);
+        let built = AskPayload::builder(&request)
+            .api_version(version)
+            .frontend_uuids(FRONTEND_UUID, CONTEXT_UUID)
+            .build()
+            .unwrap();
+        let params = &serde_json::to_value(built).unwrap()["params"];
+        assert_eq!(params["search_focus"], "scholar");
+        assert_eq!(params["version"], "2.13");
+        assert!(params.get("sources").is_none());
+
+        let request = SearchRequest::new("q").sources(vec![Source::Web, Source::Social]);
+        assert!(matches!(
+            AskPayload::builder(&request).api_version(version).build(),
+            Err(Error::InvalidParameter { name: "sources", .. })
+        ));
+    }
+}
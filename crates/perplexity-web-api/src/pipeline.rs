@@ -0,0 +1,677 @@
+//! Post-processing of search responses.
+//!
+//! A [`Pipeline`] runs [`Processor`]s over a [`SearchResponse`] in the order
+//! they were added. The built-in processors rewrite citation markers,
+//! drop duplicate sources, redact text, truncate long answers and date
+//! relative dates; custom ones implement [`Processor`].
+//!
+//! Pipelines can also be parsed from a comma-separated list of steps, for
+//! configuration files and environment variables:
+//!
+//! ```
+//! use perplexity_web_api::Pipeline;
+//!
+//! let pipeline: Pipeline = "dedupe, citations:links, redact:ACME|Project X, truncate:4000"
+//!     .parse()
+//!     .unwrap();
+//! assert_eq!(pipeline.len(), 4);
+//! ```
+//!
+//! | Step | Effect |
+//! |------|--------|
+//! | `dedupe` | [`Dedupe`] |
+//! | `citations:links`, `citations:strip` | [`CitationRewrite`] |
+//! | `redact:<term>\|<term>…` | [`Redact::terms`] |
+//! | `redact-snippets` | [`Redact::snippets`] |
+//! | `truncate:<chars>` | [`Truncate`] |
+//! | `truncate-tokens:<tokens>` | [`TruncateTokens`] |
+//! | `dates`, `dates:<utc offset>` | [`AbsoluteDates`] |
+//!
+//! [`Pipeline::parse_with`] accepts further steps defined by the application.
+
+use crate::dates::{epoch_day, parse_utc_offset, resolve_relative_dates};
+use crate::error::{Error, Result};
+use crate::tokens::{estimate_tokens, token_limit};
+use crate::types::{SearchResponse, SearchWebResult};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+/// Text that replaces redacted terms.
+const REDACTED: &str = "[redacted]";
+
+/// Names of the built-in steps, as accepted by [`Pipeline::from_str`].
+const STEPS: &[&str] = &[
+    "citations",
+    "dates",
+    "dedupe",
+    "redact",
+    "redact-snippets",
+    "truncate",
+    "truncate-tokens",
+];
+
+/// A post-processing step.
+pub trait Processor: Send + Sync {
+    /// Short name of the step, used in debug output.
+    fn name(&self) -> &str;
+
+    /// Modifies `response` in place.
+    fn process(&self, response: &mut SearchResponse);
+}
+
+/// An ordered chain of [`Processor`]s.
+#[derive(Clone, Default)]
+pub struct Pipeline {
+    processors: Vec<Arc<dyn Processor>>,
+}
+
+impl Pipeline {
+    /// Creates an empty pipeline, which leaves responses unchanged.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends `processor` to the chain.
+    #[must_use]
+    pub fn then(mut self, processor: impl Processor + 'static) -> Self {
+        self.processors.push(Arc::new(processor));
+        self
+    }
+
+    /// Runs every processor over `response`, in order.
+    pub fn run(&self, response: &mut SearchResponse) {
+        for processor in &self.processors {
+            processor.process(response);
+        }
+    }
+
+    /// Number of processors in the chain.
+    pub fn len(&self) -> usize {
+        self.processors.len()
+    }
+
+    /// Whether the pipeline has no processors.
+    pub fn is_empty(&self) -> bool {
+        self.processors.is_empty()
+    }
+
+    /// Parses a comma-separated list of steps like [`Pipeline::from_str`],
+    /// offering each step to `custom` first.
+    ///
+    /// `custom` receives the step's name and argument, and returns `None`
+    /// for steps it does not handle, letting applications add their own
+    /// steps to the syntax.
+    pub fn parse_with<F>(s: &str, mut custom: F) -> Result<Self>
+    where
+        F: FnMut(&str, Option<&str>) -> Option<Result<Box<dyn Processor>>>,
+    {
+        let mut pipeline = Self::new();
+        for step in s.split(',').map(str::trim).filter(|step| !step.is_empty()) {
+            let (name, arg) = match step.split_once(':') {
+                Some((name, arg)) => (name.trim(), Some(arg.trim())),
+                None => (step, None),
+            };
+            if let Some(processor) = custom(name, arg) {
+                pipeline.processors.push(Arc::from(processor?));
+                continue;
+            }
+            let invalid = |reason: &str| Error::InvalidPipelineStep {
+                step: step.to_string(),
+                reason: reason.to_string(),
+            };
+
+            pipeline = match (name, arg) {
+                ("dedupe", None) => pipeline.then(Dedupe),
+                ("dates", None) => pipeline.then(AbsoluteDates::default()),
+                ("dates", Some(offset)) => pipeline.then(AbsoluteDates {
+                    utc_offset: Some(
+                        parse_utc_offset(offset)
+                            .ok_or_else(|| invalid("expected a UTC offset such as +02:00"))?,
+                    ),
+                }),
+                ("redact-snippets", None) => pipeline.then(Redact::snippets()),
+                ("citations", Some(style)) => pipeline.then(CitationRewrite(style.parse()?)),
+                ("redact", Some(terms)) => pipeline.then(Redact::terms(
+                    terms.split('|').map(str::trim).filter(|t| !t.is_empty()),
+                )),
+                ("truncate", Some(max_chars)) => pipeline.then(Truncate {
+                    max_chars: max_chars
+                        .parse()
+                        .map_err(|_| invalid("expected a number of characters"))?,
+                }),
+                ("truncate-tokens", Some(max_tokens)) => pipeline.then(TruncateTokens {
+                    max_tokens: max_tokens
+                        .parse()
+                        .map_err(|_| invalid("expected a number of tokens"))?,
+                }),
+                ("dedupe" | "redact-snippets", Some(_)) => {
+                    return Err(invalid("takes no argument"));
+                }
+                ("citations" | "redact" | "truncate" | "truncate-tokens", None) => {
+                    return Err(invalid("needs an argument after ':'"));
+                }
+                _ => {
+                    return Err(Error::UnknownVariant {
+                        kind: "pipeline step",
+                        value: name.to_string(),
+                        suggestions: STEPS.to_vec(),
+                    });
+                }
+            };
+        }
+        Ok(pipeline)
+    }
+}
+
+impl fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.processors.iter().map(|p| p.name())).finish()
+    }
+}
+
+impl FromStr for Pipeline {
+    type Err = Error;
+
+    /// Parses a comma-separated list of steps; see the module docs.
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse_with(s, |_, _| None)
+    }
+}
+
+/// How [`CitationRewrite`] rewrites `[n]` markers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CitationStyle {
+    /// Turns each marker into a Markdown link to its source: `[1](https://…)`.
+    Links,
+    /// Removes the markers, along with the whitespace before them.
+    Strip,
+}
+
+impl FromStr for CitationStyle {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "links" | "link" => Ok(Self::Links),
+            "strip" | "none" => Ok(Self::Strip),
+            _ => Err(Error::UnknownVariant {
+                kind: "citation style",
+                value: s.to_string(),
+                suggestions: vec!["links", "strip"],
+            }),
+        }
+    }
+}
+
+/// Rewrites the answer's `[n]` citation markers, which refer to
+/// `web_results[n - 1]`.
+#[derive(Debug, Clone, Copy)]
+pub struct CitationRewrite(pub CitationStyle);
+
+impl Processor for CitationRewrite {
+    fn name(&self) -> &str {
+        "citations"
+    }
+
+    fn process(&self, response: &mut SearchResponse) {
+        let Some(answer) = &response.answer else {
+            return;
+        };
+        let urls: Vec<&str> = response.web_results.iter().map(|r| r.url.as_str()).collect();
+        let rewritten = match self.0 {
+            CitationStyle::Links => rewrite_markers(answer, |n, text| {
+                let url = urls.get(n.checked_sub(1)?)?;
+                text.push_str(&format!("[{}]({})", n, url));
+                Some(())
+            }),
+            CitationStyle::Strip => rewrite_markers(answer, |_, text| {
+                text.truncate(text.trim_end().len());
+                Some(())
+            }),
+        };
+        response.answer = Some(rewritten);
+    }
+}
+
+/// Drops web results that repeat an earlier URL, ignoring fragments and
+/// trailing slashes, and renumbers the citation markers accordingly.
+#[derive(Debug, Clone, Copy)]
+pub struct Dedupe;
+
+impl Processor for Dedupe {
+    fn name(&self) -> &str {
+        "dedupe"
+    }
+
+    fn process(&self, response: &mut SearchResponse) {
+        let mut first_index: HashMap<String, usize> = HashMap::new();
+        // Old citation number to new citation number.
+        let mut numbers = Vec::with_capacity(response.web_results.len());
+        let mut kept = Vec::with_capacity(response.web_results.len());
+        for result in std::mem::take(&mut response.web_results) {
+            let next = kept.len() + 1;
+            let number = *first_index.entry(normalize_url(&result.url)).or_insert(next);
+            if number == next {
+                kept.push(result);
+            }
+            numbers.push(number);
+        }
+        response.web_results = kept;
+
+        if let Some(answer) = &response.answer {
+            response.answer = Some(rewrite_markers(answer, |n, text| {
+                let number = numbers.get(n.checked_sub(1)?)?;
+                text.push_str(&format!("[{}]", number));
+                Some(())
+            }));
+        }
+    }
+}
+
+/// Merges the sources of several answers into one numbered list, as when
+/// combining the answers to related queries into one report.
+///
+/// Sources are deduplicated by URL like [`Dedupe`] does, and each answer's
+/// citation markers are renumbered to the merged list.
+#[derive(Debug, Clone, Default)]
+pub struct CitationMerger {
+    results: Vec<SearchWebResult>,
+    numbers: HashMap<String, usize>,
+}
+
+impl CitationMerger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the sources `results` of `answer` and returns the answer with
+    /// its citation markers renumbered to the merged list.
+    pub fn add(&mut self, answer: &str, results: &[SearchWebResult]) -> String {
+        let numbers: Vec<usize> = results
+            .iter()
+            .map(|result| {
+                let next = self.results.len() + 1;
+                let number = *self.numbers.entry(normalize_url(&result.url)).or_insert(next);
+                if number == next {
+                    self.results.push(result.clone());
+                }
+                number
+            })
+            .collect();
+        rewrite_markers(answer, |n, text| {
+            let number = numbers.get(n.checked_sub(1)?)?;
+            text.push_str(&format!("[{}]", number));
+            Some(())
+        })
+    }
+
+    /// The merged sources, in citation order.
+    pub fn results(&self) -> &[SearchWebResult] {
+        &self.results
+    }
+
+    pub fn into_results(self) -> Vec<SearchWebResult> {
+        self.results
+    }
+}
+
+/// Removes sensitive text from responses.
+#[derive(Debug, Clone, Default)]
+pub struct Redact {
+    terms: Vec<String>,
+    snippets: bool,
+}
+
+impl Redact {
+    /// Replaces each of `terms`, matched ignoring ASCII case, with
+    /// `[redacted]` in the answer and the web result names and snippets.
+    pub fn terms<T: Into<String>>(terms: impl IntoIterator<Item = T>) -> Self {
+        Self { terms: terms.into_iter().map(Into::into).collect(), snippets: false }
+    }
+
+    /// Clears the web result snippets, keeping names and URLs.
+    pub fn snippets() -> Self {
+        Self { terms: Vec::new(), snippets: true }
+    }
+
+    fn redact(&self, text: &mut String) {
+        for term in &self.terms {
+            *text = replace_ignore_ascii_case(text, term, REDACTED);
+        }
+    }
+}
+
+impl Processor for Redact {
+    fn name(&self) -> &str {
+        "redact"
+    }
+
+    fn process(&self, response: &mut SearchResponse) {
+        if let Some(answer) = &mut response.answer {
+            self.redact(answer);
+        }
+        for result in &mut response.web_results {
+            self.redact(&mut result.name);
+            if self.snippets {
+                result.snippet.clear();
+            } else {
+                self.redact(&mut result.snippet);
+            }
+        }
+    }
+}
+
+/// Shortens answers longer than `max_chars` characters, preferring to cut
+/// at the end of a paragraph or sentence, and adds a warning.
+#[derive(Debug, Clone, Copy)]
+pub struct Truncate {
+    pub max_chars: usize,
+}
+
+impl Processor for Truncate {
+    fn name(&self) -> &str {
+        "truncate"
+    }
+
+    fn process(&self, response: &mut SearchResponse) {
+        let Some(answer) = &mut response.answer else {
+            return;
+        };
+        let Some((limit, _)) = answer.char_indices().nth(self.max_chars) else {
+            return;
+        };
+        let length = answer.chars().count();
+        shorten(answer, limit);
+
+        response.warnings.push(format!(
+            "Answer truncated from {} to {} characters",
+            length,
+            answer.chars().count()
+        ));
+    }
+}
+
+/// Shortens answers longer than `max_tokens` estimated tokens like
+/// [`Truncate`], for budgets counted in tokens; see [`estimate_tokens`].
+#[derive(Debug, Clone, Copy)]
+pub struct TruncateTokens {
+    pub max_tokens: usize,
+}
+
+impl Processor for TruncateTokens {
+    fn name(&self) -> &str {
+        "truncate-tokens"
+    }
+
+    fn process(&self, response: &mut SearchResponse) {
+        let Some(answer) = &mut response.answer else {
+            return;
+        };
+        // Leave room for the ellipsis.
+        let budget = self.max_tokens.saturating_sub(estimate_tokens("…"));
+        let Some(limit) = token_limit(answer, budget) else {
+            return;
+        };
+        let length = estimate_tokens(answer);
+        shorten(answer, limit);
+
+        response.warnings.push(format!(
+            "Answer truncated from about {} to {} tokens",
+            length,
+            estimate_tokens(answer)
+        ));
+    }
+}
+
+/// Appends the absolute date to relative dates in the answer, such as
+/// "yesterday (2026-10-13)", so that stored answers stay correct. Dates are
+/// resolved against the time the query started, from
+/// [`SearchMetrics::started_at`](crate::SearchMetrics::started_at); responses
+/// without it are left unchanged.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AbsoluteDates {
+    /// UTC offset of the calendar to resolve in, in minutes. Without one,
+    /// the query's time zone is used if it is an offset such as `+02:00`,
+    /// and UTC otherwise.
+    pub utc_offset: Option<i32>,
+}
+
+impl Processor for AbsoluteDates {
+    fn name(&self) -> &str {
+        "dates"
+    }
+
+    fn process(&self, response: &mut SearchResponse) {
+        let (Some(answer), Some(started_at)) = (&response.answer, response.metrics.started_at)
+        else {
+            return;
+        };
+        let secs = started_at.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let offset = self
+            .utc_offset
+            .or_else(|| response.metrics.timezone.as_deref().and_then(parse_utc_offset))
+            .unwrap_or(0);
+        let today = epoch_day(i64::try_from(secs).unwrap_or(i64::MAX), offset);
+        response.answer = Some(resolve_relative_dates(answer, today));
+    }
+}
+
+/// Cuts `answer` at byte offset `limit`, preferring the end of a paragraph
+/// or sentence shortly before it, and adds an ellipsis.
+fn shorten(answer: &mut String, limit: usize) {
+    let head = &answer[..limit];
+    // Only back off to a boundary in the last fifth of the allowed text.
+    let earliest = head.len() - head.len() / 5;
+    let cut = head
+        .rfind("\n\n")
+        .filter(|&i| i >= earliest)
+        .or_else(|| head.rfind(". ").filter(|&i| i >= earliest).map(|i| i + 1))
+        .unwrap_or(limit);
+    answer.truncate(cut);
+    answer.truncate(answer.trim_end().len());
+    answer.push('…');
+}
+
+/// Rebuilds `text`, calling `replace` for every `[n]` marker with the
+/// citation number and the text so far. If `replace` returns `None`, the
+/// marker is kept as is. Markers already followed by `(`, i.e. Markdown
+/// links, are left alone.
+fn rewrite_markers(
+    text: &str,
+    mut replace: impl FnMut(usize, &mut String) -> Option<()>,
+) -> String {
+    let mut rewritten = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('[') {
+        rewritten.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let marker = rest[1..]
+            .find(|c: char| !c.is_ascii_digit())
+            .filter(|&len| len > 0 && rest[1 + len..].starts_with(']'))
+            .map(|len| (&rest[..len + 2], &rest[1..=len]))
+            .filter(|(marker, _)| !rest[marker.len()..].starts_with('('));
+
+        let Some((marker, digits)) = marker else {
+            rewritten.push('[');
+            rest = &rest[1..];
+            continue;
+        };
+        let before = rewritten.len();
+        let replaced = digits.parse().ok().and_then(|n| replace(n, &mut rewritten));
+        if replaced.is_none() {
+            rewritten.truncate(before);
+            rewritten.push_str(marker);
+        }
+        rest = &rest[marker.len()..];
+    }
+    rewritten.push_str(rest);
+    rewritten
+}
+
+pub(crate) fn normalize_url(url: &str) -> String {
+    let url = url.split('#').next().unwrap_or(url);
+    url.trim_end_matches('/').to_ascii_lowercase()
+}
+
+fn replace_ignore_ascii_case(text: &str, term: &str, with: &str) -> String {
+    if term.is_empty() {
+        return text.to_string();
+    }
+    // ASCII lowercasing keeps byte offsets, so matches map back to `text`.
+    let haystack = text.to_ascii_lowercase();
+    let needle = term.to_ascii_lowercase();
+    let mut replaced = String::with_capacity(text.len());
+    let mut last = 0;
+    for (start, _) in haystack.match_indices(&needle) {
+        if start < last {
+            continue;
+        }
+        replaced.push_str(&text[last..start]);
+        replaced.push_str(with);
+        last = start + needle.len();
+    }
+    replaced.push_str(&text[last..]);
+    replaced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{SearchEvent, SearchWebResult};
+
+    fn response(answer: &str, urls: &[&str]) -> SearchResponse {
+        SearchResponse::from(SearchEvent {
+            answer: Some(answer.to_string()),
+            web_results: urls
+                .iter()
+                .map(|url| SearchWebResult {
+                    name: format!("Page at {}", url),
+                    url: (*url).to_string(),
+                    snippet: "Made by ACME Corp.".to_string(),
+                    score: None,
+                })
+                .collect(),
+            ..SearchEvent::default()
+        })
+    }
+
+    #[test]
+    fn test_pipeline_runs_in_order() {
+        let pipeline: Pipeline = "dedupe, citations:links".parse().unwrap();
+        let mut response = response(
+            "Rust is fast [1][3] and safe [2]. See [docs](https://x) and arr[i].",
+            &["https://a.com/", "https://b.com", "https://a.com#intro"],
+        );
+        pipeline.run(&mut response);
+
+        assert_eq!(response.web_results.len(), 2);
+        assert_eq!(
+            response.answer.as_deref(),
+            Some(
+                "Rust is fast [1](https://a.com/)[1](https://a.com/) and safe \
+                 [2](https://b.com). See [docs](https://x) and arr[i]."
+            )
+        );
+    }
+
+    #[test]
+    fn test_citation_merger_renumbers_answers() {
+        let first = response("Fast [1], safe [2].", &["https://a.com", "https://b.com"]);
+        let second =
+            response("Safe [1] and popular [2].", &["https://b.com/", "https://c.com"]);
+        let mut merger = CitationMerger::new();
+        assert_eq!(
+            merger.add(first.answer.as_deref().unwrap(), &first.web_results),
+            "Fast [1], safe [2]."
+        );
+        assert_eq!(
+            merger.add(second.answer.as_deref().unwrap(), &second.web_results),
+            "Safe [2] and popular [3]."
+        );
+        let urls: Vec<_> = merger.results().iter().map(|r| r.url.as_str()).collect();
+        assert_eq!(urls, ["https://a.com", "https://b.com", "https://c.com"]);
+    }
+
+    #[test]
+    fn test_strip_redact_and_truncate() {
+        let mut stripped =
+            response("Rust is fast [1][2]. It is safe [3].", &["https://a.com"]);
+        CitationRewrite(CitationStyle::Strip).process(&mut stripped);
+        assert_eq!(stripped.answer.as_deref(), Some("Rust is fast. It is safe."));
+
+        let mut redacted = response("Built at acme corp in 2015.", &["https://a.com"]);
+        Redact::terms(["ACME Corp"]).process(&mut redacted);
+        assert_eq!(redacted.answer.as_deref(), Some("Built at [redacted] in 2015."));
+        assert_eq!(redacted.web_results[0].snippet, "Made by [redacted].");
+
+        let mut truncated =
+            response(&format!("{}\n\n{}", "First paragraph.".repeat(4), "x".repeat(40)), &[]);
+        Truncate { max_chars: 75 }.process(&mut truncated);
+        assert_eq!(truncated.answer.unwrap(), format!("{}…", "First paragraph.".repeat(4)));
+        assert_eq!(truncated.warnings, ["Answer truncated from 106 to 65 characters"]);
+
+        let mut truncated =
+            response(&format!("{}\n\n{}", "First paragraph.".repeat(4), "x".repeat(40)), &[]);
+        TruncateTokens { max_tokens: 20 }.process(&mut truncated);
+        assert_eq!(truncated.answer.unwrap(), format!("{}…", "First paragraph.".repeat(4)));
+        assert_eq!(truncated.warnings, ["Answer truncated from about 22 to 18 tokens"]);
+    }
+
+    #[test]
+    fn test_absolute_dates() {
+        let pipeline: Pipeline = "dates:+05:00".parse().unwrap();
+        let mut dated = response("Released yesterday.", &[]);
+        pipeline.run(&mut dated);
+        assert_eq!(dated.answer.as_deref(), Some("Released yesterday."));
+
+        // 22:00 UTC on 14 October 2026 is already the 15th at UTC+05:00.
+        dated.metrics.started_at =
+            Some(UNIX_EPOCH + std::time::Duration::from_secs(1_792_015_200));
+        pipeline.run(&mut dated);
+        assert_eq!(dated.answer.as_deref(), Some("Released yesterday (2026-10-14)."));
+
+        let mut dated = response("Released yesterday.", &[]);
+        dated.metrics.started_at =
+            Some(UNIX_EPOCH + std::time::Duration::from_secs(1_792_015_200));
+        dated.metrics.timezone = Some("UTC".to_string());
+        AbsoluteDates::default().process(&mut dated);
+        assert_eq!(dated.answer.as_deref(), Some("Released yesterday (2026-10-13)."));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(
+            "dedupe, sort".parse::<Pipeline>(),
+            Err(Error::UnknownVariant { kind: "pipeline step", .. })
+        ));
+        assert!(matches!(
+            "truncate:lots".parse::<Pipeline>(),
+            Err(Error::InvalidPipelineStep { .. })
+        ));
+        assert!(matches!(
+            "truncate".parse::<Pipeline>(),
+            Err(Error::InvalidPipelineStep { .. })
+        ));
+        assert!(matches!(
+            "dates:Europe/Paris".parse::<Pipeline>(),
+            Err(Error::InvalidPipelineStep { .. })
+        ));
+        assert!("".parse::<Pipeline>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_with_custom_steps() {
+        let pipeline = Pipeline::parse_with("dedupe, custom:x, truncate:10", |name, arg| {
+            (name == "custom").then(|| {
+                assert_eq!(arg, Some("x"));
+                Ok(Box::new(Dedupe) as Box<dyn Processor>)
+            })
+        })
+        .unwrap();
+        assert_eq!(format!("{:?}", pipeline), r#"["dedupe", "dedupe", "truncate"]"#);
+        assert!("custom:x".parse::<Pipeline>().is_err());
+    }
+}
@@ -0,0 +1,183 @@
+//! Client-side preprocessing of image attachments.
+//!
+//! Images are decoded, rotated according to their EXIF orientation, scaled
+//! down and re-encoded. Re-encoding drops all metadata, including EXIF
+//! location tags.
+
+use crate::error::{Error, Result};
+use crate::types::UploadFile;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use std::io::Cursor;
+
+/// Quality used when re-encoding JPEGs with [`ImageEncoding::Original`].
+const ORIGINAL_JPEG_QUALITY: u8 = 90;
+
+/// Format images are re-encoded to before upload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImageEncoding {
+    /// Keep the original format.
+    #[default]
+    Original,
+    /// Convert to JPEG with the given quality (1-100).
+    Jpeg { quality: u8 },
+    /// Convert to lossless WebP.
+    WebP,
+}
+
+/// Options for preprocessing image attachments before upload.
+///
+/// Applies to JPEG, PNG and WebP files; other files are uploaded unchanged.
+#[derive(Debug, Clone)]
+pub struct ImagePreprocessing {
+    /// Maximum width and height in pixels. Larger images are scaled down,
+    /// preserving their aspect ratio.
+    pub max_dimension: u32,
+    /// Format to re-encode images to.
+    pub encoding: ImageEncoding,
+}
+
+impl ImagePreprocessing {
+    /// Creates options that limit images to 2048 pixels and keep their format.
+    pub fn new() -> Self {
+        Self { max_dimension: 2048, encoding: ImageEncoding::Original }
+    }
+
+    /// Sets the maximum width and height.
+    pub fn max_dimension(mut self, max_dimension: u32) -> Self {
+        self.max_dimension = max_dimension;
+        self
+    }
+
+    /// Sets the output format.
+    pub fn encoding(mut self, encoding: ImageEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+}
+
+impl Default for ImagePreprocessing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Preprocesses `file` if it is a supported image.
+///
+/// Returns `None` for files that are not images or use another format.
+/// This is CPU-bound; call it from `spawn_blocking`.
+pub(crate) fn preprocess_image(
+    file: &UploadFile,
+    options: &ImagePreprocessing,
+) -> Result<Option<UploadFile>> {
+    let Ok(format) = ImageFormat::from_path(file.filename()) else {
+        return Ok(None);
+    };
+    if !matches!(format, ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP) {
+        return Ok(None);
+    }
+
+    let image = decode(&file.as_bytes(), format).map_err(processing_error)?;
+    let image =
+        if image.width() > options.max_dimension || image.height() > options.max_dimension {
+            image.resize(options.max_dimension, options.max_dimension, FilterType::Lanczos3)
+        } else {
+            image
+        };
+
+    let (target, quality) = match options.encoding {
+        ImageEncoding::Original => (format, ORIGINAL_JPEG_QUALITY),
+        ImageEncoding::Jpeg { quality } => (ImageFormat::Jpeg, quality.clamp(1, 100)),
+        ImageEncoding::WebP => (ImageFormat::WebP, 0),
+    };
+
+    let mut encoded = Vec::new();
+    match target {
+        ImageFormat::Jpeg => {
+            // JPEG has no alpha channel.
+            let rgb = DynamicImage::ImageRgb8(image.to_rgb8());
+            rgb.write_with_encoder(JpegEncoder::new_with_quality(&mut encoded, quality))
+        }
+        ImageFormat::WebP => {
+            let rgba = DynamicImage::ImageRgba8(image.to_rgba8());
+            rgba.write_with_encoder(WebPEncoder::new_lossless(&mut encoded))
+        }
+        _ => image.write_to(&mut Cursor::new(&mut encoded), target),
+    }
+    .map_err(processing_error)?;
+
+    Ok(Some(UploadFile::from_bytes(rename(file.filename(), target), encoded)))
+}
+
+/// Decodes an image and applies its EXIF orientation, which would otherwise
+/// be lost along with the rest of the metadata.
+fn decode(data: &[u8], format: ImageFormat) -> image::ImageResult<DynamicImage> {
+    let mut decoder = ImageReader::with_format(Cursor::new(data), format).into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+/// Replaces the extension of `filename` with the one for `format`.
+fn rename(filename: &str, format: ImageFormat) -> String {
+    let stem = filename.rsplit_once('.').map_or(filename, |(stem, _)| stem);
+    let extension = format.extensions_str().first().copied().unwrap_or("img");
+    format!("{}.{}", stem, extension)
+}
+
+fn processing_error(e: image::ImageError) -> Error {
+    Error::ImageProcessing(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, RgbaImage};
+
+    fn png(width: u32, height: u32) -> UploadFile {
+        let mut data = Vec::new();
+        DynamicImage::ImageRgba8(RgbaImage::new(width, height))
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+        UploadFile::from_bytes("photo.png", data)
+    }
+
+    #[test]
+    fn test_resizes_and_converts_to_jpeg() {
+        let options = ImagePreprocessing::new()
+            .max_dimension(16)
+            .encoding(ImageEncoding::Jpeg { quality: 80 });
+        let file = preprocess_image(&png(64, 32), &options).unwrap().unwrap();
+
+        assert_eq!(file.filename(), "photo.jpg");
+        let decoded = image::load_from_memory(&file.as_bytes()).unwrap();
+        assert_eq!(decoded.dimensions(), (16, 8));
+    }
+
+    #[test]
+    fn test_keeps_small_images_in_original_format() {
+        let file = preprocess_image(&png(8, 8), &ImagePreprocessing::new()).unwrap().unwrap();
+
+        assert_eq!(file.filename(), "photo.png");
+        let format = image::guess_format(&file.as_bytes()).unwrap();
+        assert_eq!(format, ImageFormat::Png);
+    }
+
+    #[test]
+    fn test_converts_to_webp() {
+        let options = ImagePreprocessing::new().encoding(ImageEncoding::WebP);
+        let file = preprocess_image(&png(4, 4), &options).unwrap().unwrap();
+
+        assert_eq!(file.filename(), "photo.webp");
+        assert_eq!(image::guess_format(&file.as_bytes()).unwrap(), ImageFormat::WebP);
+    }
+
+    #[test]
+    fn test_skips_non_images() {
+        let file = UploadFile::from_text("notes.txt", "hello");
+        assert!(preprocess_image(&file, &ImagePreprocessing::new()).unwrap().is_none());
+    }
+}
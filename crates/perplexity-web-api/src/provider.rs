@@ -0,0 +1,45 @@
+//! Pluggable research backends.
+//!
+//! [`ResearchProvider`] is the interface the MCP server runs queries
+//! through. [`Client`](crate::Client) implements it by querying Perplexity;
+//! other implementations can stand in for it, for example to fall back to
+//! another service while Perplexity is unavailable.
+
+use crate::error::Result;
+use crate::types::{SearchEvent, SearchRequest, SearchResponse};
+use std::future::Future;
+use std::pin::Pin;
+
+/// Future returned by [`ResearchProvider::research`].
+pub type ResearchFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<SearchResponse>> + Send + 'a>>;
+
+/// A backend that answers search requests.
+pub trait ResearchProvider: Send + Sync {
+    /// Short name of the provider, used in logs and warnings.
+    fn name(&self) -> &str;
+
+    /// Answers `request`, passing intermediate answers to `on_event` as they
+    /// arrive. Providers that do not stream call it once with the final
+    /// answer, or not at all.
+    fn research<'a>(
+        &'a self,
+        request: SearchRequest,
+        on_event: &'a mut (dyn FnMut(&SearchEvent) + Send),
+    ) -> ResearchFuture<'a>;
+}
+
+#[cfg(feature = "client")]
+impl ResearchProvider for crate::Client {
+    fn name(&self) -> &str {
+        "perplexity"
+    }
+
+    fn research<'a>(
+        &'a self,
+        request: SearchRequest,
+        on_event: &'a mut (dyn FnMut(&SearchEvent) + Send),
+    ) -> ResearchFuture<'a> {
+        Box::pin(self.search_with_progress(request, on_event))
+    }
+}
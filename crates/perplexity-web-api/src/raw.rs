@@ -0,0 +1,63 @@
+//! Raw events of the answer stream.
+//!
+//! The parser extracts the fields this crate models and keeps the rest in
+//! [`SearchEvent::raw`](crate::SearchEvent::raw), but only for the events it
+//! can parse. A [`RawEventSink`] registered with
+//! [`ClientBuilder::on_raw_event`](crate::ClientBuilder::on_raw_event) sees
+//! the JSON of every event exactly as Perplexity sent it, before parsing, so
+//! applications can archive or analyze the stream without the crate
+//! committing to every field.
+
+use std::fmt;
+use std::sync::Arc;
+
+/// One event of the answer stream, as Perplexity sent it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawEvent<'a> {
+    /// The SSE event type: `message`, or `error` for an error event ending
+    /// the stream.
+    pub event: &'a str,
+    /// The event's `data:` line, normally a JSON document.
+    pub data: &'a str,
+}
+
+/// Receives every [`RawEvent`] of the answer streams of a client.
+///
+/// Called on the task polling the stream, so implementations should hand
+/// slow work, such as writing to disk, off elsewhere. Closures taking a
+/// `RawEvent` are sinks.
+pub trait RawEventSink: Send + Sync {
+    fn event(&self, event: RawEvent<'_>);
+}
+
+impl<F> RawEventSink for F
+where
+    F: Fn(RawEvent<'_>) + Send + Sync,
+{
+    fn event(&self, event: RawEvent<'_>) {
+        self(event);
+    }
+}
+
+/// The sinks an answer stream tees its events to.
+#[derive(Clone, Default)]
+pub(crate) struct RawSinks(Vec<Arc<dyn RawEventSink>>);
+
+impl RawSinks {
+    #[cfg(feature = "client")]
+    pub(crate) fn push(&mut self, sink: Arc<dyn RawEventSink>) {
+        self.0.push(sink);
+    }
+
+    pub(crate) fn send(&self, event: &str, data: &str) {
+        for sink in &self.0 {
+            sink.event(RawEvent { event, data });
+        }
+    }
+}
+
+impl fmt::Debug for RawSinks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RawSinks({})", self.0.len())
+    }
+}
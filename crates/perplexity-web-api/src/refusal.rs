@@ -0,0 +1,92 @@
+//! Detection of answers in which Perplexity declines to respond.
+//!
+//! A refusal arrives as an ordinary answer, so it can only be recognized by
+//! its wording. Detection is deliberately narrow: the answer must be short,
+//! cite no sources and open with a refusal phrase, so that answers merely
+//! discussing what cannot be done are left alone.
+
+use crate::types::SearchResponse;
+
+/// Longest answer still considered a refusal.
+const MAX_REFUSAL_CHARS: usize = 600;
+
+/// How far into the answer a refusal phrase must start.
+const PHRASE_WINDOW: usize = 40;
+
+/// Phrases that open a refusal, in lower case with straight apostrophes.
+const REFUSAL_PHRASES: &[&str] = &[
+    "i can't help with",
+    "i cannot help with",
+    "i can't assist with",
+    "i cannot assist with",
+    "i can't provide",
+    "i cannot provide",
+    "i can't comply",
+    "i cannot comply",
+    "i'm not able to help",
+    "i am not able to help",
+    "i'm unable to help",
+    "i am unable to help",
+    "i'm unable to provide",
+    "i am unable to provide",
+    "i won't be able to help",
+    "i must decline",
+    "i'm sorry, but i can't",
+    "i'm sorry, but i cannot",
+];
+
+/// Returns the answer of `response` if it is a refusal to answer.
+pub fn refusal_reason(response: &SearchResponse) -> Option<String> {
+    let answer = response.answer.as_deref()?.trim();
+    if answer.is_empty() || answer.chars().count() > MAX_REFUSAL_CHARS || cites_sources(answer)
+    {
+        return None;
+    }
+
+    let normalized = answer.to_lowercase().replace('\u{2019}', "'");
+    let opens_with_refusal = REFUSAL_PHRASES
+        .iter()
+        .any(|phrase| normalized.find(phrase).is_some_and(|start| start <= PHRASE_WINDOW));
+    opens_with_refusal.then(|| answer.to_string())
+}
+
+/// Whether `answer` contains a `[n]` citation marker.
+fn cites_sources(answer: &str) -> bool {
+    answer.split('[').skip(1).any(|rest| {
+        rest.split_once(']')
+            .is_some_and(|(n, _)| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::SearchEvent;
+
+    fn reason(answer: &str) -> Option<String> {
+        refusal_reason(&SearchResponse::from(SearchEvent {
+            answer: Some(answer.to_string()),
+            ..SearchEvent::default()
+        }))
+    }
+
+    #[test]
+    fn test_refusal_reason() {
+        let refusal = "I\u{2019}m sorry, but I can\u{2019}t help with creating malware.";
+        assert_eq!(reason(refusal).as_deref(), Some(refusal));
+        assert!(reason("I cannot provide instructions for that. ").is_some());
+
+        assert!(reason("Rust is a systems programming language [1].").is_none());
+        assert!(
+            reason("I can't provide exact figures, but estimates suggest 5% [2].").is_none()
+        );
+        assert!(
+            reason("Many tools exist for this task. Note that I cannot help with paid ones.")
+                .is_none()
+        );
+        assert!(
+            reason(&format!("I cannot help with that. {}", "More. ".repeat(200))).is_none()
+        );
+        assert!(reason("").is_none());
+    }
+}
@@ -0,0 +1,49 @@
+//! The async runtime the client runs on.
+//!
+//! The client's own timers, locks and blocking work go through this module,
+//! so the rest of the crate does not name a runtime. The HTTP stack, rquest
+//! on hyper, drives its sockets with tokio's reactor, so the client needs a
+//! tokio runtime however the application is built: applications on smol or
+//! async-std run it inside one, for example by wrapping its futures with the
+//! `async-compat` crate. [`enter`] turns a missing runtime into
+//! [`Error::NoRuntime`] instead of a panic deep inside a request.
+
+use crate::error::{Error, Result};
+use std::future::Future;
+use std::time::Duration;
+
+// tokio's locks work on any executor.
+pub(crate) use tokio::net::lookup_host;
+pub(crate) use tokio::sync::Mutex;
+#[cfg(feature = "image")]
+pub(crate) use tokio::task::spawn_blocking;
+pub(crate) use tokio::time::{Instant, Sleep, sleep};
+
+/// Fails with [`Error::NoRuntime`] unless called within a tokio runtime.
+pub(crate) fn enter() -> Result<()> {
+    tokio::runtime::Handle::try_current().map(drop).map_err(|_| Error::NoRuntime)
+}
+
+/// Runs `future`, failing with [`Error::Timeout`] if it takes longer than
+/// `duration`.
+pub(crate) async fn timeout<F: Future>(duration: Duration, future: F) -> Result<F::Output> {
+    tokio::time::timeout(duration, future).await.map_err(|_| Error::Timeout(duration))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enter_requires_tokio() {
+        assert!(matches!(enter(), Err(Error::NoRuntime)));
+
+        let runtime =
+            tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            assert!(enter().is_ok());
+            let slow = timeout(Duration::from_millis(5), sleep(Duration::from_secs(1))).await;
+            assert!(matches!(slow, Err(Error::Timeout(_))));
+        });
+    }
+}
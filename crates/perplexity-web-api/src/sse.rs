@@ -1,35 +1,76 @@
-use crate::error::{Error, Result};
-use crate::parse::parse_sse_event;
+use crate::compat::ApiVersion;
+use crate::error::{Error, Result, StreamErrorKind};
+use crate::parse::parse_sse_event_as;
+use crate::raw::RawSinks;
 use crate::types::SearchEvent;
-use bytes::{Bytes, BytesMut};
+#[cfg(feature = "client")]
+use bytes::Bytes;
+use bytes::BytesMut;
+#[cfg(feature = "client")]
 use futures_util::Stream;
 use memchr::memmem;
+#[cfg(feature = "client")]
 use std::pin::Pin;
+#[cfg(feature = "client")]
 use std::task::{Context, Poll};
 
-const EVENT_MESSAGE_PREFIX: &[u8] = b"event: message\r\n";
-const EVENT_END_OF_STREAM_PREFIX: &[u8] = b"event: end_of_stream\r\n";
+const EVENT_MESSAGE: &[u8] = b"event: message";
+const EVENT_END_OF_STREAM: &[u8] = b"event: end_of_stream";
+const EVENT_ERROR: &[u8] = b"event: error";
+const LINE_END: &[u8] = b"\r\n";
 const DATA_PREFIX: &[u8] = b"data: ";
 const DELIMITER: &[u8] = b"\r\n\r\n";
 
+/// Size limits protecting against a malfunctioning endpoint.
+#[cfg(feature = "client")]
+#[derive(Debug, Clone, Copy)]
+pub struct StreamLimits {
+    /// Largest single event, in bytes.
+    pub max_event_size: usize,
+    /// Most bytes read from one stream.
+    pub max_stream_size: usize,
+}
+
+#[cfg(feature = "client")]
 pin_project_lite::pin_project! {
     pub struct SseStream<S> {
         #[pin]
         inner: S,
         buffer: BytesMut,
         finished: bool,
+        limits: StreamLimits,
+        version: ApiVersion,
+        sinks: RawSinks,
+        received: usize,
     }
 }
 
+#[cfg(feature = "client")]
 impl<S> SseStream<S>
 where
     S: Stream<Item = std::result::Result<Bytes, rquest::Error>>,
 {
-    pub fn new(inner: S) -> Self {
-        Self { inner, buffer: BytesMut::new(), finished: false }
+    /// Parses the events of `inner`, in the shape of `version`.
+    pub fn new(inner: S, limits: StreamLimits, version: ApiVersion) -> Self {
+        Self {
+            inner,
+            buffer: BytesMut::new(),
+            finished: false,
+            limits,
+            version,
+            sinks: RawSinks::default(),
+            received: 0,
+        }
+    }
+
+    /// Also hands the data of every event to `sinks`, before parsing it.
+    pub fn tee(mut self, sinks: RawSinks) -> Self {
+        self.sinks = sinks;
+        self
     }
 }
 
+#[cfg(feature = "client")]
 impl<S> Stream for SseStream<S>
 where
     S: Stream<Item = std::result::Result<Bytes, rquest::Error>>,
@@ -44,7 +85,8 @@ where
         }
 
         loop {
-            if let Some(event) = try_parse_event(this.buffer, this.finished) {
+            let event = try_parse_event(this.buffer, this.finished, *this.version, this.sinks);
+            if let Some(event) = event {
                 return Poll::Ready(Some(event));
             }
 
@@ -54,7 +96,25 @@ where
 
             match this.inner.as_mut().poll_next(cx) {
                 Poll::Ready(Some(Ok(chunk))) => {
+                    *this.received += chunk.len();
+                    if *this.received > this.limits.max_stream_size {
+                        *this.finished = true;
+                        let limit = this.limits.max_stream_size;
+                        return Poll::Ready(Some(Err(Error::StreamTooLarge { limit })));
+                    }
                     this.buffer.extend_from_slice(&chunk);
+                    // Events are split off as soon as they are complete, so
+                    // the buffer only holds more than one event's worth while
+                    // an event is still arriving.
+                    if this.buffer.len() > this.limits.max_event_size
+                        && memmem::find(this.buffer, DELIMITER)
+                            .is_none_or(|end| end > this.limits.max_event_size)
+                    {
+                        *this.finished = true;
+                        this.buffer.clear();
+                        let limit = this.limits.max_event_size;
+                        return Poll::Ready(Some(Err(Error::EventTooLarge { limit })));
+                    }
                 }
                 Poll::Ready(Some(Err(e))) => {
                     return Poll::Ready(Some(Err(Error::Http(e))));
@@ -73,33 +133,211 @@ where
     }
 }
 
-#[allow(clippy::collapsible_if)]
-fn try_parse_event(buffer: &mut BytesMut, finished: &mut bool) -> Option<Result<SearchEvent>> {
+/// Parses the events of a recorded search stream response body.
+///
+/// Events are yielded in order until the `end_of_stream` event or the end
+/// of `body`; an incomplete event at the end is ignored.
+pub fn parse_sse(body: &[u8]) -> impl Iterator<Item = Result<SearchEvent>> {
+    let mut buffer = BytesMut::from(body);
+    let mut finished = false;
+    let finder = memmem::Finder::new(DELIMITER).into_owned();
+
+    let (version, sinks) = (ApiVersion::current(), RawSinks::default());
+
+    std::iter::from_fn(move || {
+        while !finished && finder.find(&buffer).is_some() {
+            if let Some(event) = try_parse_event(&mut buffer, &mut finished, version, &sinks) {
+                return Some(event);
+            }
+        }
+        None
+    })
+}
+
+fn try_parse_event(
+    buffer: &mut BytesMut,
+    finished: &mut bool,
+    version: ApiVersion,
+    sinks: &RawSinks,
+) -> Option<Result<SearchEvent>> {
     let finder = memmem::Finder::new(DELIMITER);
 
-    if let Some(pos) = finder.find(buffer) {
-        let event_bytes = buffer.split_to(pos + DELIMITER.len());
-        let event_data = &event_bytes[..pos];
+    let pos = finder.find(buffer)?;
+    let event_bytes = buffer.split_to(pos + DELIMITER.len());
+    let event_data = event_bytes.strip_suffix(DELIMITER).unwrap_or_default();
+    let (name, fields) = match memmem::find(event_data, LINE_END) {
+        Some(end) => (event_data.get(..end), event_data.get(end + LINE_END.len()..)),
+        None => (Some(event_data), None),
+    };
+    let fields = fields.unwrap_or_default();
 
-        // Check for end of stream event
-        if event_data.starts_with(EVENT_END_OF_STREAM_PREFIX) {
+    match name.unwrap_or_default() {
+        EVENT_END_OF_STREAM => {
             *finished = true;
-            return None;
+            None
         }
-
-        // Check for message event
-        if event_data.starts_with(EVENT_MESSAGE_PREFIX) {
-            let after_event = &event_data[EVENT_MESSAGE_PREFIX.len()..];
-            if let Some(data_start) = memmem::find(after_event, DATA_PREFIX) {
-                let json_bytes = &after_event[data_start + DATA_PREFIX.len()..];
-                // Validate UTF-8 and parse
-                return match std::str::from_utf8(json_bytes) {
-                    Ok(json_str) => Some(parse_sse_event(json_str)),
-                    Err(_) => Some(Err(Error::InvalidUtf8)),
-                };
+        // An error event ends the stream, whatever its data
+        EVENT_ERROR => {
+            *finished = true;
+            let data = String::from_utf8_lossy(data_of(fields).unwrap_or_default());
+            sinks.send("error", &data);
+            Some(match parse_sse_event_as(&data, version) {
+                Err(error @ Error::StreamEvent { .. }) => Err(error),
+                _ => Err(Error::StreamEvent {
+                    kind: StreamErrorKind::classify(None, &data),
+                    code: None,
+                    message: data.into_owned(),
+                }),
+            })
+        }
+        EVENT_MESSAGE => {
+            // Validate UTF-8 and parse
+            let event = match std::str::from_utf8(data_of(fields)?) {
+                Ok(json_str) => {
+                    sinks.send("message", json_str);
+                    parse_sse_event_as(json_str, version)
+                }
+                Err(_) => Err(Error::InvalidUtf8),
+            };
+            // An error payload is the last event
+            if matches!(event, Err(Error::StreamEvent { .. })) {
+                *finished = true;
             }
+            Some(event)
         }
+        _ => None,
+    }
+}
+
+/// The data of an event: everything after its `data: ` prefix.
+fn data_of(event: &[u8]) -> Option<&[u8]> {
+    let start = memmem::find(event, DATA_PREFIX)?;
+    event.get(start + DATA_PREFIX.len()..)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_recorded_body() {
+        let body = b"event: message\r\ndata: {\"answer\": \"first\"}\r\n\r\n\
+            event: ping\r\n\r\n\
+            event: message\r\ndata: {\"answer\": \"second\"}\r\n\r\n\
+            event: end_of_stream\r\ndata: {}\r\n\r\n\
+            event: message\r\ndata: {\"answer\": \"ignored\"}\r\n\r\n";
+
+        let answers: Vec<_> = parse_sse(body).map(|event| event.unwrap().answer).collect();
+        assert_eq!(answers, [Some("first".to_string()), Some("second".to_string())]);
+        assert_eq!(parse_sse(b"event: message\r\ndata: {").count(), 0);
+    }
+
+    #[test]
+    fn test_parse_malformed_events() {
+        let body = b"\r\n\r\n\
+            event: message\r\n\r\n\
+            event: message\r\ndata:\r\n\r\n\
+            data: {\"answer\": \"no event line\"}\r\n\r\n\
+            event: message\r\ndata: \xff\xfe\r\n\r\n\
+            event: message\r\ndata: {\"answer\": \r\n\r\n\
+            event: message\r\ndata: {\"answer\": \"after\"}\r\n\r\n\
+            event: error\r\n\r\n";
+        let events: Vec<_> = parse_sse(body).collect();
+        assert!(
+            matches!(
+                events.as_slice(),
+                [
+                    Err(Error::InvalidUtf8),
+                    Err(Error::Json(_)),
+                    Ok(event),
+                    Err(Error::StreamEvent { message, .. }),
+                ] if event.answer.as_deref() == Some("after") && message.is_empty()
+            ),
+            "{events:?}"
+        );
+    }
+
+    #[test]
+    fn test_error_events_end_the_stream() {
+        let body = b"event: message\r\ndata: {\"answer\": \"partial\"}\r\n\r\n\
+            event: message\r\ndata: {\"error\": \"Model overloaded\"}\r\n\r\n\
+            event: message\r\ndata: {\"answer\": \"ignored\"}\r\n\r\n";
+        let events: Vec<_> = parse_sse(body).collect();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            &events[1],
+            Err(Error::StreamEvent { kind: StreamErrorKind::Overloaded, message, .. })
+                if message == "Model overloaded"
+        ));
+
+        let body = b"event: error\r\ndata: Internal failure\r\n\r\n\
+            event: message\r\ndata: {\"answer\": \"ignored\"}\r\n\r\n";
+        let events: Vec<_> = parse_sse(body).collect();
+        assert!(matches!(
+            events.as_slice(),
+            [Err(Error::StreamEvent { kind: StreamErrorKind::Other, message, .. })]
+                if message == "Internal failure"
+        ));
     }
 
-    None
+    #[cfg(feature = "client")]
+    #[tokio::test]
+    async fn test_stream_limits() {
+        use futures_util::StreamExt;
+
+        let limits = StreamLimits { max_event_size: 64, max_stream_size: 256 };
+        let event =
+            Bytes::from_static(b"event: message\r\ndata: {\"answer\": \"ok\"}\r\n\r\n");
+        let chunks = |chunks: Vec<Bytes>| {
+            SseStream::new(
+                futures_util::stream::iter(chunks.into_iter().map(Ok)),
+                limits,
+                ApiVersion::current(),
+            )
+        };
+
+        let answers: Vec<_> = chunks(vec![event.clone(); 4]).collect().await;
+        assert!(answers.iter().all(Result::is_ok));
+
+        let oversized = Bytes::from(format!("event: message\r\ndata: \"{}", "x".repeat(80)));
+        let events: Vec<_> = chunks(vec![event.clone(), oversized]).collect().await;
+        assert!(matches!(events.as_slice(), [Ok(_), Err(Error::EventTooLarge { limit: 64 })]));
+
+        let events: Vec<_> = chunks(vec![event; 10]).collect().await;
+        assert!(matches!(events.last(), Some(Err(Error::StreamTooLarge { limit: 256 }))));
+        assert!(events.len() < 10);
+    }
+
+    #[cfg(feature = "client")]
+    #[tokio::test]
+    async fn test_raw_events_are_teed() {
+        use crate::raw::RawEvent;
+        use futures_util::StreamExt;
+        use std::sync::{Arc, Mutex};
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut sinks = RawSinks::default();
+        let sink = Arc::clone(&seen);
+        sinks.push(Arc::new(move |event: RawEvent<'_>| {
+            sink.lock().unwrap().push(format!("{}: {}", event.event, event.data));
+        }));
+
+        let body = Bytes::from_static(
+            b"event: message\r\ndata: {\"answer\": \"ok\", \"new_field\": 1}\r\n\r\n\
+            event: error\r\ndata: Internal failure\r\n\r\n",
+        );
+        let limits = StreamLimits { max_event_size: 1024, max_stream_size: 4096 };
+        let stream = SseStream::new(
+            futures_util::stream::iter([Ok(body)]),
+            limits,
+            ApiVersion::current(),
+        )
+        .tee(sinks);
+        let events: Vec<_> = stream.collect().await;
+        assert!(matches!(events.as_slice(), [Ok(_), Err(Error::StreamEvent { .. })]));
+        assert_eq!(
+            *seen.lock().unwrap(),
+            ["message: {\"answer\": \"ok\", \"new_field\": 1}", "error: Internal failure"]
+        );
+    }
 }
@@ -0,0 +1,120 @@
+//! Token estimates.
+//!
+//! [`estimate_tokens`] approximates how many tokens a BPE tokenizer such as
+//! OpenAI's `cl100k_base` splits text into, without shipping its vocabulary.
+//! Text is split the way tiktoken pre-tokenizes it (words with their
+//! leading space, runs of up to three digits, punctuation, whitespace) and
+//! each piece is costed by its length. English prose lands within about
+//! ten percent of the real count; code and non-Latin scripts come out
+//! somewhat high, which is the safe side for budgets.
+
+/// Letters a tokenizer typically covers with one token.
+const LETTERS_PER_TOKEN: usize = 8;
+
+/// Digits tiktoken groups into one token.
+const DIGITS_PER_TOKEN: usize = 3;
+
+/// Punctuation characters typically merged into one token.
+const PUNCTUATION_PER_TOKEN: usize = 2;
+
+/// Estimates the number of tokens `text` takes in a model's context.
+pub fn estimate_tokens(text: &str) -> usize {
+    Pieces { text, pos: 0 }.map(|(_, tokens)| tokens).sum()
+}
+
+/// Returns the byte offset at which `text` exceeds `max_tokens` estimated
+/// tokens, or `None` if it fits. The offset is at a piece boundary, so the
+/// text before it estimates to at most `max_tokens`.
+pub(crate) fn token_limit(text: &str, max_tokens: usize) -> Option<usize> {
+    let mut used = 0;
+    let mut start = 0;
+    for (end, tokens) in (Pieces { text, pos: 0 }) {
+        used += tokens;
+        if used > max_tokens {
+            return Some(start);
+        }
+        start = end;
+    }
+    None
+}
+
+/// Splits text into pieces like tiktoken's pre-tokenizer, yielding the end
+/// offset and estimated token count of each.
+struct Pieces<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Iterator for Pieces<'_> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = &self.text[self.pos..];
+        let c = rest.chars().next()?;
+        let run = |pred: fn(char) -> bool| rest.find(|c| !pred(c)).unwrap_or(rest.len());
+
+        let (len, tokens) = if c.is_whitespace() {
+            // A single space before a word is part of the word's token.
+            let len = run(char::is_whitespace);
+            let space = &rest[..len];
+            let merged = !space.contains('\n')
+                && rest[len..].chars().next().is_some_and(char::is_alphabetic);
+            (len, usize::from(!merged || space.chars().count() > 1))
+        } else if is_cjk(c) {
+            (c.len_utf8(), 1)
+        } else if c.is_alphabetic() {
+            let len = run(|c| c.is_alphabetic() && !is_cjk(c));
+            // Letters outside ASCII are rarer in the vocabulary, so each
+            // token covers fewer of them.
+            let weight: usize =
+                rest[..len].chars().map(|c| if c.is_ascii() { 1 } else { 2 }).sum();
+            (len, weight.div_ceil(LETTERS_PER_TOKEN))
+        } else if c.is_ascii_digit() {
+            let len = run(|c| c.is_ascii_digit());
+            (len, len.div_ceil(DIGITS_PER_TOKEN))
+        } else if c.is_ascii() {
+            let len = run(|c| c.is_ascii_punctuation());
+            let len = len.max(1);
+            (len, len.div_ceil(PUNCTUATION_PER_TOKEN))
+        } else {
+            // Other symbols, such as emoji, are split into byte tokens.
+            (c.len_utf8(), c.len_utf8().div_ceil(2))
+        };
+
+        self.pos += len;
+        Some((self.pos, tokens))
+    }
+}
+
+/// Whether `c` is a Chinese, Japanese or Korean character, which tokenizers
+/// cost about one token each.
+fn is_cjk(c: char) -> bool {
+    matches!(
+        u32::from(c),
+        0x3040..=0x30FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xAC00..=0xD7AF | 0xF900..=0xFAFF
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimates_follow_tiktoken() {
+        // Real cl100k_base counts.
+        assert_eq!(estimate_tokens("Hello, world!"), 4);
+        assert_eq!(estimate_tokens("The quick brown fox jumps over the lazy dog."), 10);
+        assert_eq!(estimate_tokens("1234567"), 3);
+        assert_eq!(estimate_tokens(""), 0);
+        // Close for longer words and other scripts, erring high.
+        assert_eq!(estimate_tokens("internationalization"), 3);
+        assert_eq!(estimate_tokens("你好世界"), 4);
+        assert_eq!(estimate_tokens("line one\n\nline two"), 5);
+
+        let text = "Rust 1.81 shipped. It stabilizes core::error::Error.";
+        let limit = token_limit(text, 6).unwrap();
+        assert_eq!(&text[..limit], "Rust 1.81 shipped");
+        assert_eq!(estimate_tokens(&text[..limit]), 6);
+        assert_eq!(token_limit(text, 100), None);
+    }
+}
@@ -1,7 +1,12 @@
+use crate::config::MAX_QUERY_LENGTH;
+use crate::error::Error;
 use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 /// Search mode for Perplexity queries.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -18,6 +23,9 @@ pub enum SearchMode {
 }
 
 impl SearchMode {
+    /// All search modes, in declaration order.
+    pub const ALL: &[Self] = &[Self::Auto, Self::Pro, Self::Reasoning, Self::DeepResearch];
+
     /// Returns the string representation used by the API.
     pub fn as_str(&self) -> &'static str {
         match self {
@@ -35,6 +43,30 @@ impl fmt::Display for SearchMode {
     }
 }
 
+impl FromStr for SearchMode {
+    type Err = Error;
+
+    /// Parses a mode name, ignoring case and separators.
+    ///
+    /// Accepts `"deep research"`, `"deep-research"`, `"deep_research"` and
+    /// `"DeepResearch"` alike, plus a few common aliases such as `"research"`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const ALIASES: &[(&str, SearchMode)] = &[
+            ("auto", SearchMode::Auto),
+            ("turbo", SearchMode::Auto),
+            ("default", SearchMode::Auto),
+            ("pro", SearchMode::Pro),
+            ("copilot", SearchMode::Pro),
+            ("reasoning", SearchMode::Reasoning),
+            ("reason", SearchMode::Reasoning),
+            ("deepresearch", SearchMode::DeepResearch),
+            ("research", SearchMode::DeepResearch),
+        ];
+
+        parse_alias("mode", s, ALIASES, Self::ALL, Self::as_str)
+    }
+}
+
 /// Information source for search queries.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum Source {
@@ -48,6 +80,9 @@ pub enum Source {
 }
 
 impl Source {
+    /// All information sources, in declaration order.
+    pub const ALL: &[Self] = &[Self::Web, Self::Scholar, Self::Social];
+
     /// Returns the string representation used by the API.
     pub fn as_str(&self) -> &'static str {
         match self {
@@ -64,6 +99,138 @@ impl fmt::Display for Source {
     }
 }
 
+impl FromStr for Source {
+    type Err = Error;
+
+    /// Parses a source name, ignoring case and separators.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const ALIASES: &[(&str, Source)] = &[
+            ("web", Source::Web),
+            ("scholar", Source::Scholar),
+            ("academic", Source::Scholar),
+            ("social", Source::Social),
+        ];
+
+        parse_alias("source", s, ALIASES, Self::ALL, Self::as_str)
+    }
+}
+
+/// How long and detailed answers should be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnswerStyle {
+    /// Short, to-the-point answers.
+    Concise,
+    /// Perplexity's usual answer length.
+    Balanced,
+    /// Long answers with more background and sources.
+    Detailed,
+}
+
+impl AnswerStyle {
+    /// All answer styles, in declaration order.
+    pub const ALL: &[Self] = &[Self::Concise, Self::Balanced, Self::Detailed];
+
+    /// Returns the string representation used by the API.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Concise => "concise",
+            Self::Balanced => "balanced",
+            Self::Detailed => "detailed",
+        }
+    }
+}
+
+impl fmt::Display for AnswerStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for AnswerStyle {
+    type Err = Error;
+
+    /// Parses a style name, ignoring case and separators.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const ALIASES: &[(&str, AnswerStyle)] = &[
+            ("concise", AnswerStyle::Concise),
+            ("short", AnswerStyle::Concise),
+            ("brief", AnswerStyle::Concise),
+            ("balanced", AnswerStyle::Balanced),
+            ("normal", AnswerStyle::Balanced),
+            ("default", AnswerStyle::Balanced),
+            ("detailed", AnswerStyle::Detailed),
+            ("verbose", AnswerStyle::Detailed),
+            ("long", AnswerStyle::Detailed),
+        ];
+
+        parse_alias("answer style", s, ALIASES, Self::ALL, Self::as_str)
+    }
+}
+
+/// How recent the sources of an answer must be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recency {
+    /// Published in the last 24 hours.
+    Day,
+    /// Published in the last week.
+    Week,
+    /// Published in the last month.
+    Month,
+    /// Published in the last year.
+    Year,
+}
+
+impl Recency {
+    /// All recency filters, in declaration order.
+    pub const ALL: &[Self] = &[Self::Day, Self::Week, Self::Month, Self::Year];
+
+    /// Returns the string representation used by the API.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Day => "day",
+            Self::Week => "week",
+            Self::Month => "month",
+            Self::Year => "year",
+        }
+    }
+
+    /// The filter's value in the ask payload.
+    pub(crate) fn api_value(self) -> &'static str {
+        match self {
+            Self::Day => "DAY",
+            Self::Week => "WEEK",
+            Self::Month => "MONTH",
+            Self::Year => "YEAR",
+        }
+    }
+}
+
+impl fmt::Display for Recency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Recency {
+    type Err = Error;
+
+    /// Parses a period name, ignoring case and separators.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const ALIASES: &[(&str, Recency)] = &[
+            ("day", Recency::Day),
+            ("24h", Recency::Day),
+            ("today", Recency::Day),
+            ("week", Recency::Week),
+            ("7d", Recency::Week),
+            ("month", Recency::Month),
+            ("30d", Recency::Month),
+            ("year", Recency::Year),
+        ];
+
+        parse_alias("recency", s, ALIASES, Self::ALL, Self::as_str)
+    }
+}
+
 /// Model selection for Pro and Reasoning modes.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Model {
@@ -91,6 +258,19 @@ pub enum Model {
 }
 
 impl Model {
+    /// All models, in declaration order.
+    pub const ALL: &[Self] = &[
+        Self::Sonar,
+        Self::Gpt52,
+        Self::Claude45Sonnet,
+        Self::Grok41,
+        Self::Gpt52Thinking,
+        Self::Claude45SonnetThinking,
+        Self::Gemini30Pro,
+        Self::KimiK2Thinking,
+        Self::Grok41Reasoning,
+    ];
+
     /// Returns the user-facing string representation.
     pub fn as_str(&self) -> &'static str {
         match self {
@@ -105,6 +285,76 @@ impl Model {
             Self::Grok41Reasoning => "grok-4.1-reasoning",
         }
     }
+
+    /// The search mode the model is available in.
+    pub fn mode(&self) -> SearchMode {
+        if self.is_reasoning() { SearchMode::Reasoning } else { SearchMode::Pro }
+    }
+
+    /// The company behind the model.
+    pub fn provider(&self) -> &'static str {
+        match self {
+            Self::Sonar => "Perplexity",
+            Self::Gpt52 | Self::Gpt52Thinking => "OpenAI",
+            Self::Claude45Sonnet | Self::Claude45SonnetThinking => "Anthropic",
+            Self::Grok41 | Self::Grok41Reasoning => "xAI",
+            Self::Gemini30Pro => "Google",
+            Self::KimiK2Thinking => "Moonshot AI",
+        }
+    }
+
+    /// Whether the model thinks step by step before answering, which makes
+    /// it slower but better at logic and analysis.
+    pub fn is_reasoning(&self) -> bool {
+        matches!(
+            self,
+            Self::Gpt52Thinking
+                | Self::Claude45SonnetThinking
+                | Self::Gemini30Pro
+                | Self::KimiK2Thinking
+                | Self::Grok41Reasoning
+        )
+    }
+
+    /// How quickly the model typically answers.
+    pub fn speed_tier(&self) -> SpeedTier {
+        match self {
+            Self::Sonar | Self::Grok41 => SpeedTier::Fast,
+            Self::Gpt52 | Self::Claude45Sonnet | Self::Gemini30Pro => SpeedTier::Standard,
+            Self::Gpt52Thinking
+            | Self::Claude45SonnetThinking
+            | Self::KimiK2Thinking
+            | Self::Grok41Reasoning => SpeedTier::Slow,
+        }
+    }
+}
+
+/// How quickly a [`Model`] typically answers, relative to the others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpeedTier {
+    /// Answers in a few seconds.
+    Fast,
+    /// Takes noticeably longer than the fast models.
+    Standard,
+    /// Thinks at length first; can take a minute or more.
+    Slow,
+}
+
+impl SpeedTier {
+    /// Returns the string representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Fast => "fast",
+            Self::Standard => "standard",
+            Self::Slow => "slow",
+        }
+    }
+}
+
+impl fmt::Display for SpeedTier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 impl fmt::Display for Model {
@@ -113,6 +363,91 @@ impl fmt::Display for Model {
     }
 }
 
+impl FromStr for Model {
+    type Err = Error;
+
+    /// Parses a model name, ignoring case and separators.
+    ///
+    /// Accepts the names returned by [`Model::as_str`] (`"gpt-5.2"`,
+    /// `"claude-4.5-sonnet"`) as well as reordered spellings such as
+    /// `"claude-sonnet-4.5"` and `"gemini-3-pro"`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const ALIASES: &[(&str, Model)] = &[
+            ("sonar", Model::Sonar),
+            ("gpt52", Model::Gpt52),
+            ("claude45sonnet", Model::Claude45Sonnet),
+            ("claudesonnet45", Model::Claude45Sonnet),
+            ("sonnet45", Model::Claude45Sonnet),
+            ("grok41", Model::Grok41),
+            ("gpt52thinking", Model::Gpt52Thinking),
+            ("claude45sonnetthinking", Model::Claude45SonnetThinking),
+            ("claudesonnet45thinking", Model::Claude45SonnetThinking),
+            ("sonnet45thinking", Model::Claude45SonnetThinking),
+            ("gemini30pro", Model::Gemini30Pro),
+            ("gemini3pro", Model::Gemini30Pro),
+            ("kimik2thinking", Model::KimiK2Thinking),
+            ("kimik2", Model::KimiK2Thinking),
+            ("grok41reasoning", Model::Grok41Reasoning),
+        ];
+
+        parse_alias("model", s, ALIASES, Self::ALL, Self::as_str)
+    }
+}
+
+/// Reduces a name to lowercase ASCII alphanumerics so that separators and
+/// casing don't matter when matching aliases.
+fn normalize_name(s: &str) -> String {
+    s.chars().filter(char::is_ascii_alphanumeric).map(|c| c.to_ascii_lowercase()).collect()
+}
+
+/// Looks up `input` in an alias table, returning an
+/// [`Error::UnknownVariant`] with the closest canonical names on mismatch.
+fn parse_alias<T: Copy>(
+    kind: &'static str,
+    input: &str,
+    aliases: &[(&str, T)],
+    all: &[T],
+    name: fn(&T) -> &'static str,
+) -> Result<T, Error> {
+    let key = normalize_name(input);
+
+    if let Some((_, value)) = aliases.iter().find(|(alias, _)| *alias == key) {
+        return Ok(*value);
+    }
+
+    let mut ranked: Vec<(usize, &'static str)> = all
+        .iter()
+        .map(|v| (edit_distance(&key, &normalize_name(name(v))), name(v)))
+        .filter(|(distance, _)| *distance <= 3)
+        .collect();
+    ranked.sort_by_key(|(distance, _)| *distance);
+
+    let suggestions = if ranked.is_empty() {
+        all.iter().map(name).collect()
+    } else {
+        ranked.into_iter().map(|(_, n)| n).collect()
+    };
+
+    Err(Error::UnknownVariant { kind, value: input.to_string(), suggestions })
+}
+
+/// Levenshtein distance between two ASCII strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.as_bytes();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.bytes().enumerate() {
+        let mut curr = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        prev = curr;
+    }
+
+    prev[b.len()]
+}
+
 /// A file to be uploaded with a search query.
 #[derive(Debug, Clone)]
 pub enum UploadFile {
@@ -154,6 +489,81 @@ impl UploadFile {
     }
 }
 
+/// A file uploaded to Perplexity, ready to be attached to a query.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attachment {
+    /// Identifier of the uploaded file.
+    pub uuid: String,
+    /// URL the file was stored at; this is what the API references.
+    pub url: String,
+    /// Original file name.
+    pub filename: String,
+    /// MIME type the file was uploaded with.
+    pub mime_type: String,
+    /// Size of the file in bytes.
+    pub size: usize,
+}
+
+/// Progress of the uploads for a search request.
+///
+/// Reported as parts of each file are sent, and once more when the file is
+/// complete with [`attachment`](Self::attachment) set.
+#[derive(Debug, Clone)]
+pub struct UploadProgress {
+    /// Zero-based index of the file being uploaded.
+    pub index: usize,
+    /// Number of files being uploaded.
+    pub count: usize,
+    /// Name of the file being uploaded.
+    pub filename: String,
+    /// Bytes of the current file uploaded so far.
+    pub file_uploaded: usize,
+    /// Size of the current file in bytes.
+    pub file_size: usize,
+    /// Bytes uploaded so far across all files.
+    pub uploaded_bytes: usize,
+    /// Total bytes to upload across all files.
+    pub total_bytes: usize,
+    /// The uploaded file, once it is complete.
+    pub attachment: Option<Attachment>,
+}
+
+/// Callback receiving [`UploadProgress`] updates.
+#[derive(Clone)]
+pub struct UploadProgressFn(Arc<dyn Fn(&UploadProgress) + Send + Sync>);
+
+impl UploadProgressFn {
+    pub(crate) fn call(&self, progress: &UploadProgress) {
+        (self.0)(progress);
+    }
+}
+
+impl fmt::Debug for UploadProgressFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("UploadProgressFn")
+    }
+}
+
+/// Name of the file long context is attached as by [`SearchRequest::context`].
+const CONTEXT_FILENAME: &str = "context.md";
+
+/// Name of the file [`QueryOverflow::Attach`] moves excess query text to.
+const OVERFLOW_FILENAME: &str = "query-continued.md";
+
+/// What to do with a query longer than the API accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QueryOverflow {
+    /// Fail with [`Error::QueryTooLong`] before sending anything.
+    #[default]
+    Reject,
+    /// Send the first part as the query and upload the rest as an attachment
+    /// the query points to. Requires an authenticated client.
+    Attach,
+    /// Cut the query at the limit and report it in
+    /// [`SearchResponse::warnings`].
+    Truncate,
+}
+
 /// Parameters for a search request.
 #[derive(Debug, Clone, Default)]
 pub struct SearchRequest {
@@ -167,12 +577,30 @@ pub struct SearchRequest {
     pub sources: Vec<Source>,
     /// Files to upload with the query.
     pub files: Vec<UploadFile>,
+    /// Previously uploaded files to attach to the query.
+    pub attachments: Vec<Attachment>,
+    /// Called as the files in `files` are uploaded.
+    pub on_upload_progress: Option<UploadProgressFn>,
+    /// How to handle a query longer than the API accepts.
+    pub overflow: QueryOverflow,
     /// Language code (ISO 639), e.g., "en-US".
     pub language: String,
+    /// IANA time zone, e.g. "Europe/Berlin", that relative dates in the
+    /// query such as "today" refer to. The account's setting if not set.
+    pub timezone: Option<String>,
     /// Context from a previous query for follow-up.
     pub follow_up: Option<FollowUpContext>,
     /// Whether to enable incognito mode.
     pub incognito: bool,
+    /// Whether to ask Perplexity to generate an image for the query.
+    pub generate_image: bool,
+    /// Requested answer length; Perplexity's default if not set.
+    pub answer_style: Option<AnswerStyle>,
+    /// Only search sources published within this period; any age if not
+    /// set.
+    pub recency: Option<Recency>,
+    /// Correlation id sent as the `x-request-id` header of the ask request.
+    pub request_id: Option<String>,
 }
 
 impl SearchRequest {
@@ -184,9 +612,17 @@ impl SearchRequest {
             model: None,
             sources: vec![Source::Web],
             files: Vec::new(),
+            attachments: Vec::new(),
+            on_upload_progress: None,
+            overflow: QueryOverflow::Reject,
             language: "en-US".to_string(),
+            timezone: None,
             follow_up: None,
             incognito: false,
+            generate_image: false,
+            answer_style: None,
+            recency: None,
+            request_id: None,
         }
     }
 
@@ -214,12 +650,91 @@ impl SearchRequest {
         self
     }
 
+    /// Adds background `context` for the query.
+    ///
+    /// Short context is inlined into the query. Context that would push the
+    /// query past the length limit is attached as a generated `context.md`
+    /// file instead, which the query then refers to; that requires an
+    /// authenticated client.
+    pub fn context(mut self, context: &str) -> Self {
+        let inlined = format!("{}\n\nContext:\n{}", self.query, context);
+
+        if inlined.chars().count() <= MAX_QUERY_LENGTH {
+            self.query = inlined;
+        } else {
+            self.query = format!(
+                "{}\n\nUse the attached file {} as context.",
+                self.query, CONTEXT_FILENAME
+            );
+            self.files.push(UploadFile::from_text(CONTEXT_FILENAME, context));
+        }
+        self
+    }
+
+    /// Sets how to handle a query longer than the API accepts.
+    pub fn on_overflow(mut self, overflow: QueryOverflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Applies the [`QueryOverflow`] strategy if the query exceeds `limit`
+    /// characters, returning a warning if the query was changed.
+    pub(crate) fn fit_query(&mut self, limit: usize) -> Result<Option<String>, Error> {
+        let length = self.query.chars().count();
+        if length <= limit {
+            return Ok(None);
+        }
+
+        match self.overflow {
+            QueryOverflow::Reject => Err(Error::QueryTooLong { length, limit }),
+            QueryOverflow::Truncate => {
+                self.query = self.query.chars().take(limit).collect();
+                Ok(Some(format!("Query truncated from {} to {} characters", length, limit)))
+            }
+            QueryOverflow::Attach => {
+                let note =
+                    format!("\n\n[Continued in the attached file {}]", OVERFLOW_FILENAME);
+                let head_length = limit.saturating_sub(note.chars().count());
+                let head: String = self.query.chars().take(head_length).collect();
+                let rest: String = self.query.chars().skip(head_length).collect();
+
+                self.query = head + &note;
+                self.files.push(UploadFile::from_text(OVERFLOW_FILENAME, rest));
+                Ok(Some(format!(
+                    "Query of {} characters exceeded the {} character limit; the rest was attached as {}",
+                    length, limit, OVERFLOW_FILENAME
+                )))
+            }
+        }
+    }
+
+    /// Attaches a file uploaded earlier with [`Client::upload`](crate::Client::upload).
+    pub fn attachment(mut self, attachment: Attachment) -> Self {
+        self.attachments.push(attachment);
+        self
+    }
+
+    /// Sets a callback reporting progress as the files in `files` are uploaded.
+    pub fn on_upload_progress(
+        mut self,
+        callback: impl Fn(&UploadProgress) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_upload_progress = Some(UploadProgressFn(Arc::new(callback)));
+        self
+    }
+
     /// Sets the language.
     pub fn language(mut self, language: impl Into<String>) -> Self {
         self.language = language.into();
         self
     }
 
+    /// Sets the time zone relative dates in the query refer to.
+    pub fn timezone(mut self, timezone: impl Into<String>) -> Self {
+        self.timezone = Some(timezone.into());
+        self
+    }
+
     /// Sets the follow-up context from a previous query.
     pub fn follow_up(mut self, context: FollowUpContext) -> Self {
         self.follow_up = Some(context);
@@ -231,6 +746,34 @@ impl SearchRequest {
         self.incognito = incognito;
         self
     }
+
+    /// Enables or disables image generation.
+    ///
+    /// Generated images are returned in [`SearchEvent::images`]. Requires an
+    /// authenticated client.
+    pub fn generate_image(mut self, generate_image: bool) -> Self {
+        self.generate_image = generate_image;
+        self
+    }
+
+    /// Sets how long and detailed the answer should be.
+    pub fn answer_style(mut self, style: AnswerStyle) -> Self {
+        self.answer_style = Some(style);
+        self
+    }
+
+    /// Limits the search to sources published within `recency`.
+    pub fn recency(mut self, recency: Recency) -> Self {
+        self.recency = Some(recency);
+        self
+    }
+
+    /// Sets a correlation id to send with the ask request, so the query can
+    /// be matched across the caller's and Perplexity's logs.
+    pub fn request_id(mut self, id: impl Into<String>) -> Self {
+        self.request_id = Some(id.into());
+        self
+    }
 }
 
 /// Context for follow-up queries, extracted from a previous response.
@@ -256,7 +799,7 @@ impl Default for FollowUpContext {
 }
 
 /// A single event from the SSE stream.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct SearchEvent {
     /// The extracted answer text, if available.
     #[serde(default)]
@@ -270,6 +813,22 @@ pub struct SearchEvent {
     /// Attachment URLs associated with this response.
     #[serde(default)]
     pub attachments: Vec<String>,
+    /// Images generated for the query.
+    #[serde(default)]
+    pub images: Vec<GeneratedImage>,
+    /// Spoken (text-to-speech) version of the answer, if Perplexity provided one.
+    #[serde(default)]
+    pub audio: Option<AnswerAudio>,
+    /// Search queries Perplexity ran for the answer so far, in order.
+    #[serde(default)]
+    pub search_queries: Vec<String>,
+    /// The model Perplexity reports serving the answer, e.g. `"sonar"`.
+    #[serde(default)]
+    pub served_model: Option<String>,
+    /// The model's thinking so far, from the reasoning steps of Reasoning
+    /// mode answers.
+    #[serde(default)]
+    pub reasoning: Option<String>,
     /// The raw JSON value from the SSE event.
     #[serde(flatten)]
     pub raw: HashMap<String, serde_json::Value>,
@@ -285,11 +844,43 @@ impl SearchEvent {
     }
 }
 
+/// An image generated in response to a query.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GeneratedImage {
+    /// URL the image is hosted at, if it was not delivered inline.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// MIME type of the image, e.g. `"image/png"`.
+    #[serde(default)]
+    pub mime_type: Option<String>,
+    /// Decoded image bytes, if the image was delivered inline.
+    #[serde(skip)]
+    pub data: Option<Bytes>,
+}
+
+/// A text-to-speech rendering of an answer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnswerAudio {
+    /// URL of the audio file.
+    pub url: String,
+    /// MIME type of the audio, e.g. `"audio/mpeg"`.
+    #[serde(default)]
+    pub mime_type: Option<String>,
+}
+
+/// A web page cited by an answer.
+///
+/// Results keep the order Perplexity ranked them in: the `n`-th result is
+/// the source of the answer's `[n]` citation markers.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchWebResult {
     pub name: String,
     pub url: String,
     pub snippet: String,
+    /// Relevance score Perplexity gave the result, when it sent one.
+    /// Higher is more relevant; the scale is Perplexity's own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
 }
 
 /// The final response from a non-streaming search.
@@ -299,32 +890,302 @@ pub struct SearchResponse {
     pub answer: Option<String>,
     /// Web search results from the response.
     pub web_results: Vec<SearchWebResult>,
+    /// Images generated for the query.
+    pub images: Vec<GeneratedImage>,
+    /// Spoken version of the answer, if available.
+    pub audio: Option<AnswerAudio>,
+    /// Search queries Perplexity ran to find sources for the answer.
+    pub search_queries: Vec<String>,
+    /// The model that served the answer, as reported by Perplexity. May
+    /// differ from the requested one when Perplexity substitutes a model.
+    pub served_model: Option<String>,
+    /// The model's thinking before it answered, in Reasoning mode.
+    pub reasoning: Option<String>,
+    /// Files uploaded for this query, reusable with [`SearchRequest::attachment`].
+    pub uploads: Vec<Attachment>,
+    /// Changes made to the request before it was sent, such as truncation.
+    pub warnings: Vec<String>,
+    /// Retries and fallbacks that happened on the way to the answer.
+    pub degradations: Vec<Degradation>,
     /// Context for making follow-up queries.
     pub follow_up: FollowUpContext,
     /// The last raw event from the stream.
     pub raw: serde_json::Value,
+    /// Timing and volume statistics for the search.
+    ///
+    /// Left at its default when the response is built from a single event.
+    pub metrics: SearchMetrics,
+}
+
+impl From<SearchEvent> for SearchResponse {
+    /// Builds a response from a stream event, typically the last one received.
+    fn from(event: SearchEvent) -> Self {
+        let follow_up = event.as_follow_up();
+        let raw = serde_json::to_value(&event).unwrap_or_default();
+
+        Self {
+            answer: event.answer,
+            web_results: event.web_results,
+            images: event.images,
+            audio: event.audio,
+            search_queries: event.search_queries,
+            served_model: event.served_model,
+            reasoning: event.reasoning,
+            uploads: Vec::new(),
+            warnings: Vec::new(),
+            degradations: Vec::new(),
+            follow_up,
+            raw,
+            metrics: SearchMetrics::default(),
+        }
+    }
+}
+
+/// Something that went wrong while answering a query and was worked around,
+/// so the answer came from a degraded path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Degradation {
+    /// What happened.
+    pub kind: DegradationKind,
+    /// Why it happened, typically the error that was worked around.
+    pub reason: String,
+    /// How many times it happened.
+    pub count: u32,
+}
+
+/// What kind of [`Degradation`] happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DegradationKind {
+    /// Upload requests were retried after timeouts, transport errors or
+    /// server errors.
+    UploadRetry,
+    /// The query was resent on a fresh anonymous session after the previous
+    /// session was rate limited.
+    SessionRetry,
+    /// Another research provider answered because the primary one was
+    /// unavailable.
+    ProviderFallback,
+}
+
+impl DegradationKind {
+    /// Returns the string representation, e.g. `"upload_retry"`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::UploadRetry => "upload_retry",
+            Self::SessionRetry => "session_retry",
+            Self::ProviderFallback => "provider_fallback",
+        }
+    }
+}
+
+impl fmt::Display for DegradationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Timing and volume statistics collected while a search ran.
+///
+/// Durations are measured from the start of the search call.
+#[derive(Debug, Clone, Default)]
+pub struct SearchMetrics {
+    /// Search mode the query ran in.
+    pub mode: SearchMode,
+    /// Model explicitly requested, if any.
+    pub model: Option<Model>,
+    /// Model preference sent to the API, e.g. `"pplx_pro"`.
+    pub model_preference: &'static str,
+    /// Time spent uploading attachments.
+    pub upload: Duration,
+    /// Time until the ask request's response headers arrived.
+    pub connect: Duration,
+    /// Time until the first SSE event.
+    pub first_event: Option<Duration>,
+    /// Time until the first event containing answer text.
+    pub first_answer: Option<Duration>,
+    /// Time until the stream ended.
+    pub total: Duration,
+    /// Number of SSE events received.
+    pub event_count: usize,
+    /// Perplexity's id for the ask request, from its `x-request-id` or
+    /// `cf-ray` response header.
+    pub server_request_id: Option<String>,
+    /// Wall-clock time the search call started.
+    pub started_at: Option<SystemTime>,
+    /// Wall-clock time the stream ended.
+    pub finished_at: Option<SystemTime>,
+    /// Time zone sent with the query, if one was set.
+    pub timezone: Option<String>,
+}
+
+/// A public link to a thread, created with
+/// [`Client::share_thread`](crate::Client::share_thread).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShareLink {
+    /// Backend UUID of the shared thread.
+    pub backend_uuid: String,
+    /// Public URL of the thread.
+    pub url: String,
+}
+
+/// Metadata about a thread, returned by
+/// [`Client::thread_info`](crate::Client::thread_info).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThreadInfo {
+    /// Backend UUID of the thread.
+    pub backend_uuid: String,
+    /// Title shown in the Perplexity library.
+    pub title: Option<String>,
+    /// Slug used in the thread's URL.
+    pub slug: Option<String>,
+    /// Mode of the most recent turn, as reported by the API (e.g. `"COPILOT"`).
+    pub mode: Option<String>,
+    /// Model that answered the most recent turn.
+    pub model: Option<String>,
+    /// When the first turn was asked (ISO 8601).
+    pub created_at: Option<String>,
+    /// When the thread was last updated (ISO 8601).
+    pub updated_at: Option<String>,
+    /// Number of question/answer turns in the thread.
+    pub turns: usize,
+}
+
+impl ThreadInfo {
+    /// Summarizes the entries of a thread, ordered oldest first.
+    pub(crate) fn from_entries(backend_uuid: &str, entries: &[ThreadEntry]) -> Self {
+        let first = entries.first();
+        let last = entries.last();
+
+        Self {
+            backend_uuid: backend_uuid.to_string(),
+            title: entries.iter().rev().find_map(|e| e.thread_title.clone()),
+            slug: entries.iter().rev().find_map(|e| e.thread_url_slug.clone()),
+            mode: last.and_then(|e| e.mode.clone()),
+            model: last.and_then(|e| e.display_model.clone()),
+            created_at: first.and_then(|e| e.entry_created_datetime.clone()),
+            updated_at: last
+                .and_then(|e| e.updated_datetime.clone().or(e.entry_created_datetime.clone())),
+            turns: entries.len(),
+        }
+    }
+}
+
+/// A thread in a listing such as
+/// [`Client::bookmarked_threads`](crate::Client::bookmarked_threads).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThreadSummary {
+    /// Backend UUID of the thread.
+    #[serde(alias = "uuid")]
+    pub backend_uuid: String,
+    /// Title shown in the Perplexity library.
+    #[serde(default, alias = "thread_title")]
+    pub title: Option<String>,
+    /// Slug used in the thread's URL.
+    #[serde(default, alias = "thread_url_slug")]
+    pub slug: Option<String>,
+    /// When the thread was last updated (ISO 8601).
+    #[serde(default, alias = "last_query_datetime")]
+    pub updated_at: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ThreadListResponse {
+    #[serde(default)]
+    pub threads: Vec<ThreadSummary>,
 }
 
 #[derive(Serialize)]
-pub(crate) struct AskPayload<'a> {
-    pub query_str: &'a str,
-    pub params: AskParams<'a>,
+pub(crate) struct ThreadBookmarkRequest<'a> {
+    pub backend_uuid: &'a str,
+    pub bookmarked: bool,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ThreadResponse {
+    #[serde(default)]
+    pub entries: Vec<ThreadEntry>,
+}
+
+/// One turn of a thread as returned by the thread endpoint.
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct ThreadEntry {
+    #[serde(default)]
+    pub thread_title: Option<String>,
+    #[serde(default)]
+    pub thread_url_slug: Option<String>,
+    #[serde(default)]
+    pub mode: Option<String>,
+    #[serde(default)]
+    pub display_model: Option<String>,
+    #[serde(default)]
+    pub entry_created_datetime: Option<String>,
+    #[serde(default)]
+    pub updated_datetime: Option<String>,
 }
 
 #[derive(Serialize)]
-pub(crate) struct AskParams<'a> {
-    pub attachments: Vec<String>,
-    pub frontend_context_uuid: String,
-    pub frontend_uuid: String,
-    pub is_incognito: bool,
-    pub language: &'a str,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub last_backend_uuid: Option<String>,
-    pub mode: &'static str,
-    pub model_preference: &'static str,
-    pub source: &'static str,
-    pub sources: Vec<&'static str>,
-    pub version: &'static str,
+pub(crate) struct ThreadTitleRequest<'a> {
+    pub backend_uuid: &'a str,
+    pub title: &'a str,
+}
+
+/// The account a client is signed in to, returned by
+/// [`Client::session`](crate::Client::session).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountSession {
+    /// Display name of the account.
+    pub name: Option<String>,
+    /// Email address of the account.
+    pub email: Option<String>,
+    /// When the session expires (ISO 8601).
+    pub expires: Option<String>,
+}
+
+/// Outcome of [`Client::check_connection`](crate::Client::check_connection).
+#[derive(Debug, Clone)]
+pub struct ConnectionCheck {
+    /// HTTP status Perplexity answered with.
+    pub status: u16,
+    /// Time from sending the request to receiving the whole response.
+    pub round_trip: Duration,
+    /// Perplexity's `Date` header, for comparing clocks.
+    pub date: Option<String>,
+}
+
+/// Body of the auth session endpoint: empty when not signed in.
+#[derive(Deserialize)]
+pub(crate) struct SessionResponse {
+    #[serde(default)]
+    user: Option<SessionUser>,
+    #[serde(default)]
+    expires: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SessionUser {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    email: Option<String>,
+}
+
+impl SessionResponse {
+    pub(crate) fn into_session(self) -> Option<AccountSession> {
+        let user = self.user?;
+        Some(AccountSession { name: user.name, email: user.email, expires: self.expires })
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct ThreadAccessRequest<'a> {
+    pub backend_uuid: &'a str,
+    pub updated_access: u8,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ThreadAccessResponse {
+    #[serde(default)]
+    pub thread_url_slug: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -338,6 +1199,8 @@ pub(crate) struct UploadUrlRequest {
 
 #[derive(Deserialize)]
 pub(crate) struct UploadUrlResponse {
+    #[serde(default)]
+    pub file_uuid: Option<String>,
     pub fields: HashMap<String, String>,
     pub s3_bucket_url: String,
     pub s3_object_url: String,
@@ -347,3 +1210,200 @@ pub(crate) struct UploadUrlResponse {
 pub(crate) struct S3UploadResponse {
     pub secure_url: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_context_is_inlined() {
+        let request = SearchRequest::new("Summarize this").context("Rust is fast.");
+
+        assert_eq!(request.query, "Summarize this\n\nContext:\nRust is fast.");
+        assert!(request.files.is_empty());
+    }
+
+    #[test]
+    fn test_long_context_is_attached() {
+        let context = "word ".repeat(MAX_QUERY_LENGTH);
+        let request = SearchRequest::new("Summarize this").context(&context);
+
+        assert!(request.query.starts_with("Summarize this"));
+        assert!(request.query.contains(CONTEXT_FILENAME));
+        assert_eq!(request.files.len(), 1);
+        assert_eq!(request.files[0].filename(), CONTEXT_FILENAME);
+        assert_eq!(request.files[0].len(), context.len());
+    }
+
+    #[test]
+    fn test_fit_query_rejects_by_default() {
+        let mut request = SearchRequest::new("x".repeat(20));
+
+        assert!(request.fit_query(20).unwrap().is_none());
+        assert!(matches!(
+            request.fit_query(10),
+            Err(Error::QueryTooLong { length: 20, limit: 10 })
+        ));
+    }
+
+    #[test]
+    fn test_fit_query_truncates() {
+        let mut request =
+            SearchRequest::new("héllo wörld").on_overflow(QueryOverflow::Truncate);
+
+        assert!(request.fit_query(5).unwrap().is_some());
+        assert_eq!(request.query, "héllo");
+        assert!(request.files.is_empty());
+    }
+
+    #[test]
+    fn test_fit_query_attaches_overflow() {
+        let query = "a".repeat(100) + &"b".repeat(100);
+        let mut request = SearchRequest::new(&query).on_overflow(QueryOverflow::Attach);
+
+        assert!(request.fit_query(150).unwrap().is_some());
+        assert_eq!(request.query.chars().count(), 150);
+        assert!(request.query.ends_with(&format!("{}]", OVERFLOW_FILENAME)));
+
+        let head = request.query.split("\n\n[").next().unwrap();
+        let rest = request.files[0].as_bytes();
+        assert_eq!(format!("{}{}", head, String::from_utf8_lossy(&rest)), query);
+    }
+
+    #[test]
+    fn test_search_mode_from_str_aliases() {
+        for input in ["deep research", "deep-research", "deep_research", "DeepResearch"] {
+            assert_eq!(input.parse::<SearchMode>().unwrap(), SearchMode::DeepResearch);
+        }
+        assert_eq!("Pro".parse::<SearchMode>().unwrap(), SearchMode::Pro);
+        assert_eq!("reason".parse::<SearchMode>().unwrap(), SearchMode::Reasoning);
+    }
+
+    #[test]
+    fn test_search_mode_round_trips_display() {
+        for mode in SearchMode::ALL {
+            assert_eq!(mode.to_string().parse::<SearchMode>().unwrap(), *mode);
+        }
+    }
+
+    #[test]
+    fn test_model_from_str_aliases() {
+        assert_eq!("gpt-5.2".parse::<Model>().unwrap(), Model::Gpt52);
+        assert_eq!("GPT_5_2".parse::<Model>().unwrap(), Model::Gpt52);
+        assert_eq!("claude-4.5-sonnet".parse::<Model>().unwrap(), Model::Claude45Sonnet);
+        assert_eq!("claude-sonnet-4.5".parse::<Model>().unwrap(), Model::Claude45Sonnet);
+        assert_eq!("gemini-3-pro".parse::<Model>().unwrap(), Model::Gemini30Pro);
+    }
+
+    #[test]
+    fn test_model_round_trips_display() {
+        for model in Model::ALL {
+            assert_eq!(model.to_string().parse::<Model>().unwrap(), *model);
+        }
+    }
+
+    #[test]
+    fn test_model_metadata_matches_modes() {
+        for model in Model::ALL {
+            assert!(
+                crate::config::model_preference(model.mode(), Some(*model)).is_some(),
+                "{model} is not available in {}",
+                model.mode()
+            );
+            assert_eq!(model.is_reasoning(), model.mode() == SearchMode::Reasoning);
+        }
+        assert_eq!(Model::Sonar.speed_tier(), SpeedTier::Fast);
+        assert_eq!(Model::KimiK2Thinking.provider(), "Moonshot AI");
+    }
+
+    #[test]
+    fn test_source_from_str() {
+        assert_eq!("WEB".parse::<Source>().unwrap(), Source::Web);
+        assert_eq!("academic".parse::<Source>().unwrap(), Source::Scholar);
+    }
+
+    #[test]
+    fn test_answer_style_from_str() {
+        assert_eq!("Brief".parse::<AnswerStyle>().unwrap(), AnswerStyle::Concise);
+        assert_eq!("detailed".parse::<AnswerStyle>().unwrap(), AnswerStyle::Detailed);
+        assert!("chatty".parse::<AnswerStyle>().is_err());
+    }
+
+    #[test]
+    fn test_unknown_value_suggests_closest() {
+        let Err(Error::UnknownVariant { kind, value, suggestions }) =
+            "gpt-5.1".parse::<Model>()
+        else {
+            panic!("expected UnknownVariant");
+        };
+
+        assert_eq!(kind, "model");
+        assert_eq!(value, "gpt-5.1");
+        assert_eq!(suggestions.first(), Some(&"gpt-5.2"));
+    }
+
+    #[test]
+    fn test_unknown_value_without_close_match_lists_all() {
+        let Err(Error::UnknownVariant { suggestions, .. }) = "xyzzy".parse::<Source>() else {
+            panic!("expected UnknownVariant");
+        };
+
+        assert_eq!(suggestions, vec!["web", "scholar", "social"]);
+    }
+
+    #[test]
+    fn test_thread_info_from_entries() {
+        let entries: ThreadResponse = serde_json::from_str(
+            r#"{"entries": [
+                {"thread_title": "Rust", "thread_url_slug": "rust-abc", "mode": "CONCISE",
+                 "entry_created_datetime": "2026-01-01T10:00:00Z"},
+                {"thread_title": "Rust async", "mode": "COPILOT", "display_model": "claude",
+                 "entry_created_datetime": "2026-01-01T10:05:00Z"}
+            ]}"#,
+        )
+        .unwrap();
+        let info = ThreadInfo::from_entries("abc", &entries.entries);
+
+        assert_eq!(info.title.as_deref(), Some("Rust async"));
+        assert_eq!(info.slug.as_deref(), Some("rust-abc"));
+        assert_eq!(info.mode.as_deref(), Some("COPILOT"));
+        assert_eq!(info.model.as_deref(), Some("claude"));
+        assert_eq!(info.created_at.as_deref(), Some("2026-01-01T10:00:00Z"));
+        assert_eq!(info.updated_at.as_deref(), Some("2026-01-01T10:05:00Z"));
+        assert_eq!(info.turns, 2);
+    }
+
+    #[test]
+    fn test_thread_list_accepts_api_field_names() {
+        let response: ThreadListResponse = serde_json::from_str(
+            r#"{"threads": [
+                {"uuid": "abc", "thread_title": "Rust", "thread_url_slug": "rust-abc",
+                 "last_query_datetime": "2026-01-01T10:00:00Z"},
+                {"backend_uuid": "def"}
+            ]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(response.threads.len(), 2);
+        assert_eq!(response.threads[0].backend_uuid, "abc");
+        assert_eq!(response.threads[0].title.as_deref(), Some("Rust"));
+        assert_eq!(response.threads[0].slug.as_deref(), Some("rust-abc"));
+        assert_eq!(response.threads[1].backend_uuid, "def");
+        assert!(response.threads[1].title.is_none());
+    }
+
+    #[test]
+    fn test_session_response() {
+        let signed_in: SessionResponse = serde_json::from_str(
+            r#"{"user": {"name": "Ada", "email": "ada@example.com", "image": null},
+                "expires": "2026-12-01T00:00:00.000Z"}"#,
+        )
+        .unwrap();
+        let session = signed_in.into_session().unwrap();
+        assert_eq!(session.email.as_deref(), Some("ada@example.com"));
+        assert_eq!(session.expires.as_deref(), Some("2026-12-01T00:00:00.000Z"));
+
+        let signed_out: SessionResponse = serde_json::from_str("{}").unwrap();
+        assert!(signed_out.into_session().is_none());
+    }
+}
@@ -1,26 +1,83 @@
-use crate::config::{API_BASE_URL, API_VERSION, ENDPOINT_UPLOAD_URL};
+use crate::compat::ApiVersion;
+use crate::config::{API_BASE_URL, ENDPOINT_UPLOAD_URL};
 use crate::error::{Error, Result};
-use crate::types::{S3UploadResponse, UploadFile, UploadUrlRequest, UploadUrlResponse};
+use crate::headers::Headers;
+use crate::hosts::HostAllowlist;
+use crate::rt;
+use crate::types::{
+    Attachment, S3UploadResponse, UploadFile, UploadUrlRequest, UploadUrlResponse,
+};
+use bytes::Bytes;
 use regex::Regex;
 use rquest::Client as HttpClient;
+use rquest::Response;
 use std::sync::LazyLock;
 use std::time::Duration;
+use uuid::Uuid;
 
 static S3_URL_REGEX: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new(r"/private/s--.*?--/v\d+/user_uploads/").expect("Invalid S3 URL regex pattern")
 });
 
+/// Size of each part of a chunked upload. The image endpoint requires parts
+/// of at least 5 MB, except for the last one.
+const CHUNK_SIZE: usize = 6 * 1024 * 1024;
+
+/// Attempts made for each upload request before giving up.
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Delay before the first retry; doubled for each further attempt.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Upload requests that were retried, and why the last one was.
+#[derive(Debug, Default)]
+pub(crate) struct UploadRetries {
+    pub(crate) count: u32,
+    pub(crate) last_reason: Option<String>,
+}
+
+impl UploadRetries {
+    fn record(&mut self, reason: String) {
+        self.count += 1;
+        self.last_reason = Some(reason);
+    }
+
+    /// Adds the retries of `other`, which happened after these.
+    pub(crate) fn merge(&mut self, other: Self) {
+        self.count += other.count;
+        if other.last_reason.is_some() {
+            self.last_reason = other.last_reason;
+        }
+    }
+}
+
+/// Uploads `file`, calling `on_progress` with the number of bytes of it
+/// uploaded so far. Returns the attachment and the retries it took.
+///
+/// Only images are chunked. They go to Cloudinary, which accepts a file in
+/// [`CHUNK_SIZE`] parts sharing an upload id, so large images are sent part
+/// by part and a dropped connection only costs the part in flight: it is
+/// retried, and the parts already acknowledged are not sent again. Every
+/// other file, PDFs and videos included, goes to an S3 presigned POST form,
+/// which takes the whole file in one request and has no way to resume, so
+/// those are sent whole and a failed attempt starts over from the first
+/// byte. Every request is retried on transport errors and server errors.
+///
+/// The upload and object URLs Perplexity returns must be on `hosts`.
 pub(crate) async fn upload_file(
     http: &HttpClient,
+    headers: &Headers,
+    hosts: &HostAllowlist,
+    api_version: ApiVersion,
     file: &UploadFile,
     timeout: Duration,
-) -> Result<String> {
-    let content_type =
-        mime_guess::from_path(file.filename()).first_or_octet_stream().to_string();
+    on_progress: &mut (dyn FnMut(usize) + Send),
+) -> Result<(Attachment, UploadRetries)> {
+    let content_type = crate::filetype::content_type(file)?.to_string();
 
-    let upload_url_fut = http
-        .post(format!("{}{}", API_BASE_URL, ENDPOINT_UPLOAD_URL))
-        .query(&[("version", API_VERSION), ("source", "default")])
+    let upload_url_fut = headers
+        .apply(http.post(format!("{}{}", API_BASE_URL, ENDPOINT_UPLOAD_URL)))
+        .query(&[("version", api_version.as_str()), ("source", "default")])
         .json(&UploadUrlRequest {
             content_type: content_type.clone(),
             file_size: file.len(),
@@ -30,36 +87,47 @@ pub(crate) async fn upload_file(
         })
         .send();
 
-    let upload_url_resp: UploadUrlResponse = tokio::time::timeout(timeout, upload_url_fut)
-        .await
-        .map_err(|_| Error::Timeout(timeout))?
+    let upload_url_resp: UploadUrlResponse = rt::timeout(timeout, upload_url_fut)
+        .await?
         .map_err(Error::Http)?
         .error_for_status()
         .map_err(|e| Error::UploadUrlFailed(e.to_string()))?
         .json()
         .await?;
+    check_target(hosts, &upload_url_resp)?;
 
-    let mut form = rquest::multipart::Form::new();
-    for (key, value) in &upload_url_resp.fields {
-        form = form.text(key.clone(), value.clone());
-    }
-
-    let file_part = rquest::multipart::Part::bytes(file.as_bytes().to_vec())
-        .file_name(file.filename().to_string())
-        .mime_str(&content_type)
-        .map_err(|e| Error::InvalidMimeType(e.to_string()))?;
-    form = form.part("file", file_part);
+    let is_image_upload = upload_url_resp.s3_object_url.contains("image/upload");
+    let data = file.as_bytes();
 
-    let s3_upload_fut = http.post(&upload_url_resp.s3_bucket_url).multipart(form).send();
+    let (upload_resp, retries) = if is_image_upload && data.len() > CHUNK_SIZE {
+        upload_chunked(
+            http,
+            &upload_url_resp,
+            file,
+            &content_type,
+            &data,
+            timeout,
+            on_progress,
+        )
+        .await?
+    } else {
+        let resp = upload_part(
+            http,
+            &upload_url_resp,
+            file,
+            &content_type,
+            data.clone(),
+            None,
+            timeout,
+        )
+        .await?;
+        on_progress(data.len());
+        resp
+    };
 
-    let upload_resp = tokio::time::timeout(timeout, s3_upload_fut)
-        .await
-        .map_err(|_| Error::Timeout(timeout))?
-        .map_err(Error::Http)?
-        .error_for_status()
-        .map_err(|e| Error::S3UploadFailed(e.to_string()))?;
+    let uuid = upload_url_resp.file_uuid.clone().unwrap_or_else(|| Uuid::new_v4().to_string());
 
-    let uploaded_url = if upload_url_resp.s3_object_url.contains("image/upload") {
+    let uploaded_url = if is_image_upload {
         let s3_resp: S3UploadResponse = upload_resp.json().await?;
         let secure_url = s3_resp.secure_url.ok_or(Error::MissingSecureUrl)?;
 
@@ -68,5 +136,153 @@ pub(crate) async fn upload_file(
         upload_url_resp.s3_object_url
     };
 
-    Ok(uploaded_url)
+    let attachment = Attachment {
+        uuid,
+        url: uploaded_url,
+        filename: file.filename().to_string(),
+        mime_type: content_type,
+        size: file.len(),
+    };
+    Ok((attachment, retries))
+}
+
+/// Fails unless both the URL the file is posted to and the URL it will be
+/// served from are on `hosts`, before any of the file is sent.
+fn check_target(hosts: &HostAllowlist, target: &UploadUrlResponse) -> Result<()> {
+    hosts.check(&target.s3_bucket_url)?;
+    hosts.check(&target.s3_object_url)
+}
+
+/// Sends `data` in consecutive parts sharing one upload id, returning the
+/// response to the last part and the retries of all parts.
+async fn upload_chunked(
+    http: &HttpClient,
+    target: &UploadUrlResponse,
+    file: &UploadFile,
+    content_type: &str,
+    data: &Bytes,
+    timeout: Duration,
+    on_progress: &mut (dyn FnMut(usize) + Send),
+) -> Result<(Response, UploadRetries)> {
+    let upload_id = Uuid::new_v4().to_string();
+    let mut start = 0;
+    let mut retries = UploadRetries::default();
+
+    loop {
+        let end = (start + CHUNK_SIZE).min(data.len());
+        let range = ChunkRange { upload_id: &upload_id, start, end, total: data.len() };
+
+        let part = data.slice(start..end);
+        let (response, part_retries) =
+            upload_part(http, target, file, content_type, part, Some(range), timeout).await?;
+        retries.merge(part_retries);
+
+        on_progress(end);
+        if end == data.len() {
+            return Ok((response, retries));
+        }
+        start = end;
+    }
+}
+
+/// Position of a part within a chunked upload.
+#[derive(Clone, Copy)]
+struct ChunkRange<'a> {
+    upload_id: &'a str,
+    start: usize,
+    end: usize,
+    total: usize,
+}
+
+/// Posts one part (or the whole file) to the presigned upload form, retrying
+/// timeouts, transport errors and server errors. Returns the response and
+/// the retries it took.
+async fn upload_part(
+    http: &HttpClient,
+    target: &UploadUrlResponse,
+    file: &UploadFile,
+    content_type: &str,
+    data: Bytes,
+    range: Option<ChunkRange<'_>>,
+    timeout: Duration,
+) -> Result<(Response, UploadRetries)> {
+    let mut attempt = 1;
+    let mut retries = UploadRetries::default();
+
+    loop {
+        // Multipart forms are consumed on send, so build a fresh one per attempt.
+        let mut form = rquest::multipart::Form::new();
+        for (key, value) in &target.fields {
+            form = form.text(key.clone(), value.clone());
+        }
+        let file_part = rquest::multipart::Part::bytes(data.to_vec())
+            .file_name(file.filename().to_string())
+            .mime_str(content_type)
+            .map_err(|e| Error::InvalidMimeType(e.to_string()))?;
+        form = form.part("file", file_part);
+
+        let mut request = http.post(&target.s3_bucket_url).multipart(form);
+        if let Some(range) = range {
+            request = request.header("X-Unique-Upload-Id", range.upload_id).header(
+                "Content-Range",
+                format!("bytes {}-{}/{}", range.start, range.end - 1, range.total),
+            );
+        }
+
+        let reason = match rt::timeout(timeout, request.send()).await {
+            Ok(Ok(response)) if response.status().is_success() => {
+                return Ok((response, retries));
+            }
+            Ok(Ok(response)) if !response.status().is_server_error() => {
+                return Err(Error::S3UploadFailed(format!(
+                    "upload responded with {}",
+                    response.status()
+                )));
+            }
+            Ok(Ok(response)) if attempt == MAX_ATTEMPTS => {
+                return Err(Error::S3UploadFailed(format!(
+                    "upload responded with {} after {} attempts",
+                    response.status(),
+                    attempt
+                )));
+            }
+            Ok(Err(e)) if attempt == MAX_ATTEMPTS => {
+                return Err(Error::S3UploadFailed(e.to_string()));
+            }
+            Err(e) if attempt == MAX_ATTEMPTS => return Err(e),
+            Ok(Ok(response)) => format!("upload responded with {}", response.status()),
+            Ok(Err(e)) => e.to_string(),
+            Err(e) => e.to_string(),
+        };
+        retries.record(reason);
+
+        rt::sleep(RETRY_DELAY * 2u32.pow(attempt - 1)).await;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upload_target_must_be_allowed() {
+        let target = |bucket: &str| -> UploadUrlResponse {
+            serde_json::from_value(serde_json::json!({
+                "fields": {},
+                "s3_bucket_url": bucket,
+                "s3_object_url": "https://ppl-ai-file-upload.s3.amazonaws.com/web/doc.pdf",
+            }))
+            .unwrap()
+        };
+        let hosts = HostAllowlist::perplexity();
+
+        let allowed = target("https://ppl-ai-file-upload.s3.amazonaws.com/");
+        assert!(check_target(&hosts, &allowed).is_ok());
+        let elsewhere = target("https://collector.evil.test/upload");
+        assert!(matches!(
+            check_target(&hosts, &elsewhere),
+            Err(Error::HostNotAllowed { host }) if host == "collector.evil.test"
+        ));
+    }
 }
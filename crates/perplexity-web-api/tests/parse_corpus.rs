@@ -0,0 +1,82 @@
+//! Snapshot tests of the parser over recorded search streams.
+//!
+//! Every file in `tests/corpus` is an anonymized response body of the ask
+//! endpoint. Its events are parsed and the extracted fields snapshotted, so
+//! a parser change that alters what is extracted shows up as a snapshot
+//! diff. After an intended change, review and accept the new snapshots with
+//! `cargo insta review`.
+
+use perplexity_web_api::{AnswerAudio, SearchEvent, SearchWebResult, parse_sse};
+use serde::Serialize;
+
+/// What the parser extracted from one event.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Parsed {
+    Event(Box<Extracted>),
+    Error { error: String },
+}
+
+#[derive(Serialize)]
+struct Extracted {
+    answer: Option<String>,
+    web_results: Vec<SearchWebResult>,
+    backend_uuid: Option<String>,
+    attachments: Vec<String>,
+    images: Vec<Image>,
+    audio: Option<AnswerAudio>,
+    search_queries: Vec<String>,
+    served_model: Option<String>,
+    reasoning: Option<String>,
+    /// Keys left in the raw map, sorted.
+    raw_keys: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Image {
+    url: Option<String>,
+    mime_type: Option<String>,
+    inline_bytes: Option<usize>,
+}
+
+impl From<SearchEvent> for Extracted {
+    fn from(event: SearchEvent) -> Self {
+        let mut raw_keys: Vec<String> = event.raw.into_keys().collect();
+        raw_keys.sort();
+        let images = event
+            .images
+            .into_iter()
+            .map(|image| Image {
+                url: image.url,
+                mime_type: image.mime_type,
+                inline_bytes: image.data.map(|data| data.len()),
+            })
+            .collect();
+        Self {
+            answer: event.answer,
+            web_results: event.web_results,
+            backend_uuid: event.backend_uuid,
+            attachments: event.attachments,
+            images,
+            audio: event.audio,
+            search_queries: event.search_queries,
+            served_model: event.served_model,
+            reasoning: event.reasoning,
+            raw_keys,
+        }
+    }
+}
+
+#[test]
+fn test_parse_recorded_streams() {
+    insta::glob!("corpus/*.sse", |path| {
+        let body = std::fs::read(path).unwrap();
+        let parsed: Vec<Parsed> = parse_sse(&body)
+            .map(|event| match event {
+                Ok(event) => Parsed::Event(Box::new(event.into())),
+                Err(e) => Parsed::Error { error: e.to_string() },
+            })
+            .collect();
+        insta::assert_yaml_snapshot!(parsed);
+    });
+}